@@ -0,0 +1,56 @@
+//! Draws a grid of cubes from one mesh, in a single instanced draw.
+
+use cgmath::{Matrix4, Vector3};
+use rt_renderer::{mesh::Mesh, App, InstanceData, MyVertex};
+
+const GRID_SIZE: usize = 32;
+const SPACING: f32 = 1.5;
+
+/// A unit cube around the origin, with flat normals.
+fn cube() -> Mesh {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for axis in 0..3 {
+        for sign in [-1.0f32, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let first = vertices.len() as u32;
+            for [a, b] in [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]] {
+                let mut position = [0.0; 3];
+                position[axis] = sign * 0.5;
+                position[u] = a * 0.5;
+                position[v] = b * 0.5;
+                vertices.push(MyVertex {
+                    position,
+                    normal,
+                    color: [1.0; 4],
+                    ..Default::default()
+                });
+            }
+            // Counter-clockwise seen from outside.
+            let quad = match sign > 0.0 {
+                true => [0, 1, 2, 0, 2, 3],
+                false => [0, 2, 1, 0, 3, 2],
+            };
+            indices.extend(quad.map(|i| first + i));
+        }
+    }
+    Mesh::new(vertices, indices).unwrap().with_name("Cube")
+}
+
+fn main() {
+    let half = (GRID_SIZE - 1) as f32 * SPACING / 2.0;
+    let instances = (0..GRID_SIZE * GRID_SIZE)
+        .map(|i| {
+            let (x, z) = ((i % GRID_SIZE) as f32, (i / GRID_SIZE) as f32);
+            let offset = Vector3::new(x * SPACING - half, 0.0, z * SPACING - half);
+            let color = [x / GRID_SIZE as f32, 0.5, z / GRID_SIZE as f32, 1.0];
+            InstanceData::new(Matrix4::from_translation(offset), color)
+        })
+        .collect();
+
+    let mut app = App::new().unwrap();
+    app.set_instances(0, Some(instances));
+    app.run_meshes(&[cube()]).unwrap();
+}
@@ -0,0 +1,396 @@
+//! Skins and animation clips read with the `gltf` crate, since `easy_gltf` only exposes geometry
+//! with node transforms already baked in.
+
+use std::path::Path;
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, VectorSpace};
+use gltf::animation::{util::ReadOutputs, Interpolation};
+
+use crate::gltf::for_each_primitive;
+
+#[derive(Clone, Copy, Debug)]
+struct Transform {
+    translation: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    scale: Vector3<f32>,
+}
+
+impl Transform {
+    fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl From<gltf::scene::Transform> for Transform {
+    fn from(transform: gltf::scene::Transform) -> Self {
+        let (translation, [x, y, z, w], scale) = transform.decomposed();
+        Self {
+            translation: translation.into(),
+            rotation: Quaternion::new(w, x, y, z),
+            scale: scale.into(),
+        }
+    }
+}
+
+struct Node {
+    children: Vec<usize>,
+    rest: Transform,
+}
+
+enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+    /// One weight per morph target for each keyframe.
+    Weights(Vec<Vec<f32>>),
+}
+
+struct Channel {
+    node: usize,
+    step: bool,
+    times: Vec<f32>,
+    keyframes: Keyframes,
+}
+
+impl Channel {
+    /// Index of the keyframe before `time` and the blend factor towards the next one.
+    fn locate(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next == self.times.len() {
+            return (next - 1, next - 1, 0.0);
+        }
+        let prev = next - 1;
+        if self.step {
+            return (prev, prev, 0.0);
+        }
+        let span = self.times[next] - self.times[prev];
+        (prev, next, (time - self.times[prev]) / span)
+    }
+
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        let (a, b, t) = self.locate(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => transform.translation = values[a].lerp(values[b], t),
+            Keyframes::Rotation(values) => {
+                // Keyframes may sit on opposite hemispheres; take the short way round.
+                let end = if values[a].dot(values[b]) < 0.0 {
+                    -values[b]
+                } else {
+                    values[b]
+                };
+                transform.rotation = values[a].nlerp(end, t).normalize();
+            }
+            Keyframes::Scale(values) => transform.scale = values[a].lerp(values[b], t),
+            // Not part of the transform, see `Channel::weights`.
+            Keyframes::Weights(_) => {}
+        }
+    }
+
+    /// Morph target weights at `time`, empty unless the channel animates weights.
+    fn weights(&self, time: f32) -> Vec<f32> {
+        let Keyframes::Weights(values) = &self.keyframes else {
+            return Vec::new();
+        };
+        let (a, b, t) = self.locate(time);
+        values[a]
+            .iter()
+            .zip(&values[b])
+            .map(|(a, b)| a + (b - a) * t)
+            .collect()
+    }
+}
+
+struct Clip {
+    channels: Vec<Channel>,
+    duration: f32,
+}
+
+impl Clip {
+    /// `time` looped into the clip.
+    fn local_time(&self, time: f32) -> f32 {
+        if self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            0.0
+        }
+    }
+}
+
+struct Skin {
+    joints: Vec<usize>,
+    inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+/// Skinning inputs for one `easy_gltf` model.
+pub struct ModelSkin {
+    skin: usize,
+    /// Inverse of the node transform `easy_gltf` baked into the vertices.
+    bind_inverse: Matrix4<f32>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+/// Animation data for one glTF scene. Models are indexed in the same order as
+/// [`easy_gltf::Scene::models`].
+pub struct SceneAnimation {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+    skins: Vec<Skin>,
+    models: Vec<Option<ModelSkin>>,
+    /// The node each model belongs to.
+    model_nodes: Vec<usize>,
+    clips: Vec<Clip>,
+}
+
+impl SceneAnimation {
+    pub fn load(path: impl AsRef<Path>, scene_index: usize) -> Result<Self, gltf::Error> {
+        let path = path.as_ref();
+        let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+        let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+        let buffer = |b: gltf::Buffer| Some(&*buffers[b.index()]);
+
+        let nodes = document
+            .nodes()
+            .map(|node| Node {
+                children: node.children().map(|c| c.index()).collect(),
+                rest: node.transform().into(),
+            })
+            .collect();
+
+        let skins = document
+            .skins()
+            .map(|skin| {
+                let joints: Vec<_> = skin.joints().map(|j| j.index()).collect();
+                let inverse_bind_matrices = match skin.reader(buffer).read_inverse_bind_matrices() {
+                    Some(matrices) => matrices.map(Matrix4::from).collect(),
+                    None => vec![Matrix4::identity(); joints.len()],
+                };
+                Skin {
+                    joints,
+                    inverse_bind_matrices,
+                }
+            })
+            .collect();
+
+        let clips = document
+            .animations()
+            .map(|animation| {
+                let channels: Vec<_> = animation
+                    .channels()
+                    .filter_map(|channel| {
+                        let reader = channel.reader(buffer);
+                        let times: Vec<f32> = reader.read_inputs()?.collect();
+                        let interpolation = channel.sampler().interpolation();
+                        let keyframes = match reader.read_outputs()? {
+                            ReadOutputs::Translations(t) => Keyframes::Translation(
+                                keyframe_values(t.map(Vector3::from).collect(), interpolation),
+                            ),
+                            ReadOutputs::Rotations(r) => Keyframes::Rotation(keyframe_values(
+                                r.into_f32()
+                                    .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                                    .collect(),
+                                interpolation,
+                            )),
+                            ReadOutputs::Scales(s) => Keyframes::Scale(keyframe_values(
+                                s.map(Vector3::from).collect(),
+                                interpolation,
+                            )),
+                            ReadOutputs::MorphTargetWeights(w) => {
+                                let weights: Vec<f32> = w.into_f32().collect();
+                                let values_per_keyframe = match interpolation {
+                                    Interpolation::CubicSpline => 3,
+                                    _ => 1,
+                                };
+                                let target_count =
+                                    weights.len() / (times.len() * values_per_keyframe).max(1);
+                                Keyframes::Weights(keyframe_values(
+                                    weights
+                                        .chunks(target_count.max(1))
+                                        .map(<[f32]>::to_vec)
+                                        .collect(),
+                                    interpolation,
+                                ))
+                            }
+                        };
+                        Some(Channel {
+                            node: channel.target().node().index(),
+                            step: interpolation == Interpolation::Step,
+                            times,
+                            keyframes,
+                        })
+                    })
+                    .collect();
+                let duration = channels
+                    .iter()
+                    .filter_map(|c| c.times.last().copied())
+                    .fold(0.0, f32::max);
+                Clip { channels, duration }
+            })
+            .collect();
+
+        let mut roots = Vec::new();
+        let mut models = Vec::new();
+        let mut model_nodes = Vec::new();
+        for node in document
+            .scenes()
+            .nth(scene_index)
+            .iter()
+            .flat_map(|s| s.nodes())
+        {
+            roots.push(node.index());
+            read_models(&node, &buffers, &mut models, &mut model_nodes);
+        }
+
+        Ok(Self {
+            nodes,
+            roots,
+            skins,
+            models,
+            model_nodes,
+            clips,
+        })
+    }
+
+    /// Length of the clip that is played, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.clips.first().map_or(0.0, |clip| clip.duration)
+    }
+
+    pub fn model_skin(&self, model: usize) -> Option<&ModelSkin> {
+        self.models.get(model)?.as_ref()
+    }
+
+    /// Size of the joint palette of `model`, zero if it isn't skinned.
+    pub fn joint_count(&self, model: usize) -> usize {
+        self.model_skin(model)
+            .map_or(0, |model_skin| self.skins[model_skin.skin].joints.len())
+    }
+
+    /// World transform of every node with the first clip sampled at `time`, looping.
+    pub fn node_transforms(&self, time: f32) -> Vec<Matrix4<f32>> {
+        let mut local: Vec<_> = self.nodes.iter().map(|node| node.rest).collect();
+        if let Some(clip) = self.clips.first() {
+            let time = clip.local_time(time);
+            for channel in &clip.channels {
+                channel.apply(time, &mut local[channel.node]);
+            }
+        }
+
+        let mut world = vec![Matrix4::identity(); self.nodes.len()];
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Matrix4::identity()))
+            .collect();
+        while let Some((node, parent)) = stack.pop() {
+            world[node] = parent * local[node].matrix();
+            stack.extend(self.nodes[node].children.iter().map(|&c| (c, world[node])));
+        }
+        world
+    }
+
+    /// Morph target weights of `model` with the first clip sampled at `time`, looping, or `None`
+    /// if the clip doesn't animate them.
+    pub fn morph_weights(&self, model: usize, time: f32) -> Option<Vec<f32>> {
+        let node = *self.model_nodes.get(model)?;
+        let clip = self.clips.first()?;
+        let channel = clip.channels.iter().find(|channel| {
+            channel.node == node && matches!(channel.keyframes, Keyframes::Weights(_))
+        })?;
+        Some(channel.weights(clip.local_time(time)))
+    }
+
+    /// Joint palette for `model`, expressed relative to the baked vertex positions.
+    pub fn joint_matrices(
+        &self,
+        model: usize,
+        node_transforms: &[Matrix4<f32>],
+    ) -> Option<Vec<Matrix4<f32>>> {
+        let model_skin = self.model_skin(model)?;
+        let skin = &self.skins[model_skin.skin];
+        Some(
+            skin.joints
+                .iter()
+                .zip(&skin.inverse_bind_matrices)
+                .map(|(&joint, inverse_bind)| {
+                    node_transforms[joint] * inverse_bind * model_skin.bind_inverse
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Cubic splines store `[in tangent, value, out tangent]` per keyframe; only the values are kept
+/// and interpolated linearly.
+fn keyframe_values<T: Clone>(values: Vec<T>, interpolation: Interpolation) -> Vec<T> {
+    match interpolation {
+        Interpolation::CubicSpline => values.chunks(3).map(|c| c[1].clone()).collect(),
+        _ => values,
+    }
+}
+
+/// Mirrors the traversal order of `easy_gltf` so that models line up with its output.
+fn read_models(
+    node: &gltf::Node,
+    buffers: &[gltf::buffer::Data],
+    models: &mut Vec<Option<ModelSkin>>,
+    model_nodes: &mut Vec<usize>,
+) {
+    for_each_primitive(
+        node,
+        Matrix4::identity(),
+        &mut |node, transform, primitive| {
+            let reader = primitive.reader(|b| Some(&*buffers[b.index()]));
+            let skin = node.skin().and_then(|skin| {
+                Some(ModelSkin {
+                    skin: skin.index(),
+                    bind_inverse: transform.invert()?,
+                    joints: reader.read_joints(0)?.into_u16().collect(),
+                    weights: reader.read_weights(0)?.into_f32().collect(),
+                })
+            });
+            models.push(skin);
+            model_nodes.push(node.index());
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_clamps_and_interpolates() {
+        let channel = Channel {
+            node: 0,
+            step: false,
+            times: vec![1.0, 2.0, 4.0],
+            keyframes: Keyframes::Scale(Vec::new()),
+        };
+        assert_eq!(channel.locate(0.0), (0, 0, 0.0));
+        assert_eq!(channel.locate(3.0), (1, 2, 0.5));
+        assert_eq!(channel.locate(5.0), (2, 2, 0.0));
+
+        let channel = Channel {
+            step: true,
+            ..channel
+        };
+        assert_eq!(channel.locate(3.0), (1, 1, 0.0));
+    }
+
+    #[test]
+    fn weights_blend_per_target() {
+        let channel = Channel {
+            node: 0,
+            step: false,
+            times: vec![0.0, 1.0],
+            keyframes: Keyframes::Weights(vec![vec![0.0, 1.0], vec![1.0, 0.0]]),
+        };
+        assert_eq!(channel.weights(0.25), [0.25, 0.75]);
+    }
+}
@@ -0,0 +1,126 @@
+use cgmath::{Matrix4, Point3, Transform, Vector3};
+
+use crate::MyVertex;
+
+/// An axis-aligned bounding box. Empty boxes have `min > max` on every axis, so that merging
+/// with them is a no-op.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |aabb, p| aabb.union(&Aabb { min: p, max: p }))
+    }
+
+    pub fn from_vertices(vertices: &[MyVertex]) -> Self {
+        Self::from_points(vertices.iter().map(|v| Point3::from(v.position)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        Point3::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// The box around these bounds' corners transformed by `matrix`.
+    pub fn transformed(&self, matrix: Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return Aabb::EMPTY;
+        }
+        Aabb::from_points((0..8).map(|corner| {
+            let pick = |axis: usize, min: f32, max: f32| match corner & (1 << axis) {
+                0 => min,
+                _ => max,
+            };
+            matrix.transform_point(Point3::new(
+                pick(0, self.min.x, self.max.x),
+                pick(1, self.min.y, self.max.y),
+                pick(2, self.min.z, self.max.z),
+            ))
+        }))
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn from_vertices() {
+        let vertex = |position| MyVertex {
+            position,
+            ..Default::default()
+        };
+        let aabb = Aabb::from_vertices(&[vertex([1.0, -2.0, 3.0]), vertex([-1.0, 4.0, 0.0])]);
+        assert_eq!(aabb.min, Point3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Point3::new(1.0, 4.0, 3.0));
+        assert_eq!(aabb.center(), Point3::new(0.0, 1.0, 1.5));
+        assert!(!aabb.is_empty());
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let empty = Aabb::from_vertices(&[]);
+        assert!(empty.is_empty());
+
+        let unit = Aabb::from_points([Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)]);
+        assert_eq!(empty.union(&unit), unit);
+        assert_eq!(unit.union(&empty), unit);
+        assert!(empty.union(&empty).is_empty());
+    }
+
+    #[test]
+    fn transformed_corners() {
+        let unit = Aabb::from_points([Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)]);
+        let moved = unit.transformed(
+            Matrix4::from_translation(Vector3::new(2.0, 0.0, 0.0))
+                * Matrix4::from_angle_z(cgmath::Deg(90.0)),
+        );
+        assert!((moved.min - Point3::new(1.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert!((moved.max - Point3::new(2.0, 1.0, 1.0)).magnitude() < 1e-5);
+        assert!(Aabb::EMPTY.transformed(Matrix4::from_scale(2.0)).is_empty());
+    }
+}
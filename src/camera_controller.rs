@@ -0,0 +1,156 @@
+//! Orbiting the camera around a point with the mouse.
+
+use cgmath::{Angle, Deg, EuclideanSpace, Matrix4, Point3, Rad, Vector3};
+use winit::event::{ElementState, MouseButton};
+
+use crate::{
+    bounds::Aabb,
+    pipeline::sample::{fit_distance, Camera},
+};
+
+/// Yaw and pitch per pixel dragged.
+const ROTATE_SPEED: Rad<f32> = Rad(0.01);
+/// Factor the distance is divided by per line scrolled up.
+const ZOOM_STEP: f32 = 1.1;
+/// Just short of straight up and down, where the view's up vector would flip.
+const MAX_PITCH: Rad<f32> = Rad(1.55);
+const MIN_DISTANCE: f32 = 0.2;
+
+/// Looks at `target` from `distance` away. Dragging with the left button rotates around it and
+/// scrolling moves closer or further.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraController {
+    pub target: Point3<f32>,
+    /// Around the Y axis, zero looking from +Z.
+    pub yaw: Rad<f32>,
+    /// Above the target, within ±[`MAX_PITCH`].
+    pub pitch: Rad<f32>,
+    pub distance: f32,
+    dragging: bool,
+    /// Last position seen in [`Self::cursor_moved`].
+    cursor: Option<[f64; 2]>,
+}
+
+impl Default for CameraController {
+    /// Where the time-based orbit starts: at `(0, 1, 3)` looking at the origin.
+    fn default() -> Self {
+        Self {
+            target: Point3::origin(),
+            yaw: Rad(0.0),
+            pitch: Rad::atan2(1.0, 3.0),
+            distance: 10.0f32.sqrt(),
+            dragging: false,
+            cursor: None,
+        }
+    }
+}
+
+impl CameraController {
+    /// Looks at the center of `bounds` from the default direction, close enough for them to fill
+    /// a view of `fovy` and `aspect`. The default for empty bounds.
+    pub fn fit_to_bounds(bounds: &Aabb, fovy: Deg<f32>, aspect: f32) -> CameraController {
+        if bounds.is_empty() {
+            return CameraController::default();
+        }
+        CameraController {
+            target: bounds.center(),
+            distance: fit_distance(bounds, fovy, aspect),
+            ..CameraController::default()
+        }
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        if button == MouseButton::Left {
+            self.dragging = state.is_pressed();
+        }
+    }
+
+    /// Returns whether the camera rotated.
+    pub fn cursor_moved(&mut self, position: [f64; 2]) -> bool {
+        let last = self.cursor.replace(position);
+        match last {
+            Some(last) if self.dragging => {
+                self.rotate([position[0] - last[0], position[1] - last[1]].map(|d| d as f32));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Scrolling up by positive `lines` moves closer.
+    pub fn scroll(&mut self, lines: f32) {
+        self.distance = (self.distance / ZOOM_STEP.powf(lines)).max(MIN_DISTANCE);
+    }
+
+    /// Dragging right turns the camera around to the right, dragging down lowers it.
+    pub fn rotate(&mut self, delta: [f32; 2]) {
+        self.yaw -= ROTATE_SPEED * delta[0];
+        let pitch = self.pitch + ROTATE_SPEED * delta[1];
+        self.pitch = Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+    }
+
+    /// Moved around the Y axis by `angle`, for orbiting without input.
+    pub fn orbited(&self, angle: Rad<f32>) -> CameraController {
+        CameraController {
+            yaw: self.yaw + angle,
+            ..*self
+        }
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        self.target
+            + Vector3::new(sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch) * self.distance
+    }
+
+    pub fn camera(&self, proj: Matrix4<f32>) -> Camera {
+        let position = self.position();
+        Camera {
+            position,
+            view: Matrix4::look_at_rh(position, self.target, Vector3::unit_y()),
+            proj,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::MetricSpace;
+
+    use super::*;
+
+    #[test]
+    fn starts_where_the_orbit_does() {
+        let controller = CameraController::default();
+        assert!(controller.position().distance(Point3::new(0.0, 1.0, 3.0)) < 1e-5);
+        let quarter = controller.orbited(Rad(std::f32::consts::FRAC_PI_2));
+        assert!(quarter.position().distance(Point3::new(3.0, 1.0, 0.0)) < 1e-5);
+    }
+
+    #[test]
+    fn fits_bounds_from_the_default_direction() {
+        let bounds = Aabb {
+            min: Point3::new(9.0, -1.0, -1.0),
+            max: Point3::new(11.0, 1.0, 1.0),
+        };
+        let controller = CameraController::fit_to_bounds(&bounds, Deg(60.0), 1.0);
+        assert_eq!(controller.target, Point3::new(10.0, 0.0, 0.0));
+        // The bounding sphere's radius is sqrt(3), and sin(30°) is a half.
+        assert!((controller.distance - 2.0 * 3.0f32.sqrt()).abs() < 1e-5);
+        assert_eq!(controller.pitch, CameraController::default().pitch);
+        let empty = CameraController::fit_to_bounds(&Aabb::EMPTY, Deg(60.0), 1.0);
+        assert_eq!(empty, CameraController::default());
+    }
+
+    #[test]
+    fn clamps_pitch_short_of_the_poles() {
+        let mut controller = CameraController::default();
+        controller.mouse_input(MouseButton::Left, ElementState::Pressed);
+        assert!(!controller.cursor_moved([0.0, 0.0]));
+        assert!(controller.cursor_moved([0.0, 1e4]));
+        assert_eq!(controller.pitch, MAX_PITCH);
+        controller.rotate([0.0, -1e4]);
+        assert_eq!(controller.pitch, -MAX_PITCH);
+    }
+}
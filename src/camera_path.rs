@@ -0,0 +1,227 @@
+//! Recording camera flythroughs and playing them back, e.g. for captures.
+
+use std::path::Path;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, VectorSpace};
+use gltf::json::{self, Value};
+use thiserror::Error;
+
+use crate::pipeline::sample::Camera;
+
+#[derive(Debug, Error)]
+pub enum CameraPathError {
+    #[error("failed to access camera path file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse camera path: {0}")]
+    Json(#[from] json::Error),
+    #[error("invalid camera path: {0}")]
+    Invalid(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    /// In seconds from the start of the path.
+    pub time: f32,
+    pub position: Point3<f32>,
+    /// Rotation of the view matrix, i.e. from world to view space.
+    pub rotation: Quaternion<f32>,
+}
+
+/// Camera states at points in time, interpolated with Catmull-Rom splines in between.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    /// Adds a keyframe for `camera`, which must not come before the last one.
+    pub fn push(&mut self, time: f32, camera: &Camera) {
+        assert!(
+            self.keyframes.last().is_none_or(|k| k.time <= time),
+            "camera keyframes must be in order"
+        );
+        let view = camera.view;
+        let rotation = Matrix3::from_cols(view.x.truncate(), view.y.truncate(), view.z.truncate());
+        self.keyframes.push(CameraKeyframe {
+            time,
+            position: camera.position,
+            rotation: Quaternion::from(rotation).normalize(),
+        });
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Position and view rotation at `time`, held at the first and last keyframes outside of
+    /// them. `None` without any keyframes.
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Quaternion<f32>)> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        if last == 0 {
+            let k = &self.keyframes[0];
+            return Some((k.position, k.rotation));
+        }
+        // The segment ends at the first keyframe after `time`.
+        let end = self
+            .keyframes
+            .partition_point(|k| k.time <= time)
+            .clamp(1, last);
+        let [k0, k1, k2, k3] =
+            [end.saturating_sub(2), end - 1, end, (end + 1).min(last)].map(|i| &self.keyframes[i]);
+        let span = k2.time - k1.time;
+        let u = match span > 0.0 {
+            true => ((time - k1.time) / span).clamp(0.0, 1.0),
+            false => 1.0,
+        };
+
+        let position = catmull_rom([k0, k1, k2, k3].map(|k| k.position.to_vec()), u);
+        // q and -q are the same rotation; take the ones closest to k1's so it doesn't spin around.
+        let rotations = [k0, k1, k2, k3].map(|k| match k.rotation.dot(k1.rotation) < 0.0 {
+            true => -k.rotation,
+            false => k.rotation,
+        });
+        let rotation = catmull_rom(rotations, u).normalize();
+        Some((Point3::from_vec(position), rotation))
+    }
+
+    /// The camera at `time`, see [`Self::sample`].
+    pub fn camera(&self, time: f32, proj: Matrix4<f32>) -> Option<Camera> {
+        let (position, rotation) = self.sample(time)?;
+        Some(Camera {
+            position,
+            view: Matrix4::from(rotation) * Matrix4::from_translation(-position.to_vec()),
+            proj,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<CameraPath, CameraPathError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CameraPathError> {
+        Ok(std::fs::write(path, self.to_json())?)
+    }
+
+    /// `{"keyframes": [{"time": t, "position": [x, y, z], "rotation": [x, y, z, w]}, ..]}`.
+    fn to_json(&self) -> String {
+        let keyframes: Vec<Value> = self
+            .keyframes
+            .iter()
+            .map(|k| {
+                let position: [f32; 3] = k.position.into();
+                let rotation: [f32; 4] = k.rotation.into();
+                Value::from_iter([
+                    ("time", Value::from(k.time)),
+                    ("position", Value::from(position.to_vec())),
+                    ("rotation", Value::from(rotation.to_vec())),
+                ])
+            })
+            .collect();
+        json::serialize::to_string_pretty(&Value::from_iter([("keyframes", keyframes)])).unwrap()
+    }
+
+    fn from_json(source: &str) -> Result<CameraPath, CameraPathError> {
+        let root: Value = json::deserialize::from_str(source)?;
+        let keyframes = root["keyframes"]
+            .as_array()
+            .ok_or(CameraPathError::Invalid("missing keyframes"))?;
+        let mut path = CameraPath::default();
+        for keyframe in keyframes {
+            let time = keyframe["time"]
+                .as_f64()
+                .ok_or(CameraPathError::Invalid("keyframe without a time"))?
+                as f32;
+            if path.keyframes.last().is_some_and(|k| k.time > time) {
+                return Err(CameraPathError::Invalid("keyframes out of order"));
+            }
+            let position: [f32; 3] = floats(&keyframe["position"])?;
+            let rotation: [f32; 4] = floats(&keyframe["rotation"])?;
+            path.keyframes.push(CameraKeyframe {
+                time,
+                position: position.into(),
+                rotation: Quaternion::from(rotation).normalize(),
+            });
+        }
+        Ok(path)
+    }
+}
+
+fn floats<const N: usize>(value: &Value) -> Result<[f32; N], CameraPathError> {
+    let invalid = || CameraPathError::Invalid("keyframe without a position or rotation");
+    let values = value
+        .as_array()
+        .filter(|v| v.len() == N)
+        .ok_or_else(invalid)?;
+    let mut floats = [0.0; N];
+    for (float, value) in floats.iter_mut().zip(values) {
+        *float = value.as_f64().ok_or_else(invalid)? as f32;
+    }
+    Ok(floats)
+}
+
+/// Uniform Catmull-Rom spline through `p[1]` at `u = 0` and `p[2]` at `u = 1`.
+fn catmull_rom<V: VectorSpace<Scalar = f32>>(p: [V; 4], u: f32) -> V {
+    let [u2, u3] = [u * u, u * u * u];
+    (p[1] * 2.0
+        + (p[2] - p[0]) * u
+        + (p[0] * 2.0 - p[1] * 5.0 + p[2] * 4.0 - p[3]) * u2
+        + (p[1] * 3.0 - p[0] - p[2] * 3.0 + p[3]) * u3)
+        * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Rotation3, SquareMatrix};
+
+    fn camera(position: [f32; 3], yaw: f32) -> Camera {
+        let position = Point3::from(position);
+        let rotation = Quaternion::from_angle_y(Deg(yaw));
+        Camera {
+            position,
+            view: Matrix4::from(rotation) * Matrix4::from_translation(-position.to_vec()),
+            proj: Matrix4::identity(),
+        }
+    }
+
+    #[test]
+    fn passes_through_keyframes() {
+        let mut path = CameraPath::default();
+        path.push(0.0, &camera([0.0, 0.0, 0.0], 0.0));
+        path.push(1.0, &camera([1.0, 0.0, 0.0], 90.0));
+        path.push(3.0, &camera([2.0, 1.0, 0.0], 90.0));
+
+        for (time, k) in [(0.0, 0), (1.0, 1), (3.0, 2), (5.0, 2)] {
+            let (position, rotation) = path.sample(time).unwrap();
+            let expected = &path.keyframes()[k];
+            assert!((position - expected.position).magnitude() < 1e-5);
+            assert!(rotation.dot(expected.rotation).abs() > 1.0 - 1e-5);
+        }
+        let (position, _) = path.sample(0.5).unwrap();
+        assert!(position.x > 0.0 && position.x < 1.0);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut path = CameraPath::default();
+        path.push(0.0, &camera([0.0, 1.0, 2.0], 30.0));
+        path.push(2.5, &camera([3.0, 4.0, 5.0], -45.0));
+        let loaded = CameraPath::from_json(&path.to_json()).unwrap();
+        assert_eq!(loaded.keyframes().len(), 2);
+        for (a, b) in loaded.keyframes().iter().zip(path.keyframes()) {
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.position, b.position);
+            assert!(a.rotation.dot(b.rotation) > 1.0 - 1e-6);
+        }
+        assert!(CameraPath::from_json(r#"{"keyframes": [{"time": 0}]}"#).is_err());
+    }
+}
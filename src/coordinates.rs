@@ -0,0 +1,99 @@
+//! Converting assets authored in other coordinate systems to glTF's, which the renderer uses.
+
+use cgmath::{Matrix4, SquareMatrix};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    /// Common in CAD and Blender exports.
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Handedness {
+    #[default]
+    Right,
+    Left,
+}
+
+/// The coordinate system an asset was authored in. The default is glTF's: Y up, right-handed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoordinateSystem {
+    pub up: UpAxis,
+    pub handedness: Handedness,
+}
+
+impl CoordinateSystem {
+    /// Maps positions and directions from this coordinate system to glTF's.
+    pub fn to_gltf(&self) -> Matrix4<f32> {
+        // Left-handed systems are mirrored along the axis that is neither up nor right.
+        let mirror = match (self.handedness, self.up) {
+            (Handedness::Right, _) => Matrix4::identity(),
+            (Handedness::Left, UpAxis::Y) => Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0),
+            (Handedness::Left, UpAxis::Z) => Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0),
+        };
+        #[rustfmt::skip]
+        let up = match self.up {
+            UpAxis::Y => Matrix4::identity(),
+            // (x, y, z) to (x, z, -y).
+            UpAxis::Z => Matrix4::new(
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 0.0, -1.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ),
+        };
+        up * mirror
+    }
+
+    /// Whether converting flips the triangles' winding.
+    pub fn mirrors(&self) -> bool {
+        self.handedness == Handedness::Left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn converts_up_axes_and_handedness() {
+        let convert = |up, handedness, [x, y, z]: [f32; 3]| -> [f32; 3] {
+            let system = CoordinateSystem { up, handedness };
+            (system.to_gltf() * Vector4::new(x, y, z, 1.0))
+                .truncate()
+                .into()
+        };
+        assert_eq!(
+            convert(UpAxis::Y, Handedness::Right, [1.0, 2.0, 3.0]),
+            [1.0, 2.0, 3.0]
+        );
+        // Up stays up, and the right-handed Z-up forward (+Y) points away from the viewer (-Z).
+        assert_eq!(
+            convert(UpAxis::Z, Handedness::Right, [0.0, 0.0, 1.0]),
+            [0.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            convert(UpAxis::Z, Handedness::Right, [0.0, 1.0, 0.0]),
+            [0.0, 0.0, -1.0]
+        );
+        assert_eq!(
+            convert(UpAxis::Y, Handedness::Left, [0.0, 0.0, 1.0]),
+            [0.0, 0.0, -1.0]
+        );
+        assert_eq!(
+            convert(UpAxis::Z, Handedness::Left, [1.0, 1.0, 1.0]),
+            [1.0, 1.0, 1.0]
+        );
+
+        for up in [UpAxis::Y, UpAxis::Z] {
+            for handedness in [Handedness::Right, Handedness::Left] {
+                let system = CoordinateSystem { up, handedness };
+                assert_eq!(system.to_gltf().determinant() < 0.0, system.mirrors());
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Flying through the scene first-person, with the keyboard and mouse.
+
+use cgmath::{Angle, InnerSpace, Matrix4, Point3, Rad, Vector3, Zero};
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::{camera_controller::CameraController, pipeline::sample::Camera};
+
+/// The default for [`FlyCamera::move_speed`].
+pub const DEFAULT_MOVE_SPEED: f32 = 2.0;
+/// The default for [`FlyCamera::look_speed`].
+pub const DEFAULT_LOOK_SPEED: Rad<f32> = Rad(0.005);
+/// Factor the movement speed is multiplied by per line scrolled up.
+const SPEED_STEP: f32 = 1.25;
+/// Longest step [`FlyCamera::update`] integrates, so that a frame after a pause doesn't jump.
+const MAX_STEP: f32 = 0.1;
+/// Just short of straight up and down, where the view's up vector would flip.
+const MAX_PITCH: Rad<f32> = Rad(1.55);
+
+/// Moves forward, back and sideways with W, S, A and D, and down and up with Ctrl and Space.
+/// Dragging with the left button looks around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlyCamera {
+    pub position: Point3<f32>,
+    /// Around the Y axis, zero looking towards -Z.
+    pub yaw: Rad<f32>,
+    /// Upwards, within ±[`MAX_PITCH`].
+    pub pitch: Rad<f32>,
+    /// In units per second.
+    pub move_speed: f32,
+    /// Yaw and pitch per pixel dragged.
+    pub look_speed: Rad<f32>,
+    /// Held movement keys: forward, back, left, right, down and up.
+    held: [bool; 6],
+    dragging: bool,
+    /// Last position seen in [`Self::cursor_moved`].
+    cursor: Option<[f64; 2]>,
+}
+
+impl FlyCamera {
+    /// Seeing what `controller` sees.
+    pub fn from_controller(
+        controller: &CameraController,
+        move_speed: f32,
+        look_speed: Rad<f32>,
+    ) -> FlyCamera {
+        FlyCamera {
+            position: controller.position(),
+            yaw: controller.yaw,
+            pitch: -controller.pitch,
+            move_speed,
+            look_speed,
+            held: [false; 6],
+            dragging: false,
+            cursor: None,
+        }
+    }
+
+    /// Tracks the movement keys. Returns whether `event` was one of them.
+    pub fn keyboard_input(&mut self, event: &KeyEvent) -> bool {
+        let PhysicalKey::Code(key) = event.physical_key else {
+            return false;
+        };
+        let index = match key {
+            KeyCode::KeyW => 0,
+            KeyCode::KeyS => 1,
+            KeyCode::KeyA => 2,
+            KeyCode::KeyD => 3,
+            KeyCode::ControlLeft | KeyCode::ControlRight => 4,
+            KeyCode::Space => 5,
+            _ => return false,
+        };
+        self.held[index] = event.state.is_pressed();
+        true
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        if button == MouseButton::Left {
+            self.dragging = state.is_pressed();
+        }
+    }
+
+    /// Returns whether the camera turned.
+    pub fn cursor_moved(&mut self, position: [f64; 2]) -> bool {
+        let last = self.cursor.replace(position);
+        match last {
+            Some(last) if self.dragging => {
+                self.rotate([position[0] - last[0], position[1] - last[1]].map(|d| d as f32));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Scrolling up by positive `lines` speeds up the movement.
+    pub fn scroll(&mut self, lines: f32) {
+        self.move_speed *= SPEED_STEP.powf(lines);
+    }
+
+    /// Dragging right turns right, dragging down looks down.
+    pub fn rotate(&mut self, delta: [f32; 2]) {
+        self.yaw -= self.look_speed * delta[0];
+        let pitch = self.pitch - self.look_speed * delta[1];
+        self.pitch = Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+    }
+
+    /// Whether any movement key is held, so that frames keep coming.
+    pub fn is_moving(&self) -> bool {
+        self.held.contains(&true)
+    }
+
+    /// Moves by the held keys for `dt` seconds, at most [`MAX_STEP`]. Forward follows the view,
+    /// up and down the Y axis.
+    pub fn update(&mut self, dt: f32) {
+        let axis = |negative: usize, positive: usize| {
+            self.held[positive] as i32 as f32 - self.held[negative] as i32 as f32
+        };
+        let forward = self.forward();
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let direction = forward * axis(1, 0) + right * axis(2, 3) + Vector3::unit_y() * axis(4, 5);
+        if direction.is_zero() {
+            return;
+        }
+        self.position += direction.normalize() * self.move_speed * dt.min(MAX_STEP);
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vector3::new(-sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch)
+    }
+
+    pub fn camera(&self, proj: Matrix4<f32>) -> Camera {
+        Camera {
+            position: self.position,
+            view: Matrix4::look_to_rh(self.position, self.forward(), Vector3::unit_y()),
+            proj,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{MetricSpace, SquareMatrix};
+
+    use super::*;
+
+    #[test]
+    fn starts_with_the_orbit_view() {
+        let controller = CameraController::default();
+        let fly = FlyCamera::from_controller(&controller, DEFAULT_MOVE_SPEED, DEFAULT_LOOK_SPEED);
+        let [orbit, fly] = [
+            controller.camera(Matrix4::identity()),
+            fly.camera(Matrix4::identity()),
+        ];
+        assert!(orbit.position.distance(fly.position) < 1e-5);
+        for (a, b) in [
+            (orbit.view.x, fly.view.x),
+            (orbit.view.y, fly.view.y),
+            (orbit.view.z, fly.view.z),
+        ] {
+            assert!((a - b).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn moves_along_the_view_for_at_most_a_step() {
+        let mut fly =
+            FlyCamera::from_controller(&CameraController::default(), 1.0, DEFAULT_LOOK_SPEED);
+        fly.pitch = Rad(0.0);
+        fly.yaw = Rad(0.0);
+        let start = fly.position;
+        fly.held[0] = true;
+        assert!(fly.is_moving());
+        fly.update(10.0);
+        assert!((fly.position - (start - Vector3::unit_z() * MAX_STEP)).magnitude() < 1e-5);
+    }
+}
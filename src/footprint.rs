@@ -0,0 +1,90 @@
+//! Estimating how much memory a scene's geometry takes once uploaded, before uploading it.
+
+use std::mem::size_of;
+
+use easy_gltf::{model::Mode, Scene};
+use vulkano::{device::physical::PhysicalDevice, memory::MemoryHeapFlags};
+
+use crate::MyVertex;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SceneFootprint {
+    pub triangles: u64,
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+}
+
+impl SceneFootprint {
+    /// Sizes of the vertex and index buffers `scene`'s models are uploaded into, including the
+    /// vertices unwelded for flat normals. Textures and morph targets aren't counted.
+    pub fn of(scene: &Scene) -> SceneFootprint {
+        let mut footprint = SceneFootprint::default();
+        for model in &scene.models {
+            let vertex_count = model.vertices().len() as u64;
+            let index_count = model.indices().map(|i| i.len() as u64);
+            let element_count = index_count.unwrap_or(vertex_count);
+            footprint.triangles += triangle_count(model.mode(), element_count);
+
+            // Strips and fans are uploaded as lists.
+            let (element_count, index_count) = match model.mode() {
+                Mode::TriangleStrip | Mode::TriangleFan => {
+                    let count = triangle_count(model.mode(), element_count) * 3;
+                    (count, Some(count))
+                }
+                _ => (element_count, index_count),
+            };
+            let triangles = matches!(
+                model.mode(),
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            );
+            let (vertex_count, index_count) = if !model.has_normals() && triangles {
+                (element_count, 0)
+            } else {
+                (vertex_count, index_count.unwrap_or(0))
+            };
+            footprint.vertex_bytes += vertex_count * size_of::<MyVertex>() as u64;
+            footprint.index_bytes += index_count * size_of::<u32>() as u64;
+        }
+        footprint
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.vertex_bytes + self.index_bytes
+    }
+
+    /// Whether the geometry fits in the largest device-local heap of `physical_device`. Other
+    /// allocations share the heap, so this is an upper bound.
+    pub fn fits(&self, physical_device: &PhysicalDevice) -> bool {
+        let heap_size = physical_device
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(0);
+        self.total_bytes() <= heap_size
+    }
+}
+
+/// Triangles drawn from `element_count` vertices or indices in `mode`.
+fn triangle_count(mode: Mode, element_count: u64) -> u64 {
+    match mode {
+        Mode::Triangles => element_count / 3,
+        Mode::TriangleStrip | Mode::TriangleFan => element_count.saturating_sub(2),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_triangles_per_mode() {
+        assert_eq!(triangle_count(Mode::Triangles, 9), 3);
+        assert_eq!(triangle_count(Mode::TriangleStrip, 5), 3);
+        assert_eq!(triangle_count(Mode::TriangleFan, 1), 0);
+        assert_eq!(triangle_count(Mode::Lines, 6), 0);
+    }
+}
@@ -0,0 +1,82 @@
+//! Timing of rendered frames.
+
+use std::time::Instant;
+
+/// Weight of the newest frame in [`FrameClock::fps`], the rest being the previous average.
+const FPS_SMOOTHING: f32 = 0.1;
+
+/// Advanced once per frame by [`crate::App::run`], see [`crate::App::frame_clock`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameClock {
+    last: Option<Instant>,
+    dt: f32,
+    fps: f32,
+    gpu_time: Option<f32>,
+}
+
+impl FrameClock {
+    /// Advances by the time since the last tick, zero for the first one. Returns that delta.
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        let dt = self.last.map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last = Some(now);
+        self.advance(dt);
+        dt
+    }
+
+    /// Advances by a fixed `dt`, for frames that aren't rendered in real time.
+    pub fn advance(&mut self, dt: f32) {
+        self.dt = dt;
+        if dt <= 0.0 {
+            return;
+        }
+        let fps = 1.0 / dt;
+        self.fps = match self.fps {
+            0.0 => fps,
+            smoothed => smoothed + (fps - smoothed) * FPS_SMOOTHING,
+        };
+    }
+
+    /// Seconds between the last two frames. Without continuous rendering, this includes the
+    /// time spent waiting for input.
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    /// Frames per second, averaged exponentially over recent frames. Zero before two frames.
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// Milliseconds the GPU spent on the scene passes of the last frame that was measured, from
+    /// the shadow or G-buffer pass to the end of the main and transmission passes, without the
+    /// post passes. `None` if the graphics queue can't write timestamps.
+    pub fn gpu_time(&self) -> Option<f32> {
+        self.gpu_time
+    }
+
+    pub(crate) fn set_gpu_time(&mut self, gpu_time: f32) {
+        self.gpu_time = Some(gpu_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooths_fps_towards_the_frame_rate() {
+        let mut clock = FrameClock::default();
+        clock.advance(0.0);
+        assert_eq!(clock.fps(), 0.0);
+        clock.advance(1.0 / 60.0);
+        assert!((clock.fps() - 60.0).abs() < 1e-3);
+        clock.advance(1.0 / 30.0);
+        assert_eq!(clock.dt(), 1.0 / 30.0);
+        assert!((clock.fps() - 57.0).abs() < 1e-3);
+        for _ in 0..200 {
+            clock.advance(1.0 / 30.0);
+        }
+        assert!((clock.fps() - 30.0).abs() < 1e-3);
+    }
+}
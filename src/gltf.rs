@@ -1,5 +1,455 @@
+//! Reading what `easy_gltf` doesn't expose, with the `gltf` crate.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector3};
+
+use crate::{
+    pipeline::sample::{Clearcoat, Transmission},
+    COLLISION_LAYER, DEFAULT_LAYER, LOD_LAYER,
+};
+
+/// Visits `node` and its descendants' mesh primitives in the order `easy_gltf` turns them into
+/// [`easy_gltf::Scene::models`], along with the world transform of the owning node.
+pub(crate) fn for_each_primitive(
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    f: &mut impl FnMut(&gltf::Node, Matrix4<f32>, gltf::Primitive),
+) {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    for child in node.children() {
+        for_each_primitive(&child, transform, f);
+    }
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            f(node, transform, primitive);
+        }
+    }
+}
+
+/// Per model data `easy_gltf` drops, indexed like [`easy_gltf::Scene::models`].
+#[derive(Debug, Default)]
+pub(crate) struct ModelExtras {
+    /// `None` for models with a single UV set.
+    pub tex_coords_1: Option<Vec<[f32; 2]>>,
+    /// The baked node transform has a negative determinant, flipping the triangles' winding.
+    pub mirrored: bool,
+    /// `COLOR_0` times the material's base color factor. Both are linear, so neither is decoded.
+    pub colors: Option<Vec<[f32; 4]>>,
+    /// The base color of models without `COLOR_0`.
+    pub base_color_factor: [f32; 4],
+    /// The material's back faces are drawn and lit too.
+    pub double_sided: bool,
+    /// The material's name mentions decals, which get [`DepthBias::DECAL`].
+    ///
+    /// [`DepthBias::DECAL`]: crate::pipeline::DepthBias::DECAL
+    pub decal: bool,
+    /// Position deltas of each morph target, in the same space as the vertices. Empty for
+    /// targets that don't move positions.
+    pub morph_targets: Vec<Vec<[f32; 3]>>,
+    /// The node's weights, else the mesh's, else zero.
+    pub morph_weights: Vec<f32>,
+    /// The largest value of the index type, which starts a new strip or fan. `None` for models
+    /// without indices.
+    pub restart_index: Option<u32>,
+    /// Derived from the node's name, see [`layer_from_name`].
+    pub layer: u32,
+    /// The node's name without its `_LOD<n>` suffix, and `n`.
+    pub lod: Option<(String, u32)>,
+    /// The factors of the material's `KHR_materials_clearcoat`. Its textures aren't read, like
+    /// the base color's.
+    pub clearcoat: Clearcoat,
+    /// The material's `KHR_materials_transmission` factor and `KHR_materials_ior`. The
+    /// transmission texture isn't read.
+    pub transmission: Transmission,
+    /// The KTX2 file the base color texture's `KHR_texture_basisu` points at, preferred over
+    /// the fallback image `easy_gltf` decodes. Shared by models with the same texture.
+    pub base_color_ktx2: Option<Arc<[u8]>>,
+}
+
+fn transmission(material: &gltf::Material) -> Transmission {
+    Transmission {
+        factor: material
+            .transmission()
+            .map_or(0.0, |transmission| transmission.transmission_factor()),
+        ior: material.ior().unwrap_or(Transmission::default().ior),
+    }
+}
+
+/// Reads the factors of `material`'s `KHR_materials_clearcoat`, which `gltf` only exposes as
+/// JSON. Both default to zero.
+fn clearcoat(material: &gltf::Material) -> Clearcoat {
+    let Some(extension) = material.extension_value("KHR_materials_clearcoat") else {
+        return Clearcoat::default();
+    };
+    let factor = |name| extension[name].as_f64().unwrap_or(0.0) as f32;
+    Clearcoat {
+        factor: factor("clearcoatFactor"),
+        roughness: factor("clearcoatRoughnessFactor"),
+    }
+}
+
+/// The image `texture`'s `KHR_texture_basisu` points at. Files that require the extension have
+/// no fallback image, which `gltf` doesn't parse, so only optional uses are found.
+fn basisu_source<'a>(
+    document: &'a gltf::Document,
+    texture: &gltf::Texture,
+) -> Option<gltf::Image<'a>> {
+    let source = texture.extension_value("KHR_texture_basisu")?["source"].as_u64()?;
+    document.images().nth(source as usize)
+}
+
+/// Reads `image` from its buffer view, or from a file relative to `base`. Data URIs aren't
+/// decoded.
+fn image_bytes(
+    image: &gltf::Image,
+    buffers: &[gltf::buffer::Data],
+    base: Option<&Path>,
+) -> Option<Vec<u8>> {
+    match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = buffers.get(view.buffer().index())?;
+            let bytes = buffer.get(view.offset()..view.offset() + view.length());
+            bytes.map(<[u8]>::to_vec)
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            if uri.starts_with("data:") {
+                log::warn!("image {} is a data URI, which isn't read", image.index());
+                return None;
+            }
+            let path = base?.join(uri);
+            match std::fs::read(&path) {
+                Ok(bytes) => Some(bytes),
+                Err(err) => {
+                    log::warn!("failed to read {}: {err}", path.display());
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Splits `<name>_LOD<n>` into `<name>` and `n`.
+fn lod_from_name(name: &str) -> Option<(&str, u32)> {
+    // ASCII case changes keep byte offsets the same.
+    let start = name.to_ascii_uppercase().rfind("_LOD")?;
+    let level = name[start + "_LOD".len()..].parse().ok()?;
+    Some((&name[..start], level))
+}
+
+/// [`COLLISION_LAYER`] for the `UCX_` and `COL_` prefixes collision meshes are exported with,
+/// [`LOD_LAYER`] for the `_LOD1` suffix and up, else [`DEFAULT_LAYER`].
+fn layer_from_name(name: &str) -> u32 {
+    let upper = name.to_ascii_uppercase();
+    if ["UCX_", "COL_"]
+        .iter()
+        .any(|prefix| upper.starts_with(prefix))
+    {
+        COLLISION_LAYER
+    } else if lod_from_name(name).is_some_and(|(_, level)| level > 0) {
+        LOD_LAYER
+    } else {
+        DEFAULT_LAYER
+    }
+}
+
+pub(crate) fn load_model_extras(
+    path: &Path,
+    scene_index: usize,
+) -> Result<Vec<ModelExtras>, gltf::Error> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+    Ok(read_model_extras(
+        &document,
+        &buffers,
+        path.parent(),
+        scene_index,
+    ))
+}
+
+/// `base` is the directory images are relative to.
+fn read_model_extras(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    base: Option<&Path>,
+    scene_index: usize,
+) -> Vec<ModelExtras> {
+    let mut models = Vec::new();
+    // By image index, so that each file is read once.
+    let mut ktx2_images: HashMap<usize, Option<Arc<[u8]>>> = HashMap::new();
+    for node in document
+        .scenes()
+        .nth(scene_index)
+        .iter()
+        .flat_map(|s| s.nodes())
+    {
+        for_each_primitive(
+            &node,
+            Matrix4::identity(),
+            &mut |node, transform, primitive| {
+                let reader = primitive.reader(|b| Some(&*buffers[b.index()]));
+                // Deltas are directions, so only the linear part of the baked transform applies.
+                let linear = Matrix3::from_cols(
+                    transform.x.truncate(),
+                    transform.y.truncate(),
+                    transform.z.truncate(),
+                );
+                let morph_targets: Vec<Vec<[f32; 3]>> = reader
+                    .read_morph_targets()
+                    .map(|(positions, _, _)| {
+                        positions
+                            .into_iter()
+                            .flatten()
+                            .map(|delta| (linear * Vector3::from(delta)).into())
+                            .collect()
+                    })
+                    .collect();
+                let morph_weights = node
+                    .weights()
+                    .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                    .map(<[f32]>::to_vec)
+                    .unwrap_or_else(|| vec![0.0; morph_targets.len()]);
+                let factor = primitive
+                    .material()
+                    .pbr_metallic_roughness()
+                    .base_color_factor();
+                let base_color_ktx2 = primitive
+                    .material()
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .and_then(|info| basisu_source(document, &info.texture()))
+                    .and_then(|image| {
+                        ktx2_images
+                            .entry(image.index())
+                            .or_insert_with(|| image_bytes(&image, buffers, base).map(Arc::from))
+                            .clone()
+                    });
+                models.push(ModelExtras {
+                    tex_coords_1: reader.read_tex_coords(1).map(|t| t.into_f32().collect()),
+                    mirrored: transform.determinant() < 0.0,
+                    colors: reader.read_colors(0).map(|colors| {
+                        colors
+                            .into_rgba_f32()
+                            .map(|color| std::array::from_fn(|i| color[i] * factor[i]))
+                            .collect()
+                    }),
+                    base_color_factor: factor,
+                    double_sided: primitive.material().double_sided(),
+                    decal: primitive
+                        .material()
+                        .name()
+                        .is_some_and(|name| name.to_lowercase().contains("decal")),
+                    morph_targets,
+                    morph_weights,
+                    restart_index: primitive
+                        .indices()
+                        .map(|indices| match indices.data_type() {
+                            gltf::accessor::DataType::U8 => u8::MAX as u32,
+                            gltf::accessor::DataType::U16 => u16::MAX as u32,
+                            _ => u32::MAX,
+                        }),
+                    layer: node.name().map_or(DEFAULT_LAYER, layer_from_name),
+                    lod: node
+                        .name()
+                        .and_then(lod_from_name)
+                        .map(|(name, level)| (name.to_owned(), level)),
+                    clearcoat: clearcoat(&primitive.material()),
+                    transmission: transmission(&primitive.material()),
+                    base_color_ktx2,
+                });
+            },
+        );
+    }
+    models
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_mirrored_nodes() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0, 1]}],
+            "nodes": [
+                {"mesh": 0, "scale": [-1, 1, 1]},
+                {"mesh": 0, "children": [2], "scale": [1, -1, 1]},
+                {"mesh": 0, "scale": [1, 1, -1]}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "accessors": [{
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [0, 0, 0]
+            }],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "buffers": [{"byteLength": 36}]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let mirrored: Vec<_> = read_model_extras(&document, &[], None, 0)
+            .iter()
+            .map(|m| m.mirrored)
+            .collect();
+        // Children come before their parent; two flips cancel out.
+        assert_eq!(mirrored, [true, false, true]);
+    }
+
+    #[test]
+    fn derives_layers_from_node_names() {
+        assert_eq!(layer_from_name("UCX_Crate_01"), COLLISION_LAYER);
+        assert_eq!(layer_from_name("col_wall"), COLLISION_LAYER);
+        assert_eq!(layer_from_name("Tree_LOD2"), LOD_LAYER);
+        assert_eq!(layer_from_name("Tree_LOD0"), DEFAULT_LAYER);
+        assert_eq!(layer_from_name("Column"), DEFAULT_LAYER);
+        assert_eq!(lod_from_name("Tree_lod3"), Some(("Tree", 3)));
+        assert_eq!(lod_from_name("Tree_LODs"), None);
+    }
+
+    #[test]
+    fn vertex_colors_stay_linear() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{
+                "attributes": {"POSITION": 0, "COLOR_0": 1}, "material": 0
+            }]}],
+            "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [0.5, 0.5, 0.5, 1]}}],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [0, 0, 0]
+                },
+                {
+                    "bufferView": 0, "byteOffset": 12, "componentType": 5121, "normalized": true,
+                    "count": 1, "type": "VEC4"
+                }
+            ],
+            "bufferViews": [{"buffer": 0, "byteLength": 16}],
+            "buffers": [{"byteLength": 16}]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let mut data = vec![0; 12];
+        data.extend([255, 128, 0, 255]);
+        let buffers = [gltf::buffer::Data(data)];
+        let extras = read_model_extras(&document, &buffers, None, 0);
+        let color = extras[0].colors.as_ref().unwrap()[0];
+        let expected = [0.5, 128.0 / 255.0 * 0.5, 0.0, 1.0];
+        assert!(color
+            .iter()
+            .zip(expected)
+            .all(|(c, e)| (c - e).abs() < 1e-6));
+    }
+
+    #[test]
+    fn reads_morph_targets_in_node_space() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0, "scale": [2, 2, 2]}],
+            "meshes": [{
+                "primitives": [{"attributes": {"POSITION": 0}, "targets": [{"POSITION": 1}]}],
+                "weights": [0.25]
+            }],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [0, 0, 0]
+                },
+                {
+                    "bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 1,
+                    "type": "VEC3", "min": [1, 0, 0], "max": [1, 0, 0]
+                }
+            ],
+            "bufferViews": [{"buffer": 0, "byteLength": 24}],
+            "buffers": [{"byteLength": 24}]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let data = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let buffers = [gltf::buffer::Data(data)];
+        let extras = read_model_extras(&document, &buffers, None, 0);
+        assert_eq!(extras[0].morph_targets, [[[2.0, 0.0, 0.0]]]);
+        assert_eq!(extras[0].morph_weights, [0.25]);
+    }
+
+    #[test]
+    fn reads_clearcoat_factors() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_materials_clearcoat"],
+            "materials": [
+                {"extensions": {"KHR_materials_clearcoat": {
+                    "clearcoatFactor": 1, "clearcoatRoughnessFactor": 0.25
+                }}},
+                {"extensions": {"KHR_materials_clearcoat": {"clearcoatRoughnessFactor": 0.5}}},
+                {}
+            ]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let coats: Vec<_> = document.materials().map(|m| clearcoat(&m)).collect();
+        let coat = |factor, roughness| Clearcoat { factor, roughness };
+        assert_eq!(coats, [coat(1.0, 0.25), coat(0.0, 0.5), coat(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn reads_transmission_with_the_default_ior() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_materials_transmission", "KHR_materials_ior"],
+            "materials": [
+                {"extensions": {
+                    "KHR_materials_transmission": {"transmissionFactor": 0.75},
+                    "KHR_materials_ior": {"ior": 1.33}
+                }},
+                {"extensions": {"KHR_materials_transmission": {"transmissionFactor": 1}}},
+                {}
+            ]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let transmissions: Vec<_> = document.materials().map(|m| transmission(&m)).collect();
+        let transmission = |factor, ior| Transmission { factor, ior };
+        assert_eq!(
+            transmissions,
+            [
+                transmission(0.75, 1.33),
+                transmission(1.0, 1.5),
+                transmission(0.0, 1.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_basisu_sources_in_buffer_views() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_texture_basisu"],
+            "buffers": [{"byteLength": 4}],
+            "bufferViews": [{"buffer": 0, "byteOffset": 1, "byteLength": 2}],
+            "images": [
+                {"bufferView": 0, "mimeType": "image/ktx2"},
+                {"uri": "fallback.png"}
+            ],
+            "textures": [
+                {"source": 1, "extensions": {"KHR_texture_basisu": {"source": 0}}},
+                {"source": 1}
+            ]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let buffers = [gltf::buffer::Data(vec![1, 2, 3, 4])];
+        let sources: Vec<_> = document
+            .textures()
+            .map(|texture| {
+                basisu_source(&document, &texture)
+                    .and_then(|image| image_bytes(&image, &buffers, None))
+            })
+            .collect();
+        assert_eq!(sources, [Some(vec![2, 3]), None]);
+    }
 
     #[test]
     fn it_works() {
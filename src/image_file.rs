@@ -0,0 +1,48 @@
+//! Writing rendered images out for other tools.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use png::{BitDepth, ColorType, EncodingError};
+
+/// Writes `texels`, tightly packed RGBA8 rows from the top, as a PNG of `extent`.
+pub(crate) fn write_png(
+    path: impl AsRef<Path>,
+    texels: &[u8],
+    extent: [u32; 2],
+) -> Result<(), EncodingError> {
+    encode_png(BufWriter::new(File::create(path)?), texels, extent)
+}
+
+fn encode_png(out: impl Write, texels: &[u8], extent: [u32; 2]) -> Result<(), EncodingError> {
+    let mut encoder = png::Encoder::new(out, extent[0], extent[1]);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(texels)?;
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn encodes_rgba8_rows() {
+        let texels = [[255, 0, 0, 255], [0, 128, 255, 0]].concat().repeat(3);
+        let mut out = Vec::new();
+        encode_png(&mut out, &texels, [2, 3]).unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(out)).read_info().unwrap();
+        let mut decoded = vec![0; texels.len()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+        assert_eq!([info.width, info.height], [2, 3]);
+        assert_eq!(info.color_type, ColorType::Rgba);
+        assert_eq!(decoded, texels);
+    }
+}
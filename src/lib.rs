@@ -1,20 +1,97 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc},
+    time::{Duration, Instant},
+};
 
-use easy_gltf::Scene;
+use animation::SceneAnimation;
+use bounds::Aabb;
+use camera_controller::CameraController;
+use camera_path::CameraPath;
+use cgmath::{Deg, InnerSpace, Matrix4, SquareMatrix, Vector3};
+use coordinates::CoordinateSystem;
+use easy_gltf::{
+    model::{Material, Mode},
+    Scene,
+};
+use fly_camera::{FlyCamera, DEFAULT_LOOK_SPEED, DEFAULT_MOVE_SPEED};
+use footprint::SceneFootprint;
+use frame_clock::FrameClock;
+use gltf::ModelExtras;
+use lod::{LodGroup, DEFAULT_LOD_SCREEN_SIZES};
+use mesh::Mesh;
 use pipeline::{
+    accumulation::AccumulationPipeline,
+    create_joint_palette,
+    culling::{CulledDraw, CullingPipeline},
+    deferred::DeferredPipeline,
+    depth_heatmap::DepthHeatmapPipeline,
+    depth_prepass::DepthPrepassPipeline,
     draw,
-    sample::{Camera, SamplePipeline},
+    fxaa::FxaaPipeline,
+    labeled,
+    line::{LinePipeline, LineStyle, LineVertex},
+    normals::NormalsPipeline,
+    outline::{OutlinePipeline, OutlineStyle},
+    point::{PointPipeline, PointStyle},
+    resume_rendering,
+    sample::{
+        AmbientSettings, Camera, Clearcoat, DebugView, FogFalloff, FogSettings, LightSettings,
+        LightingModel, MaterialOverride, PbrMaterial, SamplePipeline, SceneTexture, Transmission,
+    },
+    shadow::{ShadowMap, ShadowPipeline, ShadowSettings},
+    skybox::SkyboxPipeline,
+    ssr::{SsrPipeline, SsrSettings},
+    taa::{self, TaaPipeline},
+    tonemap::{TonemapPipeline, TonemapSettings},
+    vertex_capture::VertexCapturePipeline,
+    AaMode, ColorAttachment, DepthBias, DepthSettings, FaceCulling, JointMatrix, MorphDelta,
+    MorphTargets, MsaaResolve, PipelineError, MOTION_FORMAT,
 };
+use render_pass::{resolve_source_usage, resolve_target_usage};
+use skybox::Cubemap;
+use stats::{RenderStats, StatisticsQuery, TimestampQuery};
+use texture::PendingTexture;
+use thiserror::Error;
+use ui::{FrameStats, Settings, Ui};
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
-    command_buffer::allocator::StandardCommandBufferAllocator,
-    descriptor_set::allocator::StandardDescriptorSetAllocator,
-    device::{DeviceExtensions, Features},
-    format::Format,
-    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount},
+    command_buffer::{
+        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
+        BlitImageInfo, BufferImageCopy, CopyImageToBufferInfo, ImageBlit, RecordingCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo},
+        DescriptorSet,
+    },
+    device::{Device, DeviceExtensions, Features, Queue},
+    format::{Format, FormatFeatures},
+    image::{
+        sampler::Filter, view::ImageView, Image, ImageAspects, ImageCreateFlags, ImageCreateInfo,
+        ImageSubresourceLayers, ImageType, ImageUsage, SampleCount,
+    },
+    instance::{
+        debug::{
+            DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCallback,
+            DebugUtilsMessengerCreateInfo,
+        },
+        Instance, InstanceCreateInfo,
+    },
+    library::LoadingError,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
-    pipeline::graphics::{subpass::PipelineRenderingCreateInfo, vertex_input::Vertex},
-    swapchain::ColorSpace,
+    pipeline::graphics::{
+        depth_stencil::CompareOp,
+        rasterization::{FrontFace, PolygonMode},
+        subpass::PipelineRenderingCreateInfo,
+        vertex_input::Vertex,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+    swapchain::{ColorSpace, PresentMode, SurfaceInfo},
+    sync::{self, future::FenceSignalFuture, GpuFuture},
+    Validated, VulkanError, VulkanLibrary,
 };
 use vulkano_util::{
     context::{VulkanoConfig, VulkanoContext},
@@ -22,24 +99,558 @@ use vulkano_util::{
     window::{VulkanoWindows, WindowDescriptor},
 };
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    raw_window_handle::{HasWindowHandle, RawWindowHandle},
+    error::EventLoopError,
+    event::{Event, MouseScrollDelta, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    keyboard::{KeyCode, PhysicalKey},
+    window::WindowId,
 };
 
+#[cfg(target_os = "macos")]
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+pub mod animation;
+pub mod bounds;
+pub mod camera_controller;
+pub mod camera_path;
+pub mod coordinates;
+pub mod fly_camera;
+pub mod footprint;
+pub mod frame_clock;
 mod gltf;
-mod pipeline;
+mod image_file;
+pub mod lod;
+pub mod mesh;
+pub mod model_file;
+mod obj;
+pub mod pipeline;
+mod render_pass;
+pub mod skybox;
+pub mod stats;
+pub mod texture;
+mod ui;
+
+const Z_NEAR: f32 = 0.1;
+const Z_FAR: f32 = 100.0;
+
+/// Swapchain format and color space requested for extended dynamic range output.
+const EDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const EDR_COLOR_SPACE: ColorSpace = ColorSpace::ExtendedSrgbLinear;
+/// Format of [`App::render_to_image`]'s texels.
+const SDR_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+const DEFAULT_FOVY: Deg<f32> = Deg(60.0);
+const MIN_FOVY: Deg<f32> = Deg(10.0);
+const MAX_FOVY: Deg<f32> = Deg(120.0);
+/// Change in field of view per scroll line or key press.
+const FOVY_STEP: Deg<f32> = Deg(5.0);
+/// How often a waiting event loop wakes up to check the [`App::set_scene_source`] channel.
+const SCENE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const WINDOW_TITLE: &str = "r/place 2023 Player";
+/// Factor the ambient color is scaled by per key press.
+const AMBIENT_STEP: f32 = 1.25;
+/// Factor the tone-mapping white point is scaled by per key press.
+const WHITE_POINT_STEP: f32 = 1.25;
+/// Added to or taken from the tone-mapping contrast per key press.
+const CONTRAST_STEP: f32 = 0.1;
+/// Density the G key switches fog to after the linear falloff.
+const FOG_DENSITY: f32 = 0.05;
+/// Enabled by [`ValidationMode::Debug`].
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Vulkan's clip space Y axis points down, unlike the OpenGL convention cgmath follows.
+#[rustfmt::skip]
+const FLIP_Y: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, -1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+);
+
+/// Layer of models without another, see [`App::set_active_layers`].
+pub const DEFAULT_LAYER: u32 = 0;
+/// Layer of glTF nodes named like collision meshes.
+pub const COLLISION_LAYER: u32 = 1;
+/// Layer of glTF nodes named like levels of detail past the first.
+pub const LOD_LAYER: u32 = 2;
+
+/// Descriptor sets per pool for scenes with fewer models, as by default in vulkano.
+pub const MIN_DESCRIPTOR_SETS_PER_POOL: usize = 32;
+/// Above this, large scenes take more than one pool per layout rather than huge ones for every
+/// layout.
+pub const MAX_DESCRIPTOR_SETS_PER_POOL: usize = 1024;
+
+/// How many times [`App::run`] rebuilds the device after losing it before giving up.
+pub const MAX_DEVICE_LOST_RECOVERIES: u32 = 3;
+
+/// Most frames [`App::set_frames_in_flight`] lets the GPU work on at once.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("failed to load scene: {0}")]
+    Load(Box<dyn std::error::Error + Send + Sync>),
+    #[error("file contains no scenes")]
+    NoScene,
+    #[error("failed to create event loop: {0}")]
+    EventLoop(#[from] EventLoopError),
+    #[error("device lost {0} times, giving up")]
+    DeviceLost(u32),
+    #[error("failed to recreate the device: {0}")]
+    Recreate(#[from] AppError),
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+    #[error(transparent)]
+    Vulkan(#[from] VulkanError),
+}
+
+/// Why an [`App`] couldn't be created.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("failed to load the Vulkan library: {0}")]
+    Library(#[from] LoadingError),
+    #[error("failed to create the Vulkan instance: {0}")]
+    Instance(#[from] Validated<VulkanError>),
+    #[error(transparent)]
+    Vulkan(#[from] VulkanError),
+    #[error("no Vulkan devices found")]
+    NoDevice,
+    /// Of the first device found, when none has every required extension and feature.
+    #[error("{device} doesn't support {}", missing.join(", "))]
+    Unsupported {
+        device: String,
+        missing: Vec<&'static str>,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum SaveImageError {
+    #[error(transparent)]
+    Render(#[from] RenderError),
+    #[error("failed to write PNG: {0}")]
+    Png(#[from] png::EncodingError),
+}
+
+/// Why an offscreen render like [`App::render_thumbnails`] failed.
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("thumbnail grids need at least one column and a tile size above zero")]
+    EmptyGrid,
+    #[error("no model at index {index}, the scene has {count}")]
+    NoModel { index: usize, count: usize },
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+    #[error(transparent)]
+    Vulkan(#[from] VulkanError),
+}
 
 pub struct App {
     context: VulkanoContext,
     windows: VulkanoWindows,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    /// Of `descriptor_set_allocator`'s pools.
+    descriptor_set_count: usize,
+    /// See [`App::set_descriptor_sets_per_pool`].
+    descriptor_sets_per_pool: Option<usize>,
+    animation: Option<Arc<SceneAnimation>>,
+    front_face: FrontFace,
+    face_culling: FaceCulling,
+    shading: Shading,
+    point_style: PointStyle,
+    selection: Option<usize>,
+    outline_style: OutlineStyle,
+    /// By index into [`Scene::models`].
+    material_overrides: HashMap<usize, MaterialOverride>,
+    instances: HashMap<usize, Vec<InstanceData>>,
+    morph_weights: HashMap<usize, Vec<f32>>,
+    depth_biases: HashMap<usize, DepthBias>,
+    layers: HashMap<usize, u32>,
+    hidden_models: HashSet<usize>,
+    /// Bit `n` shows layer `n`.
+    active_layers: u32,
+    lod_groups: Vec<LodGroup>,
+    lod_screen_sizes: Vec<f32>,
+    fovy: Deg<f32>,
+    taa: bool,
+    supersampling: bool,
+    gpu_culling: bool,
+    depth_prepass: bool,
+    depth_heatmap: bool,
+    store_depth: bool,
+    ssr: Option<SsrSettings>,
+    tonemap: Option<TonemapSettings>,
+    debug_view: DebugView,
+    lighting_model: LightingModel,
+    ambient: AmbientSettings,
+    fog: Option<FogSettings>,
+    /// Per model data `easy_gltf` doesn't read, only known when loading from a file.
+    model_extras: Vec<ModelExtras>,
+    /// KTX2 data, kept so that it can be uploaded again after a device loss.
+    lightmap: Option<Vec<u8>>,
+    /// KTX2 data, like `lightmap`.
+    matcap: Option<Vec<u8>>,
+    skybox: Option<Cubemap>,
+    frame_sink: Option<FrameSink>,
+    debug_lines: Vec<LineVertex>,
+    line_style: LineStyle,
+    stats_callback: Option<Box<StatsCallback>>,
+    light: LightSettings,
+    extra_lights: Vec<LightSettings>,
+    depth: DepthSettings,
+    light_fallback: LightFallback,
+    control_flow: ControlFlow,
+    max_fps: Option<f32>,
+    vsync: bool,
+    frames_in_flight: usize,
+    msaa_resolve: MsaaResolve,
+    coordinate_system: CoordinateSystem,
+    aa_mode: AaMode,
+    /// Of the scene last passed to [`App::run`].
+    scene_footprint: Option<SceneFootprint>,
+    auto_orbit: bool,
+    polygon_mode: PolygonMode,
+    fit_camera: bool,
+    fly_move_speed: f32,
+    fly_look_speed: cgmath::Rad<f32>,
+    frame_clock: FrameClock,
+    camera_path: Option<CameraPath>,
+    camera_path_file: Option<PathBuf>,
+    scene_source: Option<Receiver<Scene>>,
+    validation: ValidationMode,
+}
+
+/// Receives the texels and extent of each rendered frame, see [`App::set_frame_sink`].
+pub type FrameCallback = dyn FnMut(&[u8], [u32; 2]);
+
+/// Receives the counters of each frame, see [`App::set_stats_callback`].
+pub type StatsCallback = dyn FnMut(&RenderStats);
+
+struct FrameSink {
+    frame_rate: f32,
+    callback: Box<FrameCallback>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shading {
+    /// Shades every object as it is drawn, with shadows.
+    #[default]
+    Forward,
+    /// Fills a G-buffer first, then shades all lights in one fullscreen pass.
+    Deferred,
+}
+
+/// What to light a scene without any lights of its own with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightFallback {
+    /// The light set with [`App::set_light`].
+    #[default]
+    DefaultLight,
+    /// Base colors darkened towards silhouettes, ignoring the light. Only applies to
+    /// [`Shading::Forward`]; deferred shading uses the default light instead.
+    Unlit,
+    /// Only the ambient term, leaving the scene nearly black.
+    Dark,
+}
+
+/// How much checking the Vulkan instance does, see [`App::with_validation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// No validation layer or debug labels.
+    Release,
+    /// The Khronos validation layer if it's installed, with its messages logged through `log`,
+    /// and debug labels around each pass for tools like RenderDoc.
+    Debug,
+}
+
+impl Default for ValidationMode {
+    /// [`ValidationMode::Debug`] in builds with debug assertions, [`ValidationMode::Release`]
+    /// otherwise.
+    fn default() -> Self {
+        match cfg!(debug_assertions) {
+            true => ValidationMode::Debug,
+            false => ValidationMode::Release,
+        }
+    }
 }
 
 struct MyModel {
+    /// Into the scene's models. Empty ones aren't uploaded, so this can be past the model's own
+    /// index in [`RenderState::models`].
+    scene_index: usize,
+    bounds: Aabb,
     vertex_buffer: Subbuffer<[MyVertex]>,
-    index_buffer: Subbuffer<[u32]>,
+    index_buffer: Option<Subbuffer<[u32]>>,
+    mode: Mode,
+    /// Triangles wind the other way, see [`ModelExtras::mirrored`].
+    mirrored: bool,
+    /// See [`ModelExtras::double_sided`].
+    double_sided: bool,
+    depth_bias: DepthBias,
+    material_override: MaterialOverride,
+    clearcoat: Clearcoat,
+    pbr: PbrMaterial,
+    /// See [`App::set_instances`].
+    instances: Option<Vec<InstanceData>>,
+    /// Drawn after the opaque models, see [`Attachments::background`].
+    transmission: Transmission,
+    /// See [`App::set_model_layer`].
+    layer: u32,
+    visible: bool,
+    morph_targets: MorphTargets,
+    /// Set with [`App::set_morph_weights`], so not animated.
+    pinned_morph_weights: bool,
+    joint_palette: Subbuffer<[JointMatrix]>,
+    /// Last frame's pose, for motion vectors.
+    previous_joint_palette: Subbuffer<[JointMatrix]>,
+    /// Written by [`RenderState::culling_pipeline`], if enabled.
+    indirect_command: Option<Subbuffer<[u32]>>,
+    sample_descriptor_set: Arc<DescriptorSet>,
+    /// The base color texture, see [`SamplePipeline::texture_descriptor_set`]. The deferred path
+    /// has no textures.
+    texture_descriptor_set: Arc<DescriptorSet>,
+    shadow_descriptor_set: Arc<DescriptorSet>,
+    deferred_descriptor_set: Option<Arc<DescriptorSet>>,
+    prepass_descriptor_set: Option<Arc<DescriptorSet>>,
+    outline_descriptor_set: Arc<DescriptorSet>,
+}
+
+impl MyModel {
+    fn is_points(&self) -> bool {
+        self.mode == Mode::Points
+    }
+
+    /// Strips and loops included, which are uploaded as lists.
+    fn is_lines(&self) -> bool {
+        matches!(self.mode, Mode::Lines | Mode::LineStrip | Mode::LineLoop)
+    }
+
+    /// Drawn by the shaded pipelines, unlike points and lines.
+    fn is_triangles(&self) -> bool {
+        !self.is_points() && !self.is_lines()
+    }
+
+    fn is_visible(&self, active_layers: u32) -> bool {
+        self.visible && active_layers & (1 << self.layer) != 0
+    }
+
+    /// Of one copy, with strips and fans already turned into lists.
+    fn triangle_count(&self) -> u64 {
+        let index_count = match &self.index_buffer {
+            Some(index_buffer) => index_buffer.len(),
+            None => self.vertex_buffer.len(),
+        };
+        index_count / 3
+    }
+}
+
+/// A model converted for upload, from a glTF scene or a [`Mesh`], see [`SceneData`].
+struct ModelData {
+    vertices: Vec<MyVertex>,
+    /// With strips, fans and line loops turned into lists. `None` to draw the vertices in order.
+    indices: Option<Vec<u32>>,
+    mode: Mode,
+    /// See [`MyModel::mirrored`].
+    mirrored: bool,
+    morph_deltas: Vec<MorphDelta>,
+    /// `None` for meshes, which get the default material.
+    material: Option<Arc<Material>>,
+}
+
+/// The models of a scene as they are uploaded, converted once rather than whenever the
+/// [`RenderState`] is rebuilt. Conversion applies the coordinate system, the [`ModelExtras`]
+/// and the skins of the [`App::set_animation`] animation.
+struct SceneData {
+    models: Vec<ModelData>,
+    /// See [`LightFallback`].
+    has_lights: bool,
+}
+
+impl SceneData {
+    fn new(app: &App, scene: &Scene) -> SceneData {
+        let animation = app.animation.as_ref();
+        let conversion = app.coordinate_system.to_gltf();
+        let coordinate_system = app.coordinate_system;
+        let model_extras = &app.model_extras;
+
+        let start = Instant::now();
+        let models = parallel_map(&scene.models, |i, model| {
+            let model_skin = animation.and_then(|a| a.model_skin(i));
+            let extras = model_extras.get(i);
+            let tex_coords_1 = extras.and_then(|e| e.tex_coords_1.as_ref());
+            let colors = extras.and_then(|e| e.colors.as_ref());
+            // `easy_gltf` reads the factor too, for scenes loaded without extras. Models without
+            // a material get its default, white.
+            let base_color_factor = extras.map_or_else(
+                || model.material().pbr.base_color_factor.into(),
+                |e| e.base_color_factor,
+            );
+            let mirrored = extras.is_some_and(|e| e.mirrored) != coordinate_system.mirrors();
+            let morph_targets = extras.map_or(&[][..], |e| &e.morph_targets);
+            let vertices: Vec<_> = model
+                .vertices()
+                .iter()
+                .enumerate()
+                .map(|(j, v)| {
+                    let mut v = MyVertex::from(*v);
+                    v.position = (conversion * Vector3::from(v.position).extend(1.0))
+                        .truncate()
+                        .into();
+                    v.normal = (conversion * Vector3::from(v.normal).extend(0.0))
+                        .truncate()
+                        .into();
+                    if let Some(tex_coords_1) = tex_coords_1 {
+                        v.tex_coord_1 = tex_coords_1[j];
+                    }
+                    v.color = colors.map_or(base_color_factor, |c| c[j]);
+                    if let Some(skin) = model_skin {
+                        v.joints = skin.joints[j];
+                        v.weights = skin.weights[j];
+                    }
+                    v
+                })
+                .collect();
+            let indices = match model.mode() {
+                mode @ (Mode::TriangleStrip | Mode::TriangleFan) => {
+                    let restart = extras.and_then(|e| e.restart_index).unwrap_or(u32::MAX);
+                    let indices = match model.indices() {
+                        Some(indices) => Cow::Borrowed(indices.as_slice()),
+                        None => Cow::Owned((0..vertices.len() as u32).collect()),
+                    };
+                    Some(Cow::Owned(triangle_list(mode, &indices, restart)))
+                }
+                mode @ (Mode::LineStrip | Mode::LineLoop) => {
+                    let indices = match model.indices() {
+                        Some(indices) => Cow::Borrowed(indices.as_slice()),
+                        None => Cow::Owned((0..vertices.len() as u32).collect()),
+                    };
+                    Some(Cow::Owned(line_list(mode, &indices)))
+                }
+                _ => model.indices().map(|i| Cow::Borrowed(i.as_slice())),
+            };
+            let triangles = matches!(
+                model.mode(),
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            );
+            if !model.has_normals() && triangles {
+                let indices = indices.as_deref();
+                // The deltas follow the vertices they were unwelded from.
+                let morph_deltas = match indices {
+                    Some(indices) => morph_deltas(
+                        morph_targets,
+                        indices.iter().map(|&i| i as usize),
+                        conversion,
+                    ),
+                    None => morph_deltas(morph_targets, 0..vertices.len(), conversion),
+                };
+                ModelData {
+                    vertices: with_flat_normals(&vertices, indices, mirrored),
+                    indices: None,
+                    mode: model.mode(),
+                    mirrored,
+                    morph_deltas,
+                    material: Some(model.material()),
+                }
+            } else {
+                let mut vertices = vertices;
+                let degenerate = vertices
+                    .iter()
+                    .any(|v| Vector3::from(v.normal).magnitude2() < MIN_NORMAL_LENGTH2);
+                if degenerate && triangles {
+                    log::debug!("model {i} has zero-length normals, generating smooth ones");
+                    compute_normals(&mut vertices, indices.as_deref(), mirrored);
+                }
+                let morph_deltas = morph_deltas(morph_targets, 0..vertices.len(), conversion);
+                ModelData {
+                    vertices,
+                    indices: indices.map(Cow::into_owned),
+                    mode: model.mode(),
+                    mirrored,
+                    morph_deltas,
+                    material: Some(model.material()),
+                }
+            }
+        });
+        log::info!(
+            "converted {} models in {:.1?}",
+            models.len(),
+            start.elapsed()
+        );
+        SceneData {
+            models,
+            has_lights: !scene.lights.is_empty(),
+        }
+    }
+
+    /// Converts `meshes` to the coordinate system like glTF scenes. Meshes have no extras and
+    /// aren't skinned, see [`Mesh::vertices`].
+    fn from_meshes(meshes: &[Mesh], coordinate_system: CoordinateSystem) -> SceneData {
+        let conversion = coordinate_system.to_gltf();
+        let mirrored = coordinate_system.mirrors();
+        let models = meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| {
+                let mut vertices: Vec<_> = mesh
+                    .vertices
+                    .iter()
+                    .map(|&v| MyVertex {
+                        position: (conversion * Vector3::from(v.position).extend(1.0))
+                            .truncate()
+                            .into(),
+                        normal: (conversion * Vector3::from(v.normal).extend(0.0))
+                            .truncate()
+                            .into(),
+                        joints: [0; 4],
+                        weights: [1.0, 0.0, 0.0, 0.0],
+                        ..v
+                    })
+                    .collect();
+                let degenerate = vertices
+                    .iter()
+                    .any(|v| Vector3::from(v.normal).magnitude2() < MIN_NORMAL_LENGTH2);
+                if degenerate {
+                    log::debug!("mesh {i} has zero-length normals, generating smooth ones");
+                    compute_normals(&mut vertices, Some(&mesh.indices), mirrored);
+                }
+                ModelData {
+                    vertices,
+                    indices: Some(mesh.indices.clone()),
+                    mode: Mode::Triangles,
+                    mirrored,
+                    morph_deltas: Vec::new(),
+                    material: None,
+                }
+            })
+            .collect();
+        SceneData {
+            models,
+            has_lights: false,
+        }
+    }
+
+    /// What [`SceneFootprint::of`] estimates for the scene, exactly.
+    fn footprint(&self) -> SceneFootprint {
+        let mut footprint = SceneFootprint::default();
+        for model in &self.models {
+            let element_count = model
+                .indices
+                .as_ref()
+                .map_or(model.vertices.len(), Vec::len);
+            if matches!(
+                model.mode,
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            ) {
+                footprint.triangles += element_count as u64 / 3;
+            }
+            footprint.vertex_bytes += (model.vertices.len() * size_of::<MyVertex>()) as u64;
+            footprint.index_bytes += model
+                .indices
+                .as_ref()
+                .map_or(0, |i| (i.len() * size_of::<u32>()) as u64);
+        }
+        footprint
+    }
 }
 
 #[derive(BufferContents, Vertex, Clone, Copy, Debug, Default)]
@@ -51,6 +662,40 @@ pub struct MyVertex {
     pub normal: [f32; 3],
     #[format(R32G32_SFLOAT)]
     pub tex_coord: [f32; 2],
+    /// `TEXCOORD_1`, or a copy of `tex_coord` for meshes with a single UV set.
+    #[format(R32G32_SFLOAT)]
+    pub tex_coord_1: [f32; 2],
+    #[format(R16G16B16A16_UINT)]
+    pub joints: [u16; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub weights: [f32; 4],
+    /// Linear base color, multiplying the material's.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+/// One copy of an instanced model, see [`App::set_instances`]. Read at instance rate from a
+/// second vertex buffer, besides the [`MyVertex`] one.
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct InstanceData {
+    /// Applied after the model's own transform.
+    #[name("instance_model")]
+    #[format(R32G32B32A32_SFLOAT)]
+    pub model: [[f32; 4]; 4],
+    /// Linear, multiplying the model's colors. Alpha is ignored.
+    #[name("instance_color")]
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+impl InstanceData {
+    pub fn new(model: Matrix4<f32>, color: [f32; 4]) -> InstanceData {
+        InstanceData {
+            model: model.into(),
+            color,
+        }
+    }
 }
 
 impl From<easy_gltf::model::Vertex> for MyVertex {
@@ -59,33 +704,251 @@ impl From<easy_gltf::model::Vertex> for MyVertex {
             position: vertex.position.into(),
             normal: vertex.normal.into(),
             tex_coord: vertex.tex_coords.into(),
+            tex_coord_1: vertex.tex_coords.into(),
+            // Fully bound to the first joint, which is the identity for unskinned models.
+            joints: [0; 4],
+            weights: [1.0, 0.0, 0.0, 0.0],
+            color: [1.0; 4],
         }
     }
 }
 
-impl App {
-    pub fn new() -> Self {
-        let mut config = VulkanoConfig {
-            device_extensions: DeviceExtensions {
-                khr_swapchain: true,
-                khr_dynamic_rendering: true,
-                // khr_acceleration_structure: true,
-                // khr_ray_tracing_pipeline: true,
-                // khr_deferred_host_operations: true,
-                ..DeviceExtensions::empty()
-            },
-            device_features: Features {
-                dynamic_rendering: true,
-                fill_mode_non_solid: true,
-                ..Features::empty()
-            },
+/// One buffer holding all of `parts` back to back, in the order given, as a slice of it per
+/// part. Empty parts get `None`, since buffers and their slices can't be empty.
+fn concatenated_buffer<'a, T: BufferContents + Copy>(
+    allocator: Arc<StandardMemoryAllocator>,
+    usage: BufferUsage,
+    parts: impl Iterator<Item = &'a [T]> + Clone,
+) -> Vec<Option<Subbuffer<[T]>>> {
+    let len: usize = parts.clone().map(<[T]>::len).sum();
+    if len == 0 {
+        return parts.map(|_| None).collect();
+    }
+    let buffer = Buffer::new_slice::<T>(
+        allocator,
+        BufferCreateInfo {
+            usage,
             ..Default::default()
-        };
-        config
-            .instance_create_info
-            .enabled_extensions
-            .ext_swapchain_colorspace = true;
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        len as u64,
+    )
+    .unwrap();
+    let mut written = buffer.write().unwrap();
+    let mut offset = 0;
+    let mut slices = Vec::new();
+    for part in parts {
+        let range = offset..offset + part.len();
+        written[range.clone()].copy_from_slice(part);
+        offset = range.end;
+        slices.push(
+            (!part.is_empty()).then(|| buffer.clone().slice(range.start as u64..range.end as u64)),
+        );
+    }
+    drop(written);
+    slices
+}
+
+/// Unwelds the triangles so that each gets the normal of its face, as glTF requires for
+/// primitives without normals. `mirrored` triangles are wound the other way.
+fn with_flat_normals(
+    vertices: &[MyVertex],
+    indices: Option<&[u32]>,
+    mirrored: bool,
+) -> Vec<MyVertex> {
+    let mut unwelded: Vec<_> = match indices {
+        Some(indices) => indices.iter().map(|&i| vertices[i as usize]).collect(),
+        None => vertices.to_vec(),
+    };
+    for triangle in unwelded.chunks_exact_mut(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(triangle[i].position));
+        let mut normal = (b - a).cross(c - a);
+        if mirrored {
+            normal = -normal;
+        }
+        // Degenerate triangles cover no pixels, so their normal doesn't matter.
+        if normal.magnitude2() > 0.0 {
+            for vertex in triangle {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+    unwelded
+}
+
+/// Squared length below which [`RenderState`] regenerates a mesh's normals.
+const MIN_NORMAL_LENGTH2: f32 = 1e-8;
+
+/// Gives each vertex the area-weighted average of the normals of the triangles around it, for
+/// meshes whose normals are missing or broken. Unlike [`with_flat_normals`] this keeps the
+/// vertices shared. Vertices outside of any triangle keep theirs. `mirrored` triangles are
+/// wound the other way.
+pub fn compute_normals(vertices: &mut [MyVertex], indices: Option<&[u32]>, mirrored: bool) {
+    let sequential: Vec<u32>;
+    let indices = match indices {
+        Some(indices) => indices,
+        None => {
+            sequential = (0..vertices.len() as u32).collect();
+            &sequential
+        }
+    };
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(vertices[triangle[i] as usize].position));
+        // As long as twice the triangle's area.
+        let normal = (b - a).cross(c - a);
+        for &i in triangle {
+            normals[i as usize] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            let normal = normal.normalize();
+            vertex.normal = match mirrored {
+                true => -normal,
+                false => normal,
+            }
+            .into();
+        }
+    }
+}
+
+fn new_descriptor_set_allocator(
+    device: &Arc<Device>,
+    set_count: usize,
+) -> Arc<StandardDescriptorSetAllocator> {
+    Arc::new(StandardDescriptorSetAllocator::new(
+        device.clone(),
+        StandardDescriptorSetAllocatorCreateInfo {
+            set_count,
+            ..Default::default()
+        },
+    ))
+}
 
+/// Pools of `configured` sets, or else enough for one set per model.
+fn descriptor_sets_per_pool(configured: Option<usize>, model_count: usize) -> usize {
+    configured.unwrap_or_else(|| {
+        model_count.clamp(MIN_DESCRIPTOR_SETS_PER_POOL, MAX_DESCRIPTOR_SETS_PER_POOL)
+    })
+}
+
+/// Turns the strips or fans in `indices` into a triangle list, so that they draw with the same
+/// pipelines as other models. Each `restart` index ends one strip or fan and starts the next.
+fn triangle_list(mode: Mode, indices: &[u32], restart: u32) -> Vec<u32> {
+    let strip = matches!(mode, Mode::TriangleStrip);
+    indices
+        .split(|&i| i == restart)
+        .flat_map(|run| {
+            (0..run.len().saturating_sub(2)).flat_map(move |i| match (strip, i % 2) {
+                // Every other triangle of a strip is flipped to keep the winding.
+                (true, 1) => [run[i + 1], run[i], run[i + 2]],
+                (true, _) => [run[i], run[i + 1], run[i + 2]],
+                (false, _) => [run[0], run[i + 1], run[i + 2]],
+            })
+        })
+        .collect()
+}
+
+/// The part of the `size` rectangle at `offset` that lies within `extent`, as an offset and
+/// size. The size is zero along an axis the rectangle misses the image on.
+fn clamp_region(offset: [u32; 2], size: [u32; 2], extent: [u32; 2]) -> ([u32; 2], [u32; 2]) {
+    let offset = [0, 1].map(|i| offset[i].min(extent[i]));
+    let size = [0, 1].map(|i| size[i].min(extent[i] - offset[i]));
+    (offset, size)
+}
+
+/// Turns a line strip or loop into a line list, so that it draws with the same pipeline as
+/// other lines.
+fn line_list(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    let closing = match (mode, indices) {
+        (Mode::LineLoop, [first, .., last]) => Some([*last, *first]),
+        _ => None,
+    };
+    indices
+        .windows(2)
+        .flatten()
+        .copied()
+        .chain(closing.into_iter().flatten())
+        .collect()
+}
+
+/// The converted deltas of every target for each of `sources`, laid out as [`MorphDelta`]s are.
+/// Targets without position deltas add zeros.
+fn morph_deltas(
+    targets: &[Vec<[f32; 3]>],
+    sources: impl Iterator<Item = usize>,
+    conversion: Matrix4<f32>,
+) -> Vec<MorphDelta> {
+    sources
+        .flat_map(|j| {
+            targets.iter().map(move |target| {
+                let delta = target.get(j).copied().unwrap_or_default();
+                (conversion * Vector3::from(delta).extend(0.0)).into()
+            })
+        })
+        .collect()
+}
+
+/// The level validation messages of `severity` are logged at. Info messages are mostly about
+/// the loader, so they only show up when debugging.
+fn log_level(severity: DebugUtilsMessageSeverity) -> log::Level {
+    if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+        log::Level::Error
+    } else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+        log::Level::Warn
+    } else {
+        log::Level::Debug
+    }
+}
+
+/// Maps `items` with `f`, which is also passed their index, on as many threads as there are
+/// cores. The results are in the order of `items`.
+fn parallel_map<'a, T: Sync, U: Send>(
+    items: &'a [T],
+    f: impl Fn(usize, &'a T) -> U + Sync,
+) -> Vec<U> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(chunk_index, chunk)| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| f(chunk_index * chunk_size + i, item))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+impl App {
+    /// An app with the [default](ValidationMode::default) validation mode.
+    pub fn new() -> Result<Self, AppError> {
+        Self::with_validation(ValidationMode::default())
+    }
+
+    /// An app whose Vulkan instance is checked according to `validation`. Kept when the device
+    /// is recreated after a loss.
+    pub fn with_validation(validation: ValidationMode) -> Result<Self, AppError> {
+        let mut config = Self::vulkano_config(validation);
+        let (optional_extensions, optional_features) = Self::check_support(&config)?;
+        config.device_extensions = config.device_extensions.union(&optional_extensions);
+        config.device_features = config.device_features.union(&optional_features);
         let context = VulkanoContext::new(config);
         let windows = VulkanoWindows::default();
 
@@ -95,233 +958,3189 @@ impl App {
             device.clone(),
             Default::default(),
         ));
-        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
-            device.clone(),
-            Default::default(),
-        ));
+        let descriptor_set_allocator =
+            new_descriptor_set_allocator(device, MIN_DESCRIPTOR_SETS_PER_POOL);
 
-        Self {
+        Ok(Self {
             context,
             windows,
             command_buffer_allocator,
             descriptor_set_allocator,
-        }
+            descriptor_set_count: MIN_DESCRIPTOR_SETS_PER_POOL,
+            descriptor_sets_per_pool: None,
+            animation: None,
+            front_face: FrontFace::CounterClockwise,
+            face_culling: FaceCulling::default(),
+            shading: Shading::default(),
+            point_style: PointStyle::default(),
+            selection: None,
+            outline_style: OutlineStyle::default(),
+            material_overrides: HashMap::new(),
+            instances: HashMap::new(),
+            morph_weights: HashMap::new(),
+            depth_biases: HashMap::new(),
+            layers: HashMap::new(),
+            hidden_models: HashSet::new(),
+            active_layers: u32::MAX,
+            lod_groups: Vec::new(),
+            lod_screen_sizes: DEFAULT_LOD_SCREEN_SIZES.to_vec(),
+            fovy: DEFAULT_FOVY,
+            taa: false,
+            supersampling: false,
+            gpu_culling: false,
+            depth_prepass: false,
+            depth_heatmap: false,
+            store_depth: false,
+            ssr: None,
+            tonemap: None,
+            debug_view: DebugView::default(),
+            lighting_model: LightingModel::default(),
+            ambient: AmbientSettings::default(),
+            fog: None,
+            model_extras: Vec::new(),
+            lightmap: None,
+            matcap: None,
+            skybox: None,
+            frame_sink: None,
+            debug_lines: Vec::new(),
+            line_style: LineStyle::default(),
+            stats_callback: None,
+            light: LightSettings::default(),
+            extra_lights: Vec::new(),
+            depth: DepthSettings::default(),
+            light_fallback: LightFallback::default(),
+            control_flow: ControlFlow::Poll,
+            max_fps: None,
+            vsync: true,
+            frames_in_flight: 2,
+            msaa_resolve: MsaaResolve::default(),
+            coordinate_system: CoordinateSystem::default(),
+            aa_mode: AaMode::default(),
+            scene_footprint: None,
+            auto_orbit: false,
+            polygon_mode: PolygonMode::Line,
+            fit_camera: true,
+            fly_move_speed: DEFAULT_MOVE_SPEED,
+            fly_look_speed: DEFAULT_LOOK_SPEED,
+            frame_clock: FrameClock::default(),
+            camera_path: None,
+            camera_path_file: None,
+            scene_source: None,
+            validation,
+        })
     }
 
-    pub fn run(&mut self, scene: &Scene) {
-        let event_loop = EventLoop::new().unwrap();
-        event_loop.set_control_flow(ControlFlow::Poll);
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
 
-        let window_id = self.windows.create_window(
-            &event_loop,
-            &self.context,
-            &WindowDescriptor {
-                width: 1280.0,
-                height: 720.0,
-                title: "r/place 2023 Player".to_string(),
-                resizable: false,
-                ..Default::default()
-            },
-            |create_info| {
-                create_info.image_format = Format::R16G16B16A16_SFLOAT;
-                create_info.image_color_space = ColorSpace::ExtendedSrgbLinear;
-            },
-        );
+    /// How point primitives are drawn. Points are only drawn with [`Shading::Forward`].
+    pub fn set_point_style(&mut self, point_style: PointStyle) {
+        self.point_style = point_style;
+    }
 
-        #[cfg(target_os = "macos")]
-        unsafe {
-            let window_handle = self
-                .windows
-                .get_window(window_id)
-                .unwrap()
-                .window_handle()
-                .unwrap()
-                .as_raw();
-            enable_edr(window_handle);
-        }
+    /// Outlines the model at this index into [`Scene::models`].
+    pub fn select(&mut self, model: Option<usize>) {
+        self.selection = model;
+    }
 
-        let queue = self.context.graphics_queue().clone();
+    /// Position, color and intensity of the scene's light, which also casts the shadows.
+    pub fn set_light(&mut self, light: LightSettings) {
+        self.light = light;
+    }
 
-        let sample_pipeline = SamplePipeline::new(
-            &self,
-            queue.clone(),
-            PipelineRenderingCreateInfo {
-                color_attachment_formats: vec![Some(
-                    self.windows
-                        .get_renderer(window_id)
-                        .unwrap()
-                        .swapchain_format(),
-                )],
-                depth_attachment_format: Some(Format::D32_SFLOAT),
-                ..Default::default()
-            },
-        );
+    /// Lights shining alongside [`App::set_light`]'s, without shadows. The forward path shades
+    /// [`MAX_LIGHTS`] in total at most.
+    ///
+    /// [`MAX_LIGHTS`]: pipeline::sample::MAX_LIGHTS
+    pub fn set_extra_lights(&mut self, lights: Vec<LightSettings>) {
+        self.extra_lights = lights;
+    }
 
-        let memory_allocator = self.memory_allocator();
+    /// How to light scenes without lights. glTF lights aren't read yet, so scenes that have them
+    /// are always lit with the default light.
+    pub fn set_light_fallback(&mut self, light_fallback: LightFallback) {
+        self.light_fallback = light_fallback;
+    }
 
-        let models = scene
-            .models
-            .iter()
-            .map(|model| {
-                let vertex_buffer = Buffer::from_iter(
-                    memory_allocator.clone(),
-                    BufferCreateInfo {
-                        usage: BufferUsage::VERTEX_BUFFER,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo {
-                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                        ..Default::default()
-                    },
-                    model.vertices().iter().map(|v| {
-                        let mut v = MyVertex::from(*v);
-                        v.position[1] *= -1.0;
-                        v
-                    }),
-                )
-                .unwrap();
-                let index_buffer = Buffer::from_iter(
-                    memory_allocator.clone(),
-                    BufferCreateInfo {
-                        usage: BufferUsage::INDEX_BUFFER,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo {
-                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                        ..Default::default()
-                    },
-                    model.indices().unwrap().iter().cloned(),
-                )
-                .unwrap();
+    /// The coordinate system scenes are authored in, converted to glTF's Y-up, right-handed one
+    /// when they are loaded.
+    pub fn set_coordinate_system(&mut self, coordinate_system: CoordinateSystem) {
+        self.coordinate_system = coordinate_system;
+    }
 
-                MyModel {
-                    vertex_buffer,
-                    index_buffer,
-                }
-            })
-            .collect::<Vec<_>>();
+    /// Whether the main pass resolves its samples, keeps them, or both. Kept samples aren't
+    /// consumed by anything yet, so [`MsaaResolve::Store`] leaves the window black.
+    pub fn set_msaa_resolve(&mut self, msaa_resolve: MsaaResolve) {
+        self.msaa_resolve = msaa_resolve;
+    }
 
-        let render_start = Instant::now();
-        let camera_fn = || {
-            let elapsed = render_start.elapsed().as_secs_f32();
-            let position = cgmath::Point3::new(
-                (elapsed * 0.5).sin() * 3.0,
-                1.0,
-                (elapsed * 0.5).cos() * 3.0,
-            );
-            Camera {
-                position,
-                view: cgmath::Matrix4::look_at_rh(
-                    position,
-                    cgmath::Point3::new(0.0, 0.0, 0.0),
-                    cgmath::Vector3::unit_y(),
-                ),
-                proj: cgmath::perspective(cgmath::Deg(60.0), 1280.0 / 720.0, 0.1, 100.0),
-            }
+    /// Depth testing and writes of [`Shading::Forward`], e.g. to draw the scene as an overlay.
+    pub fn set_depth(&mut self, depth: DepthSettings) {
+        self.depth = depth;
+    }
+
+    /// Tints and scales the roughness of the model at this index into [`Scene::models`], or
+    /// restores its material with `None`. Only applies to [`Shading::Forward`].
+    pub fn set_material_override(
+        &mut self,
+        model: usize,
+        material_override: Option<MaterialOverride>,
+    ) {
+        match material_override {
+            Some(material_override) => self.material_overrides.insert(model, material_override),
+            None => self.material_overrides.remove(&model),
         };
+    }
 
-        let extent = self
-            .windows
-            .get_renderer_mut(window_id)
-            .unwrap()
-            .swapchain_image_view()
-            .image()
-            .extent();
+    /// Draws the model at this index into [`Scene::models`] once per instance, in a single
+    /// instanced draw, or once as usual with `None`. Instances aren't shadowed, outlined or
+    /// written by the depth prepass, and only apply to [`Shading::Forward`].
+    pub fn set_instances(&mut self, model: usize, instances: Option<Vec<InstanceData>>) {
+        match instances {
+            Some(instances) => self.instances.insert(model, instances),
+            None => self.instances.remove(&model),
+        };
+    }
 
-        let samples = SampleCount::Sample4;
+    /// Offsets the depth of the model at this index into [`Scene::models`], or restores its
+    /// default with `None`: [`DepthBias::DECAL`] for materials named as decals, none otherwise.
+    pub fn set_depth_bias(&mut self, model: usize, depth_bias: Option<DepthBias>) {
+        match depth_bias {
+            Some(depth_bias) => self.depth_biases.insert(model, depth_bias),
+            None => self.depth_biases.remove(&model),
+        };
+    }
 
-        let depth_image = ImageView::new_default(
-            Image::new(
-                self.memory_allocator(),
-                ImageCreateInfo {
-                    image_type: ImageType::Dim2d,
-                    extent: [extent[0], extent[1], 1],
-                    format: Format::D32_SFLOAT,
-                    usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
-                    samples,
-                    ..Default::default()
-                },
-                AllocationCreateInfo::default(),
-            )
-            .unwrap(),
-        )
-        .unwrap();
+    /// Moves the model at this index into [`Scene::models`] to `layer`, below 32, or restores the
+    /// one derived from its glTF node's name with `None`: [`COLLISION_LAYER`] for `UCX_` and
+    /// `COL_` prefixes, [`LOD_LAYER`] for `_LOD1` suffixes and up, else [`DEFAULT_LAYER`].
+    pub fn set_model_layer(&mut self, model: usize, layer: Option<u32>) {
+        match layer {
+            Some(layer) => {
+                assert!(layer < u32::BITS, "layers must be below 32");
+                self.layers.insert(model, layer)
+            }
+            None => self.layers.remove(&model),
+        };
+    }
 
-        let msaa_color_image = ImageView::new_default(
-            Image::new(
-                self.memory_allocator(),
-                ImageCreateInfo {
-                    image_type: ImageType::Dim2d,
-                    extent: [extent[0], extent[1], 1],
-                    format: Format::R16G16B16A16_SFLOAT,
-                    usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
-                    samples,
-                    ..Default::default()
-                },
-                AllocationCreateInfo::default(),
-            )
-            .unwrap(),
-        )
-        .unwrap();
+    /// Hides or shows the model at this index into [`Scene::models`], regardless of its layer.
+    pub fn set_model_visible(&mut self, model: usize, visible: bool) {
+        match visible {
+            true => self.hidden_models.remove(&model),
+            false => self.hidden_models.insert(model),
+        };
+    }
 
-        let command_buffer_allocator = self.command_buffer_allocator.clone();
-        let redraw = |renderer: &mut VulkanoWindowRenderer| {
-            let before = renderer.acquire().unwrap();
+    /// Only draws models on the layers whose bits are set, all of them by default. The number
+    /// keys toggle layers 0 to 8 while running.
+    pub fn set_active_layers(&mut self, active_layers: u32) {
+        self.active_layers = active_layers;
+    }
 
-            let after = draw(
-                before,
-                command_buffer_allocator.clone(),
-                queue.clone(),
-                msaa_color_image.clone(),
-                renderer.swapchain_image_view(),
-                depth_image.clone(),
-                |builder| {
-                    for model in &models {
-                        let vertex_buffer = model.vertex_buffer.clone();
-                        let index_buffer = model.index_buffer.clone();
-
-                        sample_pipeline.render_object(
-                            builder,
-                            vertex_buffer,
-                            Some(index_buffer),
-                            &camera_fn(),
-                        )
+    /// Draws only one model of each group at a time, picked by how large the group appears on
+    /// screen, see [`App::set_lod_screen_sizes`]. Models are by index into [`Scene::models`].
+    /// These come before groups found by name, i.e. glTF nodes named `<name>_LOD<n>`, which
+    /// are left out if they share a model with one of these.
+    pub fn set_lod_groups(&mut self, groups: Vec<LodGroup>) {
+        self.lod_groups = groups;
+    }
+
+    /// The screen heights, as fractions of the window's, below which LOD groups switch to their
+    /// next level: the first level is drawn down to `sizes[0]`, the second down to `sizes[1]`
+    /// and so on. Defaults to [`DEFAULT_LOD_SCREEN_SIZES`].
+    pub fn set_lod_screen_sizes(&mut self, sizes: Vec<f32>) {
+        self.lod_screen_sizes = sizes;
+    }
+
+    /// Fixes the morph target weights of the model at this index into [`Scene::models`], in place
+    /// of its glTF weights and animation, or restores them with `None`. Takes effect when a new
+    /// scene is loaded.
+    pub fn set_morph_weights(&mut self, model: usize, weights: Option<Vec<f32>>) {
+        match weights {
+            Some(weights) => self.morph_weights.insert(model, weights),
+            None => self.morph_weights.remove(&model),
+        };
+    }
+
+    /// Draws line segments between each pair of `lines` on top of the scene, in world space.
+    pub fn set_debug_lines(&mut self, lines: Vec<LineVertex>, style: LineStyle) {
+        self.debug_lines = lines;
+        self.line_style = style;
+    }
+
+    pub fn set_outline_style(&mut self, outline_style: OutlineStyle) {
+        self.outline_style = outline_style;
+    }
+
+    /// Replaces the final shading with a debug view. It can also be cycled while running with the
+    /// V key. Only applies to [`Shading::Forward`].
+    pub fn set_debug_view(&mut self, debug_view: DebugView) {
+        self.debug_view = debug_view;
+    }
+
+    /// Shades with the models' metallic-roughness factors rather than the Phong material, or
+    /// back. It can also be toggled while running with the L key. Only applies to
+    /// [`Shading::Forward`].
+    pub fn set_lighting_model(&mut self, lighting_model: LightingModel) {
+        self.lighting_model = lighting_model;
+    }
+
+    /// Lights the scene's indirect term with a baked lightmap in KTX2 format, mapped with the
+    /// second UV set. Only applies to [`Shading::Forward`]. It is decoded and uploaded in the
+    /// background, with a gray placeholder until then.
+    pub fn set_lightmap(&mut self, ktx2: Vec<u8>) {
+        self.lightmap = Some(ktx2);
+    }
+
+    /// Shades every object with a material capture in KTX2 format instead of lights and
+    /// materials, looked up by the view-space normal. Only applies to [`Shading::Forward`].
+    /// Loaded in the background like the lightmap.
+    pub fn set_matcap(&mut self, ktx2: Vec<u8>) {
+        self.matcap = Some(ktx2);
+    }
+
+    /// Draws `skybox` behind the scene instead of clearing to black, e.g. from
+    /// [`Cubemap::from_equirectangular_hdr`]. Only applies to [`Shading::Forward`].
+    pub fn set_skybox(&mut self, skybox: Option<Cubemap>) {
+        self.skybox = skybox;
+    }
+
+    /// How the main pass is anti-aliased; the A key cycles through the modes while running.
+    /// FXAA is skipped with TAA or supersampling, which anti-alias already.
+    pub fn set_aa_mode(&mut self, aa_mode: AaMode) {
+        self.aa_mode = aa_mode;
+    }
+
+    /// Frustum-culls models on the GPU, drawing them from indirect commands. Skinned and morphed
+    /// models are always drawn.
+    pub fn set_gpu_culling(&mut self, gpu_culling: bool) {
+        self.gpu_culling = gpu_culling;
+    }
+
+    /// Writes the opaque models' depth before shading them, so that overlapping surfaces aren't
+    /// shaded only to be hidden. Only applies to [`Shading::Forward`] with the depth test enabled,
+    /// and needs dynamic depth state, see [`DepthSettings`].
+    pub fn set_depth_prepass(&mut self, depth_prepass: bool) {
+        self.depth_prepass = depth_prepass;
+    }
+
+    /// Shows the depth buffer as a heatmap instead of the scene, e.g. to debug depth precision.
+    /// It can also be toggled while running with the D key.
+    pub fn set_depth_heatmap(&mut self, depth_heatmap: bool) {
+        self.depth_heatmap = depth_heatmap;
+    }
+
+    /// Keeps the main pass' depth once it ends and makes it sampleable, for passes that read it
+    /// afterwards. Off by default, which saves the bandwidth of storing it unless the depth
+    /// heatmap or transmission need it anyway.
+    pub fn set_store_depth(&mut self, store_depth: bool) {
+        self.store_depth = store_depth;
+    }
+
+    /// The ambient light both shading paths add to every surface. It can also be adjusted while
+    /// running with the [ and ] keys for the color and O for the occlusion strength.
+    pub fn set_ambient(&mut self, ambient: AmbientSettings) {
+        self.ambient = ambient;
+    }
+
+    /// Fades surfaces into `fog.color` with their distance, `None` (the default) to turn it off.
+    /// G cycles between no fog, linear and exponential falloff while running.
+    pub fn set_fog(&mut self, fog: Option<FogSettings>) {
+        self.fog = fog;
+    }
+
+    /// Adds screen-space reflections to glossy surfaces, `None` to turn them off. Only applies
+    /// to [`Shading::Deferred`], which has the normals and materials to trace them from.
+    pub fn set_ssr(&mut self, ssr: Option<SsrSettings>) {
+        self.ssr = ssr;
+    }
+
+    /// Tone maps the rendered scene after the other post passes, `None` to write it out as is.
+    /// The white point and contrast can be changed with the keys while running.
+    pub fn set_tonemap(&mut self, tonemap: Option<TonemapSettings>) {
+        self.tonemap = tonemap;
+    }
+
+    /// Enables temporal anti-aliasing on top of [`App::set_aa_mode`], for both shading paths.
+    pub fn set_taa(&mut self, taa: bool) {
+        self.taa = taa;
+    }
+
+    /// Progressive supersampling: averages the rasterized frames, each jittered by a subpixel
+    /// offset, while the camera and scene hold still, converging to a supersampled image. Takes
+    /// precedence over TAA.
+    pub fn set_supersampling(&mut self, supersampling: bool) {
+        self.supersampling = supersampling;
+    }
+
+    /// Hands every frame to `callback` instead of presenting it, e.g. to feed a video encoder.
+    /// Frames are tightly packed rows of linear, extended sRGB `R16G16B16A16_SFLOAT` texels,
+    /// rendered as fast as possible but animated as if played back at `frame_rate` frames per
+    /// second.
+    pub fn set_frame_sink(
+        &mut self,
+        frame_rate: f32,
+        callback: impl FnMut(&[u8], [u32; 2]) + 'static,
+    ) {
+        self.frame_sink = Some(FrameSink {
+            frame_rate,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Reads back pipeline statistics of every frame, logging them at debug level and handing
+    /// them to `callback` a frame late. Devices without the `pipeline_statistics_query` feature,
+    /// e.g. on macOS, report none.
+    pub fn set_stats_callback(&mut self, callback: impl FnMut(&RenderStats) + 'static) {
+        self.stats_callback = Some(Box::new(callback));
+    }
+
+    /// [`ControlFlow::Poll`], the default, draws frames as fast as possible. Otherwise frames are
+    /// only drawn after input and the camera stops orbiting, unless the scene is animated or
+    /// frames go to a frame sink.
+    pub fn set_control_flow(&mut self, control_flow: ControlFlow) {
+        self.control_flow = control_flow;
+    }
+
+    /// Caps how often windows are redrawn by sleeping out the rest of each frame's budget after
+    /// presenting, independent of the present mode and [`App::set_control_flow`]. Frame sinks
+    /// are never capped. Off by default.
+    pub fn set_max_fps(&mut self, max_fps: Option<f32>) {
+        self.max_fps = max_fps.filter(|&fps| fps > 0.0);
+    }
+
+    /// Whether windows present with [`PresentMode::Fifo`], waiting for the display's vertical
+    /// blank. Without, the first of [`PresentMode::Immediate`] and [`PresentMode::Mailbox`] the
+    /// surface supports is used, falling back to `Fifo`, which every surface supports. On by
+    /// default. Applies to windows created afterwards; the I key toggles it while running.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.vsync = vsync;
+    }
+
+    /// How many frames may be rendering at once, between 1 and [`MAX_FRAMES_IN_FLIGHT`], 2 by
+    /// default. Each gets its own uniforms, command buffer and fence. Fewer lower the latency
+    /// from input to display, more keep the GPU busy when recording a frame takes long, e.g. for
+    /// captures. Applies to scenes run afterwards.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        assert!(
+            (1..=MAX_FRAMES_IN_FLIGHT).contains(&frames_in_flight),
+            "frames in flight must be between 1 and {MAX_FRAMES_IN_FLIGHT}"
+        );
+        self.frames_in_flight = frames_in_flight;
+    }
+
+    /// The present mode for `window_id` following [`App::set_vsync`].
+    fn present_mode(&self, window_id: WindowId) -> PresentMode {
+        if self.vsync {
+            return PresentMode::Fifo;
+        }
+        let surface = self.windows.get_renderer(window_id).unwrap().surface();
+        let supported = self
+            .context
+            .device()
+            .physical_device()
+            .surface_present_modes(&surface, SurfaceInfo::default())
+            .unwrap();
+        [PresentMode::Immediate, PresentMode::Mailbox]
+            .into_iter()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or_else(|| {
+                log::warn!("presentation without vsync isn't supported, keeping it on");
+                PresentMode::Fifo
+            })
+    }
+
+    /// What the settings panel starts each frame with.
+    fn settings(&self) -> Settings {
+        Settings {
+            aa_mode: self.aa_mode,
+            face_culling: self.face_culling,
+            wireframe: self.polygon_mode == PolygonMode::Line,
+            depth_heatmap: self.depth_heatmap,
+            debug_view: self.debug_view,
+            lighting_model: self.lighting_model,
+            ambient: self.ambient,
+            fog: self.fog,
+            tonemap: self.tonemap,
+            vsync: self.vsync,
+        }
+    }
+
+    /// Applies what the settings panel changed to `state` and the window, like the keys do.
+    fn apply_settings(&mut self, settings: Settings, state: &mut RenderState, window_id: WindowId) {
+        if settings == self.settings() {
+            return;
+        }
+        if settings.aa_mode != self.aa_mode {
+            self.set_aa_mode(settings.aa_mode);
+            state.set_aa_mode(self);
+        }
+        if settings.face_culling != self.face_culling {
+            self.set_face_culling(settings.face_culling);
+            state.set_face_culling(self);
+        }
+        if settings.wireframe != (self.polygon_mode == PolygonMode::Line) {
+            self.set_polygon_mode(match settings.wireframe {
+                true => PolygonMode::Line,
+                false => PolygonMode::Fill,
+            });
+            state.set_polygon_mode(self);
+        }
+        if settings.depth_heatmap != self.depth_heatmap {
+            self.set_depth_heatmap(settings.depth_heatmap);
+            state.set_depth_heatmap(self);
+        }
+        if settings.vsync != self.vsync {
+            self.set_vsync(settings.vsync);
+            let present_mode = self.present_mode(window_id);
+            let renderer = self.windows.get_renderer_mut(window_id).unwrap();
+            renderer.set_present_mode(present_mode);
+        }
+        self.set_debug_view(settings.debug_view);
+        self.set_lighting_model(settings.lighting_model);
+        self.set_ambient(settings.ambient);
+        self.set_fog(settings.fog);
+        // The panel can't turn tone mapping on or off, only adjust it.
+        if let Some(tonemap) = settings.tonemap {
+            self.set_tonemap(Some(tonemap));
+            state.tonemap = tonemap;
+        }
+        state.debug_view = self.debug_view;
+        state.lighting_model = self.lighting_model;
+        state.ambient = self.ambient;
+        state.fog = self.fog;
+    }
+
+    /// Swaps in scenes sent through `receiver` while running, e.g. from a file watcher on another
+    /// thread, in place of the one passed to [`App::run`]. Only the last scene received since
+    /// the previous frame is uploaded. Settings keyed by model index, like
+    /// [`App::set_material_override`], carry over to the new models.
+    pub fn set_scene_source(&mut self, receiver: Receiver<Scene>) {
+        self.scene_source = Some(receiver);
+    }
+
+    /// Orbits the camera around the scene over time instead of following the mouse, e.g. for
+    /// frame sinks, which get no input. Off by default.
+    pub fn set_auto_orbit(&mut self, auto_orbit: bool) {
+        self.auto_orbit = auto_orbit;
+    }
+
+    /// Whether [`App::run`] starts the camera looking at the scene's bounds, close enough for it
+    /// to fill the window. Otherwise it starts at `(0, 1, 3)` looking at the origin, whatever the
+    /// scene's size. On by default.
+    pub fn set_fit_camera(&mut self, fit_camera: bool) {
+        self.fit_camera = fit_camera;
+    }
+
+    /// Initial speeds of the fly camera the F key switches to, in units per second and radians
+    /// per pixel dragged. Scrolling changes the movement speed while flying.
+    pub fn set_fly_speeds(&mut self, move_speed: f32, look_speed: cgmath::Rad<f32>) {
+        self.fly_move_speed = move_speed;
+        self.fly_look_speed = look_speed;
+    }
+
+    /// Flies the camera along `path` instead of orbiting, looping in windows and played once by
+    /// frame sinks. While running, R adds the current camera to the path as a waypoint and P
+    /// toggles playback.
+    pub fn set_camera_path(&mut self, path: Option<CameraPath>) {
+        self.camera_path = path;
+    }
+
+    /// The path set with [`App::set_camera_path`], plus the waypoints recorded since.
+    pub fn camera_path(&self) -> Option<&CameraPath> {
+        self.camera_path.as_ref()
+    }
+
+    /// Where S saves the camera path while running, to be loaded with [`CameraPath::load`].
+    pub fn set_camera_path_file(&mut self, file: Option<PathBuf>) {
+        self.camera_path_file = file;
+    }
+
+    /// Vertical field of view, clamped to 10–120 degrees. It can also be changed while running
+    /// with the +/- keys, or the scroll wheel with [`App::set_auto_orbit`].
+    pub fn set_fovy(&mut self, fovy: impl Into<Deg<f32>>) {
+        self.fovy = Deg(fovy.into().0.clamp(MIN_FOVY.0, MAX_FOVY.0));
+    }
+
+    /// Winding of front-facing triangles. Defaults to counter-clockwise, as specified by glTF.
+    pub fn set_front_face(&mut self, front_face: FrontFace) {
+        self.front_face = front_face;
+    }
+
+    /// Which faces are culled in the main pass, back faces by default. `C` cycles through the
+    /// options while running.
+    pub fn set_face_culling(&mut self, face_culling: FaceCulling) {
+        self.face_culling = face_culling;
+    }
+
+    /// How the main pass rasterizes triangles, [`PolygonMode::Line`] by default to show the mesh
+    /// topology. `W` switches between it and [`PolygonMode::Fill`] while running.
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.polygon_mode = polygon_mode;
+    }
+
+    /// Skins models with `animation`, which must have been loaded from the same file and scene
+    /// as the one passed to [`App::run`].
+    pub fn set_animation(&mut self, animation: SceneAnimation) {
+        self.animation = Some(Arc::new(animation));
+    }
+
+    /// Enabled where supported, for point sizes and [`App::set_stats_callback`]. Without dynamic
+    /// rendering, passes fall back to render passes and framebuffers.
+    const OPTIONAL_FEATURES: Features = Features {
+        large_points: true,
+        pipeline_statistics_query: true,
+        dynamic_rendering: true,
+        ..Features::empty()
+    };
+
+    /// Enabled where supported, like [`App::OPTIONAL_FEATURES`]. Vulkan 1.3 devices have dynamic
+    /// rendering without the extension.
+    const OPTIONAL_EXTENSIONS: DeviceExtensions = DeviceExtensions {
+        khr_dynamic_rendering: true,
+        ..DeviceExtensions::empty()
+    };
+
+    fn vulkano_config(validation: ValidationMode) -> VulkanoConfig {
+        let mut config = VulkanoConfig {
+            device_extensions: DeviceExtensions {
+                khr_swapchain: true,
+                // khr_acceleration_structure: true,
+                // khr_ray_tracing_pipeline: true,
+                // khr_deferred_host_operations: true,
+                ..DeviceExtensions::empty()
+            },
+            device_features: Features {
+                fill_mode_non_solid: true,
+                ..Features::empty()
+            },
+            ..Default::default()
+        };
+        // The default filter only looks at the extensions.
+        let (extensions, features) = (config.device_extensions, config.device_features);
+        config.device_filter_fn = Arc::new(move |p| {
+            p.supported_extensions().contains(&extensions)
+                && p.supported_features().contains(&features)
+        });
+        config
+            .instance_create_info
+            .enabled_extensions
+            .ext_swapchain_colorspace = true;
+        if validation == ValidationMode::Debug {
+            Self::enable_validation(&mut config);
+        }
+        config
+    }
+
+    /// Checks for what [`VulkanoContext::new`] would panic on: loading the library, creating the
+    /// instance and finding a device with everything `config` requires. Returns the
+    /// [`App::OPTIONAL_EXTENSIONS`] and [`App::OPTIONAL_FEATURES`] the device it picks supports.
+    fn check_support(config: &VulkanoConfig) -> Result<(DeviceExtensions, Features), AppError> {
+        let instance = Instance::new(
+            VulkanLibrary::new()?,
+            InstanceCreateInfo {
+                flags: config.instance_create_info.flags,
+                enabled_extensions: config.instance_create_info.enabled_extensions,
+                ..Default::default()
+            },
+        )?;
+        let mut missing = None;
+        let mut supported = Vec::new();
+        for device in instance.enumerate_physical_devices()? {
+            let extensions = config
+                .device_extensions
+                .difference(device.supported_extensions());
+            let features = config
+                .device_features
+                .difference(device.supported_features());
+            let unsupported: Vec<_> = (extensions.into_iter().chain(features))
+                .filter_map(|(name, missing)| missing.then_some(name))
+                .collect();
+            if unsupported.is_empty() {
+                supported.push(device);
+                continue;
+            }
+            missing.get_or_insert(AppError::Unsupported {
+                device: device.properties().device_name.clone(),
+                missing: unsupported,
+            });
+        }
+        // Picked like `VulkanoContext::new` does.
+        match supported
+            .into_iter()
+            .min_by_key(|p| (config.device_priority_fn)(p))
+        {
+            Some(device) => Ok((
+                device
+                    .supported_extensions()
+                    .intersection(&Self::OPTIONAL_EXTENSIONS),
+                device
+                    .supported_features()
+                    .intersection(&Self::OPTIONAL_FEATURES),
+            )),
+            None => Err(missing.unwrap_or(AppError::NoDevice)),
+        }
+    }
+
+    /// Enables the validation layer and a messenger logging its messages, if available.
+    fn enable_validation(config: &mut VulkanoConfig) {
+        let Ok(library) = VulkanLibrary::new() else {
+            // Creating the context reports this.
+            return;
+        };
+        let has_layer = library
+            .layer_properties()
+            .is_ok_and(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER));
+        if has_layer {
+            config
+                .instance_create_info
+                .enabled_layers
+                .push(VALIDATION_LAYER.to_owned());
+        } else {
+            log::warn!("{VALIDATION_LAYER} isn't installed, running without validation");
+        }
+        if !library.supported_extensions().ext_debug_utils {
+            log::warn!("debug utils aren't supported, validation messages won't be logged");
+            return;
+        }
+        config
+            .instance_create_info
+            .enabled_extensions
+            .ext_debug_utils = true;
+        // SAFETY: The callback only logs, without calling into Vulkan.
+        let callback = unsafe {
+            DebugUtilsMessengerCallback::new(|severity, _, data| {
+                let id = data.message_id_name.unwrap_or("unnamed");
+                log::log!(target: "vulkan", log_level(severity), "{id}: {}", data.message);
+            })
+        };
+        config.debug_create_info = Some(DebugUtilsMessengerCreateInfo {
+            message_severity: DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING
+                | DebugUtilsMessageSeverity::INFO,
+            message_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+        });
+    }
+
+    /// Drops every window and recreates the device and allocators from scratch.
+    fn recreate_device(&mut self) -> Result<(), AppError> {
+        self.windows = VulkanoWindows::default();
+        let App {
+            context,
+            windows,
+            command_buffer_allocator,
+            ..
+        } = Self::with_validation(self.validation)?;
+        self.context = context;
+        self.windows = windows;
+        self.command_buffer_allocator = command_buffer_allocator;
+        self.descriptor_set_allocator =
+            new_descriptor_set_allocator(self.context.device(), self.descriptor_set_count);
+        Ok(())
+    }
+
+    /// How many descriptor sets each pool of the allocator holds. `None`, the default, sizes them
+    /// by the model count of the scene being rendered, between [`MIN_DESCRIPTOR_SETS_PER_POOL`]
+    /// and [`MAX_DESCRIPTOR_SETS_PER_POOL`], so that each per-model layout mostly fits one pool.
+    pub fn set_descriptor_sets_per_pool(&mut self, set_count: Option<usize>) {
+        self.descriptor_sets_per_pool = set_count;
+    }
+
+    /// Swaps in an allocator with pools sized for `scene` if the current one's differ. Sets
+    /// allocated before keep their pools alive.
+    fn fit_descriptor_pools(&mut self, scene: &SceneData) {
+        let set_count = descriptor_sets_per_pool(self.descriptor_sets_per_pool, scene.models.len());
+        if set_count != self.descriptor_set_count {
+            log::debug!("{set_count} descriptor sets per pool");
+            self.descriptor_set_allocator =
+                new_descriptor_set_allocator(self.context.device(), set_count);
+            self.descriptor_set_count = set_count;
+        }
+    }
+
+    fn create_window(&mut self, event_loop: &EventLoopWindowTarget<()>) -> WindowId {
+        // Copied from to fill the transmission background, see `Attachments::background`. Without
+        // tone mapping, the main pass may also resolve into it.
+        let image_usage = resolve_target_usage(self.context.device(), ImageUsage::TRANSFER_SRC);
+        let window_id = self.windows.create_window(
+            event_loop,
+            &self.context,
+            &WindowDescriptor {
+                width: 1280.0,
+                height: 720.0,
+                title: WINDOW_TITLE.to_string(),
+                resizable: true,
+                ..Default::default()
+            },
+            |create_info| {
+                create_info.image_format = EDR_FORMAT;
+                create_info.image_color_space = EDR_COLOR_SPACE;
+                create_info.image_usage |= image_usage;
+            },
+        );
+
+        log::info!(
+            "supported surface formats: {:?}",
+            self.surface_formats(window_id)
+        );
+        let present_mode = self.present_mode(window_id);
+        // Recreates the swapchain on the first frame if not the descriptor's.
+        self.windows
+            .get_renderer_mut(window_id)
+            .unwrap()
+            .set_present_mode(present_mode);
+
+        #[cfg(target_os = "macos")]
+        unsafe {
+            let window_handle = self
+                .windows
+                .get_window(window_id)
+                .unwrap()
+                .window_handle()
+                .unwrap()
+                .as_raw();
+            enable_edr(window_handle);
+        }
+
+        window_id
+    }
+
+    /// Triangle count and geometry buffer sizes of the scene last passed to [`App::run`], computed
+    /// once when it started. Use [`SceneFootprint::of`] before running.
+    pub fn scene_footprint(&self) -> Option<SceneFootprint> {
+        self.scene_footprint
+    }
+
+    /// Delta time and frame rate of [`App::run`]'s frames. Frame sinks advance it by their fixed
+    /// frame time.
+    pub fn frame_clock(&self) -> &FrameClock {
+        &self.frame_clock
+    }
+
+    /// Runs the event loop until the window is closed. F1 shows a panel over the window with the
+    /// render settings the keys change, and F2 the frame rate, frame time and triangle count.
+    ///
+    /// If the device is lost, all device-dependent resources are rebuilt and rendering resumes.
+    /// After [`MAX_DEVICE_LOST_RECOVERIES`] losses, [`RunError::DeviceLost`] is returned instead.
+    pub fn run(&mut self, scene: &Scene) -> Result<(), RunError> {
+        let scene = SceneData::new(self, scene);
+        self.run_scene_data(&scene)
+    }
+
+    /// [`App::run`] with the scene already converted.
+    fn run_scene_data(&mut self, scene: &SceneData) -> Result<(), RunError> {
+        let footprint = scene.footprint();
+        self.scene_footprint = Some(footprint);
+        if !footprint.fits(self.context.device().physical_device()) {
+            log::warn!(
+                "scene geometry takes {} bytes, more than device-local memory",
+                footprint.total_bytes()
+            );
+        }
+
+        self.fit_descriptor_pools(scene);
+
+        let event_loop = EventLoop::new()?;
+        event_loop.set_control_flow(self.control_flow);
+        // Frame sinks and animations need every frame regardless.
+        let continuous = self.control_flow == ControlFlow::Poll
+            || self.frame_sink.is_some()
+            || self.animation.is_some();
+        // Whether input changed the view since the last frame, when not `continuous`.
+        let mut dirty = true;
+
+        let mut window_id = self.create_window(&event_loop);
+        let mut state = RenderState::for_window(self, window_id, scene)?;
+        let mut ui = Ui::new(self, window_id);
+
+        // Playing back `camera_path` since then, instead of orbiting.
+        let mut playback = self.camera_path.is_some().then(Instant::now);
+        let mut last_waypoint: Option<Instant> = None;
+        // For `max_fps`.
+        let mut last_present: Option<Instant> = None;
+
+        let render_start = Instant::now();
+        let proj = |fovy, extent| Camera::perspective(fovy, extent, Z_NEAR, Z_FAR);
+        let mut controller = match self.fit_camera {
+            true => {
+                let [width, height] = state.extent.map(|e| e.max(1) as f32);
+                CameraController::fit_to_bounds(&state.scene_bounds(), self.fovy, width / height)
+            }
+            false => CameraController::default(),
+        };
+        let auto_orbit = self.auto_orbit;
+        // Replacing `controller` while flying.
+        let mut fly: Option<FlyCamera> = None;
+        let camera_fn =
+            |controller: &CameraController, fly: Option<&FlyCamera>, fovy, elapsed: f32, extent| {
+                if let Some(fly) = fly {
+                    return fly.camera(proj(fovy, extent));
+                }
+                match auto_orbit {
+                    true => controller.orbited(cgmath::Rad(elapsed * 0.5)),
+                    false => *controller,
+                }
+                .camera(proj(fovy, extent))
+            };
+
+        let mut device_losses = 0;
+        let mut result = Ok(());
+
+        // The last scene from `scene_source`, replacing `scene`.
+        let mut swapped: Option<SceneData> = None;
+
+        event_loop.run(|event, elwt| match event {
+            Event::WindowEvent { event, .. }
+                if ui.on_window_event(self.windows.get_window(window_id).unwrap(), &event) =>
+            {
+                dirty = true;
+            }
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => elwt.exit(),
+                WindowEvent::Resized(..) => {
+                    self.windows.get_renderer_mut(window_id).unwrap().resize();
+                    dirty = true;
+                }
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    self.windows.get_renderer_mut(window_id).unwrap().resize();
+                    dirty = true;
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    // Scrolling up zooms in.
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                    };
+                    match (&mut fly, auto_orbit) {
+                        (Some(fly), _) => fly.scroll(lines),
+                        (None, true) => self.set_fovy(self.fovy - FOVY_STEP * lines),
+                        (None, false) => controller.scroll(lines),
+                    }
+                    dirty = true;
+                }
+                WindowEvent::MouseInput {
+                    state: button_state,
+                    button,
+                    ..
+                } => match &mut fly {
+                    Some(fly) => fly.mouse_input(button, button_state),
+                    None => controller.mouse_input(button, button_state),
+                },
+                WindowEvent::CursorMoved { position, .. } => {
+                    let position = [position.x, position.y];
+                    dirty |= match &mut fly {
+                        Some(fly) => fly.cursor_moved(position),
+                        None => controller.cursor_moved(position) && !auto_orbit,
+                    };
+                }
+                WindowEvent::KeyboardInput { event, .. }
+                    if fly.as_mut().is_some_and(|fly| fly.keyboard_input(&event)) =>
+                {
+                    dirty = true;
+                }
+                WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() => {
+                    dirty = true;
+                    match event.physical_key {
+                        PhysicalKey::Code(KeyCode::F1) => ui.toggle_settings(),
+                        PhysicalKey::Code(KeyCode::F2) => ui.toggle_stats(),
+                        PhysicalKey::Code(KeyCode::Equal | KeyCode::NumpadAdd) => {
+                            self.set_fovy(self.fovy - FOVY_STEP);
+                        }
+                        PhysicalKey::Code(KeyCode::Minus | KeyCode::NumpadSubtract) => {
+                            self.set_fovy(self.fovy + FOVY_STEP);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyA) => {
+                            self.set_aa_mode(self.aa_mode.next());
+                            log::info!("anti-aliasing: {:?}", self.aa_mode);
+                            state.set_aa_mode(self);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyD) => {
+                            self.set_depth_heatmap(!self.depth_heatmap);
+                            log::info!("depth heatmap: {}", self.depth_heatmap);
+                            state.set_depth_heatmap(self);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyC) => {
+                            self.set_face_culling(self.face_culling.next());
+                            log::info!("face culling: {:?}", self.face_culling);
+                            state.set_face_culling(self);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyW) => {
+                            self.set_polygon_mode(match self.polygon_mode {
+                                PolygonMode::Line => PolygonMode::Fill,
+                                _ => PolygonMode::Line,
+                            });
+                            log::info!("polygon mode: {:?}", self.polygon_mode);
+                            state.set_polygon_mode(self);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyV) => {
+                            self.set_debug_view(self.debug_view.next());
+                            state.debug_view = self.debug_view;
+                            log::info!("debug view: {:?}", self.debug_view);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyL) => {
+                            self.set_lighting_model(self.lighting_model.toggled());
+                            state.lighting_model = self.lighting_model;
+                            log::info!("lighting model: {:?}", self.lighting_model);
+                        }
+                        PhysicalKey::Code(key @ (KeyCode::BracketLeft | KeyCode::BracketRight)) => {
+                            let scale = match key {
+                                KeyCode::BracketLeft => 1.0 / AMBIENT_STEP,
+                                _ => AMBIENT_STEP,
+                            };
+                            let color = self.ambient.color.map(|c| c * scale);
+                            self.set_ambient(AmbientSettings { color, ..self.ambient });
+                            state.ambient = self.ambient;
+                            log::info!("ambient color: {:?}", self.ambient.color);
+                        }
+                        PhysicalKey::Code(
+                            key @ (KeyCode::Digit1
+                            | KeyCode::Digit2
+                            | KeyCode::Digit3
+                            | KeyCode::Digit4
+                            | KeyCode::Digit5
+                            | KeyCode::Digit6
+                            | KeyCode::Digit7
+                            | KeyCode::Digit8
+                            | KeyCode::Digit9),
+                        ) => {
+                            // The digit keys are declared in order.
+                            let layer = key as u32 - KeyCode::Digit1 as u32;
+                            self.set_active_layers(self.active_layers ^ (1 << layer));
+                            state.active_layers = self.active_layers;
+                            log::info!("active layers: {:#b}", self.active_layers);
+                        }
+                        PhysicalKey::Code(KeyCode::KeyO) => {
+                            // 1, 0.5, 0 and around again.
+                            let occlusion_strength = match self.ambient.occlusion_strength {
+                                s if s > 0.5 => 0.5,
+                                s if s > 0.0 => 0.0,
+                                _ => 1.0,
+                            };
+                            self.set_ambient(AmbientSettings {
+                                occlusion_strength,
+                                ..self.ambient
+                            });
+                            state.ambient = self.ambient;
+                            log::info!("ambient occlusion strength: {occlusion_strength}");
+                        }
+                        PhysicalKey::Code(key @ (KeyCode::Comma | KeyCode::Period))
+                            if self.tonemap.is_some() =>
+                        {
+                            let scale = match key {
+                                KeyCode::Comma => 1.0 / WHITE_POINT_STEP,
+                                _ => WHITE_POINT_STEP,
+                            };
+                            let mut tonemap = self.tonemap.unwrap();
+                            tonemap.white_point = (tonemap.white_point * scale).max(1.0);
+                            self.set_tonemap(Some(tonemap));
+                            state.tonemap = tonemap;
+                            log::info!("tone-mapping white point: {}", tonemap.white_point);
+                        }
+                        PhysicalKey::Code(key @ (KeyCode::Semicolon | KeyCode::Quote))
+                            if self.tonemap.is_some() =>
+                        {
+                            let step = match key {
+                                KeyCode::Semicolon => -CONTRAST_STEP,
+                                _ => CONTRAST_STEP,
+                            };
+                            let mut tonemap = self.tonemap.unwrap();
+                            tonemap.contrast = (tonemap.contrast + step).max(CONTRAST_STEP);
+                            self.set_tonemap(Some(tonemap));
+                            state.tonemap = tonemap;
+                            log::info!("tone-mapping contrast: {}", tonemap.contrast);
+                        }
+                        PhysicalKey::Code(
+                            KeyCode::Comma | KeyCode::Period | KeyCode::Semicolon | KeyCode::Quote,
+                        ) => {
+                            log::info!("tone mapping is off, see App::set_tonemap");
+                        }
+                        PhysicalKey::Code(KeyCode::KeyI) => {
+                            self.set_vsync(!self.vsync);
+                            let present_mode = self.present_mode(window_id);
+                            let renderer = self.windows.get_renderer_mut(window_id).unwrap();
+                            renderer.set_present_mode(present_mode);
+                            log::info!("present mode: {present_mode:?}");
+                        }
+                        PhysicalKey::Code(KeyCode::KeyG) => {
+                            let fog = match self.fog {
+                                None => Some(FogSettings::default()),
+                                Some(FogSettings {
+                                    color,
+                                    falloff: FogFalloff::Linear { .. },
+                                }) => Some(FogSettings {
+                                    color,
+                                    falloff: FogFalloff::Exponential {
+                                        density: FOG_DENSITY,
+                                    },
+                                }),
+                                Some(_) => None,
+                            };
+                            self.set_fog(fog);
+                            state.fog = fog;
+                            log::info!("fog: {fog:?}");
+                        }
+                        PhysicalKey::Code(KeyCode::KeyR) if playback.is_none() => {
+                            let elapsed = render_start.elapsed().as_secs_f32();
+                            let orbit = if continuous { elapsed } else { 0.0 };
+                            let path = self.camera_path.get_or_insert_with(CameraPath::default);
+                            // Waypoints are as far apart in time as they were recorded.
+                            let time = path.duration()
+                                + last_waypoint.map_or(0.0, |t| t.elapsed().as_secs_f32());
+                            let extent = state.extent;
+                            let camera =
+                                camera_fn(&controller, fly.as_ref(), self.fovy, orbit, extent);
+                            path.push(time, &camera);
+                            last_waypoint = Some(Instant::now());
+                            log::info!(
+                                "camera waypoint {} at {time:.2}s",
+                                path.keyframes().len()
+                            );
+                        }
+                        PhysicalKey::Code(KeyCode::KeyP) => {
+                            playback = match playback {
+                                Some(_) => None,
+                                None if self.camera_path.as_ref().is_some_and(|p| !p.is_empty()) => {
+                                    Some(Instant::now())
+                                }
+                                None => {
+                                    log::warn!("no camera waypoints to play back, record some with R");
+                                    None
+                                }
+                            };
+                        }
+                        PhysicalKey::Code(KeyCode::KeyS) => self.save_camera_path(),
+                        PhysicalKey::Code(KeyCode::KeyF) => {
+                            fly = match fly {
+                                Some(_) => None,
+                                None => Some(FlyCamera::from_controller(
+                                    &controller,
+                                    self.fly_move_speed,
+                                    self.fly_look_speed,
+                                )),
+                            };
+                            log::info!("fly camera: {}", fly.is_some());
+                        }
+                        _ => {}
+                    }
+                }
+                WindowEvent::RedrawRequested => {
+                    state.swap_loaded_textures(self);
+                    let dt = match &self.frame_sink {
+                        Some(sink) => {
+                            self.frame_clock.advance(1.0 / sink.frame_rate);
+                            self.frame_clock.dt()
+                        }
+                        None => self.frame_clock.tick(),
+                    };
+                    if let Some(fly) = &mut fly {
+                        fly.update(dt);
+                    }
+                    let redrawn = match &mut self.frame_sink {
+                        Some(sink) => {
+                            let time = state.frame as f32 / sink.frame_rate;
+                            let camera = match (&self.camera_path, playback) {
+                                (Some(path), Some(_)) => {
+                                    path.camera(time, proj(self.fovy, state.extent))
+                                }
+                                _ => None,
+                            };
+                            let camera = camera.unwrap_or_else(|| {
+                                camera_fn(&controller, fly.as_ref(), self.fovy, time, state.extent)
+                            });
+                            state.capture(&camera, time, &mut sink.callback)
+                        }
+                        None => {
+                            let mut settings = self.settings();
+                            let stats = FrameStats {
+                                fps: self.frame_clock.fps(),
+                                frame_time: dt * 1000.0,
+                                gpu_time: self.frame_clock.gpu_time(),
+                                triangles: state.drawn_triangles.get(),
+                            };
+                            let window = self.windows.get_window(window_id).unwrap();
+                            ui.run(window, &mut settings, &stats);
+                            self.apply_settings(settings, &mut state, window_id);
+                            let renderer = self.windows.get_renderer_mut(window_id).unwrap();
+                            let time = render_start.elapsed().as_secs_f32();
+                            let orbit = if continuous { time } else { 0.0 };
+                            let camera = match (&self.camera_path, playback) {
+                                (Some(path), Some(start)) => {
+                                    let duration = path.duration();
+                                    let elapsed = start.elapsed().as_secs_f32();
+                                    let time = if duration > 0.0 { elapsed % duration } else { 0.0 };
+                                    path.camera(time, proj(self.fovy, state.extent))
+                                }
+                                _ => None,
+                            };
+                            let mut camera = camera.unwrap_or_else(|| {
+                                camera_fn(&controller, fly.as_ref(), self.fovy, orbit, state.extent)
+                            });
+                            let redrawn = match renderer.acquire() {
+                                Ok(before) => {
+                                    // Acquiring recreates the swapchain after a resize.
+                                    let extent = renderer.swapchain_image_view().image().extent();
+                                    if [extent[0], extent[1]] != state.extent {
+                                        state.resize(self, [extent[0], extent[1]]);
+                                        camera.proj = proj(self.fovy, state.extent);
+                                    }
+                                    let renderer =
+                                        self.windows.get_renderer_mut(window_id).unwrap();
+                                    state.redraw(renderer, before, &camera, time, &mut ui)
+                                }
+                                Err(VulkanError::OutOfDate) => Ok(()),
+                                Err(err) => Err(err),
+                            };
+                            if let Some(max_fps) = self.max_fps {
+                                let budget = Duration::from_secs_f32(1.0 / max_fps);
+                                if let Some(last) = last_present {
+                                    std::thread::sleep(budget.saturating_sub(last.elapsed()));
+                                }
+                                last_present = Some(Instant::now());
+                            }
+                            redrawn
+                        }
+                    };
+                    match redrawn {
+                        Ok(()) => {
+                            if let (Some(stats), Some(callback)) =
+                                (state.stats.take(), &mut self.stats_callback)
+                            {
+                                log::debug!("render stats: {stats:?}");
+                                callback(&stats);
+                            }
+                            if let Some(gpu_time) = state.gpu_time.take() {
+                                self.frame_clock.set_gpu_time(gpu_time);
+                                log::debug!(
+                                    "frame time: {:.2} ms, gpu: {gpu_time:.2} ms",
+                                    self.frame_clock.dt() * 1000.0
+                                );
+                            }
+                        }
+                        Err(VulkanError::DeviceLost)
+                            if device_losses < MAX_DEVICE_LOST_RECOVERIES =>
+                        {
+                            device_losses += 1;
+                            log::warn!(
+                                "device lost, recreating device ({device_losses}/{MAX_DEVICE_LOST_RECOVERIES})"
+                            );
+                            if let Err(err) = self.recreate_device() {
+                                result = Err(err.into());
+                                elwt.exit();
+                                return;
+                            }
+                            window_id = self.create_window(elwt);
+                            let scene = swapped.as_ref().unwrap_or(scene);
+                            state = match RenderState::for_window(self, window_id, scene) {
+                                Ok(state) => state,
+                                Err(err) => {
+                                    result = Err(err.into());
+                                    elwt.exit();
+                                    return;
+                                }
+                            };
+                            ui = Ui::new(self, window_id);
+                        }
+                        Err(VulkanError::DeviceLost) => {
+                            result = Err(RunError::DeviceLost(device_losses));
+                            elwt.exit();
+                        }
+                        Err(err) => {
+                            result = Err(err.into());
+                            elwt.exit();
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::AboutToWait => {
+                let received = self.scene_source.as_ref().and_then(|r| r.try_iter().last());
+                if let Some(new_scene) = received {
+                    log::info!("swapping in a new scene");
+                    // Extras were read from the file of the previous scene.
+                    self.model_extras.clear();
+                    let new_scene = SceneData::new(self, &new_scene);
+                    self.scene_footprint = Some(new_scene.footprint());
+                    self.fit_descriptor_pools(&new_scene);
+                    let new_scene = swapped.insert(new_scene);
+                    // Frames still executing keep the old buffers alive until they finish.
+                    state = match RenderState::for_window(self, window_id, new_scene) {
+                        Ok(state) => state,
+                        Err(err) => {
+                            result = Err(err.into());
+                            elwt.exit();
+                            return;
+                        }
+                    };
+                    dirty = true;
+                }
+                if self.scene_source.is_some() && self.control_flow != ControlFlow::Poll {
+                    elwt.set_control_flow(ControlFlow::WaitUntil(
+                        Instant::now() + SCENE_POLL_INTERVAL,
+                    ));
+                }
+
+                if continuous
+                    || playback.is_some()
+                    || fly.as_ref().is_some_and(FlyCamera::is_moving)
+                    || !state.pending_textures.is_empty()
+                    || ui.take_repaint()
+                    || std::mem::take(&mut dirty)
+                {
+                    self.windows.get_window(window_id).unwrap().request_redraw();
+                }
+            }
+            _ => {}
+        })?;
+
+        result
+    }
+
+    /// The `(Format, ColorSpace)` pairs a window's surface supports.
+    pub fn surface_formats(&self, window_id: WindowId) -> Vec<(Format, ColorSpace)> {
+        let surface = self.windows.get_renderer(window_id).unwrap().surface();
+        self.context
+            .device()
+            .physical_device()
+            .surface_formats(&surface, SurfaceInfo::default())
+            .unwrap()
+    }
+
+    /// Loads the model file at `path` and runs it. glTF files run their first scene, see
+    /// [`App::run`], and unlike `run` this also reads a second UV set where present and culls
+    /// mirrored nodes correctly. Other files run their models, see [`model_file::load_models`]
+    /// and [`App::run_meshes`].
+    pub fn run_file(&mut self, path: &Path) -> Result<(), RunError> {
+        if !model_file::is_gltf(path) {
+            let meshes = model_file::load_models(path).map_err(RunError::Load)?;
+            return self.run_meshes(&meshes);
+        }
+        let scenes = easy_gltf::load(path).map_err(RunError::Load)?;
+        let scene = scenes.first().ok_or(RunError::NoScene)?;
+        self.model_extras =
+            gltf::load_model_extras(path, 0).map_err(|err| RunError::Load(err.into()))?;
+        let result = self.run(scene);
+        self.model_extras.clear();
+        result
+    }
+
+    /// Runs `meshes` like a scene holding them, one model each at the origin, see [`App::run`].
+    /// Fails with [`RunError::Load`] if any isn't valid, see [`Mesh::validate`].
+    pub fn run_meshes(&mut self, meshes: &[Mesh]) -> Result<(), RunError> {
+        for mesh in meshes {
+            mesh.validate().map_err(|err| RunError::Load(err.into()))?;
+        }
+        let scene = SceneData::from_meshes(meshes, self.coordinate_system);
+        self.run_scene_data(&scene)
+    }
+
+    /// Renders one frame of `scene` seen by `camera`, with the animation posed at `time` seconds
+    /// rather than played back. Returns the texels, in the format of [`App::set_frame_sink`]. No
+    /// window is needed, and the same time always gives the same pose.
+    pub fn render_pose(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+    ) -> Result<Vec<u8>, RenderError> {
+        self.capture_frame(scene, camera, extent, time, EDR_FORMAT)
+    }
+
+    /// Like [`App::render_pose`], but as tightly packed RGBA8 sRGB texels, with colors outside
+    /// of `[0, 1]` clamped, e.g. for reference images.
+    pub fn render_to_image(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+    ) -> Result<Vec<u8>, RenderError> {
+        self.capture_frame(scene, camera, extent, time, SDR_FORMAT)
+    }
+
+    /// Writes [`App::render_to_image`] to a PNG file at `path`.
+    pub fn save_png(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SaveImageError> {
+        let texels = self.render_to_image(scene, camera, extent, time)?;
+        Ok(image_file::write_png(path, &texels, extent)?)
+    }
+
+    fn capture_frame(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+        format: Format,
+    ) -> Result<Vec<u8>, RenderError> {
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let mut state = RenderState::new(self, format, extent, true, scene)?;
+        let mut texels = Vec::new();
+        state.capture(camera, time, |data, _| texels = data.to_vec())?;
+        Ok(texels)
+    }
+
+    /// Like [`App::render_pose`], but reads back only the `size` pixels at `offset`, clamped to
+    /// `extent`. Returns the texels and the clamped size, which is empty if the region lies
+    /// outside of the frame.
+    #[allow(clippy::too_many_arguments)]
+    pub fn capture_region(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+        offset: [u32; 2],
+        size: [u32; 2],
+    ) -> Result<(Vec<u8>, [u32; 2]), RenderError> {
+        let (offset, size) = clamp_region(offset, size, extent);
+        if size.contains(&0) {
+            return Ok((Vec::new(), size));
+        }
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let mut state = RenderState::new(self, EDR_FORMAT, extent, true, scene)?;
+        let mut texels = Vec::new();
+        state.capture_region(camera, time, offset, size, |data| texels = data.to_vec())?;
+        Ok((texels, size))
+    }
+
+    /// Renders `scene` from `position` into the six faces of a cubemap of `size` pixels, e.g. for
+    /// reflection probes or environment lighting. The animation is posed at `time`. The image has
+    /// six `R16G16B16A16_SFLOAT` layers, like [`App::set_frame_sink`]'s frames, and can be sampled
+    /// through a cube view. No window is needed.
+    pub fn render_cubemap(
+        &mut self,
+        scene: &Scene,
+        position: cgmath::Point3<f32>,
+        size: u32,
+        time: f32,
+    ) -> Result<Arc<Image>, RenderError> {
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let mut state = RenderState::new(self, EDR_FORMAT, [size, size], true, scene)?;
+        let cubemap = Image::new(
+            self.memory_allocator(),
+            ImageCreateInfo {
+                flags: ImageCreateFlags::CUBE_COMPATIBLE,
+                image_type: ImageType::Dim2d,
+                extent: [size, size, 1],
+                array_layers: 6,
+                format: EDR_FORMAT,
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let (face_image, _) = state.readback.clone().unwrap();
+        let face_layer = |layer: u32| ImageSubresourceLayers {
+            aspects: ImageAspects::COLOR,
+            mip_level: 0,
+            array_layers: layer..layer + 1,
+        };
+
+        for face in 0..6 {
+            let camera = Camera::cube_face(position, face);
+            // Faces point in unrelated directions, so none reuses another's history.
+            state.previous_view_proj = None;
+            state.accumulated = 0;
+            state.frame = 0;
+
+            let before = sync::now(self.context.device().clone()).boxed();
+            state
+                .render(before, face_image.clone(), &camera, time, |builder| {
+                    builder
+                        .blit_image(BlitImageInfo {
+                            regions: [ImageBlit {
+                                src_subresource: face_layer(0),
+                                src_offsets: [[0, 0, 0], [size, size, 1]],
+                                dst_subresource: face_layer(face as u32),
+                                // Flipped horizontally, see `Camera::cube_face`.
+                                dst_offsets: [[size, 0, 0], [0, size, 1]],
+                                ..Default::default()
+                            }]
+                            .into(),
+                            filter: Filter::Nearest,
+                            ..BlitImageInfo::images(face_image.image().clone(), cubemap.clone())
+                        })
+                        .unwrap();
+                })?
+                .wait(None)
+                .map_err(Validated::unwrap)?;
+        }
+
+        Ok(cubemap)
+    }
+
+    /// Renders each model of `scene` on its own into a square tile of `tile_size` pixels, framed
+    /// to fit, and lays the tiles out in rows of `columns`. Models without vertices get no tile.
+    /// The animation is posed at `time`. Returns the texels, in the format of
+    /// [`App::set_frame_sink`], and the extent of the whole grid. No window is needed. Fails with
+    /// [`RenderError::EmptyGrid`] if `columns` or `tile_size` is zero.
+    pub fn render_thumbnails(
+        &mut self,
+        scene: &Scene,
+        columns: u32,
+        tile_size: u32,
+        time: f32,
+    ) -> Result<(Vec<u8>, [u32; 2]), RenderError> {
+        if columns == 0 || tile_size == 0 {
+            return Err(RenderError::EmptyGrid);
+        }
+        let tile = [tile_size, tile_size];
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let mut state = RenderState::new(self, EDR_FORMAT, tile, true, scene)?;
+
+        let rows = (state.models.len() as u32).div_ceil(columns);
+        let extent = [columns * tile_size, rows * tile_size];
+        let texel_size = EDR_FORMAT.block_size() as usize;
+        let row_size = tile_size as usize * texel_size;
+        let mut grid = vec![0; extent[0] as usize * extent[1] as usize * texel_size];
+
+        for i in 0..state.models.len() {
+            let camera = Camera::fit_to_bounds(&state.models[i].bounds, self.fovy, 1.0);
+            // Start every tile without history from the previous one.
+            state.isolated = Some(i);
+            state.previous_view_proj = None;
+            state.accumulated = 0;
+            state.frame = 0;
+
+            let [column, row] = [i as u32 % columns, i as u32 / columns];
+            state.capture(&camera, time, |texels, _| {
+                for (y, texels) in texels.chunks_exact(row_size).enumerate() {
+                    let grid_y = (row * tile_size) as usize + y;
+                    let start =
+                        (grid_y * extent[0] as usize + (column * tile_size) as usize) * texel_size;
+                    grid[start..start + row_size].copy_from_slice(texels);
+                }
+            })?;
+        }
+
+        Ok((grid, extent))
+    }
+
+    /// Renders only the world-space normals of `scene` seen by `camera`, as RGBA8 texels mapped
+    /// to `[0, 1]`, with the animation posed at `time`. Unlike [`DebugView::Normals`] this needs
+    /// no window and skips the rest of the frame; the alpha is zero wherever nothing was drawn.
+    pub fn render_normals(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+    ) -> Result<Vec<u8>, RenderError> {
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let mut state = RenderState::new(self, EDR_FORMAT, extent, false, scene)?;
+        let normals = NormalsPipeline::new(
+            self,
+            state.queue.clone(),
+            self.depth_stencil_format(),
+            extent,
+        );
+        Ok(state.capture_normals(&normals, camera, time)?)
+    }
+
+    /// Writes [`App::render_normals`] to a PNG file at `path`.
+    pub fn save_normals(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        extent: [u32; 2],
+        time: f32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SaveImageError> {
+        let texels = self.render_normals(scene, camera, extent, time)?;
+        Ok(image_file::write_png(path, &texels, extent)?)
+    }
+
+    /// Positions of `scene`'s model at index `model`, posed at `time` and projected by `camera`,
+    /// in normalized device coordinates, for checking skinning and animation. They are
+    /// computed like the main pass' vertex shader does, without the TAA jitter. Fails with
+    /// [`RenderError::NoModel`] if `scene` has no such model.
+    pub fn capture_transformed_vertices(
+        &mut self,
+        scene: &Scene,
+        model: usize,
+        camera: &Camera,
+        time: f32,
+    ) -> Result<Vec<[f32; 3]>, RenderError> {
+        if model >= scene.models.len() {
+            return Err(RenderError::NoModel {
+                index: model,
+                count: scene.models.len(),
+            });
+        }
+        let scene = &SceneData::new(self, scene);
+        self.fit_descriptor_pools(scene);
+        let state = RenderState::new(self, EDR_FORMAT, [1, 1], false, scene)?;
+        if let Some(animation) = &self.animation {
+            state.update_joint_palettes(animation, time, true);
+            state.update_morph_weights(animation, time);
+        }
+        // Empty models aren't uploaded, see `MyModel::scene_index`.
+        let Some(model) = state.models.iter().find(|m| m.scene_index == model) else {
+            return Ok(Vec::new());
+        };
+        Ok(VertexCapturePipeline::new(state.queue.clone()).capture(
+            self,
+            &model.vertex_buffer,
+            model.joint_palette.clone(),
+            &model.morph_targets,
+            camera,
+        )?)
+    }
+
+    /// Writes the triangles of `scene` to a Wavefront OBJ file as they would be uploaded,
+    /// converted from [`App::set_coordinate_system`] and in the bind pose, with positions,
+    /// normals and the first set of texture coordinates. No GPU is needed.
+    pub fn export_obj(&self, scene: &Scene, path: impl AsRef<Path>) -> std::io::Result<()> {
+        obj::export_obj(&SceneData::new(self, scene).models, path)
+    }
+
+    fn save_camera_path(&self) {
+        let (Some(path), Some(file)) = (&self.camera_path, &self.camera_path_file) else {
+            log::warn!("no camera path or file to save it to, see App::set_camera_path_file");
+            return;
+        };
+        match path.save(file) {
+            Ok(()) => log::info!("saved camera path to {}", file.display()),
+            Err(err) => log::error!("{err}"),
+        }
+    }
+
+    /// Depth/stencil format of the main pass; the stencil aspect is used for outlines.
+    fn depth_stencil_format(&self) -> Format {
+        let physical_device = self.context.device().physical_device();
+        [Format::D32_SFLOAT_S8_UINT, Format::D24_UNORM_S8_UINT]
+            .into_iter()
+            .find(|&format| {
+                physical_device
+                    .format_properties(format)
+                    .unwrap()
+                    .optimal_tiling_features
+                    .intersects(FormatFeatures::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .expect("no supported depth/stencil format")
+    }
+
+    pub(crate) fn memory_allocator(&self) -> Arc<StandardMemoryAllocator> {
+        self.context.memory_allocator().clone()
+    }
+
+    /// Queue for staging copies. `VulkanoContext` doesn't create transfer-only queues, but its
+    /// compute queue comes from a separate family when the device has one, and compute families
+    /// also take transfers. Falls back to the graphics queue.
+    pub(crate) fn transfer_queue(&self) -> Arc<Queue> {
+        self.context.compute_queue().clone()
+    }
+
+    /// Families that access resources uploaded on [`App::transfer_queue`], one if it is the
+    /// graphics queue's.
+    pub(crate) fn upload_queue_families(&self) -> Vec<u32> {
+        let graphics = self.context.graphics_queue().queue_family_index();
+        let transfer = self.transfer_queue().queue_family_index();
+        if graphics == transfer {
+            vec![graphics]
+        } else {
+            vec![graphics, transfer]
+        }
+    }
+}
+
+impl Default for App {
+    /// Panics where [`App::new`] fails.
+    fn default() -> Self {
+        Self::new().expect("failed to create app")
+    }
+}
+
+/// The main pass' attachments, sized like the frame and rebuilt when it is resized, see
+/// [`RenderState::resize`].
+struct Attachments {
+    depth: Arc<ImageView>,
+    /// Color and motion, resolved into the other targets. `None` without MSAA, which renders
+    /// into those directly.
+    msaa: Option<(Arc<ImageView>, Arc<ImageView>)>,
+    /// Resolved motion vectors of the last frame, for temporal effects.
+    motion: Arc<ImageView>,
+    /// Rendered into instead of the swapchain and copied to the buffer, with a frame sink.
+    readback: Option<(Arc<ImageView>, Subbuffer<[u8]>)>,
+    /// What transmissive models see through them: a copy of the main pass after the opaque
+    /// models, which they are drawn over in a second pass. Only with forward shading of a scene
+    /// that has any.
+    background: Option<Arc<ImageView>>,
+    format: Format,
+    samples: SampleCount,
+}
+
+impl Attachments {
+    /// For `format` frames of `extent`. With `readback`, for [`RenderState::capture`], and with
+    /// a background if the scene is `transmissive`.
+    fn new(
+        app: &App,
+        format: Format,
+        extent: [u32; 2],
+        samples: SampleCount,
+        readback: bool,
+        transmissive: bool,
+    ) -> Attachments {
+        let image = |format, usage| {
+            Image::new(
+                app.memory_allocator(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    extent: [extent[0], extent[1], 1],
+                    format,
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap()
+        };
+        let background = transmissive.then(|| {
+            ImageView::new_default(image(
+                format,
+                ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ))
+            .unwrap()
+        });
+        let (depth, msaa) = Self::multisampled(app, extent, samples, background.is_some());
+        // The MSAA images are resolved into these.
+        let device = app.context.device();
+        let motion = ImageView::new_default(image(
+            MOTION_FORMAT,
+            resolve_target_usage(device, ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED),
+        ))
+        .unwrap();
+        let readback = readback.then(|| {
+            let image = image(
+                format,
+                resolve_target_usage(
+                    device,
+                    ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                ),
+            );
+            let buffer = Buffer::new_slice(
+                app.memory_allocator(),
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                    ..Default::default()
+                },
+                extent[0] as u64 * extent[1] as u64 * format.block_size(),
+            )
+            .unwrap();
+            (ImageView::new_default(image).unwrap(), buffer)
+        });
+        Attachments {
+            depth,
+            msaa,
+            motion,
+            readback,
+            background,
+            format,
+            samples,
+        }
+    }
+
+    /// The depth image and the MSAA images, which have `samples` per texel.
+    fn multisampled(
+        app: &App,
+        extent: [u32; 2],
+        samples: SampleCount,
+        transmissive: bool,
+    ) -> (Arc<ImageView>, Option<(Arc<ImageView>, Arc<ImageView>)>) {
+        let image = |format, usage| {
+            ImageView::new_default(
+                Image::new(
+                    app.memory_allocator(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [extent[0], extent[1], 1],
+                        format,
+                        usage,
+                        samples,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let depth = image(
+            app.depth_stencil_format(),
+            // Sampled usage is what makes `draw` store it; the transmission pass continues with
+            // it.
+            if app.store_depth || app.depth_heatmap || transmissive {
+                ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED
+            } else {
+                ImageUsage::DEPTH_STENCIL_ATTACHMENT
+            },
+        );
+        let msaa = (samples != SampleCount::Sample1).then(|| {
+            let device = app.context.device();
+            let color = image(
+                Format::R16G16B16A16_SFLOAT,
+                // Kept samples are left for a custom resolve or the transmission pass.
+                resolve_source_usage(
+                    device,
+                    if app.msaa_resolve.stores() || transmissive {
+                        ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED
+                    } else {
+                        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT
+                    },
+                ),
+            );
+            let motion = image(
+                MOTION_FORMAT,
+                resolve_source_usage(
+                    device,
+                    if transmissive {
+                        ImageUsage::COLOR_ATTACHMENT
+                    } else {
+                        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT
+                    },
+                ),
+            );
+            (color, motion)
+        });
+        (depth, msaa)
+    }
+
+    /// Recreates the depth and MSAA images, of `extent`, with `samples` per texel.
+    fn set_samples(&mut self, app: &App, extent: [u32; 2], samples: SampleCount) {
+        (self.depth, self.msaa) =
+            Self::multisampled(app, extent, samples, self.background.is_some());
+        self.samples = samples;
+    }
+
+    /// The same attachments for frames of `extent`.
+    fn resized(&self, app: &App, extent: [u32; 2]) -> Attachments {
+        Attachments::new(
+            app,
+            self.format,
+            extent,
+            self.samples,
+            self.readback.is_some(),
+            self.background.is_some(),
+        )
+    }
+}
+
+/// Everything that has to be rebuilt when the device is recreated.
+struct RenderState {
+    queue: Arc<Queue>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    sample_pipeline: SamplePipeline,
+    /// Swapped into `sample_pipeline` once loaded.
+    pending_textures: Vec<(SceneTexture, PendingTexture)>,
+    deferred_pipeline: Option<DeferredPipeline>,
+    depth_prepass_pipeline: Option<DepthPrepassPipeline>,
+    skybox_pipeline: Option<SkyboxPipeline>,
+    culling_pipeline: Option<CullingPipeline>,
+    point_pipeline: PointPipeline,
+    point_style: PointStyle,
+    outline_pipeline: OutlinePipeline,
+    line_pipeline: LinePipeline,
+    /// With their vertex count.
+    debug_lines: Option<(Arc<DescriptorSet>, u32)>,
+    line_style: LineStyle,
+    selection: Option<usize>,
+    outline_style: OutlineStyle,
+    shadow_pipeline: ShadowPipeline,
+    shadow_map: ShadowMap,
+    shadow_settings: ShadowSettings,
+    models: Vec<MyModel>,
+    animation: Option<Arc<SceneAnimation>>,
+    attachments: Attachments,
+    /// `None` until the first frame, which then has no motion.
+    previous_view_proj: Option<Matrix4<f32>>,
+    taa_pipeline: Option<TaaPipeline>,
+    fxaa_pipeline: Option<FxaaPipeline>,
+    depth_heatmap_pipeline: Option<DepthHeatmapPipeline>,
+    ssr_pipeline: Option<SsrPipeline>,
+    accumulation_pipeline: Option<AccumulationPipeline>,
+    tonemap_pipeline: Option<TonemapPipeline>,
+    tonemap: TonemapSettings,
+    /// Frames in the accumulation since the camera or scene last changed.
+    accumulated: u32,
+    debug_view: DebugView,
+    lighting_model: LightingModel,
+    ambient: AmbientSettings,
+    fog: Option<FogSettings>,
+    depth: DepthSettings,
+    msaa_resolve: MsaaResolve,
+    /// From the scene's coordinate system to the one it is rendered in.
+    conversion: Matrix4<f32>,
+    /// Frames presented so far, driving the TAA jitter and history.
+    frame: u32,
+    /// Of every attachment, see [`Self::resize`].
+    extent: [u32; 2],
+    /// One per frame in flight, indexed like `frame_fences`. Empty if unsupported.
+    stats_queries: Vec<StatisticsQuery>,
+    timestamp_queries: Vec<TimestampQuery>,
+    /// Whether passes are wrapped in debug labels, see [`ValidationMode::Debug`].
+    debug_labels: bool,
+    /// Draws only the model at this index, e.g. for thumbnails.
+    isolated: Option<usize>,
+    active_layers: u32,
+    /// Manual groups first, then ones found by name.
+    lod_groups: Vec<LodGroup>,
+    lod_screen_sizes: Vec<f32>,
+    /// Levels of detail not selected by [`Self::select_lods`] this frame, by model index.
+    lod_hidden: Vec<bool>,
+    /// Of the last finished frame, until taken.
+    stats: Option<RenderStats>,
+    /// GPU milliseconds of the last finished frame, until taken.
+    gpu_time: Option<f32>,
+    /// Of the last frame submitted with each slot of [`App::set_frames_in_flight`], waited for
+    /// before the slot's uniforms are written again, see [`Self::select_frame`].
+    frame_fences: Vec<Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>>,
+    /// Index into `frame_fences` of the frame being recorded.
+    frame_slot: usize,
+    /// Triangles the main or G-buffer pass of the frame being recorded draws, or the last frame
+    /// once recorded, see [`Self::count_triangles`]. Models culled on the GPU are included.
+    drawn_triangles: Cell<u64>,
+}
+
+impl RenderState {
+    /// Renders to the window's swapchain, or reads frames back with a frame sink.
+    fn for_window(
+        app: &App,
+        window_id: WindowId,
+        scene: &SceneData,
+    ) -> Result<Self, PipelineError> {
+        let renderer = app.windows.get_renderer(window_id).unwrap();
+        let extent = renderer.swapchain_image_view().image().extent();
+        Self::new(
+            app,
+            renderer.swapchain_format(),
+            [extent[0], extent[1]],
+            app.frame_sink.is_some(),
+            scene,
+        )
+    }
+
+    /// Renders `format` images of `extent`. With `readback`, frames can be [captured].
+    ///
+    /// [captured]: Self::capture
+    fn new(
+        app: &App,
+        format: Format,
+        extent: [u32; 2],
+        readback: bool,
+        scene: &SceneData,
+    ) -> Result<Self, PipelineError> {
+        let queue = app.context.graphics_queue().clone();
+
+        let shadow_settings = ShadowSettings {
+            light_direction: app.light.direction(),
+            ..Default::default()
+        };
+        let shadow_map = ShadowMap::new(app.memory_allocator(), shadow_settings.resolution);
+        let shadow_pipeline = ShadowPipeline::new(app, queue.clone());
+
+        let samples = Self::samples(app);
+        let rendering_info = Self::rendering_info(app, format);
+
+        let light = match app.light_fallback {
+            _ if scene.has_lights => Some(app.light),
+            LightFallback::DefaultLight => Some(app.light),
+            LightFallback::Unlit => None,
+            LightFallback::Dark => Some(LightSettings {
+                intensity: 0.0,
+                ..app.light
+            }),
+        };
+        // The shadow casting light first.
+        let lights: Vec<_> = std::iter::once(light.unwrap_or(app.light))
+            .chain(app.extra_lights.iter().copied())
+            .collect();
+
+        // Drawn with a gray placeholder until loaded in the background.
+        let mut pending_textures = Vec::new();
+        let mut load_texture = |texture, ktx2: &Option<Vec<u8>>| {
+            let ktx2 = ktx2.clone()?;
+            pending_textures.push((texture, texture::load_ktx2_async(app, ktx2)));
+            Some(texture::solid_color(app, [128, 128, 128, 255]))
+        };
+        let lightmap = load_texture(SceneTexture::Lightmap, &app.lightmap);
+        let matcap = load_texture(SceneTexture::Matcap, &app.matcap);
+        // Deferred shading draws transmissive models like opaque ones.
+        let transmissive = app.shading == Shading::Forward
+            && app
+                .model_extras
+                .iter()
+                .any(|e| e.transmission.is_transmissive());
+        let attachments = Attachments::new(app, format, extent, samples, readback, transmissive);
+        let sample_pipeline = SamplePipeline::new(
+            app,
+            queue.clone(),
+            rendering_info.clone(),
+            samples,
+            &shadow_map,
+            app.front_face,
+            app.face_culling,
+            app.polygon_mode,
+            lightmap,
+            matcap,
+            light.is_some().then_some(&lights[..]),
+            &app.depth,
+            attachments.background.clone(),
+        )?;
+        let skybox_pipeline = app
+            .skybox
+            .as_ref()
+            .filter(|_| app.shading == Shading::Forward)
+            .and_then(|skybox| {
+                let cubemap = texture::from_cubemap(app, skybox)
+                    .map_err(|err| log::warn!("{err}, drawing without a skybox"))
+                    .ok()?;
+                Some(SkyboxPipeline::new(
+                    app,
+                    queue.clone(),
+                    rendering_info.clone(),
+                    samples,
+                    cubemap,
+                ))
+            });
+        let point_pipeline = PointPipeline::new(queue.clone(), rendering_info.clone(), samples);
+        let outline_pipeline =
+            OutlinePipeline::new(app, queue.clone(), rendering_info.clone(), samples);
+        let line_pipeline = LinePipeline::new(queue.clone(), rendering_info.clone(), samples);
+        let debug_lines = (!app.debug_lines.is_empty()).then(|| {
+            let lines = Buffer::from_iter(
+                app.memory_allocator(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                app.debug_lines.iter().cloned(),
+            )
+            .unwrap();
+            let vertex_count = lines.len() as u32;
+            (line_pipeline.lines_descriptor_set(app, lines), vertex_count)
+        });
+        let depth_prepass_pipeline =
+            (app.depth_prepass && app.shading == Shading::Forward && app.depth.test)
+                .then(|| {
+                    if !DepthSettings::is_dynamic(queue.device()) {
+                        log::warn!("depth prepass needs dynamic depth state, drawing without one");
+                        return None;
+                    }
+                    Some(DepthPrepassPipeline::new(
+                        app,
+                        queue.clone(),
+                        rendering_info.clone(),
+                        samples,
+                        app.front_face,
+                        app.face_culling,
+                        app.polygon_mode,
+                        &app.depth,
+                    ))
+                })
+                .flatten();
+        let deferred_pipeline = (app.shading == Shading::Deferred).then(|| {
+            DeferredPipeline::new(
+                app,
+                queue.clone(),
+                rendering_info,
+                samples,
+                [extent[0], extent[1]],
+                app.front_face,
+                app.face_culling,
+                app.polygon_mode,
+                &lights,
+            )
+        });
+
+        let memory_allocator = app.memory_allocator();
+
+        let animation = app.animation.clone();
+        let model_extras = &app.model_extras;
+
+        let upload_start = Instant::now();
+
+        // All meshes share one vertex and one index buffer, each model using a slice of them.
+        let vertex_buffers = concatenated_buffer(
+            memory_allocator.clone(),
+            // Also read by `VertexCapturePipeline`.
+            BufferUsage::VERTEX_BUFFER | BufferUsage::STORAGE_BUFFER,
+            scene.models.iter().map(|model| model.vertices.as_slice()),
+        );
+        let index_buffers = concatenated_buffer(
+            memory_allocator.clone(),
+            BufferUsage::INDEX_BUFFER,
+            scene
+                .models
+                .iter()
+                .map(|model| model.indices.as_deref().unwrap_or_default()),
+        );
+
+        // Materials are shared between models, so each texture is uploaded once.
+        let mut texture_descriptor_sets = HashMap::new();
+        let mut models = scene
+            .models
+            .iter()
+            .zip(vertex_buffers.into_iter().zip(index_buffers))
+            .enumerate()
+            .filter_map(|(i, (model, buffers))| {
+                // Nothing would be drawn, and buffers can't be empty.
+                let (Some(vertex_buffer), index_buffer) = buffers else {
+                    log::debug!("model {i} has no vertices, skipping it");
+                    return None;
+                };
+                let index_buffer = match (&model.indices, index_buffer) {
+                    (None, _) => None,
+                    (Some(_), Some(index_buffer)) => Some(index_buffer),
+                    (Some(_), None) => {
+                        log::debug!("model {i} has no indices, skipping it");
+                        return None;
                     }
+                };
+                let instances = app.instances.get(&i).cloned();
+                let bounds = Aabb::from_vertices(&model.vertices);
+                let bounds = match &instances {
+                    Some(instances) => instances.iter().fold(Aabb::EMPTY, |all, instance| {
+                        all.union(&bounds.transformed(instance.model.into()))
+                    }),
+                    None => bounds,
+                };
+
+                let extras = model_extras.get(i);
+                let texture = model
+                    .material
+                    .as_ref()
+                    .and_then(|material| material.pbr.base_color_texture.as_ref());
+                let texture_descriptor_set = match texture {
+                    Some(texture) => texture_descriptor_sets
+                        .entry(Arc::as_ptr(texture))
+                        .or_insert_with(|| {
+                            let ktx2 = extras.and_then(|e| e.base_color_ktx2.as_deref());
+                            let ktx2 = ktx2.and_then(|ktx2| {
+                                texture::load_ktx2(app, ktx2)
+                                    .map_err(|err| {
+                                        log::warn!("{err}, using the fallback texture of model {i}")
+                                    })
+                                    .ok()
+                            });
+                            let view = ktx2.or_else(|| {
+                                let extent = [texture.width(), texture.height()];
+                                texture::from_rgba8_srgb(app, texture.as_raw().clone(), extent)
+                                    .map_err(|err| {
+                                        log::warn!("{err}, drawing model {i} untextured")
+                                    })
+                                    .ok()
+                            });
+                            sample_pipeline.texture_descriptor_set(app, view)
+                        })
+                        .clone(),
+                    None => sample_pipeline.texture_descriptor_set(app, None),
+                };
+
+                let joint_count = animation.as_ref().map_or(0, |a| a.joint_count(i)).max(1);
+                let joint_palette = create_joint_palette(memory_allocator.clone(), joint_count);
+                let previous_joint_palette =
+                    create_joint_palette(memory_allocator.clone(), joint_count);
+
+                let morph_targets = MorphTargets::new(
+                    memory_allocator.clone(),
+                    model.morph_deltas.clone(),
+                    extras.map_or(0, |e| e.morph_targets.len()),
+                );
+                let pinned_morph_weights = app.morph_weights.get(&i);
+                if let Some(weights) = pinned_morph_weights.or(extras.map(|e| &e.morph_weights)) {
+                    morph_targets.set_weights(weights);
+                }
+
+                Some(MyModel {
+                    scene_index: i,
+                    bounds,
+                    vertex_buffer,
+                    index_buffer,
+                    mode: model.mode,
+                    mirrored: model.mirrored,
+                    double_sided: extras.is_some_and(|e| e.double_sided),
+                    depth_bias: app.depth_biases.get(&i).copied().unwrap_or(
+                        match extras.is_some_and(|e| e.decal) {
+                            true => DepthBias::DECAL,
+                            false => DepthBias::default(),
+                        },
+                    ),
+                    material_override: app.material_overrides.get(&i).copied().unwrap_or_default(),
+                    clearcoat: extras.map_or(Clearcoat::default(), |e| e.clearcoat),
+                    pbr: model
+                        .material
+                        .as_ref()
+                        .map_or_else(PbrMaterial::default, |material| PbrMaterial {
+                            metallic: material.pbr.metallic_factor,
+                            roughness: material.pbr.roughness_factor,
+                        }),
+                    instances,
+                    transmission: extras.map_or(Transmission::default(), |e| e.transmission),
+                    layer: app
+                        .layers
+                        .get(&i)
+                        .copied()
+                        .unwrap_or(extras.map_or(DEFAULT_LAYER, |e| e.layer)),
+                    visible: !app.hidden_models.contains(&i),
+                    texture_descriptor_set,
+                    sample_descriptor_set: sample_pipeline.object_descriptor_set(
+                        app,
+                        joint_palette.clone(),
+                        previous_joint_palette.clone(),
+                        &morph_targets,
+                    ),
+                    shadow_descriptor_set: shadow_pipeline.object_descriptor_set(
+                        app,
+                        joint_palette.clone(),
+                        &morph_targets,
+                    ),
+                    deferred_descriptor_set: deferred_pipeline.as_ref().map(|deferred| {
+                        deferred.object_descriptor_set(
+                            app,
+                            joint_palette.clone(),
+                            previous_joint_palette.clone(),
+                            &morph_targets,
+                        )
+                    }),
+                    prepass_descriptor_set: depth_prepass_pipeline.as_ref().map(|prepass| {
+                        prepass.object_descriptor_set(app, joint_palette.clone(), &morph_targets)
+                    }),
+                    outline_descriptor_set: outline_pipeline.object_descriptor_set(
+                        app,
+                        joint_palette.clone(),
+                        &morph_targets,
+                    ),
+                    morph_targets,
+                    pinned_morph_weights: pinned_morph_weights.is_some(),
+                    joint_palette,
+                    previous_joint_palette,
+                    indirect_command: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        log::info!(
+            "uploaded {} models in {:.1?}",
+            models.len(),
+            upload_start.elapsed()
+        );
+
+        let culling_pipeline = (app.gpu_culling && !models.is_empty()).then(|| {
+            let draws: Vec<_> = models
+                .iter()
+                .map(|model| CulledDraw {
+                    bounds: model.bounds,
+                    // Skinning and morphing move vertices past the bind pose's bounds.
+                    cullable: animation
+                        .as_ref()
+                        .is_none_or(|a| a.model_skin(model.scene_index).is_none())
+                        && model_extras
+                            .get(model.scene_index)
+                            .is_none_or(|e| e.morph_targets.is_empty()),
+                    index_count: model.index_buffer.as_ref().map(|b| b.len() as u32),
+                    vertex_count: model.vertex_buffer.len() as u32,
+                })
+                .collect();
+            let culling_pipeline = CullingPipeline::new(app, queue.clone(), &draws);
+            for (i, model) in models.iter_mut().enumerate() {
+                model.indirect_command = Some(culling_pipeline.command(i));
+            }
+            culling_pipeline
+        });
+
+        let stats_queries: Vec<_> = match app.stats_callback {
+            Some(_) => (0..app.frames_in_flight)
+                .map_while(|_| StatisticsQuery::new(queue.device().clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        if app.stats_callback.is_some() && stats_queries.is_empty() {
+            log::warn!("pipeline statistics queries aren't supported, no render stats");
+        }
+        let timestamp_queries: Vec<_> = (0..app.frames_in_flight)
+            .map_while(|_| TimestampQuery::new(&queue))
+            .collect();
+        if timestamp_queries.is_empty() {
+            log::debug!("the graphics queue doesn't write timestamps, no GPU frame times");
+        }
+
+        let accumulation_pipeline = app
+            .supersampling
+            .then(|| AccumulationPipeline::new(app, queue.clone(), format, [extent[0], extent[1]]));
+        let taa_pipeline = (app.taa && !app.supersampling).then(|| {
+            TaaPipeline::new(
+                app,
+                queue.clone(),
+                format,
+                [extent[0], extent[1]],
+                attachments.motion.clone(),
+            )
+        });
+
+        let tonemap_pipeline = app
+            .tonemap
+            .map(|_| TonemapPipeline::new(app, queue.clone(), format, [extent[0], extent[1]]));
+
+        let fxaa_pipeline = Self::fxaa(app)
+            .then(|| FxaaPipeline::new(app, queue.clone(), format, [extent[0], extent[1]]));
+        let depth_heatmap_pipeline = app.depth_heatmap.then(|| {
+            DepthHeatmapPipeline::new(
+                app,
+                queue.clone(),
+                attachments.depth.image().clone(),
+                format,
+            )
+        });
+        let ssr_pipeline = app
+            .ssr
+            .zip(deferred_pipeline.as_ref())
+            .map(|(settings, deferred)| {
+                SsrPipeline::new(
+                    app,
+                    queue.clone(),
+                    deferred.gbuffer(),
+                    format,
+                    [extent[0], extent[1]],
+                    settings,
+                )
+            });
+
+        let mut lod_groups: Vec<LodGroup> = app.lod_groups.clone();
+        let named = LodGroup::from_names(
+            app.model_extras
+                .iter()
+                .map(|e| e.lod.as_ref().map(|(name, level)| (name.as_str(), *level))),
+        );
+        let grouped = |m: &usize| lod_groups.iter().any(|g| g.levels.contains(m));
+        let named: Vec<_> = named
+            .into_iter()
+            .filter(|g| !g.levels.iter().any(grouped))
+            .collect();
+        lod_groups.extend(named);
+        // From scene indices to ones into `models`, leaving out skipped models.
+        let position = |m: usize| models.iter().position(|model| model.scene_index == m);
+        for group in &mut lod_groups {
+            group.levels = group.levels.iter().filter_map(|&m| position(m)).collect();
+        }
+        let selection = app.selection.and_then(position);
+        let lod_hidden = vec![false; models.len()];
+
+        let state = Self {
+            queue,
+            // Each frame in flight holds on to one command buffer until it's done.
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                queue.device().clone(),
+                StandardCommandBufferAllocatorCreateInfo {
+                    primary_buffer_count: app.frames_in_flight,
+                    ..Default::default()
                 },
+            )),
+            sample_pipeline,
+            pending_textures,
+            deferred_pipeline,
+            depth_prepass_pipeline,
+            skybox_pipeline,
+            point_pipeline,
+            point_style: app.point_style,
+            outline_pipeline,
+            line_pipeline,
+            debug_lines,
+            line_style: app.line_style,
+            selection,
+            outline_style: app.outline_style,
+            shadow_pipeline,
+            shadow_map,
+            shadow_settings,
+            models,
+            animation,
+            attachments,
+            previous_view_proj: None,
+            taa_pipeline,
+            fxaa_pipeline,
+            depth_heatmap_pipeline,
+            ssr_pipeline,
+            culling_pipeline,
+            accumulation_pipeline,
+            tonemap_pipeline,
+            tonemap: app.tonemap.unwrap_or_default(),
+            accumulated: 0,
+            debug_view: app.debug_view,
+            lighting_model: app.lighting_model,
+            ambient: app.ambient,
+            fog: app.fog,
+            depth: app.depth,
+            msaa_resolve: app.msaa_resolve,
+            conversion: app.coordinate_system.to_gltf(),
+            frame: 0,
+            extent,
+            stats_queries,
+            timestamp_queries,
+            debug_labels: app.context.instance().enabled_extensions().ext_debug_utils,
+            isolated: None,
+            active_layers: app.active_layers,
+            lod_groups,
+            lod_screen_sizes: app.lod_screen_sizes.clone(),
+            lod_hidden,
+            stats: None,
+            gpu_time: None,
+            frame_fences: vec![None; app.frames_in_flight],
+            frame_slot: 0,
+            drawn_triangles: Cell::new(0),
+        };
+        let bounds = state.scene_bounds();
+        log::info!("scene bounds: {:?} to {:?}", bounds.min, bounds.max);
+        Ok(state)
+    }
+
+    /// The sample count closest to the one [`App::set_aa_mode`] asked for that the device
+    /// supports.
+    fn samples(app: &App) -> SampleCount {
+        let properties = app.context.device().physical_device().properties();
+        let supported =
+            properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+        let requested = app.aa_mode.samples();
+        let samples = [
+            SampleCount::Sample64,
+            SampleCount::Sample32,
+            SampleCount::Sample16,
+            SampleCount::Sample8,
+            SampleCount::Sample4,
+            SampleCount::Sample2,
+        ]
+        .into_iter()
+        .find(|&s| s as u32 <= requested as u32 && supported.contains_enum(s))
+        .unwrap_or(SampleCount::Sample1);
+        if samples != requested {
+            log::warn!("{requested:?} MSAA isn't supported, using {samples:?}");
+        }
+        samples
+    }
+
+    /// Of the main pass rendering `format` frames.
+    fn rendering_info(app: &App, format: Format) -> PipelineRenderingCreateInfo {
+        let depth_stencil_format = app.depth_stencil_format();
+        PipelineRenderingCreateInfo {
+            color_attachment_formats: vec![Some(format), Some(MOTION_FORMAT)],
+            depth_attachment_format: Some(depth_stencil_format),
+            stencil_attachment_format: Some(depth_stencil_format),
+            ..Default::default()
+        }
+    }
+
+    /// Whether FXAA runs after the main pass; the other temporal passes smooth edges already.
+    fn fxaa(app: &App) -> bool {
+        app.aa_mode == AaMode::Fxaa && !app.supersampling && !app.taa
+    }
+
+    /// Switches to the anti-aliasing mode of `app`. Only the pipelines drawing into the main
+    /// pass, its multisampled attachments and the FXAA pass are rebuilt.
+    fn set_aa_mode(&mut self, app: &App) {
+        let samples = Self::samples(app);
+        let format = self.attachments.format;
+        if samples != self.attachments.samples {
+            let rendering_info = Self::rendering_info(app, format);
+            let queue = self.queue.clone();
+            self.attachments.set_samples(app, self.extent, samples);
+            self.sample_pipeline
+                .set_samples(app, rendering_info.clone(), samples);
+            if let Some(skybox) = &mut self.skybox_pipeline {
+                skybox.set_samples(rendering_info.clone(), samples);
+            }
+            if let Some(deferred) = &mut self.deferred_pipeline {
+                deferred.set_samples(rendering_info.clone(), samples);
+            }
+            self.recreate_depth_prepass(app);
+            self.point_pipeline =
+                PointPipeline::new(queue.clone(), rendering_info.clone(), samples);
+            self.outline_pipeline =
+                OutlinePipeline::new(app, queue.clone(), rendering_info.clone(), samples);
+            self.line_pipeline = LinePipeline::new(queue.clone(), rendering_info, samples);
+            // Its shader reads the depth image's samples.
+            if self.depth_heatmap_pipeline.is_some() {
+                self.depth_heatmap_pipeline = Some(DepthHeatmapPipeline::new(
+                    app,
+                    queue.clone(),
+                    self.attachments.depth.image().clone(),
+                    format,
+                ));
+            }
+        }
+        if Self::fxaa(app) != self.fxaa_pipeline.is_some() {
+            self.fxaa_pipeline = Self::fxaa(app)
+                .then(|| FxaaPipeline::new(app, self.queue.clone(), format, self.extent));
+        }
+        self.accumulated = 0;
+    }
+
+    /// Recreates the pipelines drawing the scene's faces with the face culling of `app`, keeping
+    /// the models, textures and attachments.
+    fn set_face_culling(&mut self, app: &App) {
+        self.sample_pipeline.set_face_culling(app);
+        if let Some(deferred) = &mut self.deferred_pipeline {
+            deferred.set_face_culling(app);
+        }
+        self.recreate_depth_prepass(app);
+        self.accumulated = 0;
+    }
+
+    /// Recreates the pipelines drawing the scene's faces with the polygon mode of `app`, keeping
+    /// the models, textures and attachments.
+    fn set_polygon_mode(&mut self, app: &App) {
+        self.sample_pipeline.set_polygon_mode(app);
+        if let Some(deferred) = &mut self.deferred_pipeline {
+            deferred.set_polygon_mode(app);
+        }
+        self.recreate_depth_prepass(app);
+        self.accumulated = 0;
+    }
+
+    /// Creates or drops the depth heatmap pass for the setting of `app`, keeping the rest.
+    fn set_depth_heatmap(&mut self, app: &App) {
+        if !app.depth_heatmap {
+            self.depth_heatmap_pipeline = None;
+            return;
+        }
+        // `draw` only stores the depth of sampleable images, which it is created as when
+        // something reads it.
+        if !self
+            .attachments
+            .depth
+            .image()
+            .usage()
+            .intersects(ImageUsage::SAMPLED)
+        {
+            let samples = self.attachments.samples;
+            self.attachments.set_samples(app, self.extent, samples);
+        }
+        self.depth_heatmap_pipeline = Some(DepthHeatmapPipeline::new(
+            app,
+            self.queue.clone(),
+            self.attachments.depth.image().clone(),
+            self.attachments.format,
+        ));
+    }
+
+    /// Recreates the depth prepass, if there is one, to rasterize like the sample pipeline.
+    fn recreate_depth_prepass(&mut self, app: &App) {
+        if let Some(prepass) = &mut self.depth_prepass_pipeline {
+            *prepass = DepthPrepassPipeline::new(
+                app,
+                self.queue.clone(),
+                Self::rendering_info(app, self.attachments.format),
+                self.attachments.samples,
+                app.front_face,
+                app.face_culling,
+                app.polygon_mode,
+                &app.depth,
+            );
+        }
+    }
+
+    /// Recreates the attachments and the targets of the post passes for frames of `extent`,
+    /// keeping the pipelines, models and textures. Temporal history starts over.
+    fn resize(&mut self, app: &App, extent: [u32; 2]) {
+        self.attachments = self.attachments.resized(app, extent);
+        if let Some(background) = &self.attachments.background {
+            self.sample_pipeline.set_background(app, background.clone());
+        }
+        if let Some(deferred) = &mut self.deferred_pipeline {
+            deferred.resize(app, extent);
+            if let Some(ssr) = &mut self.ssr_pipeline {
+                ssr.resize(app, deferred.gbuffer(), extent);
+            }
+        }
+        if let Some(taa) = &mut self.taa_pipeline {
+            taa.resize(app, extent, self.attachments.motion.clone());
+        }
+        if let Some(accumulation) = &mut self.accumulation_pipeline {
+            accumulation.resize(app, extent);
+        }
+        if let Some(tonemap) = &mut self.tonemap_pipeline {
+            tonemap.resize(app, extent);
+        }
+        if let Some(fxaa) = &mut self.fxaa_pipeline {
+            fxaa.resize(app, extent);
+        }
+        if let Some(heatmap) = &mut self.depth_heatmap_pipeline {
+            heatmap.set_depth_image(app, self.attachments.depth.image().clone());
+        }
+        self.extent = extent;
+        self.previous_view_proj = None;
+        self.accumulated = 0;
+        self.frame = 0;
+    }
+
+    /// Renders and presents one frame into the swapchain image acquired with `before`, whose
+    /// extent must match, see [`Self::resize`], with `ui` over it. Vulkan errors, most
+    /// importantly [`VulkanError::DeviceLost`], are returned to the caller.
+    fn redraw(
+        &mut self,
+        renderer: &mut VulkanoWindowRenderer,
+        before: Box<dyn GpuFuture>,
+        camera: &Camera,
+        time: f32,
+        ui: &mut Ui,
+    ) -> Result<(), VulkanError> {
+        // The frames in flight take turns, so the oldest one is waited for.
+        let slot = (self.frame_slot + 1) % self.frame_fences.len();
+        self.select_frame(slot)?;
+        let swapchain_image = renderer.swapchain_image_view();
+        let after =
+            Arc::new(
+                self.render(before, swapchain_image.clone(), camera, time, |builder| {
+                    ui.render(builder, swapchain_image)
+                })?,
             );
-            renderer.present(after, true);
+        self.frame_fences[slot] = Some(after.clone());
+        // The fence is waited for when the slot comes around again.
+        renderer.present(after.boxed(), false);
+        Ok(())
+    }
+
+    /// Waits until the last frame recorded with `slot` is done, so that writing its uniforms
+    /// can't fail, then selects it in the pipelines.
+    fn select_frame(&mut self, slot: usize) -> Result<(), VulkanError> {
+        if let Some(fence) = self.frame_fences[slot].take() {
+            fence.wait(None).map_err(Validated::unwrap)?;
+        }
+        self.sample_pipeline.select_frame(slot);
+        if let Some(deferred) = &mut self.deferred_pipeline {
+            deferred.select_frame(slot);
+        }
+        self.frame_slot = slot;
+        Ok(())
+    }
+
+    /// Waits until every frame in flight is done, before writing buffers they all share.
+    fn wait_for_frames(&mut self) -> Result<(), VulkanError> {
+        for fence in self.frame_fences.iter_mut().filter_map(Option::take) {
+            fence.wait(None).map_err(Validated::unwrap)?;
+        }
+        Ok(())
+    }
+
+    /// Renders one frame into the readback image instead of the swapchain, and hands its texels
+    /// to `callback` once the GPU is done with it.
+    fn capture(
+        &mut self,
+        camera: &Camera,
+        time: f32,
+        callback: impl FnOnce(&[u8], [u32; 2]),
+    ) -> Result<(), VulkanError> {
+        let extent = self.extent;
+        self.capture_region(camera, time, [0, 0], extent, |texels| {
+            callback(texels, extent)
+        })
+    }
+
+    /// Like [`Self::capture`], but only copies the `size` texels at `offset`, which must lie
+    /// within the frame, tightly packed.
+    fn capture_region(
+        &mut self,
+        camera: &Camera,
+        time: f32,
+        offset: [u32; 2],
+        size: [u32; 2],
+        callback: impl FnOnce(&[u8]),
+    ) -> Result<(), VulkanError> {
+        let (image, buffer) = self
+            .attachments
+            .readback
+            .clone()
+            .expect("no readback image without a frame sink");
+        // There's a single readback image, and the frame is waited for below.
+        self.select_frame(0)?;
+        let before = sync::now(self.queue.device().clone()).boxed();
+        self.render(before, image.clone(), camera, time, |builder| {
+            builder
+                .copy_image_to_buffer(CopyImageToBufferInfo {
+                    regions: [BufferImageCopy {
+                        image_subresource: image.image().subresource_layers(),
+                        image_offset: [offset[0], offset[1], 0],
+                        image_extent: [size[0], size[1], 1],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    ..CopyImageToBufferInfo::image_buffer(image.image().clone(), buffer.clone())
+                })
+                .unwrap();
+        })?
+        .wait(None)
+        .map_err(Validated::unwrap)?;
+
+        let len = size[0] as usize * size[1] as usize * image.format().block_size() as usize;
+        callback(&buffer.read().unwrap()[..len]);
+        Ok(())
+    }
+
+    /// Draws the normals of the models `camera` sees with `normals` and reads them back.
+    fn capture_normals(
+        &mut self,
+        normals: &NormalsPipeline,
+        camera: &Camera,
+        time: f32,
+    ) -> Result<Vec<u8>, VulkanError> {
+        if let Some(animation) = self.animation.clone() {
+            self.wait_for_frames()?;
+            self.update_joint_palettes(&animation, time, true);
+            self.update_morph_weights(&animation, time);
+        }
+        self.select_lods(camera);
+
+        draw(
+            sync::now(self.queue.device().clone()).boxed(),
+            self.command_buffer_allocator.clone(),
+            self.queue.clone(),
+            vec![ColorAttachment::cleared(normals.image(), [0.0; 4])],
+            normals.depth_image(),
+            |_| {},
+            |builder| {
+                normals.begin_frame(builder, camera);
+                for model in self.drawn_models().filter(|m| m.is_triangles()) {
+                    normals.render_object(
+                        builder,
+                        model.sample_descriptor_set.clone(),
+                        model.vertex_buffer.clone(),
+                        model.index_buffer.clone(),
+                    );
+                }
+            },
+            |builder| normals.copy_to_buffer(builder),
+        )
+        .then_signal_fence_and_flush()
+        .map_err(Validated::unwrap)?
+        .wait(None)
+        .map_err(Validated::unwrap)?;
+
+        Ok(normals.read_buffer())
+    }
+
+    /// Records and submits one frame into `dst_image` after `before`, then `record_fn` outside of
+    /// any render pass, e.g. to copy the frame or draw the UI over it.
+    fn render(
+        &mut self,
+        before: Box<dyn GpuFuture>,
+        dst_image: Arc<ImageView>,
+        camera: &Camera,
+        time: f32,
+        record_fn: impl FnOnce(&mut RecordingCommandBuffer),
+    ) -> Result<FenceSignalFuture<Box<dyn GpuFuture>>, VulkanError> {
+        // The slot's previous frame is done by now, see `select_frame`.
+        let stats_query = self.stats_queries.get(self.frame_slot);
+        if let Some(stats) = stats_query.and_then(StatisticsQuery::read) {
+            self.stats = Some(stats);
+        }
+        let timestamp_query = self.timestamp_queries.get(self.frame_slot);
+        if let Some(gpu_time) = timestamp_query.and_then(TimestampQuery::read) {
+            self.gpu_time = Some(gpu_time);
+        }
+
+        self.drawn_triangles.set(0);
+
+        let cascades = self.shadow_settings.cascades(camera, Z_NEAR, Z_FAR);
+        self.sample_pipeline
+            .set_cascades(&cascades, self.shadow_settings.blend_band);
+        let view_proj = camera.proj * camera.view;
+        let first_frame = self.previous_view_proj.is_none();
+        if let Some(animation) = self.animation.clone() {
+            // The palettes and weights aren't kept per frame.
+            self.wait_for_frames()?;
+            self.update_joint_palettes(&animation, time, first_frame);
+            self.update_morph_weights(&animation, time);
+        }
+
+        // Any change to the image starts the accumulation over.
+        if self.animation.is_some() || self.previous_view_proj != Some(view_proj) {
+            self.accumulated = 0;
+        }
+        self.accumulated += 1;
+
+        let extent = self.attachments.depth.image().extent();
+        let jitter = if self.accumulation_pipeline.is_some() {
+            taa::sample_offset(self.accumulated - 1, [extent[0], extent[1]])
+        } else if self.taa_pipeline.is_some() {
+            taa::jitter(self.frame, [extent[0], extent[1]])
+        } else {
+            [0.0; 2]
+        };
+        let camera = &Camera {
+            proj: Matrix4::from_translation([jitter[0], jitter[1], 0.0].into()) * camera.proj,
+            ..*camera
+        };
+        let previous_view_proj = self.previous_view_proj.unwrap_or(view_proj);
+        self.sample_pipeline.set_motion(previous_view_proj, jitter);
+        self.sample_pipeline
+            .set_scene(&self.ambient, self.fog.as_ref(), self.lighting_model);
+        self.select_lods(camera);
+        if let Some(deferred) = &self.deferred_pipeline {
+            deferred.set_motion(previous_view_proj, jitter);
+        }
+
+        // What the other post passes write, tone mapped into the swapchain after them.
+        let output_image = match &self.tonemap_pipeline {
+            Some(tonemap) => tonemap.scene_image(),
+            None => dst_image.clone(),
+        };
+        // What the post passes after SSR read from, or the output image without any.
+        let post_image = if let Some(accumulation) = &self.accumulation_pipeline {
+            accumulation.scene_image()
+        } else if let Some(taa) = &self.taa_pipeline {
+            taa.scene_image()
+        } else if let Some(fxaa) = &self.fxaa_pipeline {
+            fxaa.scene_image()
+        } else {
+            output_image.clone()
+        };
+        let scene_image = match &self.ssr_pipeline {
+            Some(ssr) => ssr.scene_image(),
+            None => post_image.clone(),
+        };
+        let transmission_pass = self.drawn_models().any(|m| self.in_transmission_pass(m));
+        // The second are for the transmission pass to continue with.
+        let (color_attachments, resumed_attachments) = match &self.attachments.msaa {
+            Some((msaa_color_image, msaa_motion_image)) => {
+                let color = self
+                    .msaa_resolve
+                    .color_attachment(msaa_color_image.clone(), scene_image.clone());
+                let motion = ColorAttachment {
+                    store_op: AttachmentStoreOp::DontCare,
+                    resolve_image: Some(self.attachments.motion.clone()),
+                    ..ColorAttachment::cleared(msaa_motion_image.clone(), [0.0; 4])
+                };
+                let resumed = vec![
+                    ColorAttachment {
+                        load_op: AttachmentLoadOp::Load,
+                        ..color.clone()
+                    },
+                    ColorAttachment {
+                        load_op: AttachmentLoadOp::Load,
+                        ..motion.clone()
+                    },
+                ];
+                let (color, motion) = match transmission_pass {
+                    // Samples are kept for the second pass, and resolved to be copied.
+                    true => (
+                        ColorAttachment {
+                            store_op: AttachmentStoreOp::Store,
+                            resolve_image: Some(scene_image.clone()),
+                            ..color
+                        },
+                        ColorAttachment {
+                            store_op: AttachmentStoreOp::Store,
+                            ..motion
+                        },
+                    ),
+                    false => (color, motion),
+                };
+                (vec![color, motion], resumed)
+            }
+            None => (
+                vec![
+                    ColorAttachment::cleared(scene_image.clone(), [0.0, 0.0, 0.0, 1.0]),
+                    ColorAttachment::cleared(self.attachments.motion.clone(), [0.0; 4]),
+                ],
+                vec![
+                    ColorAttachment::loaded(scene_image.clone()),
+                    ColorAttachment::loaded(self.attachments.motion.clone()),
+                ],
+            ),
         };
 
-        event_loop
-            .run(move |event, elwt| {
-                let renderer = self.windows.get_renderer_mut(window_id).unwrap();
-                match event {
-                    Event::WindowEvent { event, .. } => match event {
-                        WindowEvent::CloseRequested => elwt.exit(),
-                        WindowEvent::Resized(..) => {
-                            renderer.resize();
+        let stats_query = self.stats_queries.get(self.frame_slot);
+        let timestamp_query = self.timestamp_queries.get(self.frame_slot);
+        let after = draw(
+            before,
+            self.command_buffer_allocator.clone(),
+            self.queue.clone(),
+            color_attachments,
+            self.attachments.depth.clone(),
+            |builder| {
+                labeled(builder, self.debug_labels, "prepass", |builder| {
+                    if let Some(query) = stats_query {
+                        query.reset(builder);
+                    }
+                    if let Some(query) = timestamp_query {
+                        query.reset(builder);
+                        query.start(builder);
+                    }
+                    if let Some(culling) = &self.culling_pipeline {
+                        culling.cull(builder, camera);
+                    }
+                    if let Some(deferred) = &self.deferred_pipeline {
+                        if let Some(query) = stats_query {
+                            query.begin(builder);
                         }
-                        WindowEvent::ScaleFactorChanged { .. } => {
-                            renderer.resize();
+                        deferred.render_gbuffer(builder, camera, |builder| {
+                            for model in self.drawn_models().filter(|m| m.is_triangles()) {
+                                self.count_triangles(model, 1);
+                                deferred.draw_object(
+                                    builder,
+                                    model.deferred_descriptor_set.clone().unwrap(),
+                                    model.vertex_buffer.clone(),
+                                    model.index_buffer.clone(),
+                                    model.indirect_command.clone(),
+                                    model.mirrored,
+                                    model.double_sided,
+                                    model.depth_bias,
+                                );
+                            }
+                        });
+                        if let Some(query) = stats_query {
+                            query.end(builder);
                         }
-                        WindowEvent::RedrawRequested => {
-                            redraw(renderer);
+                        return;
+                    }
+                    self.shadow_pipeline
+                        .render(builder, &self.shadow_map, &cascades, |builder| {
+                            let casters = self
+                                .drawn_models()
+                                .filter(|m| m.is_triangles() && m.instances.is_none());
+                            for model in casters {
+                                self.shadow_pipeline.draw_object(
+                                    builder,
+                                    model.shadow_descriptor_set.clone(),
+                                    model.vertex_buffer.clone(),
+                                    model.index_buffer.clone(),
+                                );
+                            }
+                        });
+                })
+            },
+            |builder| {
+                labeled(builder, self.debug_labels, "main pass", |builder| {
+                    if let Some(deferred) = &self.deferred_pipeline {
+                        deferred.render_lighting(builder, camera, &self.ambient, self.fog.as_ref());
+                    } else {
+                        if let Some(skybox) = &self.skybox_pipeline {
+                            skybox.render(builder, camera);
                         }
-                        _ => {}
-                    },
-                    Event::AboutToWait => {
-                        self.windows.get_window(window_id).unwrap().request_redraw();
+                        if let Some(query) = stats_query {
+                            query.begin(builder);
+                        }
+                        self.record_forward(builder, camera);
+                        if let Some(query) = stats_query {
+                            query.end(builder);
+                        }
+                    }
+
+                    let selected = self
+                        .selection
+                        .filter(|&i| i < self.models.len() && self.is_drawn(i))
+                        .map(|i| &self.models[i]);
+                    if let Some(model) =
+                        selected.filter(|m| m.is_triangles() && m.instances.is_none())
+                    {
+                        self.outline_pipeline.render_object(
+                            builder,
+                            model.outline_descriptor_set.clone(),
+                            model.vertex_buffer.clone(),
+                            model.index_buffer.clone(),
+                            camera,
+                            &self.outline_style,
+                        );
+                    }
+
+                    if let Some((lines, vertex_count)) = &self.debug_lines {
+                        let extent = self.attachments.depth.image().extent();
+                        self.line_pipeline.render_lines(
+                            builder,
+                            lines.clone(),
+                            *vertex_count,
+                            camera,
+                            [extent[0] as f32, extent[1] as f32],
+                            &self.line_style,
+                        );
                     }
-                    _ => {}
+                })
+            },
+            |builder| {
+                if transmission_pass {
+                    labeled(builder, self.debug_labels, "transmission pass", |builder| {
+                        self.record_transmission(builder, camera, scene_image, resumed_attachments);
+                    });
+                }
+                if let Some(query) = timestamp_query {
+                    query.stop(builder);
+                }
+                labeled(builder, self.debug_labels, "post passes", |builder| {
+                    if let Some(ssr) = &self.ssr_pipeline {
+                        ssr.render(builder, post_image, camera);
+                    }
+                    if let Some(depth_heatmap) = &self.depth_heatmap_pipeline {
+                        depth_heatmap.render(builder, output_image.clone(), camera);
+                    } else if let Some(accumulation) = &self.accumulation_pipeline {
+                        accumulation.render(builder, output_image.clone(), self.accumulated);
+                    } else if let Some(taa) = &self.taa_pipeline {
+                        taa.render(builder, output_image.clone(), self.frame);
+                    } else if let Some(fxaa) = &self.fxaa_pipeline {
+                        fxaa.render(builder, output_image.clone());
+                    }
+                    if let Some(tonemap) = &self.tonemap_pipeline {
+                        tonemap.render(builder, dst_image.clone(), &self.tonemap);
+                    }
+                    record_fn(builder);
+                })
+            },
+        )
+        // Flush here rather than in `present`, which only logs submission failures.
+        .then_signal_fence_and_flush()
+        .map_err(Validated::unwrap)?;
+
+        self.previous_view_proj = Some(view_proj);
+        self.frame += 1;
+        Ok(after)
+    }
+
+    /// Swaps in the textures that finished loading since the last call.
+    fn swap_loaded_textures(&mut self, app: &App) {
+        self.pending_textures.retain(|(texture, pending)| {
+            match pending.try_take() {
+                Some(Ok(view)) => {
+                    self.sample_pipeline.set_texture(app, *texture, view);
+                    self.accumulated = 0;
                 }
+                Some(Err(err)) => log::error!("failed to load {texture:?}: {err}"),
+                None => return true,
+            }
+            false
+        });
+    }
+
+    fn record_forward(&self, builder: &mut RecordingCommandBuffer, camera: &Camera) {
+        // Instances aren't written by the prepass, so they're tested as usual after the rest.
+        let (prepassed, unprepassed): (Vec<_>, Vec<_>) = self
+            .drawn_models()
+            .filter(|m| m.is_triangles() && !self.in_transmission_pass(m))
+            .partition(|m| self.depth_prepass_pipeline.is_some() && m.instances.is_none());
+        // Before `begin_frame`, whose fragment set binding the prepass's layout would disturb.
+        if let Some(prepass) = &self.depth_prepass_pipeline {
+            for model in &prepassed {
+                prepass.render_object(
+                    builder,
+                    model.prepass_descriptor_set.clone().unwrap(),
+                    model.vertex_buffer.clone(),
+                    model.index_buffer.clone(),
+                    model.indirect_command.clone(),
+                    camera,
+                    model.mirrored,
+                    model.double_sided,
+                    model.depth_bias,
+                );
+            }
+        }
+        self.sample_pipeline
+            .begin_frame(builder, camera, self.debug_view);
+        if !prepassed.is_empty() {
+            // Only the nearest surfaces pass, and their depth is written already.
+            let depth = DepthSettings {
+                test: true,
+                write: false,
+                compare_op: CompareOp::Equal,
+            };
+            self.sample_pipeline.set_depth(builder, &depth);
+            for model in prepassed {
+                self.render_sample_object(builder, model);
+            }
+        }
+        self.sample_pipeline.set_depth(builder, &self.depth);
+        for model in unprepassed {
+            self.render_sample_object(builder, model);
+        }
+        for model in self.drawn_models().filter(|m| !m.is_triangles()) {
+            self.point_pipeline.render_object(
+                builder,
+                model.vertex_buffer.clone(),
+                model.index_buffer.clone(),
+                model.is_lines(),
+                camera,
+                &self.point_style,
+            );
+        }
+    }
+
+    /// Copies `scene_image`, which the main pass rendered into, to [`Attachments::background`] and
+    /// draws the transmissive models over it with `color_attachments`, continuing the main pass.
+    fn record_transmission(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        camera: &Camera,
+        scene_image: Arc<ImageView>,
+        color_attachments: Vec<ColorAttachment>,
+    ) {
+        let background = self.attachments.background.as_ref().unwrap();
+        builder
+            .blit_image(BlitImageInfo {
+                filter: Filter::Nearest,
+                ..BlitImageInfo::images(scene_image.image().clone(), background.image().clone())
             })
             .unwrap();
+        let rendering =
+            resume_rendering(builder, color_attachments, self.attachments.depth.clone());
+        self.sample_pipeline
+            .begin_frame(builder, camera, self.debug_view);
+        self.sample_pipeline.set_depth(builder, &self.depth);
+        for model in self.drawn_models().filter(|m| self.in_transmission_pass(m)) {
+            self.render_sample_object(builder, model);
+        }
+        rendering.end(builder);
     }
 
-    pub(crate) fn memory_allocator(&self) -> Arc<StandardMemoryAllocator> {
-        self.context.memory_allocator().clone()
+    fn render_sample_object(&self, builder: &mut RecordingCommandBuffer, model: &MyModel) {
+        self.count_triangles(model, model.instances.as_ref().map_or(1, Vec::len));
+        if let Some(instances) = &model.instances {
+            self.sample_pipeline.render_instanced(
+                builder,
+                model.sample_descriptor_set.clone(),
+                model.texture_descriptor_set.clone(),
+                model.vertex_buffer.clone(),
+                model.index_buffer.clone(),
+                instances,
+                model.mirrored,
+                model.double_sided,
+                model.depth_bias,
+                model.material_override,
+                model.clearcoat,
+                model.transmission,
+                model.pbr,
+            );
+            return;
+        }
+        self.sample_pipeline.render_object(
+            builder,
+            model.sample_descriptor_set.clone(),
+            model.texture_descriptor_set.clone(),
+            model.vertex_buffer.clone(),
+            model.index_buffer.clone(),
+            model.indirect_command.clone(),
+            model.mirrored,
+            model.double_sided,
+            model.depth_bias,
+            model.material_override,
+            model.clearcoat,
+            model.transmission,
+            model.pbr,
+        );
+    }
+
+    /// Adds `copies` of `model` to [`Self::drawn_triangles`], as it is drawn.
+    fn count_triangles(&self, model: &MyModel, copies: usize) {
+        let triangles = model.triangle_count() * copies as u64;
+        self.drawn_triangles
+            .set(self.drawn_triangles.get() + triangles);
+    }
+
+    /// Whether `model` is left out of the main pass for [`Self::record_transmission`].
+    fn in_transmission_pass(&self, model: &MyModel) -> bool {
+        self.attachments.background.is_some()
+            && model.is_triangles()
+            && model.transmission.is_transmissive()
+    }
+
+    /// Whether the model at `i` is visible and either isolated or the selected level of detail.
+    fn is_drawn(&self, i: usize) -> bool {
+        self.models[i].is_visible(self.active_layers)
+            && match self.isolated {
+                // Isolating a level shows it regardless of the camera.
+                Some(isolated) => i == isolated,
+                None => !self.lod_hidden[i],
+            }
+    }
+
+    /// See [`Self::is_drawn`].
+    fn drawn_models(&self) -> impl Iterator<Item = &MyModel> {
+        (0..self.models.len())
+            .filter(|&i| self.is_drawn(i))
+            .map(|i| &self.models[i])
+    }
+
+    /// Picks the level of each LOD group to draw for `camera`, among its visible levels.
+    fn select_lods(&mut self, camera: &Camera) {
+        for group in &self.lod_groups {
+            let levels: Vec<usize> = group
+                .levels
+                .iter()
+                .copied()
+                .filter(|&m| self.models[m].is_visible(self.active_layers))
+                .collect();
+            let bounds = levels.iter().fold(Aabb::EMPTY, |bounds, &m| {
+                bounds.union(&self.models[m].bounds)
+            });
+            if bounds.is_empty() {
+                continue;
+            }
+            let size = lod::screen_size(&bounds, camera);
+            let selected = lod::select_level(size, &self.lod_screen_sizes, levels.len());
+            for (level, &m) in levels.iter().enumerate() {
+                self.lod_hidden[m] = level != selected;
+            }
+        }
+    }
+
+    /// Combined bounds of every model in the scene, in world space.
+    fn scene_bounds(&self) -> Aabb {
+        self.models
+            .iter()
+            .fold(Aabb::EMPTY, |bounds, model| bounds.union(&model.bounds))
+    }
+
+    /// Poses every skinned model at `time`, keeping the last pose for motion vectors unless this
+    /// is the `first_frame`.
+    fn update_joint_palettes(&self, animation: &SceneAnimation, time: f32, first_frame: bool) {
+        let node_transforms = animation.node_transforms(time);
+        for model in &self.models {
+            let Some(joints) = animation.joint_matrices(model.scene_index, &node_transforms) else {
+                continue;
+            };
+            let mut palette = model.joint_palette.write().unwrap();
+            let mut previous = model.previous_joint_palette.write().unwrap();
+            previous.copy_from_slice(&palette);
+            // Joints move the converted vertices, so they are converted too.
+            let inverse_conversion = self.conversion.invert().unwrap();
+            for (dst, joint) in palette.iter_mut().zip(joints) {
+                *dst = (self.conversion * joint * inverse_conversion).into();
+            }
+            if first_frame {
+                previous.copy_from_slice(&palette);
+            }
+        }
+    }
+
+    fn update_morph_weights(&self, animation: &SceneAnimation, time: f32) {
+        for model in &self.models {
+            if model.pinned_morph_weights {
+                continue;
+            }
+            if let Some(weights) = animation.morph_weights(model.scene_index, time) {
+                model.morph_targets.set_weights(&weights);
+            }
+        }
     }
 }
 
@@ -345,9 +4164,167 @@ unsafe fn enable_edr(window_handle: RawWindowHandle) {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_new() {
         println!("{}", std::env::var("DYLD_FALLBACK_LIBRARY_PATH").unwrap());
-        super::App::new();
+        assert!(super::App::new().is_ok());
+    }
+
+    #[test]
+    fn logs_validation_errors_as_errors() {
+        assert_eq!(
+            log_level(DebugUtilsMessageSeverity::ERROR),
+            log::Level::Error
+        );
+        assert_eq!(
+            log_level(DebugUtilsMessageSeverity::WARNING),
+            log::Level::Warn
+        );
+        assert_eq!(
+            log_level(DebugUtilsMessageSeverity::INFO),
+            log::Level::Debug
+        );
+    }
+
+    #[test]
+    fn parallel_map_keeps_the_order() {
+        let items: Vec<_> = (0..100).collect();
+        let mapped = parallel_map(&items, |i, item| (i, item * 2));
+        assert_eq!(mapped, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u32], |_, item| *item).is_empty());
+    }
+
+    #[test]
+    fn strips_split_at_restart_indices() {
+        let restart = u16::MAX as u32;
+        assert_eq!(
+            triangle_list(
+                Mode::TriangleStrip,
+                &[0, 1, 2, 3, restart, 4, 5, 6],
+                restart
+            ),
+            [0, 1, 2, 2, 1, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            triangle_list(Mode::TriangleFan, &[0, 1, 2, 3, restart, 4, 5], restart),
+            [0, 1, 2, 0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn sizes_descriptor_pools_by_model_count() {
+        assert_eq!(
+            descriptor_sets_per_pool(None, 3),
+            MIN_DESCRIPTOR_SETS_PER_POOL
+        );
+        assert_eq!(descriptor_sets_per_pool(None, 500), 500);
+        assert_eq!(
+            descriptor_sets_per_pool(None, 100_000),
+            MAX_DESCRIPTOR_SETS_PER_POOL
+        );
+        assert_eq!(descriptor_sets_per_pool(Some(8), 500), 8);
+    }
+
+    #[test]
+    fn clamps_regions_to_the_image() {
+        assert_eq!(
+            clamp_region([10, 20], [30, 40], [100, 100]),
+            ([10, 20], [30, 40])
+        );
+        assert_eq!(
+            clamp_region([90, 80], [30, 40], [100, 100]),
+            ([90, 80], [10, 20])
+        );
+        assert_eq!(clamp_region([120, 0], [30, 40], [100, 100]).1, [0, 40]);
+    }
+
+    #[test]
+    fn line_strips_and_loops_become_lists() {
+        assert_eq!(line_list(Mode::LineStrip, &[0, 1, 2]), [0, 1, 1, 2]);
+        assert_eq!(line_list(Mode::LineLoop, &[0, 1, 2]), [0, 1, 1, 2, 2, 0]);
+        assert!(line_list(Mode::LineLoop, &[0]).is_empty());
+    }
+
+    #[test]
+    fn flat_normals_face_the_front() {
+        let vertex = |position| MyVertex {
+            position,
+            ..Default::default()
+        };
+        let vertices = [
+            vertex([0.0, 0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0]),
+            vertex([1.0, 1.0, 0.0]),
+        ];
+        // Two counter-clockwise triangles sharing an edge, seen from +Z.
+        let indices = [0, 1, 2, 2, 1, 3];
+        let flat = with_flat_normals(&vertices, Some(&indices), false);
+        assert_eq!(flat.len(), 6);
+        // Lit by a light in front rather than black.
+        assert!(flat.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+
+        let mirrored = with_flat_normals(&vertices[..3], None, true);
+        assert!(mirrored.iter().all(|v| v.normal == [0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn computed_normals_point_out_of_a_cube() {
+        let mut vertices: Vec<_> = (0..8)
+            .map(|i| MyVertex {
+                position: [i & 1, i >> 1 & 1, i >> 2 & 1].map(|c| c as f32 * 2.0 - 1.0),
+                ..Default::default()
+            })
+            .collect();
+        // Two counter-clockwise triangles per face, seen from outside.
+        #[rustfmt::skip]
+        let indices = [
+            0, 2, 1, 1, 2, 3, // -Z
+            4, 5, 6, 5, 7, 6, // +Z
+            0, 1, 4, 1, 5, 4, // -Y
+            2, 6, 3, 3, 6, 7, // +Y
+            0, 4, 2, 2, 4, 6, // -X
+            1, 3, 5, 3, 7, 5, // +X
+        ];
+        compute_normals(&mut vertices, Some(&indices), false);
+        for v in &vertices {
+            let normal = Vector3::from(v.normal);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-5);
+            // Within the octant of the corner, pointing away from the center.
+            assert!((0..3).all(|i| normal[i] * v.position[i] > 0.0));
+        }
+    }
+
+    #[test]
+    fn meshes_keep_their_colors_and_second_uv_set() {
+        let vertex = |position, color| MyVertex {
+            position,
+            tex_coord_1: [0.5, 0.25],
+            color,
+            ..Default::default()
+        };
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let mesh = Mesh::new(
+            vec![
+                vertex([0.0, 0.0, 0.0], red),
+                vertex([1.0, 0.0, 0.0], red),
+                vertex([0.0, 1.0, 0.0], [0.0, 0.0, 1.0, 0.5]),
+            ],
+            vec![0, 1, 2],
+        )
+        .unwrap();
+        let scene = SceneData::from_meshes(&[mesh], CoordinateSystem::default());
+        let model = &scene.models[0];
+        assert_eq!(model.indices.as_deref(), Some(&[0, 1, 2][..]));
+        assert_eq!(model.vertices[2].color, [0.0, 0.0, 1.0, 0.5]);
+        assert_eq!(model.vertices[0].tex_coord_1, [0.5, 0.25]);
+        // The zero normals are regenerated.
+        assert_eq!(model.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(scene.footprint().triangles, 1);
+
+        assert!(Mesh::new(vec![vertex([0.0; 3], red)], vec![0, 0]).is_err());
+        assert!(Mesh::new(vec![vertex([0.0; 3], red)], vec![0, 0, 1]).is_err());
     }
 }
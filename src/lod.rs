@@ -0,0 +1,104 @@
+//! Picking one of several versions of an object by how large it appears on screen.
+
+use cgmath::{InnerSpace, MetricSpace};
+
+use crate::{bounds::Aabb, pipeline::sample::Camera};
+
+/// The default for [`App::set_lod_screen_sizes`].
+///
+/// [`App::set_lod_screen_sizes`]: crate::App::set_lod_screen_sizes
+pub const DEFAULT_LOD_SCREEN_SIZES: [f32; 3] = [0.5, 0.25, 0.125];
+
+/// Models that are versions of the same object, finest first. Only one of them is drawn at a
+/// time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LodGroup {
+    /// Indices into [`easy_gltf::Scene::models`].
+    pub levels: Vec<usize>,
+}
+
+impl LodGroup {
+    /// Groups models named `<name>_LOD<n>` by `<name>`, from each model's name and level.
+    /// Groups need at least two levels.
+    pub(crate) fn from_names<'a>(
+        names: impl IntoIterator<Item = Option<(&'a str, u32)>>,
+    ) -> Vec<LodGroup> {
+        let mut groups: Vec<(&str, Vec<(u32, usize)>)> = Vec::new();
+        for (model, name) in names.into_iter().enumerate() {
+            let Some((name, level)) = name else {
+                continue;
+            };
+            match groups.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, levels)) => levels.push((level, model)),
+                None => groups.push((name, vec![(level, model)])),
+            }
+        }
+        groups
+            .into_iter()
+            .filter(|(_, levels)| levels.len() > 1)
+            .map(|(_, mut levels)| {
+                levels.sort();
+                LodGroup {
+                    levels: levels.into_iter().map(|(_, model)| model).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Height of `bounds`' bounding sphere as seen by `camera`, as a fraction of the screen's.
+/// Infinite from inside the sphere.
+pub fn screen_size(bounds: &Aabb, camera: &Camera) -> f32 {
+    let radius = bounds.size().magnitude() / 2.0;
+    let distance = camera.position.distance(bounds.center());
+    if distance <= radius {
+        return f32::INFINITY;
+    }
+    // `proj.y.y` is the cotangent of half the vertical field of view, negated by the Y flip.
+    radius * camera.proj.y.y.abs() / distance
+}
+
+/// The level to draw out of `level_count` at `screen_size`: the first level down to
+/// `screen_sizes[0]`, the second down to `screen_sizes[1]` and so on, with the last level
+/// below all of them.
+pub fn select_level(screen_size: f32, screen_sizes: &[f32], level_count: usize) -> usize {
+    let level = screen_sizes
+        .iter()
+        .take_while(|&&size| screen_size < size)
+        .count();
+    level.min(level_count.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_coarser_levels_further_away() {
+        let sizes = DEFAULT_LOD_SCREEN_SIZES;
+        assert_eq!(select_level(f32::INFINITY, &sizes, 3), 0);
+        assert_eq!(select_level(0.5, &sizes, 3), 0);
+        assert_eq!(select_level(0.3, &sizes, 3), 1);
+        assert_eq!(select_level(0.2, &sizes, 3), 2);
+        // Past the coarsest level there is.
+        assert_eq!(select_level(0.01, &sizes, 3), 2);
+    }
+
+    #[test]
+    fn groups_levels_by_name() {
+        let names = [
+            Some(("Tree", 1)),
+            None,
+            Some(("Tree", 0)),
+            Some(("Rock", 0)),
+            Some(("Tree", 2)),
+        ];
+        let groups = LodGroup::from_names(names);
+        assert_eq!(
+            groups,
+            [LodGroup {
+                levels: vec![2, 0, 4]
+            }]
+        );
+    }
+}
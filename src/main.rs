@@ -1,5 +1,17 @@
-fn main() {
-    let mut app = rt_renderer::App::new();
-    let scenes = easy_gltf::load("./su.glb").unwrap();
-    app.run(&scenes[0]);
+use std::{error::Error, path::Path};
+
+use rt_renderer::{animation::SceneAnimation, model_file};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./su.glb".to_owned());
+    let path = Path::new(&path);
+    let mut app = rt_renderer::App::new()?;
+    // Only glTF files have animations.
+    if model_file::is_gltf(path) {
+        app.set_animation(SceneAnimation::load(path, 0)?);
+    }
+    app.run_file(path)?;
+    Ok(())
 }
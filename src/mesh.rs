@@ -0,0 +1,62 @@
+//! Meshes built in code rather than loaded from a file.
+
+use thiserror::Error;
+
+use crate::MyVertex;
+
+#[derive(Debug, Error)]
+pub enum MeshError {
+    #[error("{0} indices don't make whole triangles")]
+    PartialTriangle(usize),
+    #[error("index {index} is out of range of {vertex_count} vertices")]
+    IndexOutOfRange { index: u32, vertex_count: usize },
+}
+
+/// An indexed triangle list, rendered with [`crate::App::run_meshes`]. Each mesh becomes one
+/// model with the default material, tinted by its vertices' colors.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub name: Option<String>,
+    /// Joints and weights are ignored, these meshes aren't skinned. If any normal has zero
+    /// length, all of them are replaced with smooth ones.
+    pub vertices: Vec<MyVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Fails if the indices don't make whole triangles or any is out of range of `vertices`.
+    pub fn new(vertices: Vec<MyVertex>, indices: Vec<u32>) -> Result<Mesh, MeshError> {
+        let mesh = Mesh {
+            name: None,
+            vertices,
+            indices,
+        };
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    /// Checks what [`Mesh::new`] does, for meshes whose fields were set directly.
+    pub fn validate(&self) -> Result<(), MeshError> {
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(MeshError::PartialTriangle(self.indices.len()));
+        }
+        match self
+            .indices
+            .iter()
+            .find(|&&i| i as usize >= self.vertices.len())
+        {
+            Some(&index) => Err(MeshError::IndexOutOfRange {
+                index,
+                vertex_count: self.vertices.len(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn with_name(self, name: impl Into<String>) -> Mesh {
+        Mesh {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! Loading models from glTF, Wavefront OBJ and Stanford PLY files alike, as [`Mesh`]es of
+//! [`MyVertex`] data that render with [`crate::App::run_meshes`].
+
+use std::{collections::HashMap, error::Error, path::Path};
+
+use easy_gltf::model::Mode;
+use thiserror::Error;
+
+use crate::{compute_normals, mesh::Mesh, triangle_list, MyVertex};
+
+#[derive(Debug, Error)]
+pub enum ModelFileError {
+    #[error("unsupported model file extension {0:?}")]
+    Extension(String),
+    #[error("invalid OBJ file, line {line}: {message}")]
+    Obj { line: usize, message: &'static str },
+    #[error("invalid PLY file: {0}")]
+    Ply(&'static str),
+}
+
+/// Loads the models in the file at `path`, picking the format by its extension: `gltf` and
+/// `glb`, `obj`, or `ply`. Models without normals get smooth ones.
+///
+/// glTF files give the triangles of their first scene, without materials, see
+/// [`crate::App::run_file`] for rendering them in full. OBJ files give one model per object or
+/// group, and PLY files a single one. Their materials and colors are ignored.
+pub fn load_models(path: impl AsRef<Path>) -> Result<Vec<Mesh>, Box<dyn Error + Send + Sync>> {
+    let path = path.as_ref();
+    let extension = extension(path);
+    let meshes = match extension.as_str() {
+        "gltf" | "glb" => {
+            let scenes = easy_gltf::load(path)?;
+            return Ok(scenes.first().map_or_else(Vec::new, |scene| {
+                scene.models.iter().filter_map(gltf_mesh).collect()
+            }));
+        }
+        "obj" => parse_obj(&std::fs::read_to_string(path)?)?,
+        "ply" => vec![parse_ply(&std::fs::read(path)?)?],
+        _ => return Err(ModelFileError::Extension(extension).into()),
+    };
+    Ok(meshes.into_iter().map(FileMesh::into_mesh).collect())
+}
+
+/// Whether [`crate::App::run_file`] loads `path` as a glTF scene rather than through
+/// [`load_models`].
+pub fn is_gltf(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "gltf" | "glb")
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// `model` as a triangle list, or `None` for lines and points.
+fn gltf_mesh(model: &easy_gltf::Model) -> Option<Mesh> {
+    let mut vertices: Vec<_> = model
+        .vertices()
+        .iter()
+        .map(|&v| MyVertex::from(v))
+        .collect();
+    let indices = match model.indices() {
+        Some(indices) => indices.clone(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    let indices = match model.mode() {
+        Mode::Triangles => indices,
+        mode @ (Mode::TriangleStrip | Mode::TriangleFan) => triangle_list(mode, &indices, u32::MAX),
+        _ => return None,
+    };
+    if !model.has_normals() {
+        compute_normals(&mut vertices, Some(&indices), false);
+    }
+    Some(Mesh {
+        name: None,
+        vertices,
+        indices,
+    })
+}
+
+/// An indexed triangle list, as read from a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct FileMesh {
+    name: Option<String>,
+    positions: Vec<[f32; 3]>,
+    /// Either empty or one per position, as are the other attributes.
+    normals: Vec<[f32; 3]>,
+    /// From the top left, like glTF's.
+    tex_coords: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl FileMesh {
+    /// With smooth normals if the file has none, and the texture coordinates in both UV sets.
+    fn into_mesh(self) -> Mesh {
+        let mut vertices: Vec<_> = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, &position)| {
+                let tex_coord = self.tex_coords.get(i).copied().unwrap_or_default();
+                MyVertex {
+                    position,
+                    normal: self.normals.get(i).copied().unwrap_or_default(),
+                    tex_coord,
+                    tex_coord_1: tex_coord,
+                    joints: [0; 4],
+                    weights: [1.0, 0.0, 0.0, 0.0],
+                    color: [1.0; 4],
+                }
+            })
+            .collect();
+        if self.normals.is_empty() {
+            compute_normals(&mut vertices, Some(&self.indices), false);
+        }
+        Mesh {
+            name: self.name,
+            vertices,
+            indices: self.indices,
+        }
+    }
+}
+
+/// Triangulates polygons as fans. Each distinct combination of position, texture coordinate
+/// and normal indices becomes one vertex.
+fn parse_obj(source: &str) -> Result<Vec<FileMesh>, ModelFileError> {
+    let mut positions = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut normals = Vec::new();
+    let mut meshes = Vec::new();
+    let mut mesh = FileMesh::default();
+    let mut vertices = HashMap::new();
+    // Faces with missing normals or texture coordinates are given zero ones, see `FileMesh::normals`.
+    let (mut missing_normals, mut missing_tex_coords) = (false, false);
+
+    let mut finish = |mut mesh: FileMesh, missing_normals: bool, missing_tex_coords: bool| {
+        if missing_normals {
+            mesh.normals.clear();
+        }
+        if missing_tex_coords {
+            mesh.tex_coords.clear();
+        }
+        if !mesh.indices.is_empty() {
+            meshes.push(mesh);
+        }
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |message| ModelFileError::Obj {
+            line: i + 1,
+            message,
+        };
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => positions.push(floats(words).ok_or(error("invalid position"))?),
+            Some("vn") => normals.push(floats(words).ok_or(error("invalid normal"))?),
+            Some("vt") => {
+                let [u, v] = floats(words).ok_or(error("invalid texture coordinate"))?;
+                tex_coords.push([u, 1.0 - v]);
+            }
+            Some("o" | "g") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                let next = FileMesh {
+                    name: (!name.is_empty()).then_some(name),
+                    ..Default::default()
+                };
+                let done = std::mem::replace(&mut mesh, next);
+                finish(done, missing_normals, missing_tex_coords);
+                vertices.clear();
+                (missing_normals, missing_tex_coords) = (false, false);
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for word in words {
+                    let mut refs = word.split('/');
+                    let mut index = |len: usize| match refs.next() {
+                        None | Some("") => Ok(None),
+                        Some(index) => obj_index(index, len)
+                            .map(Some)
+                            .ok_or(error("index out of range")),
+                    };
+                    let key = (
+                        index(positions.len())?.ok_or(error("face vertex without a position"))?,
+                        index(tex_coords.len())?,
+                        index(normals.len())?,
+                    );
+                    let vertex = *vertices.entry(key).or_insert_with(|| {
+                        let (position, tex_coord, normal) = key;
+                        missing_tex_coords |= tex_coord.is_none();
+                        missing_normals |= normal.is_none();
+                        mesh.positions.push(positions[position]);
+                        mesh.tex_coords
+                            .push(tex_coord.map_or([0.0; 2], |t| tex_coords[t]));
+                        mesh.normals.push(normal.map_or([0.0; 3], |n| normals[n]));
+                        mesh.positions.len() as u32 - 1
+                    });
+                    face.push(vertex);
+                }
+                if face.len() < 3 {
+                    return Err(error("face with fewer than three vertices"));
+                }
+                for j in 1..face.len() - 1 {
+                    mesh.indices.extend([face[0], face[j], face[j + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(mesh, missing_normals, missing_tex_coords);
+    Ok(meshes)
+}
+
+/// Resolves a 1-based OBJ index, or one counting back from the end if negative, into one of
+/// `len` elements.
+fn obj_index(index: &str, len: usize) -> Option<usize> {
+    let index: i64 = index.parse().ok()?;
+    let index = match index < 0 {
+        true => len as i64 + index,
+        false => index - 1,
+    };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// The first `N` of `words` as floats, ignoring the rest, like the `w` of OBJ positions.
+fn floats<'a, const N: usize>(mut words: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let mut floats = [0.0; N];
+    for float in &mut floats {
+        *float = words.next()?.parse().ok()?;
+    }
+    Some(floats)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Option<PlyScalar> {
+        Some(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+}
+
+enum PlyProperty {
+    Scalar(String, PlyScalar),
+    /// A count of type `.1` followed by that many items of type `.2`.
+    List(String, PlyScalar, PlyScalar),
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads the scalars of a PLY file's body one by one.
+struct PlyReader<'a> {
+    format: PlyFormat,
+    body: &'a [u8],
+    words: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, scalar: PlyScalar) -> Result<f64, ModelFileError> {
+        let truncated = ModelFileError::Ply("truncated body");
+        if self.format == PlyFormat::Ascii {
+            let word = self.words.next().ok_or(truncated)?;
+            return word
+                .parse()
+                .map_err(|_| ModelFileError::Ply("invalid number"));
+        }
+        if self.body.len() < scalar.size() {
+            return Err(truncated);
+        }
+        let (bytes, rest) = self.body.split_at(scalar.size());
+        self.body = rest;
+        let mut buf = [0; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let big_endian = self.format == PlyFormat::BigEndian;
+        macro_rules! decode {
+            ($ty:ty) => {{
+                let bytes = buf[..size_of::<$ty>()].try_into().unwrap();
+                match big_endian {
+                    true => <$ty>::from_be_bytes(bytes),
+                    false => <$ty>::from_le_bytes(bytes),
+                }
+            }};
+        }
+        Ok(match scalar {
+            PlyScalar::I8 => decode!(i8) as f64,
+            PlyScalar::U8 => decode!(u8) as f64,
+            PlyScalar::I16 => decode!(i16) as f64,
+            PlyScalar::U16 => decode!(u16) as f64,
+            PlyScalar::I32 => decode!(i32) as f64,
+            PlyScalar::U32 => decode!(u32) as f64,
+            PlyScalar::F32 => decode!(f32) as f64,
+            PlyScalar::F64 => decode!(f64),
+        })
+    }
+}
+
+/// Reads the `vertex` and `face` elements, in ASCII or either binary format. Faces are
+/// triangulated as fans.
+fn parse_ply(bytes: &[u8]) -> Result<FileMesh, ModelFileError> {
+    const END: &[u8] = b"end_header";
+    let header_end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or(ModelFileError::Ply("missing end_header"))?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| header_end + i + 1);
+    let header = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| ModelFileError::Ply("header isn't text"))?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(ModelFileError::Ply("missing ply magic"));
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    let invalid = ModelFileError::Ply;
+    for line in lines {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    _ => return Err(invalid("unknown format")),
+                });
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_owned(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let scalar = |s| PlyScalar::parse(s).ok_or(invalid("unknown property type"));
+                elements
+                    .last_mut()
+                    .ok_or(invalid("property outside of an element"))?
+                    .properties
+                    .push(PlyProperty::List(
+                        name.to_owned(),
+                        scalar(count)?,
+                        scalar(item)?,
+                    ));
+            }
+            ["property", ty, name] => {
+                let scalar = PlyScalar::parse(ty).ok_or(invalid("unknown property type"))?;
+                elements
+                    .last_mut()
+                    .ok_or(invalid("property outside of an element"))?
+                    .properties
+                    .push(PlyProperty::Scalar(name.to_owned(), scalar));
+            }
+            _ => {}
+        }
+    }
+
+    let body = &bytes[body_start..];
+    let format = format.ok_or(invalid("missing format"))?;
+    let words = match format {
+        PlyFormat::Ascii => std::str::from_utf8(body)
+            .map_err(|_| invalid("ASCII body isn't text"))?
+            .split_ascii_whitespace(),
+        _ => "".split_ascii_whitespace(),
+    };
+    let mut reader = PlyReader {
+        format,
+        body,
+        words,
+    };
+
+    let mut mesh = FileMesh::default();
+    let (mut has_normals, mut has_tex_coords) = (false, false);
+    for element in &elements {
+        // Reading the others takes some of the body per item, which bounds their count.
+        if element.properties.is_empty() && element.count > 0 {
+            return Err(invalid("element without properties"));
+        }
+        for _ in 0..element.count {
+            let mut position = [0.0; 3];
+            let mut normal = [0.0; 3];
+            let mut tex_coord = [0.0; 2];
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar(name, scalar) => {
+                        let value = reader.read(*scalar)? as f32;
+                        match name.as_str() {
+                            "x" => position[0] = value,
+                            "y" => position[1] = value,
+                            "z" => position[2] = value,
+                            "nx" => normal[0] = value,
+                            "ny" => normal[1] = value,
+                            "nz" => normal[2] = value,
+                            "s" | "u" | "texture_u" => tex_coord[0] = value,
+                            "t" | "v" | "texture_v" => tex_coord[1] = value,
+                            _ => {}
+                        }
+                    }
+                    PlyProperty::List(name, count, item) => {
+                        let is_face = element.name == "face"
+                            && matches!(name.as_str(), "vertex_indices" | "vertex_index");
+                        let count = reader.read(*count)? as usize;
+                        let mut face = Vec::new();
+                        for _ in 0..count {
+                            let index = reader.read(*item)?;
+                            if is_face && index < 0.0 {
+                                return Err(invalid("negative face index"));
+                            }
+                            face.push(index as u32);
+                        }
+                        if is_face && count >= 3 {
+                            for j in 1..count - 1 {
+                                mesh.indices.extend([face[0], face[j], face[j + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                mesh.positions.push(position);
+                mesh.normals.push(normal);
+                // PLY texture coordinates start at the bottom, like OBJ ones.
+                mesh.tex_coords.push([tex_coord[0], 1.0 - tex_coord[1]]);
+            }
+        }
+        if element.name == "vertex" {
+            let names = element.properties.iter().map(|p| match p {
+                PlyProperty::Scalar(name, _) | PlyProperty::List(name, _, _) => name.as_str(),
+            });
+            for name in names {
+                has_normals |= name == "nx";
+                has_tex_coords |= matches!(name, "s" | "u" | "texture_u");
+            }
+        }
+    }
+
+    if mesh
+        .indices
+        .iter()
+        .any(|&i| i as usize >= mesh.positions.len())
+    {
+        return Err(invalid("face index out of range"));
+    }
+    if !has_normals {
+        mesh.normals.clear();
+    }
+    if !has_tex_coords {
+        mesh.tex_coords.clear();
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD_OBJ: &str = "\
+o Quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+f 1/1 2/1 3/2 4/2
+";
+
+    #[test]
+    fn triangulates_obj_faces() {
+        let meshes = parse_obj(QUAD_OBJ).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.name.as_deref(), Some("Quad"));
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        // Flipped to start at the top, and without any normals to keep.
+        assert_eq!(mesh.tex_coords[0], [0.0, 1.0]);
+        assert!(mesh.normals.is_empty());
+
+        assert!(parse_obj("v 0 0 0\nf 1 2 3\n").is_err());
+        assert_eq!(obj_index("-1", 4), Some(3));
+    }
+
+    #[test]
+    fn reads_ascii_and_binary_ply() {
+        let header = |format| {
+            format!(
+                "ply\nformat {format} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                 property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+                 end_header\n"
+            )
+        };
+        let ascii = header("ascii") + "0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let mut binary = header("binary_little_endian").into_bytes();
+        for f in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend(f.to_le_bytes());
+        }
+        binary.push(3);
+        for i in [0i32, 1, 2] {
+            binary.extend(i.to_le_bytes());
+        }
+
+        for bytes in [ascii.into_bytes(), binary] {
+            let mesh = parse_ply(&bytes).unwrap();
+            assert_eq!(mesh.positions[1], [1.0, 0.0, 0.0]);
+            assert_eq!(mesh.indices, [0, 1, 2]);
+            assert!(mesh.normals.is_empty() && mesh.tex_coords.is_empty());
+        }
+
+        // A face claiming more indices than the body holds.
+        let huge = header("ascii").replace("uchar int", "uint int")
+            + "0 0 0\n1 0 0\n0 1 0\n4000000000 0\n";
+        assert!(parse_ply(huge.as_bytes()).is_err());
+
+        // Nothing to read per vertex, so nothing bounds how many there are.
+        let empty = "ply\nformat ascii 1.0\nelement vertex 18446744073709551615\nend_header\n";
+        assert!(parse_ply(empty.as_bytes()).is_err());
+        let negative = header("ascii") + "0 0 0\n1 0 0\n0 1 0\n3 0 -1 2\n";
+        assert!(parse_ply(negative.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn converts_file_meshes_to_vertices() {
+        let meshes = parse_obj(QUAD_OBJ).unwrap();
+        let mesh = meshes[0].clone().into_mesh();
+        assert_eq!(mesh.name.as_deref(), Some("Quad"));
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        let vertex = mesh.vertices[2];
+        assert_eq!(vertex.position, [1.0, 1.0, 0.0]);
+        assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        assert_eq!(vertex.tex_coord, [1.0, 0.0]);
+        assert_eq!(vertex.tex_coord_1, vertex.tex_coord);
+        assert!(mesh.validate().is_ok());
+    }
+}
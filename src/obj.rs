@@ -0,0 +1,108 @@
+//! Writing the converted geometry out as Wavefront OBJ, e.g. to check the coordinate conversion
+//! in another tool.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use easy_gltf::model::Mode;
+
+use crate::{ModelData, MyVertex};
+
+/// Writes the triangles of `models` as one object each, named by their index in the scene.
+/// Vertices are in the bind pose, without skinning or morphing.
+pub(crate) fn export_obj(models: &[ModelData], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut base = 1;
+    for (i, model) in models.iter().enumerate() {
+        // Strips and fans were already turned into lists, lines and points have no faces.
+        // Models without vertices aren't uploaded either.
+        let triangles = matches!(
+            model.mode,
+            Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+        );
+        if !triangles || model.vertices.is_empty() {
+            continue;
+        }
+        writeln!(out, "o model_{i}")?;
+        write_mesh(
+            &mut out,
+            &model.vertices,
+            model.indices.as_deref(),
+            model.mirrored,
+            base,
+        )?;
+        base += model.vertices.len() as u32;
+    }
+    out.flush()
+}
+
+/// Writes one mesh whose first vertex is numbered `base`, counting from 1 across the file.
+/// Mirrored triangles are flipped back to counter-clockwise.
+fn write_mesh(
+    out: &mut impl Write,
+    vertices: &[MyVertex],
+    indices: Option<&[u32]>,
+    mirrored: bool,
+    base: u32,
+) -> io::Result<()> {
+    for v in vertices {
+        let [x, y, z] = v.position;
+        writeln!(out, "v {x} {y} {z}")?;
+    }
+    for v in vertices {
+        let [x, y, z] = v.normal;
+        writeln!(out, "vn {x} {y} {z}")?;
+    }
+    // OBJ texture coordinates start at the bottom, glTF ones at the top.
+    for v in vertices {
+        let [u, v] = v.tex_coord;
+        writeln!(out, "vt {u} {}", 1.0 - v)?;
+    }
+
+    let sequential: Vec<u32>;
+    let indices = match indices {
+        Some(indices) => indices,
+        None => {
+            sequential = (0..vertices.len() as u32).collect();
+            &sequential
+        }
+    };
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i + base);
+        let [a, b, c] = match mirrored {
+            true => [a, c, b],
+            false => [a, b, c],
+        };
+        writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_faces_from_the_base() {
+        let vertex = |position| MyVertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            tex_coord: [0.0, 0.25],
+            ..Default::default()
+        };
+        let vertices = [
+            vertex([0.0, 0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0]),
+        ];
+        let mut out = Vec::new();
+        write_mesh(&mut out, &vertices, Some(&[0, 1, 2]), true, 4).unwrap();
+        let obj = String::from_utf8(out).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 3);
+        assert!(obj.contains("vt 0 0.75\n"));
+        assert!(obj.ends_with("f 4/4/4 6/6/6 5/5/5\n"));
+    }
+}
@@ -0,0 +1,22 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+layout(location = 1) out vec4 outSum;
+
+layout(set = 0, binding = 0) uniform sampler2D currentTexture;
+layout(set = 0, binding = 1) uniform sampler2D sumTexture;
+
+layout(push_constant) uniform PushConstants {
+  uint samples; // including the current frame
+}
+pc;
+
+void main() {
+  ivec2 texel = ivec2(gl_FragCoord.xy);
+  vec3 sum = texelFetch(currentTexture, texel, 0).rgb;
+  if (pc.samples > 1) {
+    sum += texelFetch(sumTexture, texel, 0).rgb;
+  }
+  outSum = vec4(sum, 1.0);
+  outColor = vec4(sum / float(pc.samples), 1.0);
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{
+        sampler::{Sampler, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass, resolve_target_usage},
+    App,
+};
+
+use super::{pipeline_layout, taa::HISTORY_FORMAT};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/accumulation/accumulate.frag");
+}
+
+/// Format of the running sum; full floats so that thousands of samples don't lose precision.
+pub const SUM_FORMAT: Format = Format::R32G32B32A32_SFLOAT;
+
+/// Progressive supersampling of the rasterized frame: averages every jittered frame since the last
+/// reset into a running sum, so a static view converges as the jitter covers each pixel.
+pub struct AccumulationPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    scene_image: Arc<ImageView>,
+    sums: [Arc<ImageView>; 2],
+    /// Index `i` reads `sums[i]`, and is used while `sums[1 - i]` is written.
+    descriptor_sets: [Arc<DescriptorSet>; 2],
+}
+
+impl AccumulationPipeline {
+    /// `output_format` is the format of the images passed to [`Self::render`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        output_format: Format,
+        extent: [u32; 2],
+    ) -> AccumulationPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = fs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(output_format), Some(SUM_FORMAT)],
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(VertexInputState::default()),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        rendering_info.color_attachment_formats.len() as u32,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        let sampler = Sampler::new(device.clone(), SamplerCreateInfo::default()).unwrap();
+        let (scene_image, sums, descriptor_sets) = Self::targets(app, &pipeline, &sampler, extent);
+
+        Self {
+            pipeline,
+            sampler,
+            scene_image,
+            sums,
+            descriptor_sets,
+        }
+    }
+
+    /// Recreates the images for frames of `extent`. The next frame should start over.
+    pub fn resize(&mut self, app: &App, extent: [u32; 2]) {
+        (self.scene_image, self.sums, self.descriptor_sets) =
+            Self::targets(app, &self.pipeline, &self.sampler, extent);
+    }
+
+    /// The scene image and sums of `extent`, and the sets reading them.
+    fn targets(
+        app: &App,
+        pipeline: &GraphicsPipeline,
+        sampler: &Arc<Sampler>,
+        extent: [u32; 2],
+    ) -> (Arc<ImageView>, [Arc<ImageView>; 2], [Arc<DescriptorSet>; 2]) {
+        let image = |format| {
+            ImageView::new_default(
+                Image::new(
+                    app.memory_allocator(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [extent[0], extent[1], 1],
+                        format,
+                        // The main pass may resolve into the scene image.
+                        usage: resolve_target_usage(
+                            app.context.device(),
+                            ImageUsage::COLOR_ATTACHMENT
+                                | ImageUsage::SAMPLED
+                                | ImageUsage::TRANSFER_SRC,
+                        ),
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let scene_image = image(HISTORY_FORMAT);
+        let sums = [image(SUM_FORMAT), image(SUM_FORMAT)];
+        let descriptor_sets = sums.clone().map(|sum| {
+            DescriptorSet::new(
+                app.descriptor_set_allocator.clone(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view_sampler(0, scene_image.clone(), sampler.clone()),
+                    WriteDescriptorSet::image_view_sampler(1, sum, sampler.clone()),
+                ],
+                [],
+            )
+            .unwrap()
+        });
+        (scene_image, sums, descriptor_sets)
+    }
+
+    /// The image the main pass should render into instead of the swapchain.
+    pub fn scene_image(&self) -> Arc<ImageView> {
+        self.scene_image.clone()
+    }
+
+    /// Adds the scene image to the running sum and writes the average to `dst_image`.
+    /// `samples` counts the frames accumulated so far including this one; 1 starts over.
+    /// Must be recorded outside of any render pass, after the main pass.
+    pub fn render(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        samples: u32,
+    ) {
+        let read = (samples % 2) as usize;
+        let extent = self.scene_image.image().extent();
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: [dst_image, self.sums[1 - read].clone()]
+                    .into_iter()
+                    .map(|view| {
+                        Some(RenderingAttachmentInfo {
+                            load_op: AttachmentLoadOp::DontCare,
+                            store_op: AttachmentStoreOp::Store,
+                            ..RenderingAttachmentInfo::image_view(view)
+                        })
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[read].clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::PushConstants { samples },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+
+        rendering.end(builder);
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{DrawIndexedIndirectCommand, DrawIndirectCommand, RecordingCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        compute::ComputePipelineCreateInfo, ComputePipeline, Pipeline, PipelineBindPoint,
+        PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{pipeline_layout, sample::Camera};
+use crate::{bounds::Aabb, App};
+
+mod cs {
+    vulkano_shaders::shader!(ty: "compute", path: "src/pipeline/culling/cull.comp");
+}
+
+const LOCAL_SIZE: u32 = 64;
+
+/// What [`CullingPipeline`] decides on for one model.
+pub struct CulledDraw {
+    pub bounds: Aabb,
+    /// Models that move past their bounds, e.g. skinned ones, are always drawn.
+    pub cullable: bool,
+    /// `Some(index_count)` for indexed draws, else the vertex count is used.
+    pub index_count: Option<u32>,
+    pub vertex_count: u32,
+}
+
+/// Frustum-culls models on the GPU, writing one indirect draw command per model whose instance
+/// count is zero when it is out of view. Models bind their own descriptor sets, so each command is
+/// still drawn on its own rather than with a single multi-draw.
+pub struct CullingPipeline {
+    pipeline: Arc<ComputePipeline>,
+    descriptor_set: Arc<DescriptorSet>,
+    model_count: u32,
+    /// One per model, each an indexed or non-indexed command.
+    commands: Vec<Subbuffer<[u32]>>,
+}
+
+impl CullingPipeline {
+    pub fn new(app: &App, queue: Arc<Queue>, draws: &[CulledDraw]) -> CullingPipeline {
+        assert!(size_of::<DrawIndexedIndirectCommand>() == 5 * size_of::<u32>());
+        assert!(size_of::<DrawIndirectCommand>() == 4 * size_of::<u32>());
+
+        let device = queue.device();
+
+        let pipeline = {
+            let cs = cs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stage = PipelineShaderStageCreateInfo::new(cs);
+            let layout = pipeline_layout(device, [&stage]);
+
+            ComputePipeline::new(
+                device.clone(),
+                None,
+                ComputePipelineCreateInfo::stage_layout(stage, layout),
+            )
+            .unwrap()
+        };
+
+        // Everything starts out visible, until the first dispatch.
+        let mut words = Vec::new();
+        let mut ranges = Vec::new();
+        let models: Vec<_> = draws
+            .iter()
+            .map(|draw| {
+                let offset = words.len() as u32;
+                // Laid out like `DrawIndexedIndirectCommand` and `DrawIndirectCommand`.
+                match draw.index_count {
+                    Some(index_count) => words.extend([index_count, 1, 0, 0, 0]),
+                    None => words.extend([draw.vertex_count, 1, 0, 0]),
+                }
+                ranges.push(offset as u64..words.len() as u64);
+                cs::CulledModel {
+                    min: draw.bounds.min.to_homogeneous().into(),
+                    max: draw.bounds.max.to_homogeneous().into(),
+                    draw: [offset, draw.cullable as u32, 0, 0],
+                }
+            })
+            .collect();
+
+        let allocation_info = || AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let model_buffer = Buffer::from_iter(
+            app.memory_allocator(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            allocation_info(),
+            models,
+        )
+        .unwrap();
+        let command_buffer = Buffer::from_iter(
+            app.memory_allocator(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::INDIRECT_BUFFER,
+                ..Default::default()
+            },
+            allocation_info(),
+            words,
+        )
+        .unwrap();
+
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, model_buffer),
+                WriteDescriptorSet::buffer(1, command_buffer.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+
+        Self {
+            pipeline,
+            descriptor_set,
+            model_count: draws.len() as u32,
+            commands: ranges
+                .into_iter()
+                .map(|range| command_buffer.clone().slice(range))
+                .collect(),
+        }
+    }
+
+    /// The draw command of the model at this index into the draws passed to [`Self::new`].
+    pub fn command(&self, model: usize) -> Subbuffer<[u32]> {
+        self.commands[model].clone()
+    }
+
+    /// Updates the commands for `camera`. Must be recorded outside of any render pass, before
+    /// the commands are drawn.
+    pub fn cull(&self, builder: &mut RecordingCommandBuffer, camera: &Camera) {
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                cs::PushConstants {
+                    view_proj: (camera.proj * camera.view).into(),
+                    model_count: self.model_count,
+                },
+            )
+            .unwrap();
+        unsafe { builder.dispatch([self.model_count.div_ceil(LOCAL_SIZE), 1, 1]) }.unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+#version 460
+
+layout(location = 0) in vec3 fragPos;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 currentClip;
+layout(location = 3) in vec4 previousClip;
+layout(location = 6) in vec3 fragColor;
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outMaterial;
+layout(location = 3) out vec2 outMotion;
+
+layout(set = 1, binding = 0) uniform Material {
+  vec3 diffuse;
+  vec3 specular;
+  float shininess;
+}
+material;
+
+void main() {
+  // Alpha marks covered pixels so the lighting pass can skip the background.
+  outAlbedo = vec4(material.diffuse * fragColor, 1.0);
+  // Back faces are only drawn for double-sided materials, lit from the side they are seen from.
+  outNormal = vec4(normalize(gl_FrontFacing ? fragNormal : -fragNormal), 0.0);
+  // Occlusion first, though nothing is baked on this path. Specular is stored as a scalar
+  // strength.
+  outMaterial = vec4(1.0, dot(material.specular, vec3(1.0 / 3.0)), material.shininess, 0.0);
+  outMotion = (currentClip.xy / currentClip.w - previousClip.xy / previousClip.w) * 0.5;
+}
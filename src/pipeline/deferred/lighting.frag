@@ -0,0 +1,87 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+layout(location = 1) out vec2 outMotion;
+
+struct Light {
+  vec3 position;
+  uint kind; // 0 point, 1 directional
+  vec3 direction; // normalized, that directional lights travel in
+  vec3 diffuse;
+  vec3 specular;
+};
+
+layout(set = 0, binding = 0) uniform sampler2D albedoTexture;
+layout(set = 0, binding = 1) uniform sampler2D normalTexture;
+layout(set = 0, binding = 2) uniform sampler2D materialTexture;
+layout(set = 0, binding = 3) uniform sampler2D depthTexture;
+layout(set = 0, binding = 4) readonly buffer Lights { Light lights[]; };
+layout(set = 0, binding = 5) uniform sampler2D motionTexture;
+layout(set = 0, binding = 6) uniform Fog {
+  vec3 color;
+  uint falloff; // 0 for no fog
+  float start;
+  float end;
+  float density;
+}
+fog;
+
+layout(push_constant) uniform PushConstants {
+  mat4 inv_view_proj;
+  vec3 camera_pos;
+  float occlusion_strength;
+  vec3 ambient;
+  vec3 camera_forward;
+}
+pc;
+
+// Must match `FogSettings::shader_terms` in `pipeline/sample/mod.rs`.
+const uint FOG_LINEAR = 1;
+const uint FOG_EXPONENTIAL = 2;
+
+vec3 applyFog(vec3 color, float depth) {
+  float amount = 0.0;
+  switch (fog.falloff) {
+  case FOG_LINEAR:
+    amount = clamp((depth - fog.start) / (fog.end - fog.start), 0.0, 1.0);
+    break;
+  case FOG_EXPONENTIAL:
+    amount = 1.0 - exp(-fog.density * max(depth, 0.0));
+    break;
+  }
+  return mix(color, fog.color, amount);
+}
+
+void main() {
+  ivec2 texel = ivec2(gl_FragCoord.xy);
+  vec4 albedo = texelFetch(albedoTexture, texel, 0);
+  outMotion = texelFetch(motionTexture, texel, 0).xy;
+  if (albedo.a == 0.0) {
+    outColor = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+  vec3 norm = texelFetch(normalTexture, texel, 0).xyz;
+  vec3 params = texelFetch(materialTexture, texel, 0).xyz;
+  float depth = texelFetch(depthTexture, texel, 0).r;
+
+  vec2 ndc = (vec2(texel) + 0.5) / vec2(textureSize(depthTexture, 0)) * 2.0 - 1.0;
+  vec4 world = pc.inv_view_proj * vec4(ndc, depth, 1.0);
+  vec3 fragPos = world.xyz / world.w;
+  vec3 viewDir = normalize(pc.camera_pos - fragPos);
+
+  vec3 result = pc.ambient * mix(1.0, params.x, pc.occlusion_strength);
+  for (int i = 0; i < lights.length(); i++) {
+    Light light = lights[i];
+
+    vec3 lightDir = light.kind == 1 ? -light.direction : normalize(light.position - fragPos);
+    float diff = max(dot(norm, lightDir), 0.0);
+    vec3 diffuse = light.diffuse * (diff * albedo.rgb);
+
+    vec3 reflectDir = reflect(-lightDir, norm);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), params.z);
+    vec3 specular = light.specular * (spec * params.y);
+
+    result += diffuse + specular;
+  }
+  outColor = vec4(applyFog(result, dot(fragPos - pc.camera_pos, pc.camera_forward)), 1.0);
+}
@@ -0,0 +1,715 @@
+use std::sync::Arc;
+
+use cgmath::SquareMatrix;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned, Queue},
+    format::{ClearValue, Format},
+    image::{
+        sampler::{Sampler, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    padded::Padded,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::{DepthBiasState, FrontFace, PolygonMode, RasterizationState},
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::{
+    create_uniform_buffer_from_data, draw_mesh, draw_mesh_indirect, flipped, pipeline_layout,
+    sample::{AmbientSettings, Camera, DebugView, FogSettings, LightSettings},
+    update_uniform_buffer, ColorAttachment, DepthBias, FaceCulling, JointMatrix, MorphTargets,
+    MOTION_FORMAT,
+};
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass},
+    App, MyVertex,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/sample/sample.vert");
+}
+
+mod gbuffer_fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/deferred/gbuffer.frag");
+}
+
+mod lighting_vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod lighting_fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/deferred/lighting.frag");
+}
+
+pub use lighting_fs::Light;
+
+/// Formats of the albedo, normal, material and motion attachments, in that order.
+pub const GBUFFER_FORMATS: [Format; 4] = [
+    Format::R8G8B8A8_UNORM,
+    Format::R16G16B16A16_SFLOAT,
+    Format::R16G16B16A16_SFLOAT,
+    MOTION_FORMAT,
+];
+
+pub const GBUFFER_DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+
+pub struct GBuffer {
+    color: [Arc<ImageView>; 4],
+    depth: Arc<ImageView>,
+}
+
+impl GBuffer {
+    pub fn new(allocator: Arc<StandardMemoryAllocator>, extent: [u32; 2]) -> Self {
+        let image = |format, usage| {
+            ImageView::new_default(
+                Image::new(
+                    allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [extent[0], extent[1], 1],
+                        format,
+                        usage: usage | ImageUsage::SAMPLED,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        Self {
+            color: GBUFFER_FORMATS.map(|format| image(format, ImageUsage::COLOR_ATTACHMENT)),
+            depth: image(GBUFFER_DEPTH_FORMAT, ImageUsage::DEPTH_STENCIL_ATTACHMENT),
+        }
+    }
+
+    pub fn normal(&self) -> Arc<ImageView> {
+        self.color[1].clone()
+    }
+
+    /// Ambient occlusion, specular strength and shininess in x, y and z.
+    pub fn material(&self) -> Arc<ImageView> {
+        self.color[2].clone()
+    }
+
+    pub fn depth(&self) -> Arc<ImageView> {
+        self.depth.clone()
+    }
+}
+
+/// Writes surfaces into a [`GBuffer`], then shades every light in one fullscreen pass.
+///
+/// Shadows are not applied on this path.
+pub struct DeferredPipeline {
+    geometry_pipeline: Arc<GraphicsPipeline>,
+    mirrored_geometry_pipeline: Arc<GraphicsPipeline>,
+    /// Without culling, front and mirrored.
+    double_sided_geometry_pipelines: [Arc<GraphicsPipeline>; 2],
+    lighting_pipeline: Arc<GraphicsPipeline>,
+    gbuffer: GBuffer,
+    model_uniform: Subbuffer<vs::ModelBuffer>,
+    lights: Subbuffer<[Light]>,
+    sampler: Arc<Sampler>,
+    /// One per frame in flight, see [`Self::select_frame`].
+    frames: Vec<DeferredFrame>,
+    /// Index into `frames` of the slot being written and bound.
+    frame: usize,
+}
+
+/// The uniforms rewritten every frame, and the sets reading them.
+struct DeferredFrame {
+    motion_uniform: Subbuffer<vs::Motion>,
+    frame_uniform: Subbuffer<vs::Frame>,
+    material_descriptor_set: Arc<DescriptorSet>,
+    fog_uniform: Subbuffer<lighting_fs::Fog>,
+    /// Reads the G-buffer, see [`DeferredPipeline::resize`].
+    lighting_descriptor_set: Arc<DescriptorSet>,
+}
+
+impl DeferredPipeline {
+    /// `rendering_info` describes the main pass the lighting is drawn in; `extent` must match
+    /// its attachments.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+        extent: [u32; 2],
+        front_face: FrontFace,
+        face_culling: FaceCulling,
+        polygon_mode: PolygonMode,
+        lights: &[LightSettings],
+    ) -> DeferredPipeline {
+        let device = queue.device();
+
+        // Mirrored models have their winding flipped by the baked transform.
+        let [geometry_pipeline, mirrored_geometry_pipeline, double_sided, mirrored_double_sided] =
+            Self::geometry_pipelines(device, None, front_face, face_culling, polygon_mode);
+
+        let lighting_pipeline = Self::lighting_pipeline(device, None, rendering_info, samples);
+
+        let gbuffer = GBuffer::new(app.memory_allocator(), extent);
+
+        // set = 0, binding = 0
+        let model_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::ModelBuffer {
+                model: cgmath::Matrix4::identity().into(),
+            },
+        );
+
+        // set = 1, binding = 0
+        let material_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            gbuffer_fs::Material {
+                diffuse: Padded([1.0, 1.0, 1.0]),
+                specular: [0.5, 0.5, 0.5],
+                shininess: 32.0,
+            },
+        );
+
+        let lights = Buffer::from_iter(
+            app.memory_allocator(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            lights.iter().map(|light| {
+                let (kind, position, direction) = light.shader_terms();
+                Light {
+                    position,
+                    kind,
+                    direction: Padded(direction),
+                    diffuse: Padded(light.diffuse()),
+                    specular: light.specular(),
+                }
+            }),
+        )
+        .unwrap();
+
+        // G-buffer texels are fetched directly, so the sampler's filtering is irrelevant.
+        let sampler = Sampler::new(device.clone(), SamplerCreateInfo::default()).unwrap();
+        let frames = (0..app.frames_in_flight)
+            .map(|_| {
+                // set = 1, binding = 4
+                let motion_uniform = create_uniform_buffer_from_data(
+                    app.memory_allocator(),
+                    vs::Motion {
+                        previous_view_proj: cgmath::Matrix4::identity().into(),
+                        jitter: [0.0; 2],
+                    },
+                );
+
+                // set = 1, binding = 8
+                let frame_uniform = create_uniform_buffer_from_data(
+                    app.memory_allocator(),
+                    vs::Frame {
+                        view: cgmath::Matrix4::identity().into(),
+                        proj: cgmath::Matrix4::identity().into(),
+                        camera_pos: [0.0; 3],
+                        debug_view: DebugView::Shaded as u32,
+                    },
+                );
+
+                let material_descriptor_set = DescriptorSet::new(
+                    app.descriptor_set_allocator.clone(),
+                    geometry_pipeline.layout().set_layouts()[1].clone(),
+                    [
+                        WriteDescriptorSet::buffer(0, material_uniform.clone()),
+                        WriteDescriptorSet::buffer(4, motion_uniform.clone()),
+                        WriteDescriptorSet::buffer(8, frame_uniform.clone()),
+                    ],
+                    [],
+                )
+                .unwrap();
+
+                let fog_uniform = create_uniform_buffer_from_data(
+                    app.memory_allocator(),
+                    lighting_fs::Fog {
+                        color: [0.0; 3],
+                        falloff: 0,
+                        start: 0.0,
+                        end: 0.0,
+                        density: 0.0,
+                    },
+                );
+                let lighting_descriptor_set = Self::lighting_descriptor_set(
+                    app,
+                    &lighting_pipeline,
+                    &gbuffer,
+                    &lights,
+                    &fog_uniform,
+                    &sampler,
+                );
+
+                DeferredFrame {
+                    motion_uniform,
+                    frame_uniform,
+                    material_descriptor_set,
+                    fog_uniform,
+                    lighting_descriptor_set,
+                }
+            })
+            .collect();
+
+        Self {
+            geometry_pipeline,
+            mirrored_geometry_pipeline,
+            double_sided_geometry_pipelines: [double_sided, mirrored_double_sided],
+            lighting_pipeline,
+            gbuffer,
+            model_uniform,
+            lights,
+            sampler,
+            frames,
+            frame: 0,
+        }
+    }
+
+    /// Recreates the G-buffer for frames of `extent`. Passes reading it, like
+    /// [`SsrPipeline`](super::ssr::SsrPipeline), need resizing after this.
+    pub fn resize(&mut self, app: &App, extent: [u32; 2]) {
+        self.gbuffer = GBuffer::new(app.memory_allocator(), extent);
+        for frame in &mut self.frames {
+            frame.lighting_descriptor_set = Self::lighting_descriptor_set(
+                app,
+                &self.lighting_pipeline,
+                &self.gbuffer,
+                &self.lights,
+                &frame.fog_uniform,
+                &self.sampler,
+            );
+        }
+    }
+
+    /// Makes the per-frame uniforms of slot `index` the ones written and bound, like
+    /// [`SamplePipeline::select_frame`](super::sample::SamplePipeline::select_frame).
+    pub fn select_frame(&mut self, index: usize) {
+        self.frame = index;
+    }
+
+    fn current_frame(&self) -> &DeferredFrame {
+        &self.frames[self.frame]
+    }
+
+    /// Recreates the lighting pipeline for `samples` per pixel, e.g. after the anti-aliasing
+    /// mode changed. The G-buffer is never multisampled.
+    pub fn set_samples(
+        &mut self,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) {
+        self.lighting_pipeline = Self::lighting_pipeline(
+            self.lighting_pipeline.device(),
+            Some(self.lighting_pipeline.layout().clone()),
+            rendering_info,
+            samples,
+        );
+    }
+
+    /// Recreates the G-buffer pipelines with the face culling of `app`, e.g. after it was
+    /// changed while running.
+    pub fn set_face_culling(&mut self, app: &App) {
+        self.recreate_geometry_pipelines(app);
+    }
+
+    /// Recreates the G-buffer pipelines with the polygon mode of `app`, e.g. after it was
+    /// changed while running.
+    pub fn set_polygon_mode(&mut self, app: &App) {
+        self.recreate_geometry_pipelines(app);
+    }
+
+    fn recreate_geometry_pipelines(&mut self, app: &App) {
+        let [pipeline, mirrored_pipeline, double_sided, mirrored_double_sided] =
+            Self::geometry_pipelines(
+                self.geometry_pipeline.device(),
+                Some(self.geometry_pipeline.layout().clone()),
+                app.front_face,
+                app.face_culling,
+                app.polygon_mode,
+            );
+        self.geometry_pipeline = pipeline;
+        self.mirrored_geometry_pipeline = mirrored_pipeline;
+        self.double_sided_geometry_pipelines = [double_sided, mirrored_double_sided];
+    }
+
+    /// The culled front and mirrored G-buffer pipelines, then the double-sided ones. Creates
+    /// their layout unless given one.
+    fn geometry_pipelines(
+        device: &Arc<Device>,
+        layout: Option<Arc<PipelineLayout>>,
+        front_face: FrontFace,
+        face_culling: FaceCulling,
+        polygon_mode: PolygonMode,
+    ) -> [Arc<GraphicsPipeline>; 4] {
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = gbuffer_fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = MyVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = layout.unwrap_or_else(|| pipeline_layout(device, &stages));
+
+        let gbuffer_rendering_info = PipelineRenderingCreateInfo {
+            color_attachment_formats: GBUFFER_FORMATS.map(Some).to_vec(),
+            depth_attachment_format: Some(GBUFFER_DEPTH_FORMAT),
+            ..Default::default()
+        };
+
+        let pipeline = |(front_face, cull_mode)| {
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::TriangleList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState {
+                        polygon_mode,
+                        line_width: 1.0,
+                        cull_mode,
+                        front_face,
+                        // Set per object, see `DepthBias`.
+                        depth_bias: Some(DepthBiasState::default()),
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        GBUFFER_FORMATS.len() as u32,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth: Some(DepthState {
+                            compare_op: CompareOp::Less,
+                            write_enable: true,
+                        }),
+                        ..Default::default()
+                    }),
+                    dynamic_state: [DynamicState::Viewport, DynamicState::DepthBias]
+                        .into_iter()
+                        .collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        gbuffer_rendering_info.clone(),
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
+        [
+            (front_face, face_culling.cull_mode(false)),
+            (flipped(front_face), face_culling.cull_mode(false)),
+            (front_face, face_culling.cull_mode(true)),
+            (flipped(front_face), face_culling.cull_mode(true)),
+        ]
+        .map(pipeline)
+    }
+
+    /// Creates its layout unless given one.
+    fn lighting_pipeline(
+        device: &Arc<Device>,
+        layout: Option<Arc<PipelineLayout>>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) -> Arc<GraphicsPipeline> {
+        let vs = lighting_vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = lighting_fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = layout.unwrap_or_else(|| pipeline_layout(device, &stages));
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(VertexInputState::default()),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    rendering_info.color_attachment_formats.len() as u32,
+                    ColorBlendAttachmentState::default(),
+                )),
+                // The fullscreen triangle neither tests nor writes depth.
+                depth_stencil_state: Some(DepthStencilState::default()),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                subpass: Some(pipeline_subpass(device, rendering_info, samples)),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    }
+
+    fn lighting_descriptor_set(
+        app: &App,
+        lighting_pipeline: &GraphicsPipeline,
+        gbuffer: &GBuffer,
+        lights: &Subbuffer<[Light]>,
+        fog_uniform: &Subbuffer<lighting_fs::Fog>,
+        sampler: &Arc<Sampler>,
+    ) -> Arc<DescriptorSet> {
+        let [albedo, normal, material, motion] = gbuffer.color.clone();
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            lighting_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, albedo, sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(1, normal, sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(2, material, sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(3, gbuffer.depth.clone(), sampler.clone()),
+                WriteDescriptorSet::buffer(4, lights.clone()),
+                WriteDescriptorSet::image_view_sampler(5, motion, sampler.clone()),
+                WriteDescriptorSet::buffer(6, fog_uniform.clone()),
+            ],
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Creates the per-object set (set 0) skinning with `joint_palette` and morphing with
+    /// `morph_targets`, and with `previous_joint_palette` for motion vectors.
+    pub fn object_descriptor_set(
+        &self,
+        app: &App,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        previous_joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.geometry_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.model_uniform.clone()),
+                WriteDescriptorSet::buffer(1, joint_palette),
+                WriteDescriptorSet::buffer(2, previous_joint_palette),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(3)),
+            [],
+        )
+        .unwrap()
+    }
+
+    /// See [`SamplePipeline::set_motion`](super::sample::SamplePipeline::set_motion).
+    pub fn set_motion(&self, previous_view_proj: cgmath::Matrix4<f32>, jitter: [f32; 2]) {
+        update_uniform_buffer(
+            &self.current_frame().motion_uniform,
+            vs::Motion {
+                previous_view_proj: previous_view_proj.into(),
+                jitter,
+            },
+        );
+    }
+
+    /// Fills the G-buffer. Must be recorded outside of any render pass; `draw_fn` should only
+    /// call [`DeferredPipeline::draw_object`]. Must not be called while a frame recorded with
+    /// the same [`Self::select_frame`] slot is still executing, as `camera` is uploaded rather
+    /// than pushed.
+    pub fn render_gbuffer(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        camera: &Camera,
+        draw_fn: impl FnOnce(&mut RecordingCommandBuffer),
+    ) {
+        update_uniform_buffer(
+            &self.current_frame().frame_uniform,
+            vs::Frame {
+                view: camera.view.into(),
+                proj: camera.proj.into(),
+                camera_pos: camera.position.into(),
+                debug_view: DebugView::Shaded as u32,
+            },
+        );
+        let extent = self.gbuffer.depth.image().extent();
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: self
+                    .gbuffer
+                    .color
+                    .iter()
+                    .map(|view| {
+                        Some(ColorAttachment::cleared(view.clone(), [0.0; 4]).into_rendering_info())
+                    })
+                    .collect(),
+                depth_attachment: Some(RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::Clear,
+                    store_op: AttachmentStoreOp::Store,
+                    clear_value: Some(ClearValue::Depth(1.0)),
+                    ..RenderingAttachmentInfo::image_view(self.gbuffer.depth.clone())
+                }),
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.geometry_pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.geometry_pipeline.bind_point(),
+                self.geometry_pipeline.layout().clone(),
+                1,
+                self.current_frame().material_descriptor_set.clone(),
+            )
+            .unwrap();
+
+        draw_fn(builder);
+
+        rendering.end(builder);
+    }
+
+    /// Draws one object; `mirrored` objects are drawn with the opposite front face, and
+    /// `double_sided` ones without culling. With an `indirect_command` the counts are read from
+    /// it, see [`draw_mesh_indirect`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        indirect_command: Option<Subbuffer<[u32]>>,
+        mirrored: bool,
+        double_sided: bool,
+        depth_bias: DepthBias,
+    ) {
+        // All variants share a layout, so the bound sets and push constants stay valid.
+        let pipeline = match (double_sided, mirrored) {
+            (true, _) => &self.double_sided_geometry_pipelines[mirrored as usize],
+            (false, true) => &self.mirrored_geometry_pipeline,
+            (false, false) => &self.geometry_pipeline,
+        };
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.geometry_pipeline.bind_point(),
+                self.geometry_pipeline.layout().clone(),
+                0,
+                object_descriptor_set,
+            )
+            .unwrap();
+        depth_bias.record(builder);
+
+        match indirect_command {
+            Some(command) => draw_mesh_indirect(builder, vertex_buffer, index_buffer, command),
+            None => draw_mesh(builder, vertex_buffer, index_buffer),
+        }
+    }
+
+    pub fn gbuffer(&self) -> &GBuffer {
+        &self.gbuffer
+    }
+
+    /// Shades the G-buffer into the current render pass. Uploads `fog` like
+    /// [`Self::render_gbuffer`] uploads the camera.
+    pub fn render_lighting(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        camera: &Camera,
+        ambient: &AmbientSettings,
+        fog: Option<&FogSettings>,
+    ) {
+        let inv_view_proj = (camera.proj * camera.view)
+            .invert()
+            .expect("camera matrix is not invertible");
+        let (color, falloff, [start, end, density]) = FogSettings::shader_terms(fog);
+        update_uniform_buffer(
+            &self.current_frame().fog_uniform,
+            lighting_fs::Fog {
+                color,
+                falloff,
+                start,
+                end,
+                density,
+            },
+        );
+        let view = camera.view;
+
+        builder
+            .bind_pipeline_graphics(self.lighting_pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.lighting_pipeline.bind_point(),
+                self.lighting_pipeline.layout().clone(),
+                0,
+                self.current_frame().lighting_descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.lighting_pipeline.layout().clone(),
+                0,
+                lighting_fs::PushConstants {
+                    inv_view_proj: inv_view_proj.into(),
+                    camera_pos: camera.position.into(),
+                    occlusion_strength: ambient.occlusion_strength,
+                    ambient: Padded(ambient.color),
+                    camera_forward: [-view.x.z, -view.y.z, -view.z.z],
+                },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+
+#ifdef MULTISAMPLED
+layout(set = 0, binding = 0) uniform sampler2DMS depthTexture;
+#else
+layout(set = 0, binding = 0) uniform sampler2D depthTexture;
+#endif
+
+layout(push_constant) uniform PushConstants {
+  // proj[2][2] and proj[3][2], which map view-space z to depth.
+  vec2 depth_terms;
+  float z_near;
+  float z_far;
+} pc;
+
+// Polynomial fit of the Turbo colormap, from blue at 0 to red at 1.
+vec3 turbo(float t) {
+  const vec4 kR = vec4(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+  const vec4 kG = vec4(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+  const vec4 kB = vec4(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+  const vec2 kR2 = vec2(-152.94239396, 59.28637943);
+  const vec2 kG2 = vec2(4.27729857, 2.82956604);
+  const vec2 kB2 = vec2(-89.90310912, 27.34824973);
+  vec4 v4 = vec4(1.0, t, t * t, t * t * t);
+  vec2 v2 = v4.zw * v4.z;
+  return vec3(dot(v4, kR) + dot(v2, kR2), dot(v4, kG) + dot(v2, kG2),
+              dot(v4, kB) + dot(v2, kB2));
+}
+
+void main() {
+  // The first sample is enough to see the depth distribution.
+  float depth = texelFetch(depthTexture, ivec2(gl_FragCoord.xy), 0).r;
+  if (depth == 1.0) {
+    // Still cleared: nothing was drawn here.
+    outColor = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+  // Inverts the projection back to the distance along the view direction.
+  float distance = pc.depth_terms.y / (depth + pc.depth_terms.x);
+  // Logarithmic, so that detail near the camera and far away both show.
+  float t = log(max(distance, pc.z_near) / pc.z_near) / log(pc.z_far / pc.z_near);
+  outColor = vec4(clamp(turbo(t), 0.0, 1.0), 1.0);
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{
+        sampler::{Sampler, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo},
+        Image, ImageAspects, ImageSubresourceRange, SampleCount,
+    },
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::{pipeline_layout, sample::Camera};
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass},
+    App, Z_FAR, Z_NEAR,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/depth_heatmap/depth_heatmap.frag");
+}
+
+mod fs_multisampled {
+    vulkano_shaders::shader!(
+        ty: "fragment",
+        path: "src/pipeline/depth_heatmap/depth_heatmap.frag",
+        define: [("MULTISAMPLED", "1")],
+    );
+}
+
+/// Shows the main pass's depth buffer instead of the scene, linearized and mapped to a color
+/// ramp from near (blue) to far (red). Parts nothing was drawn to stay black.
+pub struct DepthHeatmapPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    descriptor_set: Arc<DescriptorSet>,
+    extent: [u32; 2],
+}
+
+impl DepthHeatmapPipeline {
+    /// `depth_image` needs sampled usage. `output_format` is the format of the images passed to
+    /// [`Self::render`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        depth_image: Arc<Image>,
+        output_format: Format,
+    ) -> DepthHeatmapPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = match depth_image.samples() {
+                SampleCount::Sample1 => fs::load(device.clone()),
+                _ => fs_multisampled::load(device.clone()),
+            }
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(output_format)],
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(VertexInputState::default()),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        1,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // Texels are fetched, so the sampler's filtering doesn't matter.
+        let sampler = Sampler::new(device.clone(), SamplerCreateInfo::default()).unwrap();
+        let descriptor_set = Self::descriptor_set(app, &pipeline, &sampler, &depth_image);
+
+        let extent = depth_image.extent();
+        Self {
+            pipeline,
+            sampler,
+            descriptor_set,
+            extent: [extent[0], extent[1]],
+        }
+    }
+
+    /// Shows `depth_image` from now on, e.g. after a resize. It needs the sample count of the one
+    /// given to [`Self::new`].
+    pub fn set_depth_image(&mut self, app: &App, depth_image: Arc<Image>) {
+        self.descriptor_set =
+            Self::descriptor_set(app, &self.pipeline, &self.sampler, &depth_image);
+        let extent = depth_image.extent();
+        self.extent = [extent[0], extent[1]];
+    }
+
+    fn descriptor_set(
+        app: &App,
+        pipeline: &GraphicsPipeline,
+        sampler: &Arc<Sampler>,
+        depth_image: &Arc<Image>,
+    ) -> Arc<DescriptorSet> {
+        // Only the depth aspect can be sampled.
+        let depth_view = ImageView::new(
+            depth_image.clone(),
+            ImageViewCreateInfo {
+                subresource_range: ImageSubresourceRange {
+                    aspects: ImageAspects::DEPTH,
+                    mip_levels: 0..1,
+                    array_layers: 0..1,
+                },
+                ..ImageViewCreateInfo::from_image(depth_image)
+            },
+        )
+        .unwrap();
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                depth_view,
+                sampler.clone(),
+            )],
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Draws the heatmap of the depth `camera` rendered into `dst_image`. Must be recorded
+    /// outside of any render pass, after the main pass.
+    pub fn render(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        camera: &Camera,
+    ) {
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: vec![Some(RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::DontCare,
+                    store_op: AttachmentStoreOp::Store,
+                    ..RenderingAttachmentInfo::image_view(dst_image)
+                })],
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [self.extent[0] as f32, self.extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::PushConstants {
+                    depth_terms: [camera.proj.z.z, camera.proj.w.z],
+                    z_near: Z_NEAR,
+                    z_far: Z_FAR,
+                },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+
+        rendering.end(builder);
+    }
+}
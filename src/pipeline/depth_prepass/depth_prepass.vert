@@ -0,0 +1,39 @@
+#version 460
+
+// Must come out exactly as in `sample.vert`, for the main pass's equal depth test.
+invariant gl_Position;
+
+layout(push_constant) uniform PushConstants {
+  mat4 view;
+  mat4 proj;
+}
+pc;
+
+layout(set = 0, binding = 0) uniform ModelBuffer { mat4 model; };
+layout(set = 0, binding = 1) readonly buffer JointPalette { mat4 joint_matrices[]; };
+layout(set = 0, binding = 2) readonly buffer MorphTargets { vec4 morph_deltas[]; };
+layout(set = 0, binding = 3) readonly buffer MorphWeights { float morph_weights[]; };
+
+layout(location = 0) in vec3 position;
+layout(location = 3) in uvec4 joints;
+layout(location = 4) in vec4 weights;
+
+// Adds the weighted morph target deltas of `vertex`, stored target by target for each vertex.
+vec3 morphed(vec3 position, uint vertex) {
+  uint target_count = uint(morph_weights.length());
+  for (uint i = 0; i < target_count; i++) {
+    if (morph_weights[i] != 0.0) {
+      position += morph_weights[i] * morph_deltas[vertex * target_count + i].xyz;
+    }
+  }
+  return position;
+}
+
+void main() {
+  mat4 skin = weights.x * joint_matrices[joints.x] + weights.y * joint_matrices[joints.y] +
+              weights.z * joint_matrices[joints.z] + weights.w * joint_matrices[joints.w];
+  mat4 skinned_model = model * skin;
+  vec3 morphed_position = morphed(position, uint(gl_VertexIndex));
+  vec3 worldPos = vec3(skinned_model * vec4(morphed_position, 1.0));
+  gl_Position = pc.proj * pc.view * vec4(worldPos, 1.0);
+}
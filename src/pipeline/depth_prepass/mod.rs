@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use cgmath::SquareMatrix;
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::RecordingCommandBuffer,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    image::SampleCount,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorComponents},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::{DepthBiasState, FrontFace, PolygonMode, RasterizationState},
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{
+    color_only_blend_state, create_uniform_buffer_from_data, draw_mesh, draw_mesh_indirect,
+    flipped, pipeline_layout, sample::Camera, DepthBias, DepthSettings, FaceCulling, JointMatrix,
+    MorphTargets,
+};
+use crate::{render_pass::pipeline_subpass, App, MyVertex};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/depth_prepass/depth_prepass.vert");
+}
+
+/// Writes the depth of the opaque objects before the main pass shades them, so that the main
+/// pass, testing for equal depth without writing it, shades each pixel once rather than once per
+/// overlapping surface.
+pub struct DepthPrepassPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    mirrored_pipeline: Arc<GraphicsPipeline>,
+    /// Without culling, front and mirrored.
+    double_sided_pipelines: [Arc<GraphicsPipeline>; 2],
+    model_uniform: Subbuffer<vs::ModelBuffer>,
+}
+
+impl DepthPrepassPipeline {
+    /// Rasterizes like the [`super::sample::SamplePipeline`] created with the same arguments,
+    /// testing with `depth`'s compare op and always writing.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+        front_face: FrontFace,
+        face_culling: FaceCulling,
+        polygon_mode: PolygonMode,
+        depth: &DepthSettings,
+    ) -> DepthPrepassPipeline {
+        let device = queue.device();
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = MyVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        // Depth only, so no fragment shader is needed.
+        let stages = [PipelineShaderStageCreateInfo::new(vs)];
+        let layout = pipeline_layout(device, &stages);
+        let depth = DepthSettings {
+            test: true,
+            write: true,
+            ..*depth
+        };
+
+        let pipeline = |(front_face, cull_mode)| {
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::TriangleList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState {
+                        polygon_mode,
+                        line_width: 1.0,
+                        cull_mode,
+                        front_face,
+                        // Set per object like in the main pass, or the depths wouldn't match.
+                        depth_bias: Some(DepthBiasState::default()),
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: samples,
+                        ..Default::default()
+                    }),
+                    color_blend_state: Some(color_only_blend_state(
+                        &rendering_info,
+                        ColorBlendAttachmentState {
+                            color_write_mask: ColorComponents::empty(),
+                            ..Default::default()
+                        },
+                    )),
+                    depth_stencil_state: Some(depth.depth_stencil_state(false)),
+                    dynamic_state: [DynamicState::Viewport, DynamicState::DepthBias]
+                        .into_iter()
+                        .collect(),
+                    subpass: Some(pipeline_subpass(device, rendering_info.clone(), samples)),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
+        let [pipeline, mirrored_pipeline, double_sided, mirrored_double_sided] = [
+            (front_face, face_culling.cull_mode(false)),
+            (flipped(front_face), face_culling.cull_mode(false)),
+            (front_face, face_culling.cull_mode(true)),
+            (flipped(front_face), face_culling.cull_mode(true)),
+        ]
+        .map(pipeline);
+
+        // set = 0, binding = 0
+        let model_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::ModelBuffer {
+                model: cgmath::Matrix4::identity().into(),
+            },
+        );
+
+        Self {
+            pipeline,
+            mirrored_pipeline,
+            double_sided_pipelines: [double_sided, mirrored_double_sided],
+            model_uniform,
+        }
+    }
+
+    /// Creates the per-object set (set 0) skinning with `joint_palette` and morphing with
+    /// `morph_targets`.
+    pub fn object_descriptor_set(
+        &self,
+        app: &App,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.model_uniform.clone()),
+                WriteDescriptorSet::buffer(1, joint_palette),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(2)),
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Writes the depth of one object, seen by `camera`, which must be the one the main pass
+    /// shades with. The arguments otherwise match [`super::sample::SamplePipeline::render_object`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        indirect_command: Option<Subbuffer<[u32]>>,
+        camera: &Camera,
+        mirrored: bool,
+        double_sided: bool,
+        depth_bias: DepthBias,
+    ) {
+        let pipeline = match (double_sided, mirrored) {
+            (true, _) => &self.double_sided_pipelines[mirrored as usize],
+            (false, true) => &self.mirrored_pipeline,
+            (false, false) => &self.pipeline,
+        };
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                pipeline.bind_point(),
+                pipeline.layout().clone(),
+                0,
+                object_descriptor_set,
+            )
+            .unwrap()
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                vs::PushConstants {
+                    view: camera.view.into(),
+                    proj: camera.proj.into(),
+                },
+            )
+            .unwrap();
+        depth_bias.record(builder);
+
+        match indirect_command {
+            Some(command) => draw_mesh_indirect(builder, vertex_buffer, index_buffer, command),
+            None => draw_mesh(builder, vertex_buffer, index_buffer),
+        }
+    }
+}
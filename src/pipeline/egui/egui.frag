@@ -0,0 +1,13 @@
+#version 460
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+layout(location = 0) out vec4 outColor;
+
+// sRGB, decoded by its format.
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+void main() {
+  // Both premultiplied.
+  outColor = fragColor * texture(tex, fragUv);
+}
@@ -0,0 +1,25 @@
+#version 460
+
+layout(location = 0) in vec2 position; // in points
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color; // premultiplied sRGB
+
+layout(push_constant) uniform PushConstants {
+  vec2 screen_size; // in points
+}
+pc;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+
+vec3 linearFromSrgb(vec3 srgb) {
+  return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)),
+             greaterThan(srgb, vec3(0.04045)));
+}
+
+void main() {
+  gl_Position = vec4(position / pc.screen_size * 2.0 - 1.0, 0.0, 1.0);
+  fragUv = uv;
+  // The attachment is linear, or encodes to sRGB by its format.
+  fragColor = vec4(linearFromSrgb(color.rgb), color.a);
+}
@@ -0,0 +1,452 @@
+use std::{collections::HashMap, sync::Arc};
+
+use egui::{
+    epaint::{ImageDelta, Primitive},
+    ClippedPrimitive, ImageData, Rect, TextureFilter, TextureId, TexturesDelta,
+};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage,
+    },
+    command_buffer::{
+        BufferImageCopy, CopyBufferToImageInfo, RecordingCommandBuffer, RenderingAttachmentInfo,
+        RenderingInfo,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::{DeviceOwned, Queue},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::{
+                AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+            },
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Scissor, Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::pipeline_layout;
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass},
+    App,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/egui/egui.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/egui/egui.frag");
+}
+
+/// An [`egui::epaint::Vertex`] as the vertex shader reads it.
+#[derive(BufferContents, Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+struct EguiVertex {
+    /// In points.
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+    /// Premultiplied sRGB.
+    #[format(R8G8B8A8_UNORM)]
+    color: [u8; 4],
+}
+
+/// Draws egui's meshes over a finished frame, e.g. the settings panel, and keeps the textures
+/// they sample.
+///
+/// Texture changes are only queued by [`Self::set_textures`] and uploaded by the next
+/// [`Self::render`], so that none are lost when no frame is drawn.
+pub struct EguiPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    /// Each frame's vertices and indices. Its buffers are reused once the frames that read them
+    /// are dropped.
+    buffer_allocator: SubbufferAllocator,
+    /// With the set (set 0) sampling them.
+    textures: HashMap<TextureId, (Arc<Image>, Arc<DescriptorSet>)>,
+    /// Copies into `textures`, in the order egui sent them.
+    uploads: Vec<CopyBufferToImageInfo>,
+    /// Freed once the next frame is recorded, which may still draw with them.
+    freed: Vec<TextureId>,
+}
+
+impl EguiPipeline {
+    /// `output_format` is the format of the images passed to [`Self::render`].
+    pub fn new(app: &App, queue: Arc<Queue>, output_format: Format) -> EguiPipeline {
+        let device = queue.device();
+
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = EguiVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = pipeline_layout(device, &stages);
+
+        let rendering_info = PipelineRenderingCreateInfo {
+            color_attachment_formats: vec![Some(output_format)],
+            ..Default::default()
+        };
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                // egui's colors are premultiplied.
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    1,
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend {
+                            src_color_blend_factor: BlendFactor::One,
+                            dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                            color_blend_op: BlendOp::Add,
+                            src_alpha_blend_factor: BlendFactor::OneMinusDstAlpha,
+                            dst_alpha_blend_factor: BlendFactor::One,
+                            alpha_blend_op: BlendOp::Add,
+                        }),
+                        ..Default::default()
+                    },
+                )),
+                dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                    .into_iter()
+                    .collect(),
+                subpass: Some(pipeline_subpass(
+                    device,
+                    rendering_info,
+                    SampleCount::Sample1,
+                )),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap();
+
+        Self {
+            pipeline,
+            memory_allocator: app.memory_allocator(),
+            descriptor_set_allocator: app.descriptor_set_allocator.clone(),
+            buffer_allocator: SubbufferAllocator::new(
+                app.memory_allocator(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER | BufferUsage::INDEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+            textures: HashMap::new(),
+            uploads: Vec::new(),
+            freed: Vec::new(),
+        }
+    }
+
+    /// Queues the texture changes of an egui frame for the next [`Self::render`].
+    pub fn set_textures(&mut self, textures_delta: &TexturesDelta) {
+        for (id, delta) in &textures_delta.set {
+            self.set_texture(*id, delta);
+        }
+        self.freed.extend_from_slice(&textures_delta.free);
+    }
+
+    fn set_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let [width, height] = delta.image.size().map(|size| size as u32);
+        let texels: Vec<[u8; 4]> = match &delta.image {
+            ImageData::Color(image) => image.pixels.iter().map(|c| c.to_array()).collect(),
+            ImageData::Font(image) => image.srgba_pixels(None).map(|c| c.to_array()).collect(),
+        };
+        let staging_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            texels,
+        )
+        .unwrap();
+
+        // Without a position, the whole texture is replaced.
+        let offset = match delta.pos {
+            Some([x, y]) => [x as u32, y as u32],
+            None => {
+                let image = Image::new(
+                    self.memory_allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [width, height, 1],
+                        format: Format::R8G8B8A8_SRGB,
+                        usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap();
+                let filter = |filter| match filter {
+                    TextureFilter::Nearest => Filter::Nearest,
+                    TextureFilter::Linear => Filter::Linear,
+                };
+                let sampler = Sampler::new(
+                    self.pipeline.device().clone(),
+                    SamplerCreateInfo {
+                        mag_filter: filter(delta.options.magnification),
+                        min_filter: filter(delta.options.minification),
+                        address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let descriptor_set = DescriptorSet::new(
+                    self.descriptor_set_allocator.clone(),
+                    self.pipeline.layout().set_layouts()[0].clone(),
+                    [WriteDescriptorSet::image_view_sampler(
+                        0,
+                        ImageView::new_default(image.clone()).unwrap(),
+                        sampler,
+                    )],
+                    [],
+                )
+                .unwrap();
+                self.textures.insert(id, (image, descriptor_set));
+                [0, 0]
+            }
+        };
+        let Some((image, _)) = self.textures.get(&id) else {
+            log::warn!("egui updated {id:?} before creating it");
+            return;
+        };
+        self.uploads.push(CopyBufferToImageInfo {
+            regions: [BufferImageCopy {
+                image_subresource: image.subresource_layers(),
+                image_offset: [offset[0], offset[1], 0],
+                image_extent: [width, height, 1],
+                ..Default::default()
+            }]
+            .into(),
+            ..CopyBufferToImageInfo::buffer_image(staging_buffer, image.clone())
+        });
+    }
+
+    /// Uploads the queued textures and draws `primitives` over `dst_image`, `pixels_per_point`
+    /// being the scale egui laid them out with, then drops the textures egui freed. Must be
+    /// recorded outside of any render pass.
+    pub fn render(
+        &mut self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        primitives: &[ClippedPrimitive],
+        pixels_per_point: f32,
+    ) {
+        for upload in self.uploads.drain(..) {
+            builder.copy_buffer_to_image(upload).unwrap();
+        }
+        self.draw(builder, dst_image, primitives, pixels_per_point);
+        for id in self.freed.drain(..) {
+            // Frames still executing keep their images alive until they finish.
+            self.textures.remove(&id);
+        }
+    }
+
+    fn draw(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        primitives: &[ClippedPrimitive],
+        pixels_per_point: f32,
+    ) {
+        // Callbacks paint with another renderer's API, so only meshes are drawn.
+        let meshes: Vec<_> = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                Primitive::Mesh(mesh) if !mesh.indices.is_empty() => {
+                    Some((primitive.clip_rect, mesh))
+                }
+                _ => None,
+            })
+            .collect();
+        if meshes.is_empty() {
+            return;
+        }
+
+        let vertex_count: usize = meshes.iter().map(|(_, mesh)| mesh.vertices.len()).sum();
+        let index_count: usize = meshes.iter().map(|(_, mesh)| mesh.indices.len()).sum();
+        let vertex_buffer = self
+            .buffer_allocator
+            .allocate_slice::<EguiVertex>(vertex_count as u64)
+            .unwrap();
+        let index_buffer = self
+            .buffer_allocator
+            .allocate_slice::<u32>(index_count as u64)
+            .unwrap();
+        {
+            let mut vertices = vertex_buffer.write().unwrap();
+            let mut indices = index_buffer.write().unwrap();
+            let all_vertices = meshes.iter().flat_map(|(_, mesh)| &mesh.vertices);
+            for (dst, vertex) in vertices.iter_mut().zip(all_vertices) {
+                *dst = EguiVertex {
+                    position: [vertex.pos.x, vertex.pos.y],
+                    uv: [vertex.uv.x, vertex.uv.y],
+                    color: vertex.color.to_array(),
+                };
+            }
+            // Each mesh's indices stay relative to its first vertex, see the draws below.
+            let all_indices = meshes.iter().flat_map(|(_, mesh)| &mesh.indices);
+            for (dst, &index) in indices.iter_mut().zip(all_indices) {
+                *dst = index;
+            }
+        }
+
+        let extent = dst_image.image().extent();
+        let extent = [extent[0], extent[1]];
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: vec![Some(RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::Load,
+                    store_op: AttachmentStoreOp::Store,
+                    ..RenderingAttachmentInfo::image_view(dst_image)
+                })],
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vs::PushConstants {
+                    screen_size: extent.map(|e| e as f32 / pixels_per_point),
+                },
+            )
+            .unwrap()
+            .bind_vertex_buffers(0, vertex_buffer)
+            .unwrap()
+            .bind_index_buffer(index_buffer)
+            .unwrap();
+
+        let mut first_vertex = 0;
+        let mut first_index = 0;
+        for (clip_rect, mesh) in meshes {
+            let (vertex_offset, index_offset) = (first_vertex, first_index);
+            first_vertex += mesh.vertices.len() as u32;
+            first_index += mesh.indices.len() as u32;
+            let Some(scissor) = scissor(clip_rect, pixels_per_point, extent) else {
+                continue;
+            };
+            let Some((_, descriptor_set)) = self.textures.get(&mesh.texture_id) else {
+                log::warn!("egui drew with {:?}, which it never set", mesh.texture_id);
+                continue;
+            };
+            builder
+                .set_scissor(0, [scissor].into_iter().collect())
+                .unwrap()
+                .bind_descriptor_sets(
+                    self.pipeline.bind_point(),
+                    self.pipeline.layout().clone(),
+                    0,
+                    descriptor_set.clone(),
+                )
+                .unwrap();
+            unsafe {
+                builder.draw_indexed(
+                    mesh.indices.len() as u32,
+                    1,
+                    index_offset,
+                    vertex_offset as i32,
+                    0,
+                )
+            }
+            .unwrap();
+        }
+
+        rendering.end(builder);
+    }
+}
+
+/// The pixels of an image of `extent` within `clip_rect`, which is in points. `None` if none
+/// are.
+fn scissor(clip_rect: Rect, pixels_per_point: f32, extent: [u32; 2]) -> Option<Scissor> {
+    let to_pixels = |point: f32, extent: u32| {
+        (point * pixels_per_point).round().clamp(0.0, extent as f32) as u32
+    };
+    let min = [
+        to_pixels(clip_rect.min.x, extent[0]),
+        to_pixels(clip_rect.min.y, extent[1]),
+    ];
+    let max = [
+        to_pixels(clip_rect.max.x, extent[0]),
+        to_pixels(clip_rect.max.y, extent[1]),
+    ];
+    (max[0] > min[0] && max[1] > min[1]).then(|| Scissor {
+        offset: min,
+        extent: [max[0] - min[0], max[1] - min[1]],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+
+    use super::*;
+
+    #[test]
+    fn scissors_are_scaled_and_clamped_to_the_image() {
+        let clip_rect = Rect::from_min_max(pos2(-10.0, 5.0), pos2(50.0, 500.0));
+        let scissor = scissor(clip_rect, 2.0, [80, 60]).unwrap();
+        assert_eq!(scissor.offset, [0, 10]);
+        assert_eq!(scissor.extent, [80, 50]);
+    }
+
+    #[test]
+    fn clip_rects_outside_the_image_draw_nothing() {
+        let clip_rect = Rect::from_min_max(pos2(100.0, 0.0), pos2(200.0, 10.0));
+        assert!(scissor(clip_rect, 1.0, [80, 60]).is_none());
+    }
+}
@@ -0,0 +1,44 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform sampler2D sceneTexture;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+// Longest blur along an edge, in pixels.
+const float SPAN_MAX = 8.0;
+
+// FXAA expects perceptual LDR luma; the scene is linear HDR, so it is tonemapped first.
+float luma(vec3 color) {
+  vec3 mapped = color / (1.0 + color);
+  return sqrt(dot(mapped, vec3(0.299, 0.587, 0.114)));
+}
+
+vec3 fetch(vec2 uv) { return texture(sceneTexture, uv).rgb; }
+
+void main() {
+  vec2 texelSize = 1.0 / vec2(textureSize(sceneTexture, 0));
+  vec2 uv = gl_FragCoord.xy * texelSize;
+
+  vec3 colorM = fetch(uv);
+  float lumaNW = luma(fetch(uv + vec2(-1.0, -1.0) * texelSize));
+  float lumaNE = luma(fetch(uv + vec2(1.0, -1.0) * texelSize));
+  float lumaSW = luma(fetch(uv + vec2(-1.0, 1.0) * texelSize));
+  float lumaSE = luma(fetch(uv + vec2(1.0, 1.0) * texelSize));
+  float lumaM = luma(colorM);
+  float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+  float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+  // Perpendicular to the luma gradient, i.e. along the edge.
+  vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+  float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * REDUCE_MUL), REDUCE_MIN);
+  float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+  dir = clamp(dir * rcpDirMin, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texelSize;
+
+  vec3 colorA = 0.5 * (fetch(uv + dir * (1.0 / 3.0 - 0.5)) + fetch(uv + dir * (2.0 / 3.0 - 0.5)));
+  vec3 colorB = colorA * 0.5 + 0.25 * (fetch(uv - dir * 0.5) + fetch(uv + dir * 0.5));
+  // The wider blur overshot the local range, so it crossed another edge.
+  float lumaB = luma(colorB);
+  outColor = vec4(lumaB < lumaMin || lumaB > lumaMax ? colorA : colorB, 1.0);
+}
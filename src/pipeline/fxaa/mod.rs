@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass, resolve_target_usage},
+    App,
+};
+
+use super::{pipeline_layout, taa::HISTORY_FORMAT};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/fxaa/fxaa.frag");
+}
+
+/// Fast approximate anti-aliasing: blurs the single-sampled scene image along the edges found
+/// in its luma.
+pub struct FxaaPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    scene_image: Arc<ImageView>,
+    descriptor_set: Arc<DescriptorSet>,
+}
+
+impl FxaaPipeline {
+    /// `output_format` is the format of the images passed to [`Self::render`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        output_format: Format,
+        extent: [u32; 2],
+    ) -> FxaaPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = fs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(output_format)],
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(VertexInputState::default()),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        1,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // Samples between texels, along the edge.
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (scene_image, descriptor_set) = Self::targets(app, &pipeline, &sampler, extent);
+
+        Self {
+            pipeline,
+            sampler,
+            scene_image,
+            descriptor_set,
+        }
+    }
+
+    /// Recreates the scene image for frames of `extent`.
+    pub fn resize(&mut self, app: &App, extent: [u32; 2]) {
+        (self.scene_image, self.descriptor_set) =
+            Self::targets(app, &self.pipeline, &self.sampler, extent);
+    }
+
+    /// The scene image of `extent` and the set reading it.
+    fn targets(
+        app: &App,
+        pipeline: &GraphicsPipeline,
+        sampler: &Arc<Sampler>,
+        extent: [u32; 2],
+    ) -> (Arc<ImageView>, Arc<DescriptorSet>) {
+        let scene_image = ImageView::new_default(
+            Image::new(
+                app.memory_allocator(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    extent: [extent[0], extent[1], 1],
+                    format: HISTORY_FORMAT,
+                    // The main pass may resolve into it.
+                    usage: resolve_target_usage(
+                        app.context.device(),
+                        ImageUsage::COLOR_ATTACHMENT
+                            | ImageUsage::SAMPLED
+                            | ImageUsage::TRANSFER_SRC,
+                    ),
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                scene_image.clone(),
+                sampler.clone(),
+            )],
+            [],
+        )
+        .unwrap();
+        (scene_image, descriptor_set)
+    }
+
+    /// The image the main pass should render into instead of the swapchain.
+    pub fn scene_image(&self) -> Arc<ImageView> {
+        self.scene_image.clone()
+    }
+
+    /// Filters the scene image into `dst_image`. Must be recorded outside of any render pass,
+    /// after the main pass.
+    pub fn render(&self, builder: &mut RecordingCommandBuffer, dst_image: Arc<ImageView>) {
+        let extent = self.scene_image.image().extent();
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: vec![Some(RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::DontCare,
+                    store_op: AttachmentStoreOp::Store,
+                    ..RenderingAttachmentInfo::image_view(dst_image)
+                })],
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+
+        rendering.end(builder);
+    }
+}
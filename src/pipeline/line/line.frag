@@ -0,0 +1,19 @@
+#version 460
+
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in float edgeDistance;
+layout(location = 0) out vec4 outColor;
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec2 viewport_size;
+  float width;
+  uint expand;
+}
+pc;
+
+void main() {
+  // Always 1 for hardware lines, which have no edge distance.
+  float coverage = clamp(pc.width * 0.5 + 0.5 - abs(edgeDistance), 0.0, 1.0);
+  outColor = vec4(fragColor.rgb, fragColor.a * coverage);
+}
@@ -0,0 +1,63 @@
+#version 460
+
+struct LineVertex {
+  vec3 position;
+  vec4 color;
+};
+
+layout(set = 0, binding = 0) readonly buffer Lines { LineVertex vertices[]; };
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec2 viewport_size; // in pixels
+  float width;        // in pixels
+  uint expand;        // draw each segment as a screen-space quad
+}
+pc;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out float edgeDistance; // from the center of the line, in pixels
+
+// Two triangles per segment: which end, and which side of the line.
+const ivec2 CORNERS[6] = ivec2[](ivec2(0, -1), ivec2(1, -1), ivec2(1, 1), ivec2(0, -1),
+                                 ivec2(1, 1), ivec2(0, 1));
+
+void main() {
+  if (pc.expand == 0) {
+    LineVertex v = vertices[gl_VertexIndex];
+    gl_Position = pc.view_proj * vec4(v.position, 1.0);
+    fragColor = v.color;
+    edgeDistance = 0.0;
+    return;
+  }
+
+  int segment = gl_VertexIndex / 6;
+  ivec2 corner = CORNERS[gl_VertexIndex % 6];
+  LineVertex a = vertices[segment * 2];
+  LineVertex b = vertices[segment * 2 + 1];
+  vec4 clipA = pc.view_proj * vec4(a.position, 1.0);
+  vec4 clipB = pc.view_proj * vec4(b.position, 1.0);
+
+  // Clip to the near plane first; behind it the screen-space direction flips.
+  if (clipA.z < 0.0 && clipB.z < 0.0) {
+    gl_Position = vec4(0.0, 0.0, -1.0, 1.0);
+    return;
+  }
+  if (clipA.z < 0.0) {
+    clipA = mix(clipA, clipB, clipA.z / (clipA.z - clipB.z));
+  } else if (clipB.z < 0.0) {
+    clipB = mix(clipB, clipA, clipB.z / (clipB.z - clipA.z));
+  }
+
+  vec2 halfViewport = pc.viewport_size * 0.5;
+  vec2 direction = clipB.xy / clipB.w * halfViewport - clipA.xy / clipA.w * halfViewport;
+  direction = length(direction) > 0.0 ? normalize(direction) : vec2(1.0, 0.0);
+  vec2 normal = vec2(-direction.y, direction.x);
+
+  // A pixel beyond the width on each side for the falloff.
+  float extent = pc.width * 0.5 + 1.0;
+  vec4 clip = corner.x == 0 ? clipA : clipB;
+  gl_Position = clip + vec4(normal * (corner.y * extent) / halfViewport * clip.w, 0.0, 0.0);
+  fragColor = corner.x == 0 ? a.color : b.color;
+  edgeDistance = corner.y * extent;
+}
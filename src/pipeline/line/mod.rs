@@ -0,0 +1,191 @@
+use std::{mem::size_of, sync::Arc};
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::RecordingCommandBuffer,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    image::SampleCount,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{color_only_blend_state, pipeline_layout, sample::Camera};
+use crate::{render_pass::pipeline_subpass, App};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/line/line.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/line/line.frag");
+}
+
+pub use vs::LineVertex;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LineStyle {
+    /// In pixels.
+    pub width: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self { width: 2.0 }
+    }
+}
+
+/// Draws line lists, every two [`LineVertex`]es forming a segment, e.g. for debug visuals.
+///
+/// Lines use the hardware line width if the device has `wide_lines` enabled. Otherwise each
+/// segment is expanded to a screen-space quad with an anti-aliased falloff at its edges.
+pub struct LinePipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    expand: bool,
+}
+
+impl LinePipeline {
+    pub fn new(
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) -> LinePipeline {
+        assert!(size_of::<vs::PushConstants>() == size_of::<fs::PushConstants>());
+
+        let device = queue.device();
+        let expand = !device.enabled_features().wide_lines;
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = pipeline_layout(device, &stages);
+
+        let (topology, dynamic_state) = match expand {
+            true => (
+                PrimitiveTopology::TriangleList,
+                vec![DynamicState::Viewport],
+            ),
+            false => (
+                PrimitiveTopology::LineList,
+                vec![DynamicState::Viewport, DynamicState::LineWidth],
+            ),
+        };
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(VertexInputState::default()),
+                input_assembly_state: Some(InputAssemblyState {
+                    topology,
+                    ..Default::default()
+                }),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                }),
+                color_blend_state: Some(color_only_blend_state(
+                    &rendering_info,
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend::alpha()),
+                        ..Default::default()
+                    },
+                )),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState {
+                        compare_op: CompareOp::Less,
+                        write_enable: false,
+                    }),
+                    ..Default::default()
+                }),
+                dynamic_state: dynamic_state.into_iter().collect(),
+                subpass: Some(pipeline_subpass(device, rendering_info, samples)),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap();
+
+        Self { pipeline, expand }
+    }
+
+    /// Creates the set (set 0) reading segments from `lines`, which needs storage buffer usage.
+    pub fn lines_descriptor_set(
+        &self,
+        app: &App,
+        lines: Subbuffer<[LineVertex]>,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, lines)],
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Draws `vertex_count` vertices of the lines in `lines_descriptor_set` into a viewport of
+    /// `viewport_size` pixels.
+    pub fn render_lines(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        lines_descriptor_set: Arc<DescriptorSet>,
+        vertex_count: u32,
+        camera: &Camera,
+        viewport_size: [f32; 2],
+        style: &LineStyle,
+    ) {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                lines_descriptor_set,
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vs::PushConstants {
+                    view_proj: (camera.proj * camera.view).into(),
+                    viewport_size,
+                    width: style.width,
+                    expand: self.expand as u32,
+                },
+            )
+            .unwrap();
+
+        let vertex_count = match self.expand {
+            // Six vertices for each pair.
+            true => vertex_count / 2 * 6,
+            false => {
+                builder.set_line_width(style.width).unwrap();
+                vertex_count
+            }
+        };
+        unsafe { builder.draw(vertex_count, 1, 0, 0) }.unwrap();
+    }
+}
@@ -1,29 +1,651 @@
 use std::sync::Arc;
 
+use cgmath::{Matrix4, SquareMatrix};
+use thiserror::Error;
 use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, CommandBufferBeginInfo, CommandBufferLevel,
         CommandBufferUsage, RecordingCommandBuffer, RenderingAttachmentInfo,
         RenderingAttachmentResolveInfo, RenderingInfo,
     },
-    device::Queue,
-    format::ClearValue,
-    image::view::ImageView,
-    pipeline::graphics::viewport::Viewport,
+    descriptor_set::{layout::DescriptorType, WriteDescriptorSet},
+    device::{Device, Queue},
+    format::{ClearValue, Format},
+    image::{view::ImageView, ImageUsage, SampleCount},
+    instance::debug::DebugUtilsLabel,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState, ColorComponents},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            rasterization::{CullMode, FrontFace},
+            subpass::PipelineRenderingCreateInfo,
+            viewport::Viewport,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
     render_pass::{AttachmentLoadOp, AttachmentStoreOp},
     sync::GpuFuture,
+    Version,
 };
 
+use crate::{
+    render_pass::{begin_rendering, Rendering},
+    InstanceData, MyVertex,
+};
+
+pub mod accumulation;
+pub mod culling;
+pub mod deferred;
+pub mod depth_heatmap;
+pub mod depth_prepass;
+pub mod egui;
+pub mod fxaa;
+pub mod line;
+pub mod normals;
+pub mod outline;
+pub mod point;
 pub mod sample;
+pub mod shadow;
+pub mod skybox;
+pub mod ssr;
+pub mod taa;
+pub mod tonemap;
+pub mod vertex_capture;
+
+/// Format of the main pass' second color attachment: screen-space motion since the previous
+/// frame, in UV units.
+pub const MOTION_FORMAT: Format = Format::R16G16_SFLOAT;
+
+/// Why a pipeline couldn't be created on the device.
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("push constants take {size} bytes, more than the device's limit of {limit}")]
+    PushConstantsSize { size: u32, limit: u32 },
+}
+
+/// Layout for a pipeline made of `stages`, whose push constants fit in the 128 bytes every
+/// device supports. Pipelines with larger ones use [`pipeline_layout_with_dynamic_uniforms`],
+/// which returns the error instead.
+pub(crate) fn pipeline_layout<'a>(
+    device: &Arc<Device>,
+    stages: impl IntoIterator<Item = &'a PipelineShaderStageCreateInfo>,
+) -> Arc<PipelineLayout> {
+    pipeline_layout_with_dynamic_uniforms(device, stages, &[])
+        .expect("push constants beyond the guaranteed 128 bytes")
+}
+
+/// Layout for a pipeline made of `stages`, with their push constants checked against the
+/// device's limit up front rather than failing validation at creation. The uniform buffers at
+/// the `(set, binding)`s of `dynamic` are bound with a dynamic offset, which lets one set pick
+/// among several uniforms.
+pub(crate) fn pipeline_layout_with_dynamic_uniforms<'a>(
+    device: &Arc<Device>,
+    stages: impl IntoIterator<Item = &'a PipelineShaderStageCreateInfo>,
+    dynamic: &[(usize, u32)],
+) -> Result<Arc<PipelineLayout>, PipelineError> {
+    let mut layout_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(stages);
+    for &(set, binding) in dynamic {
+        let binding = layout_info.set_layouts[set]
+            .bindings
+            .get_mut(&binding)
+            .unwrap();
+        assert_eq!(binding.descriptor_type, DescriptorType::UniformBuffer);
+        binding.descriptor_type = DescriptorType::UniformBufferDynamic;
+    }
+    let create_info = layout_info
+        .into_pipeline_layout_create_info(device.clone())
+        .unwrap();
+    let push_constants_size = create_info
+        .push_constant_ranges
+        .iter()
+        .map(|range| range.offset + range.size)
+        .max()
+        .unwrap_or(0);
+    let limit = device
+        .physical_device()
+        .properties()
+        .max_push_constants_size;
+    if push_constants_size > limit {
+        return Err(PipelineError::PushConstantsSize {
+            size: push_constants_size,
+            limit,
+        });
+    }
+    Ok(PipelineLayout::new(device.clone(), create_info).unwrap())
+}
+
+/// Records `record_fn` inside a debug label named `name`, shown by tools like RenderDoc, if
+/// `enabled`. Labels need `ext_debug_utils` on the instance.
+pub(crate) fn labeled(
+    builder: &mut RecordingCommandBuffer,
+    enabled: bool,
+    name: &str,
+    record_fn: impl FnOnce(&mut RecordingCommandBuffer),
+) {
+    if !enabled {
+        record_fn(builder);
+        return;
+    }
+    builder
+        .begin_debug_utils_label(DebugUtilsLabel {
+            label_name: name.to_owned(),
+            ..Default::default()
+        })
+        .unwrap();
+    record_fn(builder);
+    // SAFETY: Ends the label begun above.
+    unsafe { builder.end_debug_utils_label() }.unwrap();
+}
+
+/// Blend state for main pass pipelines that only shade color. Writes to the motion attachment
+/// are masked, leaving it at its cleared value of zero.
+pub(crate) fn color_only_blend_state(
+    rendering_info: &PipelineRenderingCreateInfo,
+    color: ColorBlendAttachmentState,
+) -> ColorBlendState {
+    let mut state = ColorBlendState::with_attachment_states(
+        rendering_info.color_attachment_formats.len() as u32,
+        color,
+    );
+    for attachment in &mut state.attachments[1..] {
+        attachment.color_write_mask = ColorComponents::empty();
+    }
+    state
+}
+
+/// How the main pass is anti-aliased.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AaMode {
+    Off,
+    /// Falls back to the highest count the device supports below this one.
+    Msaa(SampleCount),
+    /// A post-process on the single-sampled render.
+    Fxaa,
+}
+
+impl Default for AaMode {
+    fn default() -> Self {
+        AaMode::Msaa(SampleCount::Sample4)
+    }
+}
+
+impl AaMode {
+    /// Samples per pixel of the main pass.
+    pub fn samples(self) -> SampleCount {
+        match self {
+            AaMode::Msaa(samples) => samples,
+            AaMode::Off | AaMode::Fxaa => SampleCount::Sample1,
+        }
+    }
+
+    /// Cycles through off, 2x, 4x and 8x MSAA, and FXAA.
+    pub fn next(self) -> Self {
+        match self {
+            AaMode::Off => AaMode::Msaa(SampleCount::Sample2),
+            AaMode::Msaa(SampleCount::Sample2) => AaMode::Msaa(SampleCount::Sample4),
+            AaMode::Msaa(SampleCount::Sample4) => AaMode::Msaa(SampleCount::Sample8),
+            AaMode::Msaa(_) => AaMode::Fxaa,
+            AaMode::Fxaa => AaMode::Off,
+        }
+    }
+}
+
+/// Which faces the main pass culls, e.g. to look for flipped winding. Double-sided materials
+/// aren't culled unless front faces are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaceCulling {
+    #[default]
+    Back,
+    Front,
+    None,
+}
+
+impl FaceCulling {
+    pub fn next(self) -> Self {
+        match self {
+            FaceCulling::Back => FaceCulling::Front,
+            FaceCulling::Front => FaceCulling::None,
+            FaceCulling::None => FaceCulling::Back,
+        }
+    }
+
+    pub(crate) fn cull_mode(self, double_sided: bool) -> CullMode {
+        match (self, double_sided) {
+            (FaceCulling::Back, false) => CullMode::Back,
+            (FaceCulling::Front, _) => CullMode::Front,
+            _ => CullMode::None,
+        }
+    }
+}
+
+/// What the main pass does with the multisampled color at its end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MsaaResolve {
+    /// Resolves into the destination image and discards the samples.
+    #[default]
+    Resolve,
+    /// Keeps the samples for a custom resolve, leaving the destination image untouched.
+    Store,
+    /// Resolves and keeps the samples.
+    ResolveAndStore,
+}
+
+impl MsaaResolve {
+    /// Whether the multisampled image outlives the pass, so it can't be transient.
+    pub fn stores(self) -> bool {
+        self != MsaaResolve::Resolve
+    }
+
+    /// `msaa_image` cleared to opaque black, then resolved into `dst_image` and/or stored.
+    pub fn color_attachment(
+        self,
+        msaa_image: Arc<ImageView>,
+        dst_image: Arc<ImageView>,
+    ) -> ColorAttachment {
+        ColorAttachment {
+            store_op: if self.stores() {
+                AttachmentStoreOp::Store
+            } else {
+                AttachmentStoreOp::DontCare
+            },
+            resolve_image: (self != MsaaResolve::Store).then_some(dst_image),
+            ..ColorAttachment::cleared(msaa_image, [0.0, 0.0, 0.0, 1.0])
+        }
+    }
+}
+
+/// A color attachment of [`draw`] or another multi-target pass, with its own clear value and
+/// load and store ops.
+#[derive(Clone, Debug)]
+pub struct ColorAttachment {
+    pub image: Arc<ImageView>,
+    /// Only used with [`AttachmentLoadOp::Clear`].
+    pub clear_value: ClearValue,
+    pub load_op: AttachmentLoadOp,
+    pub store_op: AttachmentStoreOp,
+    /// Receives the resolved samples of `image` at the end of the pass.
+    pub resolve_image: Option<Arc<ImageView>>,
+}
+
+impl ColorAttachment {
+    /// Loaded and stored, without a resolve.
+    pub fn loaded(image: Arc<ImageView>) -> Self {
+        Self {
+            load_op: AttachmentLoadOp::Load,
+            ..Self::cleared(image, [0.0; 4])
+        }
+    }
+
+    /// Cleared to `clear_value` and stored, without a resolve.
+    pub fn cleared(image: Arc<ImageView>, clear_value: impl Into<ClearValue>) -> Self {
+        Self {
+            image,
+            clear_value: clear_value.into(),
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            resolve_image: None,
+        }
+    }
+
+    pub(crate) fn into_rendering_info(self) -> RenderingAttachmentInfo {
+        RenderingAttachmentInfo {
+            load_op: self.load_op,
+            store_op: self.store_op,
+            clear_value: (self.load_op == AttachmentLoadOp::Clear).then_some(self.clear_value),
+            resolve_info: self
+                .resolve_image
+                .map(RenderingAttachmentResolveInfo::image_view),
+            ..RenderingAttachmentInfo::image_view(self.image)
+        }
+    }
+}
+
+/// Depth test and write state of the forward pass. It is dynamic where the device supports it
+/// (Vulkan 1.3 or `ext_extended_dynamic_state`), and baked into the pipelines otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthSettings {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: CompareOp::Less,
+        }
+    }
+}
+
+impl DepthSettings {
+    pub(crate) const DYNAMIC_STATES: [DynamicState; 3] = [
+        DynamicState::DepthTestEnable,
+        DynamicState::DepthWriteEnable,
+        DynamicState::DepthCompareOp,
+    ];
+
+    pub(crate) fn is_dynamic(device: &Device) -> bool {
+        device.api_version() >= Version::V1_3
+            || device.enabled_extensions().ext_extended_dynamic_state
+    }
+
+    /// With `dynamic` state the depth state only provides defaults, so it must be present even
+    /// if the test starts out disabled.
+    pub(crate) fn depth_stencil_state(&self, dynamic: bool) -> DepthStencilState {
+        DepthStencilState {
+            depth: (dynamic || self.test).then_some(DepthState {
+                compare_op: self.compare_op,
+                write_enable: self.write,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Only valid for pipelines created with [`Self::DYNAMIC_STATES`].
+    pub(crate) fn record(&self, builder: &mut RecordingCommandBuffer) {
+        builder
+            .set_depth_test_enable(self.test)
+            .unwrap()
+            .set_depth_write_enable(self.write)
+            .unwrap()
+            .set_depth_compare_op(self.compare_op)
+            .unwrap();
+    }
+}
+
+/// Depth offset of one material's surfaces, e.g. to draw decals over the surfaces they lie on
+/// without z-fighting. Negative factors pull surfaces towards the camera. The default has no
+/// effect.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    /// Scaled by the depth slope, to keep surfaces at grazing angles apart.
+    pub slope_factor: f32,
+}
+
+impl DepthBias {
+    /// In front of coplanar surfaces, used for materials named as decals.
+    pub const DECAL: DepthBias = DepthBias {
+        constant_factor: -2.0,
+        slope_factor: -2.0,
+    };
+
+    /// Only valid for pipelines with a dynamic [`DynamicState::DepthBias`].
+    pub(crate) fn record(&self, builder: &mut RecordingCommandBuffer) {
+        builder
+            .set_depth_bias(self.constant_factor, 0.0, self.slope_factor)
+            .unwrap();
+    }
+}
 
+/// The opposite winding, for geometry mirrored by its transform.
+pub(crate) fn flipped(front_face: FrontFace) -> FrontFace {
+    if front_face == FrontFace::Clockwise {
+        FrontFace::CounterClockwise
+    } else {
+        FrontFace::Clockwise
+    }
+}
+
+pub(crate) fn create_uniform_buffer_from_data<T>(
+    allocator: Arc<StandardMemoryAllocator>,
+    data: T,
+) -> Subbuffer<T>
+where
+    T: BufferContents,
+{
+    Buffer::from_data(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap()
+}
+
+/// Overwrites a buffer from [`create_uniform_buffer_from_data`] in place, e.g. once per frame.
+///
+/// The buffer is shared by every frame, so this must not be called while a frame reading it is
+/// still executing; the renderer only starts a frame after the previous one finished. Panics if
+/// the GPU is still accessing the buffer.
+pub(crate) fn update_uniform_buffer<T>(buffer: &Subbuffer<T>, data: T)
+where
+    T: BufferContents,
+{
+    *buffer.write().unwrap() = data;
+}
+
+/// A column-major joint matrix as laid out in the `JointPalette` storage buffer.
+pub type JointMatrix = [[f32; 4]; 4];
+
+/// A host-writable joint palette of `joint_count` identity matrices.
+pub(crate) fn create_joint_palette(
+    allocator: Arc<StandardMemoryAllocator>,
+    joint_count: usize,
+) -> Subbuffer<[JointMatrix]> {
+    Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        (0..joint_count).map(|_| Matrix4::identity().into()),
+    )
+    .unwrap()
+}
+
+/// A position delta as laid out in the `MorphTargets` storage buffer.
+pub type MorphDelta = [f32; 4];
+
+/// Per-object morph target deltas, every target of the first vertex then of the second and so
+/// on, and a host-writable weight for each target.
+#[derive(Clone)]
+pub struct MorphTargets {
+    pub deltas: Subbuffer<[MorphDelta]>,
+    pub weights: Subbuffer<[f32]>,
+    target_count: usize,
+}
+
+impl MorphTargets {
+    /// Both buffers are padded to at least one element. Targets weighted zero are skipped, so the
+    /// padding is never read.
+    pub(crate) fn new(
+        allocator: Arc<StandardMemoryAllocator>,
+        mut deltas: Vec<MorphDelta>,
+        target_count: usize,
+    ) -> MorphTargets {
+        if deltas.is_empty() {
+            deltas.push([0.0; 4]);
+        }
+        let create_info = || BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        };
+        let allocation_info = || AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        Self {
+            deltas: Buffer::from_iter(allocator.clone(), create_info(), allocation_info(), deltas)
+                .unwrap(),
+            weights: Buffer::from_iter(
+                allocator,
+                create_info(),
+                allocation_info(),
+                vec![0.0; target_count.max(1)],
+            )
+            .unwrap(),
+            target_count,
+        }
+    }
+
+    /// Weights past `weights` are zero and past the target count are ignored.
+    pub(crate) fn set_weights(&self, weights: &[f32]) {
+        let mut dst = self.weights.write().unwrap();
+        for (i, dst) in dst.iter_mut().take(self.target_count).enumerate() {
+            *dst = weights.get(i).copied().unwrap_or(0.0);
+        }
+    }
+
+    /// The deltas at `binding` and the weights right after.
+    pub(crate) fn writes(&self, binding: u32) -> [WriteDescriptorSet; 2] {
+        [
+            WriteDescriptorSet::buffer(binding, self.deltas.clone()),
+            WriteDescriptorSet::buffer(binding + 1, self.weights.clone()),
+        ]
+    }
+}
+
+/// Binds the geometry and issues the draw. The pipeline and its descriptor sets must already be
+/// bound.
+pub fn draw_mesh(
+    builder: &mut RecordingCommandBuffer,
+    vertex_buffer: Subbuffer<[MyVertex]>,
+    index_buffer: Option<Subbuffer<[u32]>>,
+) {
+    let vertex_count = vertex_buffer.len() as u32;
+
+    builder.bind_vertex_buffers(0, vertex_buffer).unwrap();
+    unsafe {
+        if let Some(index_buffer) = index_buffer {
+            let index_count = index_buffer.len() as u32;
+            builder
+                .bind_index_buffer(index_buffer)
+                .unwrap()
+                .draw_indexed(index_count, 1, 0, 0, 0)
+                .unwrap()
+        } else {
+            builder.draw(vertex_count, 1, 0, 0).unwrap()
+        }
+    };
+}
+
+/// Like [`draw_mesh`], but with the counts read from `command`, see
+/// [`culling::CullingPipeline::command`].
+pub fn draw_mesh_indirect(
+    builder: &mut RecordingCommandBuffer,
+    vertex_buffer: Subbuffer<[MyVertex]>,
+    index_buffer: Option<Subbuffer<[u32]>>,
+    command: Subbuffer<[u32]>,
+) {
+    builder.bind_vertex_buffers(0, vertex_buffer).unwrap();
+    unsafe {
+        if let Some(index_buffer) = index_buffer {
+            builder
+                .bind_index_buffer(index_buffer)
+                .unwrap()
+                .draw_indexed_indirect(command.reinterpret())
+                .unwrap()
+        } else {
+            builder.draw_indirect(command.reinterpret()).unwrap()
+        }
+    };
+}
+
+/// Like [`draw_mesh`], but once per element of `instances`, bound as the second vertex buffer.
+pub fn draw_mesh_instanced(
+    builder: &mut RecordingCommandBuffer,
+    vertex_buffer: Subbuffer<[MyVertex]>,
+    index_buffer: Option<Subbuffer<[u32]>>,
+    instances: Subbuffer<[InstanceData]>,
+) {
+    let vertex_count = vertex_buffer.len() as u32;
+    let instance_count = instances.len() as u32;
+
+    builder
+        .bind_vertex_buffers(0, (vertex_buffer, instances))
+        .unwrap();
+    unsafe {
+        if let Some(index_buffer) = index_buffer {
+            let index_count = index_buffer.len() as u32;
+            builder
+                .bind_index_buffer(index_buffer)
+                .unwrap()
+                .draw_indexed(index_count, instance_count, 0, 0, 0)
+                .unwrap()
+        } else {
+            builder.draw(vertex_count, instance_count, 0, 0).unwrap()
+        }
+    };
+}
+
+/// Continues the main pass of [`draw`] in its `postpass_fn`, e.g. to draw over a copy of what it
+/// rendered. `color_attachments` should load images the main pass stored. Depth is loaded, so
+/// `depth_image` needs sampled usage for the main pass to store it, and then discarded.
+pub(crate) fn resume_rendering(
+    builder: &mut RecordingCommandBuffer,
+    color_attachments: Vec<ColorAttachment>,
+    depth_image: Arc<ImageView>,
+) -> Rendering {
+    let extent = depth_image.image().extent();
+    let rendering = begin_rendering(
+        builder,
+        RenderingInfo {
+            color_attachments: color_attachments
+                .into_iter()
+                .map(|attachment| Some(attachment.into_rendering_info()))
+                .collect(),
+            depth_attachment: Some(RenderingAttachmentInfo {
+                load_op: AttachmentLoadOp::Load,
+                store_op: AttachmentStoreOp::DontCare,
+                ..RenderingAttachmentInfo::image_view(depth_image.clone())
+            }),
+            stencil_attachment: Some(RenderingAttachmentInfo {
+                load_op: AttachmentLoadOp::DontCare,
+                store_op: AttachmentStoreOp::DontCare,
+                ..RenderingAttachmentInfo::image_view(depth_image)
+            }),
+            ..Default::default()
+        },
+    );
+    builder
+        .set_viewport(
+            0,
+            [Viewport {
+                extent: [extent[0] as f32, extent[1] as f32],
+                ..Default::default()
+            }]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+    rendering
+}
+
+/// Records `prepass_fn` outside of any render pass (e.g. shadow maps), then the main pass with
+/// `record_fn`, then `postpass_fn` outside of any render pass again (e.g. TAA).
+///
+/// `depth_image` must have both a depth and a stencil aspect and the extent of the color
+/// attachments; depth is cleared to one and the stencil to zero. Depth is only stored if the
+/// image has sampled usage, for passes that read it.
+#[allow(clippy::too_many_arguments)]
 pub fn draw(
     before: Box<dyn GpuFuture>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     queue: Arc<Queue>,
-    msaa_color_image: Arc<ImageView>,
-    dst_image: Arc<ImageView>,
+    color_attachments: Vec<ColorAttachment>,
     depth_image: Arc<ImageView>,
+    prepass_fn: impl FnOnce(&mut RecordingCommandBuffer),
     record_fn: impl FnOnce(&mut RecordingCommandBuffer),
+    postpass_fn: impl FnOnce(&mut RecordingCommandBuffer),
 ) -> Box<dyn GpuFuture> {
     let mut builder = RecordingCommandBuffer::new(
         command_buffer_allocator.clone(),
@@ -36,39 +658,52 @@ pub fn draw(
     )
     .unwrap();
 
+    prepass_fn(&mut builder);
+
     let viewport: Viewport = {
-        let extent = msaa_color_image.image().extent();
+        let extent = depth_image.image().extent();
         Viewport {
             extent: [extent[0] as f32, extent[1] as f32],
             ..Default::default()
         }
     };
 
-    builder
-        .begin_rendering(RenderingInfo {
-            color_attachments: vec![Some(RenderingAttachmentInfo {
-                load_op: AttachmentLoadOp::Clear,
-                store_op: AttachmentStoreOp::Store,
-                clear_value: Some([0.0, 0.0, 0.0, 1.0].into()),
-                resolve_info: Some(RenderingAttachmentResolveInfo::image_view(dst_image)),
-                ..RenderingAttachmentInfo::image_view(msaa_color_image)
-            })],
+    let rendering = begin_rendering(
+        &mut builder,
+        RenderingInfo {
+            color_attachments: color_attachments
+                .into_iter()
+                .map(|attachment| Some(attachment.into_rendering_info()))
+                .collect(),
             depth_attachment: Some(RenderingAttachmentInfo {
                 load_op: AttachmentLoadOp::Clear,
-                store_op: AttachmentStoreOp::DontCare,
+                store_op: if depth_image.usage().intersects(ImageUsage::SAMPLED) {
+                    AttachmentStoreOp::Store
+                } else {
+                    AttachmentStoreOp::DontCare
+                },
                 clear_value: Some(ClearValue::Depth(1.0)),
+                ..RenderingAttachmentInfo::image_view(depth_image.clone())
+            }),
+            stencil_attachment: Some(RenderingAttachmentInfo {
+                load_op: AttachmentLoadOp::Clear,
+                store_op: AttachmentStoreOp::DontCare,
+                clear_value: Some(ClearValue::Stencil(0)),
                 ..RenderingAttachmentInfo::image_view(depth_image)
             }),
 
             ..Default::default()
-        })
-        .unwrap()
+        },
+    );
+    builder
         .set_viewport(0, [viewport].into_iter().collect())
         .unwrap();
 
     record_fn(&mut builder);
 
-    builder.end_rendering().unwrap();
+    rendering.end(&mut builder);
+
+    postpass_fn(&mut builder);
 
     let command_buffer = builder.end().unwrap();
 
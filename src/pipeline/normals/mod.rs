@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use cgmath::SquareMatrix;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{CopyImageToBufferInfo, RecordingCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{
+    create_uniform_buffer_from_data, draw_mesh, pipeline_layout, sample::Camera,
+    update_uniform_buffer,
+};
+use crate::{render_pass::pipeline_subpass, App, MyVertex};
+
+/// The main pass' vertex shader, so skinning and morphing match.
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/sample/sample.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/normals/normals.frag");
+}
+
+/// Format of [`NormalsPipeline::image`].
+pub const NORMALS_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// Renders world-space normals, mapped to `[0, 1]`, into an image of its own for tools to read.
+/// Parts nothing was drawn to are cleared to zero, alpha included.
+pub struct NormalsPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    frame_uniform: Subbuffer<vs::Frame>,
+    descriptor_set: Arc<DescriptorSet>,
+    image: Arc<ImageView>,
+    depth_image: Arc<ImageView>,
+    /// Host-visible copy of `image`.
+    buffer: Subbuffer<[u8]>,
+}
+
+impl NormalsPipeline {
+    /// `depth_stencil_format` is that of the main pass, whose object sets (set 0) are reused.
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        depth_stencil_format: Format,
+        extent: [u32; 2],
+    ) -> NormalsPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = fs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let vertex_input_state = MyVertex::per_vertex()
+                .definition(&vs.info().input_interface)
+                .unwrap();
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(NORMALS_FORMAT)],
+                depth_attachment_format: Some(depth_stencil_format),
+                stencil_attachment_format: Some(depth_stencil_format),
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(vertex_input_state),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::TriangleList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    // Unculled, so that flipped winding shows up rather than holes.
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        1,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth: Some(DepthState {
+                            compare_op: CompareOp::Less,
+                            write_enable: true,
+                        }),
+                        ..Default::default()
+                    }),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // set = 1, binding = 4. Nothing reads the motion vectors.
+        let motion_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::Motion {
+                previous_view_proj: cgmath::Matrix4::identity().into(),
+                jitter: [0.0; 2],
+            },
+        );
+        // set = 1, binding = 8
+        let frame_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::Frame {
+                view: cgmath::Matrix4::identity().into(),
+                proj: cgmath::Matrix4::identity().into(),
+                camera_pos: [0.0; 3],
+                debug_view: 0,
+            },
+        );
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::buffer(4, motion_uniform),
+                WriteDescriptorSet::buffer(8, frame_uniform.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+
+        let image = |format, usage| {
+            ImageView::new_default(
+                Image::new(
+                    app.memory_allocator(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [extent[0], extent[1], 1],
+                        format,
+                        usage,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let buffer = Buffer::new_slice(
+            app.memory_allocator(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            extent[0] as u64 * extent[1] as u64 * NORMALS_FORMAT.block_size(),
+        )
+        .unwrap();
+        Self {
+            pipeline,
+            frame_uniform,
+            descriptor_set,
+            image: image(
+                NORMALS_FORMAT,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ),
+            depth_image: image(depth_stencil_format, ImageUsage::DEPTH_STENCIL_ATTACHMENT),
+            buffer,
+        }
+    }
+
+    /// What [`Self::render_object`] draws into, to be cleared by the pass.
+    pub fn image(&self) -> Arc<ImageView> {
+        self.image.clone()
+    }
+
+    /// The depth/stencil attachment to go with [`Self::image`].
+    pub fn depth_image(&self) -> Arc<ImageView> {
+        self.depth_image.clone()
+    }
+
+    /// Copies [`Self::image`] to the buffer [`Self::read_buffer`] reads. Must be recorded outside
+    /// of any render pass.
+    pub fn copy_to_buffer(&self, builder: &mut RecordingCommandBuffer) {
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.image.image().clone(),
+                self.buffer.clone(),
+            ))
+            .unwrap();
+    }
+
+    /// The texels of the last [`Self::copy_to_buffer`], once it finished executing.
+    pub fn read_buffer(&self) -> Vec<u8> {
+        self.buffer.read().unwrap().to_vec()
+    }
+
+    /// Binds the pipeline and uploads `camera`. Must be called before [`Self::render_object`],
+    /// and not while a frame using this pipeline is still executing.
+    pub fn begin_frame(&self, builder: &mut RecordingCommandBuffer, camera: &Camera) {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                1,
+                self.descriptor_set.clone(),
+            )
+            .unwrap();
+        update_uniform_buffer(
+            &self.frame_uniform,
+            vs::Frame {
+                view: camera.view.into(),
+                proj: camera.proj.into(),
+                camera_pos: camera.position.into(),
+                debug_view: 0,
+            },
+        );
+    }
+
+    /// Draws one object with a set from [`SamplePipeline::object_descriptor_set`].
+    ///
+    /// [`SamplePipeline::object_descriptor_set`]: super::sample::SamplePipeline::object_descriptor_set
+    pub fn render_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+    ) {
+        builder
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                object_descriptor_set,
+            )
+            .unwrap();
+        draw_mesh(builder, vertex_buffer, index_buffer);
+    }
+}
@@ -0,0 +1,10 @@
+#version 460
+
+layout(location = 1) in vec3 fragNormal;
+layout(location = 0) out vec4 outColor;
+
+void main() {
+  // Back faces show the side facing the camera, like the shaded view.
+  vec3 norm = normalize(gl_FrontFacing ? fragNormal : -fragNormal);
+  outColor = vec4(norm * 0.5 + 0.5, 1.0);
+}
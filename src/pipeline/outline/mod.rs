@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use cgmath::SquareMatrix;
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::RecordingCommandBuffer,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    image::SampleCount,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorComponents},
+            depth_stencil::{
+                CompareOp, DepthStencilState, StencilOp, StencilOpState, StencilOps, StencilState,
+            },
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::{CullMode, RasterizationState},
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{
+    color_only_blend_state, create_uniform_buffer_from_data, draw_mesh, pipeline_layout,
+    sample::Camera, JointMatrix, MorphTargets,
+};
+use crate::{render_pass::pipeline_subpass, App, MyVertex};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/outline/outline.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/outline/outline.frag");
+}
+
+const SELECTED_STENCIL: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct OutlineStyle {
+    pub color: [f32; 4],
+    /// Distance the outline extends past the surface, in world units.
+    pub width: f32,
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 0.6, 0.1, 1.0],
+            width: 0.02,
+        }
+    }
+}
+
+/// Draws an outline around selected objects in two steps: their silhouette is written to the
+/// stencil buffer, then a hull pushed out along the normals is filled wherever the stencil
+/// wasn't written. Both ignore depth, so outlines stay visible behind other geometry.
+pub struct OutlinePipeline {
+    mask_pipeline: Arc<GraphicsPipeline>,
+    outline_pipeline: Arc<GraphicsPipeline>,
+    model_uniform: Subbuffer<vs::ModelBuffer>,
+}
+
+impl OutlinePipeline {
+    /// `rendering_info` must have a stencil attachment format.
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) -> OutlinePipeline {
+        let device = queue.device();
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = MyVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        // Shared so that both passes can use the same object descriptor sets.
+        let layout = pipeline_layout(device, &stages);
+
+        let pipeline = |color_write_mask, stencil_ops| {
+            let stencil = StencilOpState {
+                ops: stencil_ops,
+                reference: SELECTED_STENCIL,
+                ..Default::default()
+            };
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::TriangleList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState {
+                        cull_mode: CullMode::None,
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: samples,
+                        ..Default::default()
+                    }),
+                    color_blend_state: Some(color_only_blend_state(
+                        &rendering_info,
+                        ColorBlendAttachmentState {
+                            color_write_mask,
+                            ..Default::default()
+                        },
+                    )),
+                    depth_stencil_state: Some(DepthStencilState {
+                        stencil: Some(StencilState {
+                            front: stencil,
+                            back: stencil,
+                        }),
+                        ..Default::default()
+                    }),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(device, rendering_info.clone(), samples)),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
+
+        let mask_pipeline = pipeline(
+            ColorComponents::empty(),
+            StencilOps {
+                pass_op: StencilOp::Replace,
+                compare_op: CompareOp::Always,
+                ..Default::default()
+            },
+        );
+        let outline_pipeline = pipeline(
+            ColorComponents::all(),
+            StencilOps {
+                compare_op: CompareOp::NotEqual,
+                ..Default::default()
+            },
+        );
+
+        // set = 0, binding = 0
+        let model_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::ModelBuffer {
+                model: cgmath::Matrix4::identity().into(),
+            },
+        );
+
+        Self {
+            mask_pipeline,
+            outline_pipeline,
+            model_uniform,
+        }
+    }
+
+    /// Creates the per-object set (set 0) skinning with `joint_palette` and morphing with
+    /// `morph_targets`.
+    pub fn object_descriptor_set(
+        &self,
+        app: &App,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.mask_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.model_uniform.clone()),
+                WriteDescriptorSet::buffer(1, joint_palette),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(2)),
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Outlines one object. Must be recorded after the rest of the scene, in a render pass with a
+    /// stencil attachment cleared to zero.
+    pub fn render_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        camera: &Camera,
+        style: &OutlineStyle,
+    ) {
+        let view_proj = (camera.proj * camera.view).into();
+        for (pipeline, width) in [
+            (&self.mask_pipeline, 0.0),
+            (&self.outline_pipeline, style.width),
+        ] {
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .unwrap()
+                .bind_descriptor_sets(
+                    pipeline.bind_point(),
+                    pipeline.layout().clone(),
+                    0,
+                    object_descriptor_set.clone(),
+                )
+                .unwrap()
+                .push_constants(
+                    pipeline.layout().clone(),
+                    0,
+                    vs::PushConstants {
+                        view_proj,
+                        color: style.color,
+                        width,
+                    },
+                )
+                .unwrap();
+
+            draw_mesh(builder, vertex_buffer.clone(), index_buffer.clone());
+        }
+    }
+}
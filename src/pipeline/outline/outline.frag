@@ -0,0 +1,12 @@
+#version 460
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec4 color;
+  float width;
+}
+pc;
+
+layout(location = 0) out vec4 outColor;
+
+void main() { outColor = pc.color; }
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::RecordingCommandBuffer,
+    device::Queue,
+    image::SampleCount,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendAttachmentState,
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{color_only_blend_state, draw_mesh, pipeline_layout, sample::Camera};
+use crate::{render_pass::pipeline_subpass, MyVertex};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/point/point.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/point/point.frag");
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointStyle {
+    /// Diameter in pixels, clamped to what the device supports: 1 without the `large_points`
+    /// feature.
+    pub size: f32,
+    /// Discards the corners of each point so it renders as a disc.
+    pub round: bool,
+    pub color: [f32; 4],
+}
+
+impl Default for PointStyle {
+    fn default() -> Self {
+        Self {
+            size: 4.0,
+            round: true,
+            color: [0.7, 0.7, 0.7, 1.0],
+        }
+    }
+}
+
+/// Draws meshes as point lists, e.g. point clouds, or as line lists, unlit in the style's color.
+pub struct PointPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    line_pipeline: Arc<GraphicsPipeline>,
+    point_size_range: [f32; 2],
+}
+
+impl PointPipeline {
+    pub fn new(
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) -> PointPipeline {
+        let device = queue.device();
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = MyVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = pipeline_layout(device, &stages);
+
+        let pipeline = |topology| {
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: samples,
+                        ..Default::default()
+                    }),
+                    color_blend_state: Some(color_only_blend_state(
+                        &rendering_info,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth: Some(DepthState {
+                            compare_op: CompareOp::Less,
+                            write_enable: true,
+                        }),
+                        ..Default::default()
+                    }),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(device, rendering_info.clone(), samples)),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
+
+        let point_size_range = match device.enabled_features().large_points {
+            true => device.physical_device().properties().point_size_range,
+            false => [1.0; 2],
+        };
+
+        Self {
+            pipeline: pipeline(PrimitiveTopology::PointList),
+            line_pipeline: pipeline(PrimitiveTopology::LineList),
+            point_size_range,
+        }
+    }
+
+    /// Draws one object as points, or with `lines` as a line list, one pixel wide.
+    pub fn render_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        lines: bool,
+        camera: &Camera,
+        style: &PointStyle,
+    ) {
+        let pipeline = match lines {
+            true => &self.line_pipeline,
+            false => &self.pipeline,
+        };
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vs::PushConstants {
+                    view_proj: (camera.proj * camera.view).into(),
+                    color: style.color,
+                    point_size: style
+                        .size
+                        .clamp(self.point_size_range[0], self.point_size_range[1]),
+                    round_points: (style.round && !lines) as u32,
+                },
+            )
+            .unwrap();
+
+        draw_mesh(builder, vertex_buffer, index_buffer);
+    }
+}
@@ -0,0 +1,18 @@
+#version 460
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec4 color;
+  float point_size;
+  uint round_points;
+}
+pc;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+  if (pc.round_points != 0 && length(gl_PointCoord - 0.5) > 0.5) {
+    discard;
+  }
+  outColor = pc.color;
+}
@@ -0,0 +1,16 @@
+#version 460
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec4 color;
+  float point_size;
+  uint round_points;
+}
+pc;
+
+layout(location = 0) in vec3 position;
+
+void main() {
+  gl_Position = pc.view_proj * vec4(position, 1.0);
+  gl_PointSize = pc.point_size;
+}
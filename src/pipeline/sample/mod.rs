@@ -1,45 +1,484 @@
-use std::{mem::size_of, sync::Arc};
+use std::{cell::Cell, mem::size_of, sync::Arc};
 
-use cgmath::SquareMatrix;
+use cgmath::{Deg, InnerSpace, Rad, SquareMatrix, Vector3};
 use vulkano::{
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
+    },
     command_buffer::RecordingCommandBuffer,
-    descriptor_set::{DescriptorSet, WriteDescriptorSet},
-    device::Queue,
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, CopyDescriptorSet, DescriptorSet,
+        DescriptorSetWithOffsets, WriteDescriptorSet,
+    },
+    device::{Device, DeviceOwned, Queue},
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        SampleCount,
+    },
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     padded::Padded,
     pipeline::{
         graphics::{
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
-            rasterization::{CullMode, PolygonMode, RasterizationState},
+            rasterization::{DepthBiasState, FrontFace, PolygonMode, RasterizationState},
             subpass::PipelineRenderingCreateInfo,
-            vertex_input::{Vertex, VertexDefinition},
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
             viewport::ViewportState,
             GraphicsPipelineCreateInfo,
         },
-        layout::PipelineDescriptorSetLayoutCreateInfo,
         DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
     },
 };
 
-use crate::{App, MyVertex};
+use super::{
+    create_uniform_buffer_from_data, draw_mesh, draw_mesh_indirect, draw_mesh_instanced, flipped,
+    pipeline_layout_with_dynamic_uniforms,
+    shadow::{Cascade, ShadowMap, CASCADE_COUNT},
+    update_uniform_buffer, DepthBias, DepthSettings, FaceCulling, JointMatrix, MorphTargets,
+    PipelineError,
+};
+use crate::{
+    bounds::Aabb, render_pass::pipeline_subpass, texture, App, InstanceData, MyVertex, FLIP_Y,
+    Z_FAR, Z_NEAR,
+};
 
 mod vs {
     vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/sample/sample.vert");
 }
 
+/// [`vs`] reading [`InstanceData`] too.
+mod instanced_vs {
+    vulkano_shaders::shader!(
+        ty: "vertex",
+        path: "src/pipeline/sample/sample.vert",
+        define: [("INSTANCED", "")],
+    );
+}
+
 mod fs {
     vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/sample/sample.frag");
 }
 
 pub use fs::{Light, Material};
 
+pub const DEFAULT_LIGHT_POSITION: [f32; 3] = [3.0, 3.0, 3.0];
+/// Lights the forward path shades with at most, see [`SamplePipeline::set_lights`].
+pub const MAX_LIGHTS: usize = 64;
+
+/// Initial parameters of one of the scene's lights, shared by both shading paths.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSettings {
+    pub kind: LightKind,
+    /// Linear; see [`crate::texture::srgb_to_linear`] for sRGB-authored colors.
+    pub color: [f32; 3],
+    /// Scales the diffuse and specular terms; the ambient one is [`AmbientSettings`].
+    pub intensity: f32,
+}
+
+impl Default for LightSettings {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Point {
+                position: DEFAULT_LIGHT_POSITION,
+            },
+            color: [1.0; 3],
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Point {
+        position: [f32; 3],
+    },
+    /// Shining everywhere from the same direction, like the sun. `direction` is the one the light
+    /// travels in and needn't be normalized.
+    Directional {
+        direction: [f32; 3],
+    },
+}
+
+impl LightSettings {
+    /// Direction the light travels in, for the shadows. Point lights are taken to shine towards
+    /// the origin.
+    pub(crate) fn direction(&self) -> Vector3<f32> {
+        match self.kind {
+            LightKind::Point { position } => -Vector3::from(position).normalize(),
+            LightKind::Directional { direction } => Vector3::from(direction).normalize(),
+        }
+    }
+
+    /// `(kind, position, direction)` as the shaders take them, with 0 for point lights and 1 for
+    /// directional ones. The field that doesn't apply is zero.
+    pub(super) fn shader_terms(&self) -> (u32, [f32; 3], [f32; 3]) {
+        match self.kind {
+            LightKind::Point { position } => (0, position, [0.0; 3]),
+            LightKind::Directional { .. } => (1, [0.0; 3], self.direction().into()),
+        }
+    }
+
+    pub(super) fn diffuse(&self) -> [f32; 3] {
+        self.color.map(|c| c * self.intensity)
+    }
+
+    pub(super) fn specular(&self) -> [f32; 3] {
+        self.diffuse().map(|c| c * 2.0)
+    }
+}
+
+/// The constant light every surface gets regardless of the lights, shared by both shading
+/// paths.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientSettings {
+    /// Linear, multiplied by each object's tint.
+    pub color: [f32; 3],
+    /// How much baked occlusion darkens the ambient term, from 0 (ignored) to 1. On the forward
+    /// path this blends the lightmap in over `color`; the deferred path has none baked.
+    pub occlusion_strength: f32,
+}
+
+impl Default for AmbientSettings {
+    fn default() -> Self {
+        Self {
+            color: [0.1; 3],
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+/// Distance fog, blending shaded surfaces towards `color` with their view-space depth. Shared by
+/// both shading paths; the background stays as it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogSettings {
+    /// Linear.
+    pub color: [f32; 3],
+    pub falloff: FogFalloff,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            color: [0.5; 3],
+            falloff: FogFalloff::Linear {
+                start: 10.0,
+                end: 50.0,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogFalloff {
+    /// None before `start`, fully fogged from `end` on, which must be further away.
+    Linear { start: f32, end: f32 },
+    /// `1 - e^(-density * depth)` of the fog color.
+    Exponential { density: f32 },
+}
+
+impl FogSettings {
+    /// The falloff and its `[start, end, density]` as the shaders take them, falloff 0 being no
+    /// fog.
+    pub(super) fn shader_terms(fog: Option<&Self>) -> ([f32; 3], u32, [f32; 3]) {
+        match fog {
+            None => ([0.0; 3], 0, [0.0; 3]),
+            Some(fog) => match fog.falloff {
+                FogFalloff::Linear { start, end } => (fog.color, 1, [start, end, 0.0]),
+                FogFalloff::Exponential { density } => (fog.color, 2, [0.0, 0.0, density]),
+            },
+        }
+    }
+}
+
+/// What the fragment shader outputs instead of the final shading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    /// World-space normals, mapped to `[0, 1]`.
+    Normals,
+    /// View-space depth, linear from the camera to the far plane.
+    Depth,
+    /// The UV set the base color is mapped with.
+    TexCoords,
+    /// The diffuse material color.
+    BaseColor,
+    /// Metallic in red and roughness in green. Under [`LightingModel::Phong`], specular strength
+    /// and shininess instead, the closest its material has to them.
+    Material,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 6] = [
+        DebugView::Shaded,
+        DebugView::Normals,
+        DebugView::Depth,
+        DebugView::TexCoords,
+        DebugView::BaseColor,
+        DebugView::Material,
+    ];
+
+    /// The next view, wrapping around to [`DebugView::Shaded`].
+    pub fn next(self) -> DebugView {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// How the forward path shades lit surfaces, see [`SamplePipeline::set_scene`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightingModel {
+    /// Phong diffuse and specular terms from the scene's [`Material`], which ignore
+    /// the objects' metallic and roughness.
+    #[default]
+    Phong,
+    /// Cook-Torrance, from each object's base color and [`PbrMaterial`].
+    Pbr,
+}
+
+impl LightingModel {
+    /// The other model.
+    pub fn toggled(self) -> LightingModel {
+        match self {
+            LightingModel::Phong => LightingModel::Pbr,
+            LightingModel::Pbr => LightingModel::Phong,
+        }
+    }
+}
+
+/// The factors of glTF's metallic-roughness model, shaded with [`LightingModel::Pbr`]. Their
+/// textures aren't read, like the base color's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PbrMaterial {
+    /// From 0 for dielectrics to 1 for metals.
+    pub metallic: f32,
+    /// Perceptual roughness, from 0 to 1, multiplied by [`MaterialOverride::roughness_scale`].
+    pub roughness: f32,
+}
+
+impl Default for PbrMaterial {
+    /// glTF's default, a rough metal.
+    fn default() -> Self {
+        Self {
+            metallic: 1.0,
+            roughness: 1.0,
+        }
+    }
+}
+
+/// Multipliers on top of an object's material, for experimenting without touching its buffers.
+/// The default leaves the material as is.
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct MaterialOverride {
+    /// Multiplies the diffuse and ambient colors, in linear space.
+    pub tint: [f32; 3],
+    /// Multiplies the roughness, dividing the Phong shininess.
+    pub roughness_scale: f32,
+}
+
+/// `KHR_materials_clearcoat`: a clear dielectric layer over the material, like lacquer or car
+/// paint, with a specular highlight of its own. The default has no coat.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clearcoat {
+    /// How much of the surface is coated, from 0 to 1.
+    pub factor: f32,
+    /// Perceptual roughness of the coat, from 0 to 1.
+    pub roughness: f32,
+}
+
+/// `KHR_materials_transmission` with `KHR_materials_ior`: how much light passes through the
+/// surface, e.g. for glass, showing what's behind it refracted. The default is opaque.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transmission {
+    /// How much of the diffuse light is replaced by transmitted light, from 0 to 1.
+    pub factor: f32,
+    pub ior: f32,
+}
+
+impl Default for Transmission {
+    fn default() -> Self {
+        Self {
+            factor: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl Transmission {
+    pub fn is_transmissive(&self) -> bool {
+        self.factor > 0.0
+    }
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        Self {
+            tint: [1.0; 3],
+            roughness_scale: 1.0,
+        }
+    }
+}
+
 pub struct SamplePipeline {
     pipeline: Arc<GraphicsPipeline>,
-    descriptor_sets: [Arc<DescriptorSet>; 2],
+    mirrored_pipeline: Arc<GraphicsPipeline>,
+    /// Without culling, front and mirrored.
+    double_sided_pipelines: [Arc<GraphicsPipeline>; 2],
+    /// For [`Self::render_instanced`]: culled front and mirrored, then double-sided ones.
+    instanced_pipelines: [Arc<GraphicsPipeline>; 4],
+    /// Whether [`Self::set_depth`] takes effect.
+    dynamic_depth: bool,
+    /// What the pipelines were last created for, see [`Self::set_samples`].
+    rendering_info: PipelineRenderingCreateInfo,
+    samples: SampleCount,
+    model_uniform: Subbuffer<vs::ModelBuffer>,
+    /// One per frame in flight, see [`Self::select_frame`].
+    frames: Vec<FrameResources>,
+    /// Index into `frames` of the slot being written and bound.
+    frame: usize,
+    light_buffer: Subbuffer<fs::Lights>,
+    /// Created without lights, see [`Self::new`].
+    unlit: bool,
+    /// Indexed by [`SceneTexture`].
+    texture_samplers: [Arc<Sampler>; 2],
+    /// The texture set (set 2) of objects without textures.
+    untextured_descriptor_set: Arc<DescriptorSet>,
+    /// Bytes between material slots, see [`FrameResources::materials`].
+    material_stride: u64,
+    /// For [`Self::render_instanced`]. Its buffers are reused once the frames that read them
+    /// are dropped.
+    instance_allocator: SubbufferAllocator,
+}
+
+/// Materials a frame has room for, the scene's included. Further
+/// [`SamplePipeline::render_object_with_material`] calls draw with the scene's.
+const MATERIAL_SLOTS: u64 = 256;
+
+/// The uniforms rewritten every frame, and the fragment set (set 1) reading them along with
+/// the bindings shared by all frames.
+struct FrameResources {
+    fragment_descriptor_set: Arc<DescriptorSet>,
+    /// [`MATERIAL_SLOTS`] materials, the scene's first. The set's material binding has a
+    /// dynamic offset selecting one, so drawing with another material doesn't need another set.
+    materials: Subbuffer<[u8]>,
+    /// Slots of `materials` written this frame.
+    used_materials: Cell<u64>,
+    shadow_uniform: Subbuffer<fs::ShadowData>,
+    motion_uniform: Subbuffer<vs::Motion>,
+    scene_uniform: Subbuffer<fs::Scene>,
+    frame_uniform: Subbuffer<vs::Frame>,
+}
+
+impl FrameResources {
+    /// Creates the uniforms, with `material` in the first of the slots `material_stride` apart,
+    /// and passes their writes to `fragment_descriptor_set`.
+    fn new(
+        allocator: Arc<StandardMemoryAllocator>,
+        material: fs::Material,
+        material_stride: u64,
+        fragment_descriptor_set: impl FnOnce([WriteDescriptorSet; 5]) -> Arc<DescriptorSet>,
+    ) -> FrameResources {
+        // set = 1, binding = 0
+        let materials = Buffer::new_slice(
+            allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            MATERIAL_SLOTS * material_stride,
+        )
+        .unwrap();
+        let first_material = material_slot(&materials, 0, material_stride);
+        *first_material.write().unwrap() = material;
+
+        // set = 1, binding = 2
+        let shadow_uniform = create_uniform_buffer_from_data(
+            allocator.clone(),
+            fs::ShadowData {
+                light_view_proj: [cgmath::Matrix4::identity().into(); CASCADE_COUNT],
+                splits: [0.0; CASCADE_COUNT],
+                blend_band: 0.0,
+            },
+        );
+
+        // set = 1, binding = 4
+        let motion_uniform = create_uniform_buffer_from_data(
+            allocator.clone(),
+            vs::Motion {
+                previous_view_proj: cgmath::Matrix4::identity().into(),
+                jitter: [0.0; 2],
+            },
+        );
+
+        // set = 1, binding = 7
+        let ambient = AmbientSettings::default();
+        let scene_uniform = create_uniform_buffer_from_data(
+            allocator.clone(),
+            fs::Scene {
+                ambient: ambient.color,
+                occlusion_strength: ambient.occlusion_strength,
+                fog_color: [0.0; 3],
+                fog_falloff: 0,
+                fog_start: 0.0,
+                fog_end: 0.0,
+                fog_density: 0.0,
+                lighting_model: LightingModel::default() as u32,
+            },
+        );
+
+        // set = 1, binding = 8
+        let frame_uniform = create_uniform_buffer_from_data(
+            allocator,
+            vs::Frame {
+                view: cgmath::Matrix4::identity().into(),
+                proj: cgmath::Matrix4::identity().into(),
+                camera_pos: [0.0; 3],
+                debug_view: DebugView::Shaded as u32,
+            },
+        );
+
+        FrameResources {
+            fragment_descriptor_set: fragment_descriptor_set([
+                WriteDescriptorSet::buffer(0, first_material),
+                WriteDescriptorSet::buffer(2, shadow_uniform.clone()),
+                WriteDescriptorSet::buffer(4, motion_uniform.clone()),
+                WriteDescriptorSet::buffer(7, scene_uniform.clone()),
+                WriteDescriptorSet::buffer(8, frame_uniform.clone()),
+            ]),
+            materials,
+            used_materials: Cell::new(1),
+            shadow_uniform,
+            motion_uniform,
+            scene_uniform,
+            frame_uniform,
+        }
+    }
+}
+
+/// Textures shared by every object, see [`SamplePipeline::set_texture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneTexture {
+    Lightmap,
+    Matcap,
+}
+
+impl SceneTexture {
+    /// In the fragment set (set 1).
+    fn binding(self) -> u32 {
+        match self {
+            SceneTexture::Lightmap => 5,
+            SceneTexture::Matcap => 6,
+        }
+    }
 }
 
 pub struct Camera {
@@ -48,205 +487,922 @@ pub struct Camera {
     pub position: cgmath::Point3<f32>,
 }
 
-fn create_uniform_buffer_from_data<T>(
-    allocator: Arc<StandardMemoryAllocator>,
-    data: T,
-) -> Subbuffer<T>
-where
-    T: BufferContents,
-{
-    Buffer::from_data(
-        allocator,
-        BufferCreateInfo {
-            usage: BufferUsage::UNIFORM_BUFFER,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        data,
-    )
-    .unwrap()
+impl Camera {
+    /// Projection for images of `extent`, with `fovy` the vertical field of view.
+    pub fn perspective(
+        fovy: Deg<f32>,
+        extent: [u32; 2],
+        z_near: f32,
+        z_far: f32,
+    ) -> cgmath::Matrix4<f32> {
+        let aspect = extent[0].max(1) as f32 / extent[1].max(1) as f32;
+        FLIP_Y * cgmath::perspective(fovy, aspect, z_near, z_far)
+    }
+
+    /// Looks at `bounds` from above and to the side, close enough for them to fill the view.
+    pub fn fit_to_bounds(bounds: &Aabb, fovy: Deg<f32>, aspect: f32) -> Camera {
+        let center = bounds.center();
+        let position = center
+            + cgmath::Vector3::new(1.0, 0.5, 1.0).normalize() * fit_distance(bounds, fovy, aspect);
+        Camera {
+            position,
+            view: cgmath::Matrix4::look_at_rh(position, center, cgmath::Vector3::unit_y()),
+            proj: FLIP_Y * cgmath::perspective(fovy, aspect, Z_NEAR, Z_FAR),
+        }
+    }
+
+    /// Looks from `position` through cubemap face `face`, in Vulkan's +X, -X, +Y, -Y, +Z, -Z
+    /// layer order, with a square 90 degree view.
+    ///
+    /// Cubemap faces are laid out left-handed, so renders come out mirrored horizontally and
+    /// have to be flipped back when copied into the face.
+    pub fn cube_face(position: cgmath::Point3<f32>, face: usize) -> Camera {
+        let (forward, up) = [
+            ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ][face];
+        Camera {
+            position,
+            view: cgmath::Matrix4::look_to_rh(position, forward.into(), up.into()),
+            proj: FLIP_Y * cgmath::perspective(Deg(90.0), 1.0, Z_NEAR, Z_FAR),
+        }
+    }
 }
 
 impl SamplePipeline {
+    /// Without `lights`, objects are shaded unlit by their facing ratio. The first light casts
+    /// the shadows. Fails if the push constants exceed the device's limit.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         app: &App,
         queue: Arc<Queue>,
         rendering_info: PipelineRenderingCreateInfo,
-    ) -> SamplePipeline {
-        assert!(size_of::<vs::PushConstants>() == size_of::<fs::PushConstants>());
-
-        let pipeline = {
-            let device = queue.device();
-            let vs = vs::load(device.clone())
-                .expect("failed to create shader module")
-                .entry_point("main")
-                .expect("shader entry point not found");
-            let fs = fs::load(device.clone())
-                .expect("failed to create shader module")
-                .entry_point("main")
-                .expect("shader entry point not found");
-            let vertex_input_state = MyVertex::per_vertex()
-                .definition(&vs.info().input_interface)
-                .unwrap();
-            let stages = [
-                PipelineShaderStageCreateInfo::new(vs),
-                PipelineShaderStageCreateInfo::new(fs),
-            ];
-            let layout = PipelineLayout::new(
-                device.clone(),
-                PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                    .into_pipeline_layout_create_info(device.clone())
-                    .unwrap(),
+        samples: SampleCount,
+        shadow_map: &ShadowMap,
+        front_face: FrontFace,
+        face_culling: FaceCulling,
+        polygon_mode: PolygonMode,
+        lightmap: Option<Arc<ImageView>>,
+        matcap: Option<Arc<ImageView>>,
+        lights: Option<&[LightSettings]>,
+        depth: &DepthSettings,
+        background: Option<Arc<ImageView>>,
+    ) -> Result<SamplePipeline, PipelineError> {
+        let dynamic_depth = DepthSettings::is_dynamic(queue.device());
+
+        // Mirrored models have their winding flipped by the baked transform.
+        let ([pipeline, mirrored_pipeline, double_sided, mirrored_double_sided], instanced) =
+            Self::pipelines(
+                queue.device(),
+                Self::layout(queue.device())?,
+                &rendering_info,
+                samples,
+                front_face,
+                face_culling,
+                polygon_mode,
+                depth,
+                dynamic_depth,
+            );
+
+        let texture_samplers = [
+            SamplerCreateInfo::simple_repeat_linear_no_mipmap(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        ]
+        .map(|create_info| Sampler::new(app.context.device().clone(), create_info).unwrap());
+
+        // Dynamic offsets have to be aligned.
+        let material_stride = (size_of::<fs::Material>() as u64).next_multiple_of(
+            app.context
+                .device()
+                .physical_device()
+                .properties()
+                .min_uniform_buffer_offset_alignment
+                .as_devicesize(),
+        );
+        let (model_uniform, scene_material, frame, light_buffer) = {
+            // set = 0, binding = 0
+            let model_uniform = create_uniform_buffer_from_data(
+                app.memory_allocator(),
+                vs::ModelBuffer {
+                    model: cgmath::Matrix4::identity().into(),
+                },
+            );
+
+            let scene_material = fs::Material {
+                diffuse: Padded([1.0, 1.0, 1.0]),
+                specular: [0.5, 0.5, 0.5],
+                shininess: 32.0,
+                base_color_tex_coord: 0,
+                use_lightmap: lightmap.is_some() as u32,
+                lightmap_tex_coord: 1,
+                use_matcap: matcap.is_some() as u32,
+            };
+
+            // set = 1, binding = 1
+            let light_buffer = Buffer::from_data(
+                app.memory_allocator(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                light_data(lights.unwrap_or_default(), lights.is_none()),
             )
             .unwrap();
 
+            let frame = FrameResources::new(
+                app.memory_allocator(),
+                scene_material,
+                material_stride,
+                |frame_writes| {
+                    let writes = [
+                        WriteDescriptorSet::buffer(1, light_buffer.clone()),
+                        // set = 1, binding = 3
+                        WriteDescriptorSet::image_view_sampler(
+                            3,
+                            shadow_map.array_view(),
+                            shadow_map.sampler(),
+                        ),
+                        // set = 1, binding = 5
+                        WriteDescriptorSet::image_view_sampler(
+                            5,
+                            lightmap.unwrap_or_else(|| texture::solid_color(app, [255; 4])),
+                            texture_samplers[SceneTexture::Lightmap as usize].clone(),
+                        ),
+                        // set = 1, binding = 6
+                        WriteDescriptorSet::image_view_sampler(
+                            6,
+                            matcap.unwrap_or_else(|| texture::solid_color(app, [255; 4])),
+                            texture_samplers[SceneTexture::Matcap as usize].clone(),
+                        ),
+                        // set = 1, binding = 9
+                        WriteDescriptorSet::image_view_sampler(
+                            9,
+                            background.unwrap_or_else(|| texture::solid_color(app, [0, 0, 0, 255])),
+                            texture_samplers[SceneTexture::Matcap as usize].clone(),
+                        ),
+                    ];
+                    DescriptorSet::new(
+                        app.descriptor_set_allocator.clone(),
+                        pipeline.layout().set_layouts()[1].clone(),
+                        writes.into_iter().chain(frame_writes),
+                        [],
+                    )
+                    .unwrap()
+                },
+            );
+
+            (model_uniform, scene_material, frame, light_buffer)
+        };
+        // The other slots share the first one's remaining bindings.
+        let mut frames = vec![frame];
+        for _ in 1..app.frames_in_flight {
+            let first = &frames[0].fragment_descriptor_set;
+            let frame = FrameResources::new(
+                app.memory_allocator(),
+                scene_material,
+                material_stride,
+                |writes| replace_bindings(app.descriptor_set_allocator.clone(), first, writes),
+            );
+            frames.push(frame);
+        }
+
+        let untextured_descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[2].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                texture::solid_color(app, [255; 4]),
+                texture_samplers[0].clone(),
+            )],
+            [],
+        )
+        .unwrap();
+
+        Ok(Self {
+            pipeline,
+            mirrored_pipeline,
+            double_sided_pipelines: [double_sided, mirrored_double_sided],
+            instanced_pipelines: instanced,
+            dynamic_depth,
+            rendering_info,
+            samples,
+            model_uniform,
+            frames,
+            frame: 0,
+            light_buffer,
+            unlit: lights.is_none(),
+            texture_samplers,
+            untextured_descriptor_set,
+            material_stride,
+            instance_allocator: SubbufferAllocator::new(
+                app.memory_allocator(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+        })
+    }
+
+    /// Recreates the pipelines for `samples` per pixel, e.g. after the anti-aliasing mode
+    /// changed, with the face and depth settings of `app`.
+    pub fn set_samples(
+        &mut self,
+        app: &App,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) {
+        self.rendering_info = rendering_info;
+        self.samples = samples;
+        self.recreate_pipelines(app);
+    }
+
+    /// Recreates the pipelines with the face culling of `app`, e.g. after it was changed while
+    /// running.
+    pub fn set_face_culling(&mut self, app: &App) {
+        self.recreate_pipelines(app);
+    }
+
+    /// Recreates the pipelines with the polygon mode of `app`, e.g. after it was changed while
+    /// running.
+    pub fn set_polygon_mode(&mut self, app: &App) {
+        self.recreate_pipelines(app);
+    }
+
+    fn recreate_pipelines(&mut self, app: &App) {
+        let ([pipeline, mirrored_pipeline, double_sided, mirrored_double_sided], instanced) =
+            Self::pipelines(
+                self.pipeline.device(),
+                // Keeps the sets created for the previous pipelines valid.
+                self.pipeline.layout().clone(),
+                &self.rendering_info,
+                self.samples,
+                app.front_face,
+                app.face_culling,
+                app.polygon_mode,
+                &app.depth,
+                self.dynamic_depth,
+            );
+        self.pipeline = pipeline;
+        self.mirrored_pipeline = mirrored_pipeline;
+        self.double_sided_pipelines = [double_sided, mirrored_double_sided];
+        self.instanced_pipelines = instanced;
+    }
+
+    /// The layout all of [`Self::pipelines`] share. Both vertex shaders read the same
+    /// descriptors, and the material is picked by a dynamic offset, see
+    /// `FrameResources::materials`.
+    fn layout(device: &Arc<Device>) -> Result<Arc<PipelineLayout>, PipelineError> {
+        let stages = [vs::load(device.clone()), fs::load(device.clone())].map(|module| {
+            PipelineShaderStageCreateInfo::new(
+                module
+                    .expect("failed to create shader module")
+                    .entry_point("main")
+                    .expect("shader entry point not found"),
+            )
+        });
+        pipeline_layout_with_dynamic_uniforms(device, &stages, &[(1, 0)])
+    }
+
+    /// The culled front and mirrored pipelines, then the double-sided ones, and the same four
+    /// for drawing instances.
+    #[allow(clippy::too_many_arguments)]
+    fn pipelines(
+        device: &Arc<Device>,
+        layout: Arc<PipelineLayout>,
+        rendering_info: &PipelineRenderingCreateInfo,
+        samples: SampleCount,
+        front_face: FrontFace,
+        face_culling: FaceCulling,
+        polygon_mode: PolygonMode,
+        depth: &DepthSettings,
+        dynamic_depth: bool,
+    ) -> ([Arc<GraphicsPipeline>; 4], [Arc<GraphicsPipeline>; 4]) {
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let instanced_vs = instanced_vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = MyVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let instanced_vertex_input_state = [MyVertex::per_vertex(), InstanceData::per_instance()]
+            .definition(&instanced_vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs.clone()),
+        ];
+        let instanced_stages = [
+            PipelineShaderStageCreateInfo::new(instanced_vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let mut dynamic_state = vec![DynamicState::Viewport, DynamicState::DepthBias];
+        if dynamic_depth {
+            dynamic_state.extend(DepthSettings::DYNAMIC_STATES);
+        }
+        let pipeline = |stages: &[PipelineShaderStageCreateInfo],
+                        vertex_input_state: &VertexInputState,
+                        (front_face, cull_mode)| {
             GraphicsPipeline::new(
                 device.clone(),
                 None,
                 GraphicsPipelineCreateInfo {
-                    stages: stages.into_iter().collect(),
-                    vertex_input_state: Some(vertex_input_state),
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
                     input_assembly_state: Some(InputAssemblyState {
                         topology: PrimitiveTopology::TriangleList,
                         ..Default::default()
                     }),
                     viewport_state: Some(ViewportState::default()),
                     rasterization_state: Some(RasterizationState {
-                        polygon_mode: PolygonMode::Line,
+                        polygon_mode,
+                        // Wider lines need `wide_lines`, which isn't enabled.
                         line_width: 1.0,
-                        cull_mode: CullMode::Back,
+                        cull_mode,
+                        front_face,
+                        // Set per object, see `DepthBias`.
+                        depth_bias: Some(DepthBiasState::default()),
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: samples,
                         ..Default::default()
                     }),
-                    multisample_state: Some(MultisampleState::default()),
                     color_blend_state: Some(ColorBlendState::with_attachment_states(
                         rendering_info.color_attachment_formats.len() as u32,
                         ColorBlendAttachmentState::default(),
                     )),
-                    depth_stencil_state: Some(DepthStencilState {
-                        depth: Some(DepthState {
-                            compare_op: CompareOp::Less,
-                            write_enable: true,
-                        }),
-                        ..Default::default()
-                    }),
-                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
-                    subpass: Some(rendering_info.into()),
-                    ..GraphicsPipelineCreateInfo::layout(layout)
+                    depth_stencil_state: Some(depth.depth_stencil_state(dynamic_depth)),
+                    dynamic_state: dynamic_state.iter().copied().collect(),
+                    subpass: Some(pipeline_subpass(device, rendering_info.clone(), samples)),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
                 },
             )
             .unwrap()
         };
+        let variants = [
+            (front_face, face_culling.cull_mode(false)),
+            (flipped(front_face), face_culling.cull_mode(false)),
+            (front_face, face_culling.cull_mode(true)),
+            (flipped(front_face), face_culling.cull_mode(true)),
+        ];
+        (
+            variants.map(|variant| pipeline(&stages, &vertex_input_state, variant)),
+            variants
+                .map(|variant| pipeline(&instanced_stages, &instanced_vertex_input_state, variant)),
+        )
+    }
 
-        let descriptor_sets = {
-            // set = 0, binding = 0
-            let model_uniform = create_uniform_buffer_from_data(
-                app.memory_allocator(),
-                vs::ModelBuffer {
-                    model: cgmath::Matrix4::identity().into(),
-                },
-            );
+    /// Swaps `view` in for a texture given to [`Self::new`], e.g. once it finished loading in
+    /// the background. Frames recorded before keep drawing with the previous one.
+    pub fn set_texture(&mut self, app: &App, texture: SceneTexture, view: Arc<ImageView>) {
+        let sampler = self.texture_samplers[texture as usize].clone();
+        self.replace_image(app, texture.binding(), view, sampler);
+    }
 
-            // set = 1, binding = 0
-            let material_uniform = create_uniform_buffer_from_data(
-                app.memory_allocator(),
-                fs::Material {
-                    ambient: Padded([0.1, 0.1, 0.1]),
-                    diffuse: Padded([0.7, 0.7, 0.7]),
-                    specular: [0.5, 0.5, 0.5],
-                    shininess: 32.0,
-                },
+    /// Swaps in the opaque scene copy that transmissive surfaces refract, after it was recreated
+    /// for a new extent.
+    pub fn set_background(&mut self, app: &App, view: Arc<ImageView>) {
+        let sampler = self.texture_samplers[SceneTexture::Matcap as usize].clone();
+        self.replace_image(app, 9, view, sampler);
+    }
+
+    fn replace_image(
+        &mut self,
+        app: &App,
+        binding: u32,
+        view: Arc<ImageView>,
+        sampler: Arc<Sampler>,
+    ) {
+        // Frames in flight may still use the sets, so the rest of them is copied into new ones.
+        for frame in &mut self.frames {
+            frame.fragment_descriptor_set = replace_bindings(
+                app.descriptor_set_allocator.clone(),
+                &frame.fragment_descriptor_set,
+                [WriteDescriptorSet::image_view_sampler(
+                    binding,
+                    view.clone(),
+                    sampler.clone(),
+                )],
             );
+        }
+    }
 
-            // set = 1, binding = 1
-            let light_uniform = create_uniform_buffer_from_data(
-                app.memory_allocator(),
-                fs::Light {
-                    position: Padded([3.0, 3.0, 3.0]),
-                    ambient: Padded([1.0, 1.0, 1.0]),
-                    diffuse: Padded([1.0, 1.0, 1.0]),
-                    specular: [2.0, 2.0, 2.0],
-                },
+    /// Makes the per-frame uniforms of slot `index`, below [`App::set_frames_in_flight`], the
+    /// ones the setters write and [`Self::begin_frame`] binds, so that a frame can be recorded
+    /// while the frames of other slots are still executing.
+    pub fn select_frame(&mut self, index: usize) {
+        self.frame = index;
+        self.frames[index].used_materials.set(1);
+    }
+
+    fn current_frame(&self) -> &FrameResources {
+        &self.frames[self.frame]
+    }
+
+    /// Creates the per-object texture set (set 2), sampling `base_color` with repeating UVs.
+    /// Without one, objects share a white texture and look as if they had none.
+    pub fn texture_descriptor_set(
+        &self,
+        app: &App,
+        base_color: Option<Arc<ImageView>>,
+    ) -> Arc<DescriptorSet> {
+        let Some(base_color) = base_color else {
+            return self.untextured_descriptor_set.clone();
+        };
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.untextured_descriptor_set.layout().clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                base_color,
+                self.texture_samplers[0].clone(),
+            )],
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Creates the per-object set (set 0) skinning with `joint_palette` and morphing with
+    /// `morph_targets`. `previous_joint_palette` holds last frame's joints, for motion vectors.
+    pub fn object_descriptor_set(
+        &self,
+        app: &App,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        previous_joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.model_uniform.clone()),
+                WriteDescriptorSet::buffer(1, joint_palette),
+                WriteDescriptorSet::buffer(2, previous_joint_palette),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(3)),
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Uploads the cascades used for shadow lookups. Must not be called while a frame recorded
+    /// with the same [`Self::select_frame`] slot is still executing, likewise for the other
+    /// per-frame setters.
+    pub fn set_cascades(&self, cascades: &[Cascade; CASCADE_COUNT], blend_band: f32) {
+        update_uniform_buffer(
+            &self.current_frame().shadow_uniform,
+            fs::ShadowData {
+                light_view_proj: cascades.map(|c| c.view_proj.into()),
+                splits: cascades.map(|c| c.split_depth),
+                blend_band,
+            },
+        );
+    }
+
+    /// Uploads last frame's unjittered view-projection matrix, which motion vectors are measured
+    /// against, and the NDC offset the current projection is jittered by.
+    pub fn set_motion(&self, previous_view_proj: cgmath::Matrix4<f32>, jitter: [f32; 2]) {
+        update_uniform_buffer(
+            &self.current_frame().motion_uniform,
+            vs::Motion {
+                previous_view_proj: previous_view_proj.into(),
+                jitter,
+            },
+        );
+    }
+
+    /// Replaces the lights, keeping the first [`MAX_LIGHTS`] of them. Has no effect on a pipeline
+    /// created unlit. The lights are shared by all frames, so this must not be called while any
+    /// frame using this pipeline is still executing.
+    pub fn set_lights(&self, lights: &[LightSettings]) {
+        if lights.len() > MAX_LIGHTS {
+            log::warn!(
+                "{} lights given, only the first {MAX_LIGHTS} are shaded",
+                lights.len()
             );
+        }
+        *self.light_buffer.write().unwrap() = light_data(lights, self.unlit);
+    }
 
-            let set_layouts = pipeline.layout().set_layouts();
-            let vertex_desc_layout = set_layouts[0].clone();
-            let fragment_desc_layout = set_layouts[1].clone();
+    /// Uploads the ambient term, the fog, if any, and the lighting model.
+    pub fn set_scene(
+        &self,
+        ambient: &AmbientSettings,
+        fog: Option<&FogSettings>,
+        lighting_model: LightingModel,
+    ) {
+        let (fog_color, fog_falloff, [fog_start, fog_end, fog_density]) =
+            FogSettings::shader_terms(fog);
+        update_uniform_buffer(
+            &self.current_frame().scene_uniform,
+            fs::Scene {
+                ambient: ambient.color,
+                occlusion_strength: ambient.occlusion_strength,
+                fog_color,
+                fog_falloff,
+                fog_start,
+                fog_end,
+                fog_density,
+                lighting_model: lighting_model as u32,
+            },
+        );
+    }
 
-            let vertex_descriptor_set = DescriptorSet::new(
-                app.descriptor_set_allocator.clone(),
-                vertex_desc_layout,
-                [WriteDescriptorSet::buffer(0, model_uniform)],
-                [],
-            )
+    /// Binds the pipeline and uploads `camera`. Must be called before [`Self::render_object`],
+    /// and again after anything else was bound in between. Like the setters, this writes the
+    /// selected slot, see [`Self::set_cascades`].
+    pub fn begin_frame(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        camera: &Camera,
+        debug_view: DebugView,
+    ) {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
             .unwrap();
+        self.bind_fragment_set(builder, 0);
+        update_uniform_buffer(
+            &self.current_frame().frame_uniform,
+            vs::Frame {
+                view: camera.view.into(),
+                proj: camera.proj.into(),
+                camera_pos: camera.position.into(),
+                debug_view: debug_view as u32,
+            },
+        );
+    }
 
-            let fragment_descriptor_set = DescriptorSet::new(
-                app.descriptor_set_allocator.clone(),
-                fragment_desc_layout,
-                [
-                    WriteDescriptorSet::buffer(0, material_uniform),
-                    WriteDescriptorSet::buffer(1, light_uniform),
-                ],
-                [],
-            )
-            .unwrap();
+    /// Changes depth testing and writes for the following draws. Ignored if the device doesn't
+    /// support dynamic depth state, where the settings passed to [`Self::new`] stay in effect.
+    pub fn set_depth(&self, builder: &mut RecordingCommandBuffer, depth: &DepthSettings) {
+        if self.dynamic_depth {
+            depth.record(builder);
+        }
+    }
 
-            [vertex_descriptor_set, fragment_descriptor_set]
+    /// Draws one object; `mirrored` objects are drawn with the opposite front face, and
+    /// `double_sided` ones without culling. With an `indirect_command` the counts are read from
+    /// it, see [`draw_mesh_indirect`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        texture_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        indirect_command: Option<Subbuffer<[u32]>>,
+        mirrored: bool,
+        double_sided: bool,
+        depth_bias: DepthBias,
+        material_override: MaterialOverride,
+        clearcoat: Clearcoat,
+        transmission: Transmission,
+        pbr: PbrMaterial,
+    ) {
+        // All variants share a layout, so the bound sets and push constants stay valid.
+        let pipeline = match (double_sided, mirrored) {
+            (true, _) => &self.double_sided_pipelines[mirrored as usize],
+            (false, true) => &self.mirrored_pipeline,
+            (false, false) => &self.pipeline,
         };
-
-        Self {
+        self.bind_object(
+            builder,
             pipeline,
-            descriptor_sets,
+            object_descriptor_set,
+            texture_descriptor_set,
+            depth_bias,
+            push_constants(material_override, clearcoat, transmission, pbr),
+        );
+
+        match indirect_command {
+            Some(command) => draw_mesh_indirect(builder, vertex_buffer, index_buffer, command),
+            None => draw_mesh(builder, vertex_buffer, index_buffer),
         }
     }
 
-    pub fn render_object(
+    /// Draws `instances` of one object like [`Self::render_object`], all in one draw. Each is
+    /// placed by its model matrix and tinted by its color on top of the object's own. Instance
+    /// transforms shouldn't mirror, their winding isn't flipped. The instances only live as long
+    /// as the frame, like [`Self::render_object_with_material`]'s material.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_instanced(
         &self,
         builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        texture_descriptor_set: Arc<DescriptorSet>,
         vertex_buffer: Subbuffer<[MyVertex]>,
         index_buffer: Option<Subbuffer<[u32]>>,
-        camera: &Camera,
+        instances: &[InstanceData],
+        mirrored: bool,
+        double_sided: bool,
+        depth_bias: DepthBias,
+        material_override: MaterialOverride,
+        clearcoat: Clearcoat,
+        transmission: Transmission,
+        pbr: PbrMaterial,
     ) {
-        let vertex_count = vertex_buffer.len() as u32;
+        if instances.is_empty() {
+            return;
+        }
+        let instance_buffer = self
+            .instance_allocator
+            .allocate_slice(instances.len() as u64)
+            .unwrap();
+        instance_buffer.write().unwrap().copy_from_slice(instances);
+
+        let pipeline = &self.instanced_pipelines[double_sided as usize * 2 + mirrored as usize];
+        self.bind_object(
+            builder,
+            pipeline,
+            object_descriptor_set,
+            texture_descriptor_set,
+            depth_bias,
+            push_constants(material_override, clearcoat, transmission, pbr),
+        );
+        draw_mesh_instanced(builder, vertex_buffer, index_buffer, instance_buffer);
+    }
 
+    fn bind_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        pipeline: &Arc<GraphicsPipeline>,
+        object_descriptor_set: Arc<DescriptorSet>,
+        texture_descriptor_set: Arc<DescriptorSet>,
+        depth_bias: DepthBias,
+        push_constants: fs::PushConstants,
+    ) {
         builder
-            .bind_pipeline_graphics(self.pipeline.clone())
-            .unwrap()
-            .bind_vertex_buffers(0, vertex_buffer)
+            .bind_pipeline_graphics(pipeline.clone())
             .unwrap()
             .bind_descriptor_sets(
                 self.pipeline.bind_point(),
                 self.pipeline.layout().clone(),
                 0,
-                self.descriptor_sets.iter().cloned().collect::<Vec<_>>(),
-                // TODO: PR to improve DescriptorSetsCollection
+                object_descriptor_set,
             )
             .unwrap()
-            .push_constants(
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
                 self.pipeline.layout().clone(),
-                0,
-                vs::PushConstants {
-                    view: camera.view.into(),
-                    proj: camera.proj.into(),
-                    camera_pos: camera.position.into(),
-                },
+                2,
+                texture_descriptor_set,
             )
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
             .unwrap();
-        unsafe {
-            if let Some(index_buffer) = index_buffer {
-                let index_count = index_buffer.len() as u32;
-                builder
-                    .bind_index_buffer(index_buffer)
-                    .unwrap()
-                    .draw_indexed(index_count, 1, 0, 0, 0)
-                    .unwrap()
-            } else {
-                builder.draw(vertex_count, 1, 0, 0).unwrap()
+        depth_bias.record(builder);
+    }
+
+    /// Draws one object like [`Self::render_object`], but with `material` in place of the
+    /// scene's, e.g. for highlights or previewing an edit. The material is written into a slot
+    /// of the frame's uniforms, so it can change every frame without allocating anything.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_object_with_material(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        texture_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+        mirrored: bool,
+        double_sided: bool,
+        material: &Material,
+    ) {
+        let frame = self.current_frame();
+        let slot = frame.used_materials.get();
+        if slot == MATERIAL_SLOTS {
+            log::warn!("no material slots left this frame, drawing with the scene's material");
+        } else {
+            frame.used_materials.set(slot + 1);
+            *material_slot(&frame.materials, slot, self.material_stride)
+                .write()
+                .unwrap() = *material;
+            self.bind_fragment_set(builder, (slot * self.material_stride) as u32);
+        }
+        self.render_object(
+            builder,
+            object_descriptor_set,
+            texture_descriptor_set,
+            vertex_buffer,
+            index_buffer,
+            None,
+            mirrored,
+            double_sided,
+            DepthBias::default(),
+            MaterialOverride::default(),
+            Clearcoat::default(),
+            Transmission::default(),
+            PbrMaterial::default(),
+        );
+        self.bind_fragment_set(builder, 0);
+    }
+
+    /// Binds the current frame's fragment set, reading the material `material_offset` bytes
+    /// into its slots.
+    fn bind_fragment_set(&self, builder: &mut RecordingCommandBuffer, material_offset: u32) {
+        builder
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                1,
+                DescriptorSetWithOffsets::new(
+                    self.current_frame().fragment_descriptor_set.clone(),
+                    [material_offset],
+                ),
+            )
+            .unwrap();
+    }
+}
+
+/// Slot `index` of `materials`, see [`FrameResources::materials`].
+fn material_slot(materials: &Subbuffer<[u8]>, index: u64, stride: u64) -> Subbuffer<fs::Material> {
+    let start = index * stride;
+    materials
+        .clone()
+        .slice(start..start + size_of::<fs::Material>() as u64)
+        .reinterpret()
+}
+
+fn push_constants(
+    material_override: MaterialOverride,
+    clearcoat: Clearcoat,
+    transmission: Transmission,
+    pbr: PbrMaterial,
+) -> fs::PushConstants {
+    fs::PushConstants {
+        tint: material_override.tint,
+        roughness_scale: material_override.roughness_scale,
+        clearcoat: clearcoat.factor,
+        clearcoat_roughness: clearcoat.roughness,
+        transmission: transmission.factor,
+        ior: transmission.ior,
+        metallic: pbr.metallic,
+        roughness: pbr.roughness,
+    }
+}
+
+/// A copy of `set` with `writes` in place of their bindings.
+fn replace_bindings(
+    allocator: Arc<StandardDescriptorSetAllocator>,
+    set: &Arc<DescriptorSet>,
+    writes: impl IntoIterator<Item = WriteDescriptorSet>,
+) -> Arc<DescriptorSet> {
+    let writes = writes.into_iter().collect::<Vec<_>>();
+    let layout = set.layout().clone();
+    let copies = layout
+        .bindings()
+        .keys()
+        .filter(|&&b| writes.iter().all(|w| w.binding() != b))
+        .map(|&b| CopyDescriptorSet {
+            src_binding: b,
+            dst_binding: b,
+            descriptor_count: 1,
+            ..CopyDescriptorSet::new(set.clone())
+        })
+        .collect::<Vec<_>>();
+    DescriptorSet::new(allocator, layout, writes, copies).unwrap()
+}
+
+/// How far from the center of `bounds` their bounding sphere just fits a view of `fovy` and
+/// `aspect`, from any direction.
+pub(crate) fn fit_distance(bounds: &Aabb, fovy: Deg<f32>, aspect: f32) -> f32 {
+    let radius = (bounds.size().magnitude() / 2.0).max(Z_NEAR);
+    // The sphere has to fit the narrower of the two fields of view.
+    let half_fovy = Rad::from(fovy).0 / 2.0;
+    let half_fov = (half_fovy.tan() * aspect.min(1.0)).atan();
+    radius / half_fov.sin()
+}
+
+fn light_data(lights: &[LightSettings], unlit: bool) -> fs::Lights {
+    let lights = &lights[..lights.len().min(MAX_LIGHTS)];
+    fs::Lights {
+        light_count: lights.len() as u32,
+        unlit: Padded(unlit as u32),
+        lights: std::array::from_fn(|i| {
+            let light = lights.get(i).map_or(
+                fs::Light {
+                    position: [0.0; 3],
+                    kind: 0,
+                    direction: Padded([0.0; 3]),
+                    diffuse: Padded([0.0; 3]),
+                    specular: [0.0; 3],
+                },
+                |light| {
+                    let (kind, position, direction) = light.shader_terms();
+                    fs::Light {
+                        position,
+                        kind,
+                        direction: Padded(direction),
+                        diffuse: Padded(light.diffuse()),
+                        specular: light.specular(),
+                    }
+                },
+            );
+            Padded(light)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Transform};
+
+    use super::*;
+
+    #[test]
+    fn fit_to_bounds_keeps_the_bounds_in_view() {
+        let bounds = Aabb {
+            min: Point3::new(-1.0, 0.0, -3.0),
+            max: Point3::new(5.0, 2.0, 1.0),
+        };
+        for aspect in [0.5, 1.0, 2.0] {
+            let camera = Camera::fit_to_bounds(&bounds, Deg(60.0), aspect);
+            let view_proj = camera.proj * camera.view;
+            let center = view_proj.transform_point(bounds.center());
+            assert!(center.x.abs() < 1e-4 && center.y.abs() < 1e-4);
+            for x in [bounds.min.x, bounds.max.x] {
+                for y in [bounds.min.y, bounds.max.y] {
+                    for z in [bounds.min.z, bounds.max.z] {
+                        let corner = view_proj.transform_point(Point3::new(x, y, z));
+                        assert!(corner.x.abs() <= 1.0 && corner.y.abs() <= 1.0);
+                    }
+                }
             }
+        }
+    }
+
+    #[test]
+    fn perspective_follows_the_extent() {
+        for extent in [[1280, 720], [600, 800]] {
+            let proj = Camera::perspective(Deg(60.0), extent, Z_NEAR, Z_FAR);
+            let aspect = extent[0] as f32 / extent[1] as f32;
+            // Y is flipped for Vulkan.
+            assert!((-proj.y.y / proj.x.x - aspect).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn cube_faces_match_the_vulkan_layout() {
+        // Direction of face coordinates `s` (right) and `t` (down), from the Vulkan spec's
+        // cube map face selection table.
+        let direction = |face, s: f32, t: f32| match face {
+            0 => [1.0, -t, -s],
+            1 => [-1.0, -t, s],
+            2 => [s, 1.0, t],
+            3 => [s, -1.0, -t],
+            4 => [s, -t, 1.0],
+            _ => [-s, -t, -1.0],
+        };
+        let position = Point3::new(1.0, 2.0, 3.0);
+        for face in 0..6 {
+            let camera = Camera::cube_face(position, face);
+            let view_proj = camera.proj * camera.view;
+            for (s, t) in [(0.5, 0.0), (0.0, 0.5), (-0.3, 0.7)] {
+                let point = view_proj
+                    .transform_point(position + cgmath::Vector3::from(direction(face, s, t)));
+                // Mirrored horizontally; NDC y points down like `t`.
+                assert!((point.x + s).abs() < 1e-4 && (point.y - t).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn directional_lights_normalize_their_direction() {
+        let sun = LightSettings {
+            kind: LightKind::Directional {
+                direction: [0.0, -2.0, 0.0],
+            },
+            ..Default::default()
         };
+        assert_eq!(sun.shader_terms(), (1, [0.0; 3], [0.0, -1.0, 0.0]));
+        let (kind, position, _) = LightSettings::default().shader_terms();
+        assert_eq!((kind, position), (0, DEFAULT_LIGHT_POSITION));
     }
 }
@@ -1,48 +1,327 @@
 #version 460
 
+// Must match `CASCADE_COUNT` in `pipeline/shadow/mod.rs`.
+const int CASCADE_COUNT = 4;
+// Must match `Z_FAR` in `lib.rs`.
+const float Z_FAR = 100.0;
+// Must match `MAX_LIGHTS` in `pipeline/sample/mod.rs`.
+const uint MAX_LIGHTS = 64;
+
+// Must match `DebugView` in `pipeline/sample/mod.rs`.
+const uint DEBUG_NORMALS = 1;
+const uint DEBUG_DEPTH = 2;
+const uint DEBUG_TEX_COORDS = 3;
+const uint DEBUG_BASE_COLOR = 4;
+const uint DEBUG_MATERIAL = 5;
+
+// Must match `LightingModel` in `pipeline/sample/mod.rs`.
+const uint LIGHTING_PBR = 1;
+
 layout(location = 0) in vec3 fragPos;
 layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 currentClip;
+layout(location = 3) in vec4 previousClip;
+layout(location = 4) in vec2 fragTexCoord;
+layout(location = 5) in vec2 fragTexCoord1;
+layout(location = 6) in vec3 fragColor;
 layout(location = 0) out vec4 outColor;
+layout(location = 1) out vec2 outMotion;
 
 layout(set = 1, binding = 0) uniform Material {
-  vec3 ambient;
   vec3 diffuse;
   vec3 specular;
   float shininess;
+  uint base_color_tex_coord; // UV set, 0 or 1
+  uint use_lightmap;
+  uint lightmap_tex_coord;
+  uint use_matcap;
 }
 material;
 
-layout(set = 1, binding = 1) uniform Light {
+vec2 texCoord(uint set) { return set == 0 ? fragTexCoord : fragTexCoord1; }
+
+struct Light {
   vec3 position;
-  vec3 ambient;
+  uint kind; // 0 point, 1 directional
+  vec3 direction; // normalized, that directional lights travel in
   vec3 diffuse;
   vec3 specular;
+};
+
+// The first light casts the shadows.
+layout(set = 1, binding = 1) readonly buffer Lights {
+  uint light_count;
+  uint unlit; // shade by facing ratio only, ignoring the lights
+  Light lights[MAX_LIGHTS];
+};
+
+layout(set = 1, binding = 2) uniform ShadowData {
+  mat4 light_view_proj[CASCADE_COUNT];
+  vec4 splits; // one per cascade
+  float blend_band;
 }
-light;
+shadow;
 
-layout(push_constant) uniform PushConstants {
+layout(set = 1, binding = 3) uniform sampler2DArrayShadow shadowMap;
+// Baked indirect lighting, occlusion included, used instead of the constant ambient term if
+// enabled.
+layout(set = 1, binding = 5) uniform sampler2D lightmap;
+// Material capture, replacing all lighting if enabled.
+layout(set = 1, binding = 6) uniform sampler2D matcap;
+
+// The opaque objects, copied before transmissive ones are drawn over them.
+layout(set = 1, binding = 9) uniform sampler2D background;
+
+layout(set = 1, binding = 7) uniform Scene {
+  vec3 ambient;
+  // How much of the lightmap replaces the constant ambient color.
+  float occlusion_strength;
+  vec3 fog_color;
+  uint fog_falloff; // 0 for no fog
+  float fog_start;
+  float fog_end;
+  float fog_density;
+  uint lighting_model;
+}
+scene;
+
+// Kept out of the push constants, which only have 128 bytes guaranteed.
+layout(set = 1, binding = 8) uniform Frame {
   mat4 view;
   mat4 proj;
   vec3 camera_pos;
+  uint debug_view;
+}
+frame;
+
+layout(push_constant) uniform PushConstants {
+  // Per object, multiplying the material.
+  vec3 tint;
+  float roughness_scale;
+  // KHR_materials_clearcoat, zero without a coat.
+  float clearcoat;
+  float clearcoat_roughness;
+  // KHR_materials_transmission, zero for opaque objects.
+  float transmission;
+  float ior;
+  // The glTF metallic-roughness factors, for `LIGHTING_PBR`.
+  float metallic;
+  float roughness;
 }
 pc;
 
+// Per object, white for objects without a base color texture.
+layout(set = 2, binding = 0) uniform sampler2D baseColorTexture;
+
+vec3 baseColor() {
+  vec3 texel = texture(baseColorTexture, texCoord(material.base_color_tex_coord)).rgb;
+  return material.diffuse * fragColor * pc.tint * texel;
+}
+
+// Rougher means a broader highlight, i.e. a lower Phong exponent.
+float shininess() { return material.shininess / pc.roughness_scale; }
+
+const float PI = 3.14159265359;
+
+float perceptualRoughness() { return clamp(pc.roughness * pc.roughness_scale, 0.03, 1.0); }
+
+// Cook-Torrance with the GGX distribution, Smith-Schlick geometry and Schlick's Fresnel, and a
+// Lambertian diffuse term for the light the surface doesn't reflect. Both come out times n.l.
+void cookTorrance(vec3 n, vec3 v, vec3 l, vec3 albedo, out vec3 diffuse, out vec3 specular) {
+  vec3 h = normalize(v + l);
+  float nDotL = max(dot(n, l), 0.0);
+  float nDotV = max(dot(n, v), 1e-4);
+  float nDotH = max(dot(n, h), 0.0);
+  float vDotH = max(dot(v, h), 0.0);
+
+  float roughness = perceptualRoughness();
+  float a2 = pow(roughness, 4.0);
+  float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+  float distribution = a2 / (PI * d * d);
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float geometry = nDotV / (nDotV * (1.0 - k) + k) * nDotL / (nDotL * (1.0 - k) + k);
+  // Dielectrics reflect 4% head-on, metals their base color.
+  vec3 f0 = mix(vec3(0.04), albedo, pc.metallic);
+  vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+
+  specular = distribution * geometry * fresnel / (4.0 * nDotV);
+  diffuse = (1.0 - fresnel) * (1.0 - pc.metallic) * albedo / PI * nDotL;
+}
+
+// Schlick's approximation for the coat, a dielectric with an IOR of 1.5.
+float coatFresnel(float cosTheta) { return 0.04 + 0.96 * pow(1.0 - cosTheta, 5.0); }
+
+// GGX highlight of the clearcoat layer, which shares the base normal.
+float clearcoatSpecular(vec3 n, vec3 v, vec3 l) {
+  vec3 h = normalize(v + l);
+  float nDotH = max(dot(n, h), 0.0);
+  float vDotH = max(dot(v, h), 0.0);
+  float a2 = pow(max(pc.clearcoat_roughness, 0.03), 4.0);
+  float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+  float distribution = a2 / (PI * d * d);
+  // Kelemen's visibility term, cheap and close enough for a thin coat.
+  float visibility = 0.25 / max(vDotH * vDotH, 1e-4);
+  return distribution * visibility * coatFresnel(vDotH) * max(dot(n, l), 0.0);
+}
+
+// How far refracted rays travel before hitting what's behind, in world units. glTF leaves the
+// thickness to KHR_materials_volume, which isn't read.
+const float TRANSMISSION_THICKNESS = 0.1;
+
+// The background seen through the surface, bent by its IOR and tinted by the base color.
+vec3 transmitted(vec3 n, vec3 v) {
+  vec3 ray = refract(-v, n, 1.0 / pc.ior);
+  vec4 clip = frame.proj * frame.view * vec4(fragPos + ray * TRANSMISSION_THICKNESS, 1.0);
+  return texture(background, clip.xy / clip.w * 0.5 + 0.5).rgb * baseColor();
+}
+
+// 3x3 PCF, each tap already filtered 2x2 by the comparison sampler.
+float sampleCascade(int cascade) {
+  vec4 lightClip = shadow.light_view_proj[cascade] * vec4(fragPos, 1.0);
+  vec3 coord = lightClip.xyz / lightClip.w;
+  if (coord.z > 1.0) {
+    return 1.0;
+  }
+  vec2 uv = coord.xy * 0.5 + 0.5;
+  vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      lit += texture(shadowMap,
+                     vec4(uv + vec2(x, y) * texel, float(cascade), coord.z));
+    }
+  }
+  return lit / 9.0;
+}
+
+// Must match `FogSettings::shader_terms` in `pipeline/sample/mod.rs`.
+const uint FOG_LINEAR = 1;
+const uint FOG_EXPONENTIAL = 2;
+
+vec3 applyFog(vec3 color) {
+  float depth = -(frame.view * vec4(fragPos, 1.0)).z;
+  float fog = 0.0;
+  switch (scene.fog_falloff) {
+  case FOG_LINEAR:
+    fog = clamp((depth - scene.fog_start) / (scene.fog_end - scene.fog_start), 0.0, 1.0);
+    break;
+  case FOG_EXPONENTIAL:
+    fog = 1.0 - exp(-scene.fog_density * max(depth, 0.0));
+    break;
+  }
+  return mix(color, scene.fog_color, fog);
+}
+
+float shadowFactor() {
+  float depth = -(frame.view * vec4(fragPos, 1.0)).z;
+  for (int i = 0; i < CASCADE_COUNT; i++) {
+    if (depth < shadow.splits[i]) {
+      float lit = sampleCascade(i);
+      if (i + 1 < CASCADE_COUNT) {
+        // Fade into the next cascade near the far end to hide the seam.
+        float start = i == 0 ? 0.0 : shadow.splits[i - 1];
+        float band = (shadow.splits[i] - start) * shadow.blend_band;
+        float t = (shadow.splits[i] - depth) / band;
+        if (t < 1.0) {
+          lit = mix(sampleCascade(i + 1), lit, t);
+        }
+      }
+      return lit;
+    }
+  }
+  return 1.0;
+}
+
+vec3 debugColor() {
+  switch (frame.debug_view) {
+  case DEBUG_NORMALS:
+    return normalize(fragNormal) * 0.5 + 0.5;
+  case DEBUG_DEPTH:
+    return vec3(-(frame.view * vec4(fragPos, 1.0)).z / Z_FAR);
+  case DEBUG_TEX_COORDS:
+    return vec3(fract(texCoord(material.base_color_tex_coord)), 0.0);
+  case DEBUG_BASE_COLOR:
+    return baseColor();
+  case DEBUG_MATERIAL:
+    if (scene.lighting_model == LIGHTING_PBR) {
+      return vec3(pc.metallic, perceptualRoughness(), 0.0);
+    }
+    // The Phong stand-ins for metallic and roughness.
+    return vec3(dot(material.specular, vec3(1.0 / 3.0)), shininess() / 128.0, 0.0);
+  }
+  return vec3(0.0);
+}
+
 void main() {
+  // From last frame's position to this one, in UV units.
+  outMotion = (currentClip.xy / currentClip.w - previousClip.xy / previousClip.w) * 0.5;
+
+  if (frame.debug_view != 0) {
+    outColor = vec4(debugColor(), 1.0);
+    return;
+  }
+
+  // Back faces are only drawn for double-sided materials, lit from the side they are seen from.
+  vec3 norm = normalize(gl_FrontFacing ? fragNormal : -fragNormal);
+  vec3 viewDir = normalize(frame.camera_pos - fragPos);
+  if (material.use_matcap != 0) {
+    // Looked up by the view-space normal; texture V points down, view-space Y up.
+    vec2 normal = (mat3(frame.view) * norm).xy;
+    outColor = vec4(texture(matcap, vec2(0.5, -0.5) * normal + 0.5).rgb, 1.0);
+    return;
+  }
+  if (unlit != 0) {
+    outColor = vec4(baseColor() * (0.5 + 0.5 * max(dot(norm, viewDir), 0.0)), 1.0);
+    return;
+  }
+
   // Ambient
-  vec3 ambient = light.ambient * material.ambient;
-
-  // Diffuse
-  vec3 norm = normalize(fragNormal);
-  vec3 lightDir = normalize(light.position - fragPos);
-  float diff = max(dot(norm, lightDir), 0.0);
-  vec3 diffuse = light.diffuse * (diff * material.diffuse);
-
-  // Specular
-  vec3 viewDir = normalize(pc.camera_pos - fragPos);
-  vec3 reflectDir = reflect(-lightDir, norm);
-  float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
-  vec3 specular = light.specular * (spec * material.specular);
-
-  vec3 result = ambient + diffuse + specular;
-  outColor = vec4(result, 1.0);
+  vec3 ambient = scene.ambient * pc.tint;
+  if (material.use_lightmap != 0) {
+    vec3 baked = texture(lightmap, texCoord(material.lightmap_tex_coord)).rgb * baseColor();
+    ambient = mix(ambient, baked, scene.occlusion_strength);
+  }
+
+  vec3 diffuse = vec3(0.0);
+  vec3 specular = vec3(0.0);
+  vec3 coat = vec3(0.0);
+  for (uint i = 0; i < min(light_count, MAX_LIGHTS); i++) {
+    Light light = lights[i];
+    float lit = i == 0 ? shadowFactor() : 1.0;
+
+    vec3 lightDir = light.kind == 1 ? -light.direction : normalize(light.position - fragPos);
+    if (scene.lighting_model == LIGHTING_PBR) {
+      vec3 d, s;
+      cookTorrance(norm, viewDir, lightDir, baseColor(), d, s);
+      // Times pi, so that a white light on a white dielectric is as bright as under Phong.
+      diffuse += lit * light.diffuse * PI * d;
+      specular += lit * light.diffuse * PI * s;
+    } else {
+      // Diffuse
+      float diff = max(dot(norm, lightDir), 0.0);
+      diffuse += lit * light.diffuse * (diff * baseColor());
+
+      // Specular
+      vec3 reflectDir = reflect(-lightDir, norm);
+      float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess());
+      specular += lit * light.specular * (spec * material.specular);
+    }
+
+    if (pc.clearcoat > 0.0) {
+      coat += lit * light.specular * clearcoatSpecular(norm, viewDir, lightDir);
+    }
+  }
+
+  vec3 result = ambient + diffuse;
+  if (pc.transmission > 0.0) {
+    result = mix(result, transmitted(norm, viewDir), pc.transmission);
+  }
+  result += specular;
+  if (pc.clearcoat > 0.0) {
+    // Light the coat reflects never reaches the base.
+    result = result * (1.0 - pc.clearcoat * coatFresnel(max(dot(norm, viewDir), 0.0))) +
+             pc.clearcoat * coat;
+  }
+  outColor = vec4(applyFog(result), 1.0);
 }
@@ -1,22 +1,85 @@
 #version 460
 
-layout(push_constant) uniform PushConstants {
+// Matched by `depth_prepass.vert`.
+invariant gl_Position;
+
+layout(set = 0, binding = 0) uniform ModelBuffer { mat4 model; };
+layout(set = 0, binding = 1) readonly buffer JointPalette { mat4 joint_matrices[]; };
+layout(set = 0, binding = 2) readonly buffer PreviousJointPalette { mat4 previous_joint_matrices[]; };
+layout(set = 0, binding = 3) readonly buffer MorphTargets { vec4 morph_deltas[]; };
+layout(set = 0, binding = 4) readonly buffer MorphWeights { float morph_weights[]; };
+
+layout(set = 1, binding = 4) uniform Motion {
+  mat4 previous_view_proj;
+  vec2 jitter; // of the current projection, in NDC
+}
+motion;
+
+layout(set = 1, binding = 8) uniform Frame {
   mat4 view;
   mat4 proj;
   vec3 camera_pos;
+  uint debug_view;
 }
-pc;
-
-layout(set = 0, binding = 0) uniform ModelBuffer { mat4 model; };
+frame;
 
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
-layout(location = 2) in vec2 tex_coord; // not used
+layout(location = 2) in vec2 tex_coord;
+layout(location = 3) in uvec4 joints;
+layout(location = 4) in vec4 weights;
+layout(location = 5) in vec2 tex_coord_1;
+layout(location = 6) in vec4 color;
+#ifdef INSTANCED
+// See `InstanceData`, from a second vertex buffer.
+layout(location = 7) in mat4 instance_model;
+layout(location = 11) in vec4 instance_color;
+#endif
 layout(location = 0) out vec3 fragPos;
 layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 currentClip;
+layout(location = 3) out vec4 previousClip;
+layout(location = 4) out vec2 fragTexCoord;
+layout(location = 5) out vec2 fragTexCoord1;
+layout(location = 6) out vec3 fragColor;
+
+// Adds the weighted morph target deltas of `vertex`, stored target by target for each vertex.
+vec3 morphed(vec3 position, uint vertex) {
+  uint target_count = uint(morph_weights.length());
+  for (uint i = 0; i < target_count; i++) {
+    if (morph_weights[i] != 0.0) {
+      position += morph_weights[i] * morph_deltas[vertex * target_count + i].xyz;
+    }
+  }
+  return position;
+}
 
 void main() {
-  fragPos = vec3(model * vec4(position, 1.0));
-  fragNormal = mat3(transpose(inverse(model))) * normal;
-  gl_Position = pc.proj * pc.view * vec4(fragPos, 1.0);
+#ifdef INSTANCED
+  mat4 placed_model = instance_model * model;
+  vec3 instance_tint = instance_color.rgb;
+#else
+  mat4 placed_model = model;
+  vec3 instance_tint = vec3(1.0);
+#endif
+  mat4 skin = weights.x * joint_matrices[joints.x] + weights.y * joint_matrices[joints.y] +
+              weights.z * joint_matrices[joints.z] + weights.w * joint_matrices[joints.w];
+  mat4 skinned_model = placed_model * skin;
+  vec3 morphed_position = morphed(position, uint(gl_VertexIndex));
+  fragPos = vec3(skinned_model * vec4(morphed_position, 1.0));
+  fragNormal = mat3(transpose(inverse(skinned_model))) * normal;
+  fragTexCoord = tex_coord;
+  fragTexCoord1 = tex_coord_1;
+  fragColor = color.rgb * instance_tint;
+  gl_Position = frame.proj * frame.view * vec4(fragPos, 1.0);
+
+  mat4 previous_skin = weights.x * previous_joint_matrices[joints.x] +
+                       weights.y * previous_joint_matrices[joints.y] +
+                       weights.z * previous_joint_matrices[joints.z] +
+                       weights.w * previous_joint_matrices[joints.w];
+  // Without jitter, so that it doesn't show up as motion. Last frame's weights aren't kept, so
+  // morphing doesn't either.
+  currentClip = gl_Position - vec4(motion.jitter * gl_Position.w, 0.0, 0.0);
+  previousClip =
+      motion.previous_view_proj * placed_model * previous_skin * vec4(morphed_position, 1.0);
 }
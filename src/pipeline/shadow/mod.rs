@@ -0,0 +1,412 @@
+use std::{array, sync::Arc};
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{DeviceOwned, Queue},
+    format::{ClearValue, Format},
+    image::{
+        sampler::{BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
+        SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{CullMode, DepthBiasState, RasterizationState},
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::{
+    create_uniform_buffer_from_data, draw_mesh, pipeline_layout, sample::Camera, JointMatrix,
+    MorphTargets,
+};
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass},
+    App, MyVertex,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/shadow/shadow.vert");
+}
+
+/// Must match `CASCADE_COUNT` in `sample.frag`.
+pub const CASCADE_COUNT: usize = 4;
+
+pub const SHADOW_MAP_FORMAT: Format = Format::D32_SFLOAT;
+
+/// Maps cgmath's OpenGL-style `[-1, 1]` clip depth to Vulkan's `[0, 1]`.
+#[rustfmt::skip]
+const OPENGL_TO_VULKAN_DEPTH: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    /// Direction the light travels in, i.e. from the light towards the scene.
+    pub light_direction: Vector3<f32>,
+    /// View-space distance covered by the last cascade.
+    pub distance: f32,
+    /// Blend between uniform (`0.0`) and logarithmic (`1.0`) split distribution.
+    pub split_lambda: f32,
+    /// Fraction of each cascade's depth range blended into the next one.
+    pub blend_band: f32,
+    pub resolution: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            light_direction: Vector3::new(-1.0, -1.0, -1.0).normalize(),
+            distance: 25.0,
+            split_lambda: 0.75,
+            blend_band: 0.1,
+            resolution: 2048,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cascade {
+    /// View-space distance at which this cascade ends.
+    pub split_depth: f32,
+    pub view_proj: Matrix4<f32>,
+}
+
+impl ShadowSettings {
+    /// Cascade end distances between `near` and `far`, the latter clamped to `distance`.
+    pub fn split_depths(&self, near: f32, far: f32) -> [f32; CASCADE_COUNT] {
+        let far = self.distance.min(far);
+        array::from_fn(|i| {
+            let p = (i + 1) as f32 / CASCADE_COUNT as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            self.split_lambda * log + (1.0 - self.split_lambda) * uniform
+        })
+    }
+
+    /// Fits one orthographic light frustum around each slice of the camera frustum.
+    ///
+    /// `near` and `far` must be the planes `camera.proj` was built with.
+    pub fn cascades(&self, camera: &Camera, near: f32, far: f32) -> [Cascade; CASCADE_COUNT] {
+        let inv_view_proj = (camera.proj * camera.view)
+            .invert()
+            .expect("camera matrix is not invertible");
+        let unproject = |x: f32, y: f32, z: f32| {
+            let p = inv_view_proj * Vector4::new(x, y, z, 1.0);
+            p.truncate() / p.w
+        };
+        let corner_rays: [(Vector3<f32>, Vector3<f32>); 4] =
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+                .map(|(x, y)| (unproject(x, y, -1.0), unproject(x, y, 1.0)));
+
+        let up = if self.light_direction.y.abs() > 0.99 {
+            Vector3::unit_z()
+        } else {
+            Vector3::unit_y()
+        };
+
+        let mut slice_start = near;
+        self.split_depths(near, far).map(|split_depth| {
+            let t0 = (slice_start - near) / (far - near);
+            let t1 = (split_depth - near) / (far - near);
+            slice_start = split_depth;
+
+            let corners = corner_rays
+                .iter()
+                .flat_map(|&(n, f)| [n + (f - n) * t0, n + (f - n) * t1]);
+            let center = corners.clone().sum::<Vector3<f32>>() / 8.0;
+            // A bounding sphere keeps the projection size constant as the camera rotates.
+            let radius = corners
+                .map(|c| (c - center).magnitude())
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            let center = Point3::from_vec(center);
+            let view = Matrix4::look_at_rh(center - self.light_direction * radius, center, up);
+            let proj = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius);
+
+            // Snap to whole texels so shadow edges don't shimmer when the camera moves.
+            let texels = self.resolution as f32 / 2.0;
+            let origin = proj * view * Vector4::unit_w() * texels;
+            let offset = (origin.map(f32::round) - origin) / texels;
+            let snap = Matrix4::from_translation(Vector3::new(offset.x, offset.y, 0.0));
+
+            Cascade {
+                split_depth,
+                view_proj: OPENGL_TO_VULKAN_DEPTH * snap * proj * view,
+            }
+        })
+    }
+}
+
+/// A depth texture array with one layer per cascade.
+pub struct ShadowMap {
+    layer_views: [Arc<ImageView>; CASCADE_COUNT],
+    array_view: Arc<ImageView>,
+    sampler: Arc<Sampler>,
+}
+
+impl ShadowMap {
+    pub fn new(allocator: Arc<StandardMemoryAllocator>, resolution: u32) -> Self {
+        let device = allocator.device().clone();
+        let image = Image::new(
+            allocator,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                extent: [resolution, resolution, 1],
+                array_layers: CASCADE_COUNT as u32,
+                format: SHADOW_MAP_FORMAT,
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        let view = |view_type, array_layers| {
+            ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    view_type,
+                    subresource_range: ImageSubresourceRange {
+                        aspects: ImageAspects::DEPTH,
+                        mip_levels: 0..1,
+                        array_layers,
+                    },
+                    ..ImageViewCreateInfo::from_image(&image)
+                },
+            )
+            .unwrap()
+        };
+        let layer_views = array::from_fn(|i| view(ImageViewType::Dim2d, i as u32..i as u32 + 1));
+        let array_view = view(ImageViewType::Dim2dArray, 0..CASCADE_COUNT as u32);
+
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToBorder; 3],
+                border_color: BorderColor::FloatOpaqueWhite,
+                compare: Some(CompareOp::LessOrEqual),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            layer_views,
+            array_view,
+            sampler,
+        }
+    }
+
+    pub fn array_view(&self) -> Arc<ImageView> {
+        self.array_view.clone()
+    }
+
+    pub fn sampler(&self) -> Arc<Sampler> {
+        self.sampler.clone()
+    }
+}
+
+pub struct ShadowPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    model_uniform: Subbuffer<vs::ModelBuffer>,
+}
+
+impl ShadowPipeline {
+    pub fn new(app: &App, queue: Arc<Queue>) -> ShadowPipeline {
+        let pipeline = {
+            let device = queue.device();
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let vertex_input_state = MyVertex::per_vertex()
+                .definition(&vs.info().input_interface)
+                .unwrap();
+            let stages = [PipelineShaderStageCreateInfo::new(vs)];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                depth_attachment_format: Some(SHADOW_MAP_FORMAT),
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(vertex_input_state),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState {
+                        cull_mode: CullMode::None,
+                        depth_bias: Some(DepthBiasState {
+                            constant_factor: 1.25,
+                            clamp: 0.0,
+                            slope_factor: 1.75,
+                        }),
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState::default()),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth: Some(DepthState {
+                            compare_op: CompareOp::Less,
+                            write_enable: true,
+                        }),
+                        ..Default::default()
+                    }),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // set = 0, binding = 0
+        let model_uniform = create_uniform_buffer_from_data(
+            app.memory_allocator(),
+            vs::ModelBuffer {
+                model: Matrix4::identity().into(),
+            },
+        );
+
+        Self {
+            pipeline,
+            model_uniform,
+        }
+    }
+
+    /// Creates the per-object set (set 0) skinning with `joint_palette` and morphing with
+    /// `morph_targets`.
+    pub fn object_descriptor_set(
+        &self,
+        app: &App,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+    ) -> Arc<DescriptorSet> {
+        DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.model_uniform.clone()),
+                WriteDescriptorSet::buffer(1, joint_palette),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(2)),
+            [],
+        )
+        .unwrap()
+    }
+
+    /// Renders every cascade of `shadow_map`. `draw_fn` is called once per cascade and should
+    /// only call [`ShadowPipeline::draw_object`].
+    pub fn render(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        shadow_map: &ShadowMap,
+        cascades: &[Cascade; CASCADE_COUNT],
+        mut draw_fn: impl FnMut(&mut RecordingCommandBuffer),
+    ) {
+        for (layer_view, cascade) in shadow_map.layer_views.iter().zip(cascades) {
+            let extent = layer_view.image().extent();
+            let rendering = begin_rendering(
+                builder,
+                RenderingInfo {
+                    depth_attachment: Some(RenderingAttachmentInfo {
+                        load_op: AttachmentLoadOp::Clear,
+                        store_op: AttachmentStoreOp::Store,
+                        clear_value: Some(ClearValue::Depth(1.0)),
+                        ..RenderingAttachmentInfo::image_view(layer_view.clone())
+                    }),
+                    ..Default::default()
+                },
+            );
+            builder
+                .set_viewport(
+                    0,
+                    [Viewport {
+                        extent: [extent[0] as f32, extent[1] as f32],
+                        ..Default::default()
+                    }]
+                    .into_iter()
+                    .collect(),
+                )
+                .unwrap()
+                .bind_pipeline_graphics(self.pipeline.clone())
+                .unwrap()
+                .push_constants(
+                    self.pipeline.layout().clone(),
+                    0,
+                    vs::PushConstants {
+                        light_view_proj: cascade.view_proj.into(),
+                    },
+                )
+                .unwrap();
+
+            draw_fn(builder);
+
+            rendering.end(builder);
+        }
+    }
+
+    pub fn draw_object(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        object_descriptor_set: Arc<DescriptorSet>,
+        vertex_buffer: Subbuffer<[MyVertex]>,
+        index_buffer: Option<Subbuffer<[u32]>>,
+    ) {
+        builder
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                object_descriptor_set,
+            )
+            .unwrap();
+
+        draw_mesh(builder, vertex_buffer, index_buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_depths_increase_up_to_distance() {
+        let settings = ShadowSettings::default();
+        let splits = settings.split_depths(0.1, 100.0);
+        assert!(splits.windows(2).all(|w| w[0] < w[1]));
+        assert!((splits[CASCADE_COUNT - 1] - settings.distance).abs() < 1e-4);
+
+        let splits = settings.split_depths(0.1, 10.0);
+        assert!((splits[CASCADE_COUNT - 1] - 10.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use cgmath::{SquareMatrix, Vector4};
+use vulkano::{
+    command_buffer::RecordingCommandBuffer,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned, Queue},
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        SampleCount,
+    },
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendAttachmentState, depth_stencil::DepthStencilState,
+            input_assembly::InputAssemblyState, multisample::MultisampleState,
+            rasterization::RasterizationState, subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState, viewport::ViewportState, GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+};
+
+use super::{color_only_blend_state, pipeline_layout, sample::Camera};
+use crate::{render_pass::pipeline_subpass, App};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/skybox/skybox.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/skybox/skybox.frag");
+}
+
+/// Fills the main pass with a cubemap seen from the camera, before the scene is drawn over it.
+/// Depth is neither tested nor written.
+pub struct SkyboxPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    descriptor_set: Arc<DescriptorSet>,
+}
+
+impl SkyboxPipeline {
+    /// `cubemap` is a cube view, e.g. from [`crate::texture::from_cubemap`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+        cubemap: Arc<ImageView>,
+    ) -> SkyboxPipeline {
+        let device = queue.device();
+        let pipeline = Self::pipeline(device, None, rendering_info, samples);
+
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(0, cubemap, sampler)],
+            [],
+        )
+        .unwrap();
+
+        Self {
+            pipeline,
+            descriptor_set,
+        }
+    }
+
+    /// Recreates the pipeline for `samples` per pixel, e.g. after the anti-aliasing mode changed.
+    pub fn set_samples(
+        &mut self,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) {
+        self.pipeline = Self::pipeline(
+            self.pipeline.device(),
+            Some(self.pipeline.layout().clone()),
+            rendering_info,
+            samples,
+        );
+    }
+
+    /// Creates its layout unless given one.
+    fn pipeline(
+        device: &Arc<Device>,
+        layout: Option<Arc<PipelineLayout>>,
+        rendering_info: PipelineRenderingCreateInfo,
+        samples: SampleCount,
+    ) -> Arc<GraphicsPipeline> {
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = layout.unwrap_or_else(|| pipeline_layout(device, &stages));
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(VertexInputState::default()),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                }),
+                color_blend_state: Some(color_only_blend_state(
+                    &rendering_info,
+                    ColorBlendAttachmentState::default(),
+                )),
+                depth_stencil_state: Some(DepthStencilState::default()),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                subpass: Some(pipeline_subpass(device, rendering_info, samples)),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    }
+
+    /// Must be recorded inside the main pass, before the scene.
+    pub fn render(&self, builder: &mut RecordingCommandBuffer, camera: &Camera) {
+        let mut rotation = camera.view;
+        rotation.w = Vector4::unit_w();
+        let inverse_view_proj = (camera.proj * rotation)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::PushConstants {
+                    inverse_view_proj: inverse_view_proj.into(),
+                },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+    }
+}
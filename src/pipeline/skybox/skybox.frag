@@ -0,0 +1,18 @@
+#version 460
+
+layout(location = 0) in vec2 ndc;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform samplerCube skybox;
+
+layout(push_constant) uniform PushConstants {
+  // Of the view-projection matrix without the camera's translation, so that the sky stays
+  // infinitely far away.
+  mat4 inverse_view_proj;
+} pc;
+
+void main() {
+  vec4 direction = pc.inverse_view_proj * vec4(ndc, 1.0, 1.0);
+  outColor = vec4(texture(skybox, direction.xyz / direction.w).rgb, 1.0);
+}
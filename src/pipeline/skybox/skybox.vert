@@ -0,0 +1,10 @@
+#version 460
+
+layout(location = 0) out vec2 outNdc;
+
+// A single triangle covering the whole screen.
+void main() {
+  vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  outNdc = uv * 2.0 - 1.0;
+  gl_Position = vec4(outNdc, 0.0, 1.0);
+}
@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::{deferred::GBuffer, pipeline_layout, sample::Camera, taa::HISTORY_FORMAT};
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass, resolve_target_usage},
+    App,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/ssr/ssr.frag");
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SsrSettings {
+    /// Steps each reflected ray is marched for, over `max_distance`.
+    pub max_steps: u32,
+    /// How far behind a surface a ray may be and still count as hitting it, in world units.
+    pub thickness: f32,
+    /// How far rays are marched, in world units.
+    pub max_distance: f32,
+    /// Scales how strongly reflections show, on top of the material and Fresnel.
+    pub intensity: f32,
+    /// Reflected by rays that leave the screen or hit nothing. There is no environment map to
+    /// sample instead.
+    pub environment: [f32; 3],
+}
+
+impl Default for SsrSettings {
+    fn default() -> Self {
+        Self {
+            max_steps: 32,
+            thickness: 0.2,
+            max_distance: 10.0,
+            intensity: 1.0,
+            environment: [0.0; 3],
+        }
+    }
+}
+
+/// Screen-space reflections: marches reflected rays through the G-buffer depth and blends in the
+/// lit scene where they hit, more so on glossy surfaces and at grazing angles. Only what is on
+/// screen can be reflected.
+pub struct SsrPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    /// Linear for the scene image, nearest for the G-buffer.
+    samplers: [Arc<Sampler>; 2],
+    scene_image: Arc<ImageView>,
+    descriptor_set: Arc<DescriptorSet>,
+    settings: SsrSettings,
+}
+
+impl SsrPipeline {
+    /// `gbuffer` is the deferred pipeline's, which must match `extent`. `output_format` is the
+    /// format of the images passed to [`Self::render`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        gbuffer: &GBuffer,
+        output_format: Format,
+        extent: [u32; 2],
+        settings: SsrSettings,
+    ) -> SsrPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = fs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(output_format)],
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(VertexInputState::default()),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        1,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // Hits land between texels; the G-buffer is only ever sampled nearest.
+        let linear_sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let nearest_sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let samplers = [linear_sampler, nearest_sampler];
+        let (scene_image, descriptor_set) =
+            Self::targets(app, &pipeline, &samplers, gbuffer, extent);
+
+        Self {
+            pipeline,
+            samplers,
+            scene_image,
+            descriptor_set,
+            settings,
+        }
+    }
+
+    /// Recreates the scene image for frames of `extent`, reading `gbuffer`, which must match it.
+    pub fn resize(&mut self, app: &App, gbuffer: &GBuffer, extent: [u32; 2]) {
+        (self.scene_image, self.descriptor_set) =
+            Self::targets(app, &self.pipeline, &self.samplers, gbuffer, extent);
+    }
+
+    /// The scene image of `extent` and the set reading it and `gbuffer`.
+    fn targets(
+        app: &App,
+        pipeline: &GraphicsPipeline,
+        [linear_sampler, nearest_sampler]: &[Arc<Sampler>; 2],
+        gbuffer: &GBuffer,
+        extent: [u32; 2],
+    ) -> (Arc<ImageView>, Arc<DescriptorSet>) {
+        let scene_image = ImageView::new_default(
+            Image::new(
+                app.memory_allocator(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    extent: [extent[0], extent[1], 1],
+                    format: HISTORY_FORMAT,
+                    // The main pass may resolve into it.
+                    usage: resolve_target_usage(
+                        app.context.device(),
+                        ImageUsage::COLOR_ATTACHMENT
+                            | ImageUsage::SAMPLED
+                            | ImageUsage::TRANSFER_SRC,
+                    ),
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(
+                    0,
+                    scene_image.clone(),
+                    linear_sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    gbuffer.normal(),
+                    nearest_sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    2,
+                    gbuffer.material(),
+                    nearest_sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(3, gbuffer.depth(), nearest_sampler.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        (scene_image, descriptor_set)
+    }
+
+    /// The image the main pass should render into instead of the swapchain.
+    pub fn scene_image(&self) -> Arc<ImageView> {
+        self.scene_image.clone()
+    }
+
+    /// Adds the reflections `camera` sees to the scene image, into `dst_image`. Must be recorded
+    /// outside of any render pass, after the main pass.
+    pub fn render(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        camera: &Camera,
+    ) {
+        let extent = self.scene_image.image().extent();
+        let settings = &self.settings;
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: vec![Some(RenderingAttachmentInfo {
+                    load_op: AttachmentLoadOp::DontCare,
+                    store_op: AttachmentStoreOp::Store,
+                    ..RenderingAttachmentInfo::image_view(dst_image)
+                })],
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::PushConstants {
+                    view_proj: (camera.proj * camera.view).into(),
+                    camera_pos: camera.position.into(),
+                    max_steps: settings.max_steps.max(1),
+                    thickness: settings.thickness,
+                    max_distance: settings.max_distance,
+                    intensity: settings.intensity,
+                    environment: settings.environment,
+                },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+
+        rendering.end(builder);
+    }
+}
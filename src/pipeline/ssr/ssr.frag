@@ -0,0 +1,97 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform sampler2D sceneTexture;
+layout(set = 0, binding = 1) uniform sampler2D normalTexture;
+layout(set = 0, binding = 2) uniform sampler2D materialTexture;
+layout(set = 0, binding = 3) uniform sampler2D depthTexture;
+
+layout(push_constant) uniform PushConstants {
+  mat4 view_proj;
+  vec3 camera_pos;
+  uint max_steps;
+  vec3 environment;
+  float intensity;
+  float thickness;
+  float max_distance;
+}
+pc;
+
+const int REFINE_STEPS = 4;
+// Where reflections start fading out towards the screen edges, in UV units.
+const float EDGE_FADE = 0.1;
+
+vec3 worldPosition(vec2 uv, float depth, mat4 invViewProj) {
+  vec4 world = invViewProj * vec4(uv * 2.0 - 1.0, depth, 1.0);
+  return world.xyz / world.w;
+}
+
+// View depth of the surface the G-buffer holds at `uv`.
+float surfaceDepth(vec2 uv, mat4 invViewProj) {
+  float depth = textureLod(depthTexture, uv, 0.0).r;
+  return (pc.view_proj * vec4(worldPosition(uv, depth, invViewProj), 1.0)).w;
+}
+
+void main() {
+  ivec2 texel = ivec2(gl_FragCoord.xy);
+  vec2 uv = (vec2(texel) + 0.5) / vec2(textureSize(depthTexture, 0));
+  vec3 color = texelFetch(sceneTexture, texel, 0).rgb;
+  float depth = texelFetch(depthTexture, texel, 0).r;
+  outColor = vec4(color, 1.0);
+  if (depth == 1.0) {
+    return;
+  }
+
+  // Only matters for reflective pixels, so it isn't worth pushing another matrix.
+  mat4 invViewProj = inverse(pc.view_proj);
+  vec3 position = worldPosition(uv, depth, invViewProj);
+  vec3 normal = texelFetch(normalTexture, texel, 0).xyz;
+  vec3 params = texelFetch(materialTexture, texel, 0).xyz;
+  vec3 viewDir = normalize(position - pc.camera_pos);
+  vec3 reflectDir = reflect(viewDir, normal);
+
+  // Glossier, more specular surfaces reflect more, and all of them more at grazing angles.
+  float gloss = smoothstep(8.0, 256.0, params.z);
+  float fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(normal, -viewDir), 0.0), 5.0);
+  float strength = clamp(gloss * params.y * fresnel * pc.intensity, 0.0, 1.0);
+  if (strength <= 0.0) {
+    return;
+  }
+
+  vec3 reflected = pc.environment;
+  float stepLength = pc.max_distance / float(pc.max_steps);
+  float previous = 0.0;
+  for (uint i = 1; i <= pc.max_steps; i++) {
+    float distance = stepLength * float(i);
+    vec4 clip = pc.view_proj * vec4(position + reflectDir * distance, 1.0);
+    vec2 hitUv = clip.xy / clip.w * 0.5 + 0.5;
+    if (clip.w <= 0.0 || any(lessThan(hitUv, vec2(0.0))) || any(greaterThan(hitUv, vec2(1.0)))) {
+      // Left the screen, nothing to reflect but the environment.
+      break;
+    }
+    float behind = clip.w - surfaceDepth(hitUv, invViewProj);
+    if (behind > 0.0 && behind < pc.thickness) {
+      // Bisect the last step for the crossing.
+      float lo = previous, hi = distance;
+      for (int j = 0; j < REFINE_STEPS; j++) {
+        float mid = (lo + hi) * 0.5;
+        vec4 midClip = pc.view_proj * vec4(position + reflectDir * mid, 1.0);
+        vec2 midUv = midClip.xy / midClip.w * 0.5 + 0.5;
+        if (midClip.w > surfaceDepth(midUv, invViewProj)) {
+          hi = mid;
+          hitUv = midUv;
+        } else {
+          lo = mid;
+        }
+      }
+      vec2 edge = min(hitUv, 1.0 - hitUv);
+      float fade = clamp(min(edge.x, edge.y) / EDGE_FADE, 0.0, 1.0);
+      reflected = mix(pc.environment, textureLod(sceneTexture, hitUv, 0.0).rgb, fade);
+      break;
+    }
+    previous = distance;
+  }
+
+  outColor = vec4(mix(color, reflected, strength), 1.0);
+}
@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{RecordingCommandBuffer, RenderingAttachmentInfo, RenderingInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            subpass::PipelineRenderingCreateInfo,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        DynamicState, GraphicsPipeline, Pipeline, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp},
+};
+
+use super::pipeline_layout;
+use crate::{
+    render_pass::{begin_rendering, pipeline_subpass, resolve_target_usage},
+    App,
+};
+
+mod vs {
+    vulkano_shaders::shader!(ty: "vertex", path: "src/pipeline/deferred/lighting.vert");
+}
+
+mod fs {
+    vulkano_shaders::shader!(ty: "fragment", path: "src/pipeline/taa/taa.frag");
+}
+
+/// Format of the scene image and the history; must match the main pass' color attachment.
+pub const HISTORY_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Weight of the current frame when accumulating into the history.
+pub const DEFAULT_BLEND: f32 = 0.1;
+
+const JITTER_SEQUENCE_LENGTH: u32 = 8;
+
+/// The `index`th element of the Halton sequence in `base`, in `[0, 1)`.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Sub-pixel offset to jitter the projection by on `frame`, in NDC. Repeats every
+/// `JITTER_SEQUENCE_LENGTH` frames.
+pub fn jitter(frame: u32, extent: [u32; 2]) -> [f32; 2] {
+    sample_offset(frame % JITTER_SEQUENCE_LENGTH, extent)
+}
+
+/// The `index`th sub-pixel offset of the Halton (2, 3) sequence, in NDC.
+pub fn sample_offset(index: u32, extent: [u32; 2]) -> [f32; 2] {
+    // Skips the first element, which is 0 in every base.
+    let index = index + 1;
+    [
+        (halton(index, 2) - 0.5) * 2.0 / extent[0] as f32,
+        (halton(index, 3) - 0.5) * 2.0 / extent[1] as f32,
+    ]
+}
+
+/// Temporal anti-aliasing: accumulates jittered frames into a history image, reprojected with
+/// the main pass' motion vectors and clamped to the current frame's neighborhood.
+pub struct TaaPipeline {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    scene_image: Arc<ImageView>,
+    history: [Arc<ImageView>; 2],
+    /// Index `i` reads `history[i]`, and is used while `history[1 - i]` is written.
+    descriptor_sets: [Arc<DescriptorSet>; 2],
+}
+
+impl TaaPipeline {
+    /// `output_format` is the format of the images passed to [`Self::render`].
+    pub fn new(
+        app: &App,
+        queue: Arc<Queue>,
+        output_format: Format,
+        extent: [u32; 2],
+        motion_image: Arc<ImageView>,
+    ) -> TaaPipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let vs = vs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let fs = fs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+            let layout = pipeline_layout(device, &stages);
+
+            let rendering_info = PipelineRenderingCreateInfo {
+                color_attachment_formats: vec![Some(output_format), Some(HISTORY_FORMAT)],
+                ..Default::default()
+            };
+
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(VertexInputState::default()),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        rendering_info.color_attachment_formats.len() as u32,
+                        ColorBlendAttachmentState::default(),
+                    )),
+                    dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                    subpass: Some(pipeline_subpass(
+                        device,
+                        rendering_info,
+                        SampleCount::Sample1,
+                    )),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        // Only the history is sampled between texels; the rest is fetched directly.
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (scene_image, history, descriptor_sets) =
+            Self::targets(app, &pipeline, &sampler, extent, motion_image);
+
+        Self {
+            pipeline,
+            sampler,
+            scene_image,
+            history,
+            descriptor_sets,
+        }
+    }
+
+    /// Recreates the images for frames of `extent`, starting without history. `motion_image` is
+    /// the main pass' one of that extent.
+    pub fn resize(&mut self, app: &App, extent: [u32; 2], motion_image: Arc<ImageView>) {
+        (self.scene_image, self.history, self.descriptor_sets) =
+            Self::targets(app, &self.pipeline, &self.sampler, extent, motion_image);
+    }
+
+    /// The scene image and history of `extent`, and the sets reading them.
+    fn targets(
+        app: &App,
+        pipeline: &GraphicsPipeline,
+        sampler: &Arc<Sampler>,
+        extent: [u32; 2],
+        motion_image: Arc<ImageView>,
+    ) -> (Arc<ImageView>, [Arc<ImageView>; 2], [Arc<DescriptorSet>; 2]) {
+        let image = || {
+            ImageView::new_default(
+                Image::new(
+                    app.memory_allocator(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        extent: [extent[0], extent[1], 1],
+                        format: HISTORY_FORMAT,
+                        // The main pass may resolve into the scene image.
+                        usage: resolve_target_usage(
+                            app.context.device(),
+                            ImageUsage::COLOR_ATTACHMENT
+                                | ImageUsage::SAMPLED
+                                | ImageUsage::TRANSFER_SRC,
+                        ),
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let scene_image = image();
+        let history = [image(), image()];
+        let descriptor_sets = history.clone().map(|history| {
+            DescriptorSet::new(
+                app.descriptor_set_allocator.clone(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view_sampler(0, scene_image.clone(), sampler.clone()),
+                    WriteDescriptorSet::image_view_sampler(1, history, sampler.clone()),
+                    WriteDescriptorSet::image_view_sampler(
+                        2,
+                        motion_image.clone(),
+                        sampler.clone(),
+                    ),
+                ],
+                [],
+            )
+            .unwrap()
+        });
+        (scene_image, history, descriptor_sets)
+    }
+
+    /// The image the main pass should resolve into instead of the swapchain.
+    pub fn scene_image(&self) -> Arc<ImageView> {
+        self.scene_image.clone()
+    }
+
+    /// Accumulates the scene image of `frame` into the history and writes the result to
+    /// `dst_image`. Must be recorded outside of any render pass, after the main pass.
+    pub fn render(
+        &self,
+        builder: &mut RecordingCommandBuffer,
+        dst_image: Arc<ImageView>,
+        frame: u32,
+    ) {
+        let read = (frame % 2) as usize;
+        let extent = self.scene_image.image().extent();
+        let rendering = begin_rendering(
+            builder,
+            RenderingInfo {
+                color_attachments: [dst_image, self.history[1 - read].clone()]
+                    .into_iter()
+                    .map(|view| {
+                        Some(RenderingAttachmentInfo {
+                            load_op: AttachmentLoadOp::DontCare,
+                            store_op: AttachmentStoreOp::Store,
+                            ..RenderingAttachmentInfo::image_view(view)
+                        })
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        );
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                self.pipeline.bind_point(),
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[read].clone(),
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::PushConstants {
+                    blend: DEFAULT_BLEND,
+                    has_history: (frame > 0) as u32,
+                },
+            )
+            .unwrap();
+
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+
+        rendering.end(builder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jitter_stays_within_a_pixel() {
+        for frame in 0..JITTER_SEQUENCE_LENGTH * 2 {
+            let [x, y] = jitter(frame, [100, 50]);
+            assert!(x.abs() < 1.0 / 100.0 && y.abs() < 1.0 / 50.0);
+        }
+    }
+}
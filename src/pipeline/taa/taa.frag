@@ -0,0 +1,45 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+layout(location = 1) out vec4 outHistory;
+
+layout(set = 0, binding = 0) uniform sampler2D currentTexture;
+layout(set = 0, binding = 1) uniform sampler2D historyTexture;
+layout(set = 0, binding = 2) uniform sampler2D motionTexture;
+
+layout(push_constant) uniform PushConstants {
+  float blend; // weight of the current frame
+  uint has_history;
+}
+pc;
+
+void main() {
+  ivec2 texel = ivec2(gl_FragCoord.xy);
+  ivec2 size = textureSize(currentTexture, 0);
+  vec3 current = texelFetch(currentTexture, texel, 0).rgb;
+
+  // History outside the current 3x3 neighborhood's color range is likely stale.
+  vec3 low = current;
+  vec3 high = current;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      ivec2 neighbor = clamp(texel + ivec2(x, y), ivec2(0), size - 1);
+      vec3 color = texelFetch(currentTexture, neighbor, 0).rgb;
+      low = min(low, color);
+      high = max(high, color);
+    }
+  }
+
+  vec2 uv = (vec2(texel) + 0.5) / vec2(size);
+  vec2 previousUv = uv - texelFetch(motionTexture, texel, 0).xy;
+  bool onScreen = all(greaterThanEqual(previousUv, vec2(0.0))) &&
+                  all(lessThanEqual(previousUv, vec2(1.0)));
+
+  vec3 result = current;
+  if (pc.has_history != 0 && onScreen) {
+    vec3 history = clamp(texture(historyTexture, previousUv).rgb, low, high);
+    result = mix(history, current, pc.blend);
+  }
+  outColor = vec4(result, 1.0);
+  outHistory = vec4(result, 1.0);
+}
@@ -0,0 +1,27 @@
+#version 460
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform sampler2D sceneTexture;
+
+layout(push_constant) uniform PushConstants {
+  float white_point;
+  float contrast;
+}
+pc;
+
+const float MIDDLE_GRAY = 0.18;
+
+void main() {
+  vec3 color = max(texelFetch(sceneTexture, ivec2(gl_FragCoord.xy), 0).rgb, 0.0);
+  color = MIDDLE_GRAY * pow(color / MIDDLE_GRAY, vec3(pc.contrast));
+
+  // Scaling by the mapped luminance rather than mapping each channel keeps the hues.
+  float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+  if (luminance > 0.0) {
+    float white = pc.white_point * pc.white_point;
+    float mapped = luminance * (1.0 + luminance / white) / (1.0 + luminance);
+    color *= mapped / luminance;
+  }
+  outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,157 @@
+use std::{mem::offset_of, sync::Arc};
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        CommandBufferBeginInfo, CommandBufferLevel, CommandBufferUsage, RecordingCommandBuffer,
+    },
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        compute::ComputePipelineCreateInfo, ComputePipeline, Pipeline, PipelineBindPoint,
+        PipelineShaderStageCreateInfo,
+    },
+    sync::{self, GpuFuture},
+    Validated, VulkanError,
+};
+
+use super::{pipeline_layout, sample::Camera, JointMatrix, MorphTargets};
+use crate::{App, MyVertex};
+
+mod cs {
+    vulkano_shaders::shader!(ty: "compute", path: "src/pipeline/vertex_capture/capture.comp");
+}
+
+const LOCAL_SIZE: u32 = 64;
+
+/// Applies the main pass' skinning and transforms to a mesh on the GPU and reads the positions
+/// back, in place of transform feedback.
+pub struct VertexCapturePipeline {
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+}
+
+impl VertexCapturePipeline {
+    pub fn new(queue: Arc<Queue>) -> VertexCapturePipeline {
+        let device = queue.device();
+
+        let pipeline = {
+            let cs = cs::load(device.clone())
+                .expect("failed to create shader module")
+                .entry_point("main")
+                .expect("shader entry point not found");
+            let stage = PipelineShaderStageCreateInfo::new(cs);
+            let layout = pipeline_layout(device, [&stage]);
+
+            ComputePipeline::new(
+                device.clone(),
+                None,
+                ComputePipelineCreateInfo::stage_layout(stage, layout),
+            )
+            .unwrap()
+        };
+
+        Self { queue, pipeline }
+    }
+
+    /// Positions of `vertex_buffer` morphed by `morph_targets`, posed by `joint_palette` and
+    /// projected by `camera`, in normalized device coordinates. The vertex buffer must have been created with
+    /// [`BufferUsage::STORAGE_BUFFER`]; it may be a slice of a larger one.
+    pub fn capture(
+        &self,
+        app: &App,
+        vertex_buffer: &Subbuffer<[MyVertex]>,
+        joint_palette: Subbuffer<[JointMatrix]>,
+        morph_targets: &MorphTargets,
+        camera: &Camera,
+    ) -> Result<Vec<[f32; 3]>, VulkanError> {
+        let vertex_count = vertex_buffer.len() as u32;
+        // Storage buffer offsets have alignment requirements a slice may not meet, so the whole
+        // buffer is bound and the slice's first vertex is passed instead.
+        let vertex_size = size_of::<MyVertex>() as u64;
+        let words = Subbuffer::new(vertex_buffer.buffer().clone()).reinterpret::<[u32]>();
+        let first_vertex = (vertex_buffer.offset() / vertex_size) as u32;
+
+        let positions = Buffer::new_slice::<[f32; 4]>(
+            app.memory_allocator(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            vertex_count as u64,
+        )
+        .unwrap();
+
+        let descriptor_set = DescriptorSet::new(
+            app.descriptor_set_allocator.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, words),
+                WriteDescriptorSet::buffer(1, joint_palette),
+                WriteDescriptorSet::buffer(2, positions.clone()),
+            ]
+            .into_iter()
+            .chain(morph_targets.writes(3)),
+            [],
+        )
+        .unwrap();
+
+        let word = |offset: usize| (offset / size_of::<u32>()) as u32;
+        let mut builder = RecordingCommandBuffer::new(
+            app.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferLevel::Primary,
+            CommandBufferBeginInfo {
+                usage: CommandBufferUsage::OneTimeSubmit,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                descriptor_set,
+            )
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                cs::PushConstants {
+                    view_proj: (camera.proj * camera.view).into(),
+                    first_vertex,
+                    vertex_count,
+                    stride: word(size_of::<MyVertex>()),
+                    position_offset: word(offset_of!(MyVertex, position)),
+                    joints_offset: word(offset_of!(MyVertex, joints)),
+                    weights_offset: word(offset_of!(MyVertex, weights)),
+                },
+            )
+            .unwrap();
+        unsafe { builder.dispatch([vertex_count.div_ceil(LOCAL_SIZE), 1, 1]) }.unwrap();
+        let command_buffer = builder.end().unwrap();
+
+        sync::now(self.queue.device().clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .map_err(Validated::unwrap)?
+            .wait(None)
+            .map_err(Validated::unwrap)?;
+
+        let positions = positions.read().unwrap();
+        Ok(positions
+            .iter()
+            .map(|&[x, y, z, w]| [x / w, y / w, z / w])
+            .collect())
+    }
+}
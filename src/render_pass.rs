@@ -0,0 +1,253 @@
+//! The render pass fallback for devices without dynamic rendering.
+//!
+//! Passes and pipelines are described with the dynamic rendering types throughout. Without the
+//! `dynamic_rendering` feature, [`begin_rendering`] and [`pipeline_subpass`] build a render pass
+//! with a single subpass from them instead, and a framebuffer of the attachments. Samples are
+//! resolved with a copy after such a render pass rather than by resolve attachments, so that a
+//! pipeline stays compatible with every pass it is drawn in, whatever they resolve.
+
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{
+        RecordingCommandBuffer, RenderPassBeginInfo, RenderingInfo, ResolveImageInfo,
+        SubpassBeginInfo, SubpassEndInfo,
+    },
+    device::{Device, DeviceOwned},
+    format::ClearValue,
+    image::{Image, ImageAspects, ImageLayout, ImageUsage, SampleCount},
+    pipeline::graphics::subpass::{PipelineRenderingCreateInfo, PipelineSubpassType},
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, Subpass,
+        SubpassDescription,
+    },
+};
+
+/// Whether passes use dynamic rendering rather than the render pass fallback.
+pub(crate) fn has_dynamic_rendering(device: &Device) -> bool {
+    device.enabled_features().dynamic_rendering
+}
+
+/// What a pipeline drawing into attachments of `rendering_info` is created for: that, or with
+/// the fallback, a render pass compatible with the ones [`begin_rendering`] builds for them.
+/// `samples` must be the pipeline's rasterization samples.
+pub(crate) fn pipeline_subpass(
+    device: &Arc<Device>,
+    rendering_info: PipelineRenderingCreateInfo,
+    samples: SampleCount,
+) -> PipelineSubpassType {
+    if has_dynamic_rendering(device) {
+        return rendering_info.into();
+    }
+    // Only the formats and sample counts matter for compatibility.
+    let attachment = |format| AttachmentDescription {
+        format,
+        samples,
+        ..Default::default()
+    };
+    let color_attachments = rendering_info
+        .color_attachment_formats
+        .iter()
+        .map(|format| format.map(attachment));
+    let depth_stencil_attachment = rendering_info
+        .depth_attachment_format
+        .or(rendering_info.stencil_attachment_format)
+        .map(attachment);
+    let render_pass = render_pass(device.clone(), color_attachments, depth_stencil_attachment);
+    Subpass::from(render_pass, 0).unwrap().into()
+}
+
+/// A pass begun with [`begin_rendering`], until [`Rendering::end`].
+#[must_use = "the pass has to be ended"]
+pub(crate) enum Rendering {
+    Dynamic,
+    RenderPass {
+        /// Multisampled images, each with the image it is resolved into once the pass ended.
+        resolves: Vec<(Arc<Image>, Arc<Image>)>,
+    },
+}
+
+/// Begins drawing into the attachments of `rendering_info`, with dynamic rendering or else a
+/// render pass and framebuffer built for them. Multisampled attachments resolved by the fallback
+/// are stored regardless of their store op, and need the usage of [`resolve_source_usage`].
+pub(crate) fn begin_rendering(
+    builder: &mut RecordingCommandBuffer,
+    rendering_info: RenderingInfo,
+) -> Rendering {
+    let device = builder.device().clone();
+    if has_dynamic_rendering(&device) {
+        builder.begin_rendering(rendering_info).unwrap();
+        return Rendering::Dynamic;
+    }
+
+    let RenderingInfo {
+        color_attachments,
+        depth_attachment,
+        stencil_attachment,
+        ..
+    } = rendering_info;
+    // Of the framebuffer's attachments, in the render pass' order.
+    let mut views = Vec::new();
+    let mut clear_values = Vec::new();
+    let mut resolves = Vec::new();
+
+    let color_attachments: Vec<_> = color_attachments
+        .into_iter()
+        .map(|attachment| {
+            let attachment = attachment?;
+            let mut store_op = attachment.store_op;
+            if let Some(resolve_info) = attachment.resolve_info {
+                // The copy after the render pass reads the samples.
+                store_op = AttachmentStoreOp::Store;
+                resolves.push((
+                    attachment.image_view.image().clone(),
+                    resolve_info.image_view.image().clone(),
+                ));
+            }
+            let description = AttachmentDescription {
+                format: attachment.image_view.format(),
+                samples: attachment.image_view.image().samples(),
+                load_op: attachment.load_op,
+                store_op,
+                ..Default::default()
+            };
+            views.push(attachment.image_view);
+            clear_values.push(attachment.clear_value);
+            Some(description)
+        })
+        .collect();
+
+    // Depth and stencil are aspects of the same attachment in a render pass.
+    let depth_stencil_attachment = depth_attachment
+        .as_ref()
+        .or(stencil_attachment.as_ref())
+        .map(|attachment| {
+            let view = &attachment.image_view;
+            let has_stencil = view.format().aspects().intersects(ImageAspects::STENCIL);
+            let depth_info = depth_attachment.as_ref();
+            let stencil_info = stencil_attachment.as_ref().filter(|_| has_stencil);
+            clear_values.push(
+                match (
+                    depth_info.and_then(|a| a.clear_value),
+                    stencil_info.and_then(|a| a.clear_value),
+                ) {
+                    (Some(ClearValue::Depth(depth)), Some(ClearValue::Stencil(stencil))) => {
+                        Some(ClearValue::DepthStencil((depth, stencil)))
+                    }
+                    (depth, stencil) => depth.or(stencil),
+                },
+            );
+            views.push(view.clone());
+            AttachmentDescription {
+                format: view.format(),
+                samples: view.image().samples(),
+                load_op: depth_info.map_or(AttachmentLoadOp::DontCare, |a| a.load_op),
+                store_op: depth_info.map_or(AttachmentStoreOp::DontCare, |a| a.store_op),
+                stencil_load_op: has_stencil
+                    .then(|| stencil_info.map_or(AttachmentLoadOp::DontCare, |a| a.load_op)),
+                stencil_store_op: has_stencil
+                    .then(|| stencil_info.map_or(AttachmentStoreOp::DontCare, |a| a.store_op)),
+                ..Default::default()
+            }
+        });
+
+    let render_pass = render_pass(device, color_attachments, depth_stencil_attachment);
+    let framebuffer = Framebuffer::new(
+        render_pass,
+        FramebufferCreateInfo {
+            attachments: views,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values,
+                ..RenderPassBeginInfo::framebuffer(framebuffer)
+            },
+            SubpassBeginInfo::default(),
+        )
+        .unwrap();
+    Rendering::RenderPass { resolves }
+}
+
+impl Rendering {
+    /// Ends the pass, then resolves the samples the fallback kept.
+    pub fn end(self, builder: &mut RecordingCommandBuffer) {
+        match self {
+            Rendering::Dynamic => {
+                builder.end_rendering().unwrap();
+            }
+            Rendering::RenderPass { resolves } => {
+                builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+                for (src_image, dst_image) in resolves {
+                    builder
+                        .resolve_image(ResolveImageInfo::images(src_image, dst_image))
+                        .unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// `usage` for a multisampled attachment that may be resolved. The fallback copies from it, so
+/// it can't be transient.
+pub(crate) fn resolve_source_usage(device: &Device, usage: ImageUsage) -> ImageUsage {
+    if has_dynamic_rendering(device) {
+        return usage;
+    }
+    usage.difference(ImageUsage::TRANSIENT_ATTACHMENT) | ImageUsage::TRANSFER_SRC
+}
+
+/// `usage` for an image multisampled attachments may be resolved into, likewise.
+pub(crate) fn resolve_target_usage(device: &Device, usage: ImageUsage) -> ImageUsage {
+    if has_dynamic_rendering(device) {
+        return usage;
+    }
+    usage | ImageUsage::TRANSFER_DST
+}
+
+/// A render pass with one subpass drawing into `color_attachments` and
+/// `depth_stencil_attachment`, which stay in their attachment layouts throughout.
+fn render_pass(
+    device: Arc<Device>,
+    color_attachments: impl IntoIterator<Item = Option<AttachmentDescription>>,
+    depth_stencil_attachment: Option<AttachmentDescription>,
+) -> Arc<RenderPass> {
+    let mut attachments = Vec::new();
+    let mut reference = |description: AttachmentDescription, layout: ImageLayout| {
+        attachments.push(AttachmentDescription {
+            initial_layout: layout,
+            final_layout: layout,
+            ..description
+        });
+        AttachmentReference {
+            attachment: attachments.len() as u32 - 1,
+            layout,
+            ..Default::default()
+        }
+    };
+    let color_attachments = color_attachments
+        .into_iter()
+        .map(|attachment| {
+            attachment.map(|attachment| reference(attachment, ImageLayout::ColorAttachmentOptimal))
+        })
+        .collect();
+    let depth_stencil_attachment = depth_stencil_attachment
+        .map(|attachment| reference(attachment, ImageLayout::DepthStencilAttachmentOptimal));
+    RenderPass::new(
+        device,
+        RenderPassCreateInfo {
+            attachments,
+            subpasses: vec![SubpassDescription {
+                color_attachments,
+                depth_stencil_attachment,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
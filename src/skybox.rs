@@ -0,0 +1,337 @@
+//! Environment cubemaps drawn behind the scene, see [`crate::App::set_skybox`].
+
+use std::{f32::consts::PI, io::Cursor};
+
+use cgmath::{InnerSpace, Vector3};
+use png::{ColorType, Transformations};
+use thiserror::Error;
+
+use crate::texture::srgb_to_linear;
+
+#[derive(Debug, Error)]
+pub enum SkyboxError {
+    #[error("failed to decode cubemap face: {0}")]
+    Png(#[from] png::DecodingError),
+    #[error("cubemap faces must be square and all of the same size")]
+    FaceSize,
+    #[error("failed to parse Radiance HDR: {0}")]
+    Hdr(&'static str),
+}
+
+/// Linear RGB texels of six square faces, in Vulkan's +X, -X, +Y, -Y, +Z, -Z layer order, each
+/// in rows from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cubemap {
+    pub size: u32,
+    pub texels: Vec<[f32; 3]>,
+}
+
+impl Cubemap {
+    /// Decodes six sRGB PNG faces laid out like a cubemap texture, e.g. exported by most skybox
+    /// tools.
+    pub fn from_png_faces(faces: [&[u8]; 6]) -> Result<Cubemap, SkyboxError> {
+        let mut size = None;
+        let mut texels = Vec::new();
+        for face in faces {
+            let (extent, face_texels) = decode_png(face)?;
+            if extent[0] != extent[1] || *size.get_or_insert(extent[0]) != extent[0] {
+                return Err(SkyboxError::FaceSize);
+            }
+            texels.extend(face_texels);
+        }
+        Ok(Cubemap {
+            size: size.unwrap(),
+            texels,
+        })
+    }
+
+    /// Converts an equirectangular panorama in Radiance HDR format, with -Z in its center and +Y
+    /// at the top, to faces of `size` texels.
+    pub fn from_equirectangular_hdr(hdr: &[u8], size: u32) -> Result<Cubemap, SkyboxError> {
+        let (extent, panorama) = decode_hdr(hdr)?;
+        let texels = (0..6)
+            .flat_map(|face| {
+                (0..size * size).map(move |i| {
+                    let [i, j] = [i % size, i / size].map(|t| (t as f32 + 0.5) / size as f32);
+                    face_direction(face, [i * 2.0 - 1.0, j * 2.0 - 1.0])
+                })
+            })
+            .map(|direction| sample_equirectangular(&panorama, extent, direction))
+            .collect();
+        Ok(Cubemap { size, texels })
+    }
+
+    /// The texels as RGBA16F, for uploading.
+    pub(crate) fn to_rgba16f(&self) -> Vec<u8> {
+        self.texels
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .flat_map(|c| f32_to_f16(c).to_le_bytes())
+            .collect()
+    }
+}
+
+/// The direction the cubemap is sampled with at `uv` in `[-1, 1]` across `face`, from the left
+/// and the top, as in the Vulkan spec's cube map face selection table.
+fn face_direction(face: u32, [u, v]: [f32; 2]) -> Vector3<f32> {
+    match face {
+        0 => Vector3::new(1.0, -v, -u),
+        1 => Vector3::new(-1.0, -v, u),
+        2 => Vector3::new(u, 1.0, v),
+        3 => Vector3::new(u, -1.0, -v),
+        4 => Vector3::new(u, -v, 1.0),
+        _ => Vector3::new(-u, -v, -1.0),
+    }
+    .normalize()
+}
+
+/// Bilinearly filtered, wrapping around horizontally.
+fn sample_equirectangular(
+    panorama: &[[f32; 3]],
+    extent: [u32; 2],
+    direction: Vector3<f32>,
+) -> [f32; 3] {
+    let [width, height] = extent.map(|e| e as f32);
+    let longitude = direction.x.atan2(-direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+    let x = (longitude / (2.0 * PI) + 0.5) * width - 0.5;
+    let y = ((0.5 - latitude / PI) * height - 0.5).clamp(0.0, height - 1.0);
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(extent[0] as i64) as usize;
+        let y = (y as usize).min(extent[1] as usize - 1);
+        panorama[y * extent[0] as usize + x]
+    };
+    let [x0, y0] = [x.floor(), y.floor()];
+    let [tx, ty] = [x - x0, y - y0];
+    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t);
+    lerp(
+        lerp(texel(x0, y0), texel(x0 + 1.0, y0), tx),
+        lerp(texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0), tx),
+        ty,
+    )
+}
+
+fn decode_png(bytes: &[u8]) -> Result<([u32; 2], Vec<[f32; 3]>), SkyboxError> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size().unwrap_or(0)];
+    let info = reader.next_frame(&mut data)?;
+    let channels = match info.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        // Palettes are expanded.
+        ColorType::Rgba | ColorType::Indexed => 4,
+    };
+    let texels = data[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|texel| {
+            let rgb = if channels < 3 {
+                [texel[0]; 3]
+            } else {
+                [texel[0], texel[1], texel[2]]
+            };
+            rgb.map(|c| srgb_to_linear(c as f32 / 255.0))
+        })
+        .collect();
+    Ok(([info.width, info.height], texels))
+}
+
+/// Decodes run-length encoded or flat RGBE scanlines, in the usual `-Y <height> +X <width>`
+/// orientation only.
+fn decode_hdr(bytes: &[u8]) -> Result<([u32; 2], Vec<[f32; 3]>), SkyboxError> {
+    let mut lines = bytes.split(|&b| b == b'\n');
+    let mut offset = 0;
+    let mut next_line = || {
+        let line = lines.next()?;
+        offset += line.len() + 1;
+        Some(line)
+    };
+    let magic = next_line().ok_or(SkyboxError::Hdr("empty file"))?;
+    if !magic.starts_with(b"#?") {
+        return Err(SkyboxError::Hdr("not a Radiance HDR file"));
+    }
+    loop {
+        let line = next_line().ok_or(SkyboxError::Hdr("truncated header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(SkyboxError::Hdr("only RGBE pixels are supported"));
+            }
+        }
+    }
+    let resolution = next_line().ok_or(SkyboxError::Hdr("missing resolution"))?;
+    let resolution = std::str::from_utf8(resolution).unwrap_or_default();
+    let extent = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => [width, height].map(|e| e.parse::<u32>().ok()),
+        _ => return Err(SkyboxError::Hdr("unsupported orientation")),
+    };
+    let [Some(width), Some(height)] = extent else {
+        return Err(SkyboxError::Hdr("invalid resolution"));
+    };
+    if width == 0 || height == 0 {
+        return Err(SkyboxError::Hdr("invalid resolution"));
+    }
+
+    let mut data = bytes.get(offset..).unwrap_or_default();
+    let width = width as usize;
+    let mut texels = Vec::with_capacity(width * height as usize);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        let rle = (8..0x8000).contains(&width)
+            && data.len() >= 4
+            && data[..2] == [2, 2]
+            && ((data[2] as usize) << 8 | data[3] as usize) == width;
+        if rle {
+            take(&mut data, 4)?;
+            // The channels follow each other, each in runs that stay within the scanline.
+            let mut planes = Vec::with_capacity(width * 4);
+            while planes.len() < width * 4 {
+                let count = take(&mut data, 1)?[0] as usize;
+                let run = if count > 128 { count - 128 } else { count };
+                let plane_end = (planes.len() / width + 1) * width;
+                if run == 0 || planes.len() + run > plane_end {
+                    return Err(SkyboxError::Hdr("invalid run length"));
+                }
+                match count {
+                    129.. => {
+                        let value = take(&mut data, 1)?[0];
+                        planes.extend(std::iter::repeat_n(value, run));
+                    }
+                    _ => planes.extend_from_slice(take(&mut data, count)?),
+                }
+            }
+            for (x, texel) in scanline.iter_mut().enumerate() {
+                *texel = std::array::from_fn(|channel| planes[channel * width + x]);
+            }
+        } else {
+            for (texel, rgbe) in scanline
+                .iter_mut()
+                .zip(take(&mut data, width * 4)?.chunks_exact(4))
+            {
+                texel.copy_from_slice(rgbe);
+            }
+        }
+        texels.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+    }
+    Ok(([width as u32, height], texels))
+}
+
+/// Splits off the first `count` bytes of `data`.
+fn take<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8], SkyboxError> {
+    let (taken, rest) = data
+        .split_at_checked(count)
+        .ok_or(SkyboxError::Hdr("truncated pixels"))?;
+    *data = rest;
+    Ok(taken)
+}
+
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    // The mantissas are fractions of 256.
+    let scale = 2.0f32.powi(e as i32 - 136);
+    [r, g, b].map(|c| c as f32 * scale)
+}
+
+/// Rounds to the nearest half float, flushing values too small for it to zero.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (14 - exponent);
+        return sign | mantissa as u16;
+    }
+    let half = ((exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+    // Rounding up may carry into infinity, which is what it rounds to.
+    sign | half.min(0x7c00) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(header: &str, pixels: &[u8]) -> Vec<u8> {
+        [header.as_bytes(), pixels].concat()
+    }
+
+    #[test]
+    fn decodes_flat_and_run_length_encoded_scanlines() {
+        // 2x1 flat: 1.0 and 0.5 gray.
+        let flat = hdr(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n",
+            &[128, 128, 128, 129, 128, 128, 128, 128],
+        );
+        let (extent, texels) = decode_hdr(&flat).unwrap();
+        assert_eq!(extent, [2, 1]);
+        assert_eq!(texels, [[1.0; 3], [0.5; 3]]);
+
+        // 8x1 run-length encoded: a run of 8 per channel, exponent 130 for 2.0.
+        let rle = hdr(
+            "#?RADIANCE\n\n-Y 1 +X 8\n",
+            &[2, 2, 0, 8, 136, 128, 136, 64, 136, 0, 136, 130],
+        );
+        let (extent, texels) = decode_hdr(&rle).unwrap();
+        assert_eq!(extent, [8, 1]);
+        assert!(texels.iter().all(|&t| t == [2.0, 1.0, 0.0]));
+
+        assert!(decode_hdr(&hdr("#?RADIANCE\n\n+Y 1 +X 2\n", &[])).is_err());
+        assert!(decode_hdr(&hdr("#?RADIANCE\n\n-Y 1 +X 2\n", &[128; 5])).is_err());
+    }
+
+    #[test]
+    fn faces_look_along_their_axes() {
+        let centers = (0..6).map(|face| face_direction(face, [0.0, 0.0]));
+        let axes = [
+            Vector3::unit_x(),
+            -Vector3::unit_x(),
+            Vector3::unit_y(),
+            -Vector3::unit_y(),
+            Vector3::unit_z(),
+            -Vector3::unit_z(),
+        ];
+        for (center, axis) in centers.zip(axes) {
+            assert!((center - axis).magnitude() < 1e-6);
+        }
+        // The top of the side faces is up.
+        assert!(face_direction(4, [0.0, -1.0]).y > 0.0);
+        assert!(face_direction(0, [0.0, -1.0]).y > 0.0);
+    }
+
+    #[test]
+    fn unwraps_the_panorama_center_to_negative_z() {
+        // Left half black, right half white, so -Z sits on the seam between them and +X in the
+        // white half.
+        let panorama = [[0.0; 3], [0.0; 3], [1.0; 3], [1.0; 3]];
+        let sample = |direction| sample_equirectangular(&panorama, [4, 1], direction)[0];
+        assert!((sample(-Vector3::unit_z()) - 0.5).abs() < 1e-6);
+        assert_eq!(sample(Vector3::unit_x()), 1.0);
+        assert_eq!(sample(-Vector3::unit_x()), 0.0);
+    }
+
+    #[test]
+    fn converts_to_half_floats() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        // The smallest subnormal.
+        assert_eq!(f32_to_f16(2.0f32.powi(-24)), 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! GPU counters and timings of rendered frames, read back with queries.
+
+use std::{cell::Cell, sync::Arc};
+
+use vulkano::{
+    command_buffer::RecordingCommandBuffer,
+    device::{Device, Queue},
+    query::{
+        QueryControlFlags, QueryPipelineStatisticFlags, QueryPool, QueryPoolCreateInfo,
+        QueryResultFlags, QueryType,
+    },
+    sync::PipelineStage,
+};
+
+/// Counters of the geometry pass: the main pass with forward shading, the G-buffer pass with
+/// deferred shading. Shadow maps and post-processing aren't counted. Comparing vertex and
+/// fragment shader invocations hints at whether a scene is vertex or fragment bound.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub input_assembly_vertices: u64,
+    pub input_assembly_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    /// Primitives left after clipping.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+const STATISTICS: QueryPipelineStatisticFlags =
+    QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES
+        .union(QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES)
+        .union(QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS)
+        .union(QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES)
+        .union(QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS);
+
+impl From<[u64; 5]> for RenderStats {
+    /// Query results come in the order of the flags' bits.
+    fn from(results: [u64; 5]) -> Self {
+        Self {
+            input_assembly_vertices: results[0],
+            input_assembly_primitives: results[1],
+            vertex_shader_invocations: results[2],
+            clipping_primitives: results[3],
+            fragment_shader_invocations: results[4],
+        }
+    }
+}
+
+/// A single query around the geometry pass, read back once the frame has finished.
+pub(crate) struct StatisticsQuery {
+    pool: Arc<QueryPool>,
+    /// Whether a frame has been recorded since the last read.
+    pending: Cell<bool>,
+}
+
+impl StatisticsQuery {
+    /// `None` if the device doesn't have `pipeline_statistics_query` enabled.
+    pub fn new(device: Arc<Device>) -> Option<StatisticsQuery> {
+        if !device.enabled_features().pipeline_statistics_query {
+            return None;
+        }
+        let pool = QueryPool::new(
+            device,
+            QueryPoolCreateInfo {
+                query_count: 1,
+                pipeline_statistics: STATISTICS,
+                ..QueryPoolCreateInfo::query_type(QueryType::PipelineStatistics)
+            },
+        )
+        .unwrap();
+        Some(Self {
+            pool,
+            pending: Cell::new(false),
+        })
+    }
+
+    /// Must be recorded outside of any render pass, before [`Self::begin`].
+    pub fn reset(&self, builder: &mut RecordingCommandBuffer) {
+        unsafe { builder.reset_query_pool(self.pool.clone(), 0..1) }.unwrap();
+    }
+
+    pub fn begin(&self, builder: &mut RecordingCommandBuffer) {
+        unsafe { builder.begin_query(self.pool.clone(), 0, QueryControlFlags::empty()) }.unwrap();
+        self.pending.set(true);
+    }
+
+    pub fn end(&self, builder: &mut RecordingCommandBuffer) {
+        builder.end_query(self.pool.clone(), 0).unwrap();
+    }
+
+    /// The counters of the last recorded frame, waiting for it to finish. `None` if nothing has
+    /// been recorded since the last call.
+    pub fn read(&self) -> Option<RenderStats> {
+        if !self.pending.replace(false) {
+            return None;
+        }
+        let mut results = [0; 5];
+        self.pool
+            .get_results(0..1, &mut results, QueryResultFlags::WAIT)
+            .unwrap();
+        Some(results.into())
+    }
+}
+
+/// Two timestamps around the scene passes of a frame, read back like [`StatisticsQuery`].
+pub(crate) struct TimestampQuery {
+    pool: Arc<QueryPool>,
+    /// Nanoseconds per tick.
+    period: f32,
+    /// Of the bits of each timestamp, the rest being garbage.
+    mask: u64,
+    pending: Cell<bool>,
+}
+
+impl TimestampQuery {
+    /// `None` if `queue` doesn't write timestamps or the device doesn't say how fast they tick.
+    pub fn new(queue: &Arc<Queue>) -> Option<TimestampQuery> {
+        let physical_device = queue.device().physical_device();
+        let period = physical_device.properties().timestamp_period;
+        let valid_bits = physical_device.queue_family_properties()
+            [queue.queue_family_index() as usize]
+            .timestamp_valid_bits?;
+        if period <= 0.0 {
+            return None;
+        }
+        let pool = QueryPool::new(
+            queue.device().clone(),
+            QueryPoolCreateInfo {
+                query_count: 2,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        )
+        .unwrap();
+        Some(Self {
+            pool,
+            period,
+            mask: u64::MAX >> (64 - valid_bits.min(64)),
+            pending: Cell::new(false),
+        })
+    }
+
+    /// Must be recorded outside of any render pass, before [`Self::start`].
+    pub fn reset(&self, builder: &mut RecordingCommandBuffer) {
+        unsafe { builder.reset_query_pool(self.pool.clone(), 0..2) }.unwrap();
+    }
+
+    pub fn start(&self, builder: &mut RecordingCommandBuffer) {
+        unsafe { builder.write_timestamp(self.pool.clone(), 0, PipelineStage::TopOfPipe) }.unwrap();
+        self.pending.set(true);
+    }
+
+    pub fn stop(&self, builder: &mut RecordingCommandBuffer) {
+        unsafe { builder.write_timestamp(self.pool.clone(), 1, PipelineStage::BottomOfPipe) }
+            .unwrap();
+    }
+
+    /// Milliseconds between the timestamps of the last recorded frame, waiting for it to finish.
+    /// `None` if nothing has been recorded since the last call.
+    pub fn read(&self) -> Option<f32> {
+        if !self.pending.replace(false) {
+            return None;
+        }
+        let mut results = [0u64; 2];
+        self.pool
+            .get_results(0..2, &mut results, QueryResultFlags::WAIT)
+            .unwrap();
+        // Wrapping around within the valid bits.
+        let ticks = results[1].wrapping_sub(results[0]) & self.mask;
+        Some(ticks_to_ms(ticks, self.period))
+    }
+}
+
+/// Of a timer ticking every `period` nanoseconds.
+fn ticks_to_ms(ticks: u64, period: f32) -> f32 {
+    (ticks as f64 * period as f64 / 1e6) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_ticks_to_milliseconds() {
+        assert!((ticks_to_ms(2_999_000, 1.0) - 2.999).abs() < 1e-6);
+        assert!((ticks_to_ms(500_000, 52.08) - 26.04).abs() < 1e-4);
+    }
+
+    #[test]
+    fn results_follow_flag_order() {
+        assert_eq!(STATISTICS.count(), 5);
+        let stats = RenderStats::from([1, 2, 3, 4, 5]);
+        assert_eq!(stats.input_assembly_vertices, 1);
+        assert_eq!(stats.clipping_primitives, 4);
+        assert_eq!(stats.fragment_shader_invocations, 5);
+    }
+}
@@ -0,0 +1,600 @@
+//! KTX2 texture loading. Block-compressed data is uploaded as is when the device can sample
+//! it, and decoded to BGRA8 on the CPU otherwise. Levels may be Zstandard-supercompressed.
+//!
+//! Of the Basis Universal payloads `KHR_texture_basisu` uses, UASTC is transcoded to BC7 or
+//! ASTC 4x4, whichever the device samples, else to RGBA8. ETC1S, which comes with BasisLZ
+//! supercompression, is rejected with [`TextureError::UnsupportedSupercompression`].
+
+use std::{
+    borrow::Cow,
+    io::Read,
+    sync::{mpsc, Arc, Once},
+};
+
+use basis_universal::{
+    DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat,
+};
+
+use thiserror::Error;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, BufferImageCopy, CommandBufferBeginInfo,
+        CommandBufferLevel, CommandBufferUsage, CopyBufferToImageInfo, RecordingCommandBuffer,
+    },
+    device::Queue,
+    format::{Format, FormatFeatures},
+    image::{
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageType,
+        ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{self, GpuFuture, Sharing},
+};
+
+use crate::{skybox::Cubemap, App};
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("failed to parse KTX2 file: {0:?}")]
+    Parse(ktx2::ParseError),
+    #[error("unsupported KTX2 format {0:?}")]
+    UnsupportedFormat(Option<ktx2::Format>),
+    #[error("unsupported KTX2 supercompression {0:?}")]
+    UnsupportedSupercompression(ktx2::SupercompressionScheme),
+    #[error("only single-layer 2D KTX2 textures are supported")]
+    UnsupportedLayout,
+    #[error("failed to decode texture: {0}")]
+    Decode(&'static str),
+    #[error("failed to decompress KTX2 level {0}")]
+    Decompress(u32),
+    #[error("texture extent {0:?} is empty or exceeds the device's limit")]
+    Extent([u32; 2]),
+    #[error("{levels} mip levels don't fit a texture of {extent:?}")]
+    LevelCount { levels: usize, extent: [u32; 2] },
+    #[error("mip level {level} has {actual} bytes, expected {expected}")]
+    LevelSize {
+        level: u32,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+/// What uploads need from [`App`], which can't leave the thread it was created on.
+struct Uploader {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    queue: Arc<Queue>,
+    queue_families: Vec<u32>,
+}
+
+impl Uploader {
+    fn new(app: &App) -> Uploader {
+        Uploader {
+            memory_allocator: app.memory_allocator(),
+            command_buffer_allocator: app.command_buffer_allocator.clone(),
+            queue: app.transfer_queue(),
+            queue_families: app.upload_queue_families(),
+        }
+    }
+}
+
+/// A texture being loaded on a background thread, see [`load_ktx2_async`].
+pub struct PendingTexture {
+    receiver: mpsc::Receiver<Result<Arc<ImageView>, TextureError>>,
+}
+
+impl PendingTexture {
+    /// The loaded texture, once. `None` while it is still loading and after it was taken.
+    pub fn try_take(&self) -> Option<Result<Arc<ImageView>, TextureError>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+type BlockDecoder = fn(&[u8], usize, usize, &mut [u32]) -> Result<(), &'static str>;
+
+struct FormatInfo {
+    format: Format,
+    /// Fallback when `format` can't be sampled, writing BGRA8 texels.
+    decoder: Option<BlockDecoder>,
+    srgb: bool,
+}
+
+fn format_info(format: ktx2::Format) -> Option<FormatInfo> {
+    use ktx2::Format as K;
+
+    let info = |format, decoder, srgb| {
+        Some(FormatInfo {
+            format,
+            decoder,
+            srgb,
+        })
+    };
+    let astc_4x4: BlockDecoder =
+        |data, w, h, out| texture2ddecoder::decode_astc(data, w, h, 4, 4, out);
+    match format {
+        K::R8G8B8A8_UNORM => info(Format::R8G8B8A8_UNORM, None, false),
+        K::R8G8B8A8_SRGB => info(Format::R8G8B8A8_SRGB, None, true),
+        K::BC1_RGBA_UNORM_BLOCK => info(
+            Format::BC1_RGBA_UNORM_BLOCK,
+            Some(texture2ddecoder::decode_bc1a),
+            false,
+        ),
+        K::BC1_RGBA_SRGB_BLOCK => info(
+            Format::BC1_RGBA_SRGB_BLOCK,
+            Some(texture2ddecoder::decode_bc1a),
+            true,
+        ),
+        K::BC3_UNORM_BLOCK => info(
+            Format::BC3_UNORM_BLOCK,
+            Some(texture2ddecoder::decode_bc3),
+            false,
+        ),
+        K::BC3_SRGB_BLOCK => info(
+            Format::BC3_SRGB_BLOCK,
+            Some(texture2ddecoder::decode_bc3),
+            true,
+        ),
+        K::BC7_UNORM_BLOCK => info(
+            Format::BC7_UNORM_BLOCK,
+            Some(texture2ddecoder::decode_bc7),
+            false,
+        ),
+        K::BC7_SRGB_BLOCK => info(
+            Format::BC7_SRGB_BLOCK,
+            Some(texture2ddecoder::decode_bc7),
+            true,
+        ),
+        K::ASTC_4x4_UNORM_BLOCK => info(Format::ASTC_4x4_UNORM_BLOCK, Some(astc_4x4), false),
+        K::ASTC_4x4_SRGB_BLOCK => info(Format::ASTC_4x4_SRGB_BLOCK, Some(astc_4x4), true),
+        _ => None,
+    }
+}
+
+/// `KHR_DF_MODEL_UASTC` and `KHR_DF_TRANSFER_SRGB` of the Khronos Data Format Specification.
+const DFD_MODEL_UASTC: u8 = 166;
+const DFD_TRANSFER_SRGB: u8 = 2;
+
+/// The color model and transfer function of the basic descriptor block, which follow the
+/// descriptor's total size and the two words of the block header.
+fn color_model(bytes: &[u8], index: &ktx2::Index) -> Option<(u8, u8)> {
+    let start = index.dfd_byte_offset as usize;
+    let dfd = bytes.get(start..start.checked_add(index.dfd_byte_length as usize)?)?;
+    Some((*dfd.get(12)?, *dfd.get(14)?))
+}
+
+/// How level data turns into what is uploaded.
+#[derive(Clone, Copy)]
+enum Conversion {
+    AsIs,
+    /// Of block-compressed data the device can't sample, to BGRA8.
+    Decode(BlockDecoder),
+    /// Of UASTC blocks.
+    Transcode(TranscoderBlockFormat),
+}
+
+/// Transcodes a `width` by `height` level of UASTC blocks to `format`.
+fn transcode_uastc(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: TranscoderBlockFormat,
+) -> Result<Vec<u8>, TextureError> {
+    static INIT: Once = Once::new();
+    INIT.call_once(basis_universal::transcoder_init);
+    LowLevelUastcTranscoder::new()
+        .transcode_slice(
+            data,
+            SliceParametersUastc {
+                num_blocks_x: width.div_ceil(4),
+                num_blocks_y: height.div_ceil(4),
+                has_alpha: true,
+                original_width: width,
+                original_height: height,
+            },
+            DecodeFlags::HIGH_QUALITY,
+            format,
+        )
+        .map_err(|_| TextureError::Decode("invalid UASTC data"))
+}
+
+/// Decompresses a Zstandard-supercompressed `level`, which must inflate to the length its
+/// index entry gives.
+fn decompress(level: u32, data: &[u8], length: u64) -> Result<Vec<u8>, TextureError> {
+    let mut decoder =
+        ruzstd::StreamingDecoder::new(data).map_err(|_| TextureError::Decompress(level))?;
+    // Reads no more than the index promises, in case the stream is a bomb.
+    let mut decompressed = Vec::new();
+    (&mut decoder)
+        .take(length)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| TextureError::Decompress(level))?;
+    if decompressed.len() as u64 != length {
+        return Err(TextureError::Decompress(level));
+    }
+    Ok(decompressed)
+}
+
+/// Bytes of one layer of a `width` by `height` mip level in `format`.
+fn level_size(format: Format, [width, height]: [u32; 2]) -> u64 {
+    let [block_width, block_height, _] = format.block_extent();
+    width.div_ceil(block_width) as u64 * height.div_ceil(block_height) as u64 * format.block_size()
+}
+
+fn decode_to_bgra(
+    decoder: BlockDecoder,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, TextureError> {
+    let mut texels = vec![0; (width * height) as usize];
+    decoder(data, width as usize, height as usize, &mut texels).map_err(TextureError::Decode)?;
+    Ok(texels.into_iter().flat_map(u32::to_le_bytes).collect())
+}
+
+/// Loads a single-layer 2D KTX2 texture including all of its mip levels.
+pub fn load_ktx2(app: &App, bytes: &[u8]) -> Result<Arc<ImageView>, TextureError> {
+    load(&Uploader::new(app), bytes)
+}
+
+/// Uploads tightly packed RGBA8 texels in sRGB, e.g. a glTF base color texture.
+pub fn from_rgba8_srgb(
+    app: &App,
+    texels: Vec<u8>,
+    extent: [u32; 2],
+) -> Result<Arc<ImageView>, TextureError> {
+    let region = BufferImageCopy {
+        image_subresource: ImageSubresourceLayers {
+            aspects: ImageAspects::COLOR,
+            mip_level: 0,
+            array_layers: 0..1,
+        },
+        image_extent: [extent[0], extent[1], 1],
+        ..Default::default()
+    };
+    upload(
+        &Uploader::new(app),
+        Format::R8G8B8A8_SRGB,
+        extent,
+        texels,
+        vec![region],
+        false,
+    )
+}
+
+/// Uploads the faces of `cubemap` as a cube image in RGBA16F.
+pub fn from_cubemap(app: &App, cubemap: &Cubemap) -> Result<Arc<ImageView>, TextureError> {
+    let region = BufferImageCopy {
+        image_subresource: ImageSubresourceLayers {
+            aspects: ImageAspects::COLOR,
+            mip_level: 0,
+            array_layers: 0..6,
+        },
+        image_extent: [cubemap.size, cubemap.size, 1],
+        ..Default::default()
+    };
+    upload(
+        &Uploader::new(app),
+        Format::R16G16B16A16_SFLOAT,
+        [cubemap.size; 2],
+        cubemap.to_rgba16f(),
+        vec![region],
+        true,
+    )
+}
+
+/// Like [`load_ktx2`], but decodes and uploads on a background thread so that rendering can
+/// start with a placeholder meanwhile.
+pub fn load_ktx2_async(app: &App, bytes: Vec<u8>) -> PendingTexture {
+    let uploader = Uploader::new(app);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        // The receiver is gone if the texture is no longer needed.
+        let _ = sender.send(load(&uploader, &bytes));
+    });
+    PendingTexture { receiver }
+}
+
+fn load(uploader: &Uploader, bytes: &[u8]) -> Result<Arc<ImageView>, TextureError> {
+    let reader = ktx2::Reader::new(bytes).map_err(TextureError::Parse)?;
+    let header = reader.header();
+    let zstd = match header.supercompression_scheme {
+        None => false,
+        Some(ktx2::SupercompressionScheme::Zstandard) => true,
+        // BasisLZ needs ETC1S's global codebooks and a transcoder for them.
+        Some(scheme) => return Err(TextureError::UnsupportedSupercompression(scheme)),
+    };
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count != 1 {
+        return Err(TextureError::UnsupportedLayout);
+    }
+
+    let physical_device = uploader.queue.device().physical_device();
+    let can_sample = |format| {
+        physical_device
+            .format_properties(format)
+            .unwrap()
+            .optimal_tiling_features
+            .intersects(FormatFeatures::SAMPLED_IMAGE)
+    };
+    let (format, conversion) = match header.format {
+        Some(format) => {
+            let info = format_info(format).ok_or(TextureError::UnsupportedFormat(header.format))?;
+            match (info.decoder, info.srgb) {
+                _ if can_sample(info.format) => (info.format, Conversion::AsIs),
+                (Some(decoder), false) => (Format::B8G8R8A8_UNORM, Conversion::Decode(decoder)),
+                (Some(decoder), true) => (Format::B8G8R8A8_SRGB, Conversion::Decode(decoder)),
+                (None, _) => return Err(TextureError::UnsupportedFormat(header.format)),
+            }
+        }
+        // Basis Universal payloads have no format of their own.
+        None => {
+            let Some((DFD_MODEL_UASTC, transfer)) = color_model(bytes, &header.index) else {
+                return Err(TextureError::UnsupportedFormat(None));
+            };
+            let srgb = transfer == DFD_TRANSFER_SRGB;
+            let [bc7, astc, rgba] = if srgb {
+                [
+                    Format::BC7_SRGB_BLOCK,
+                    Format::ASTC_4x4_SRGB_BLOCK,
+                    Format::R8G8B8A8_SRGB,
+                ]
+            } else {
+                [
+                    Format::BC7_UNORM_BLOCK,
+                    Format::ASTC_4x4_UNORM_BLOCK,
+                    Format::R8G8B8A8_UNORM,
+                ]
+            };
+            let (format, target) = [
+                (bc7, TranscoderBlockFormat::BC7),
+                (astc, TranscoderBlockFormat::ASTC_4x4),
+            ]
+            .into_iter()
+            .find(|&(format, _)| can_sample(format))
+            .unwrap_or((rgba, TranscoderBlockFormat::RGBA32));
+            (format, Conversion::Transcode(target))
+        }
+    };
+
+    let level_extent = |level: u32| {
+        [
+            (header.pixel_width >> level).max(1),
+            (header.pixel_height.max(1) >> level).max(1),
+        ]
+    };
+    let mut data = Vec::new();
+    let mut regions = Vec::new();
+    for (level, level_data) in (0..).zip(reader.levels()) {
+        let [width, height] = level_extent(level);
+        let level_bytes = if zstd {
+            let length = level_data.uncompressed_byte_length;
+            Cow::Owned(decompress(level, level_data.data, length)?)
+        } else {
+            Cow::Borrowed(level_data.data)
+        };
+        regions.push(BufferImageCopy {
+            buffer_offset: data.len() as u64,
+            image_subresource: ImageSubresourceLayers {
+                aspects: ImageAspects::COLOR,
+                mip_level: level,
+                array_layers: 0..1,
+            },
+            image_extent: [width, height, 1],
+            ..Default::default()
+        });
+        match conversion {
+            Conversion::AsIs => data.extend_from_slice(&level_bytes),
+            Conversion::Decode(decoder) => {
+                data.extend(decode_to_bgra(decoder, &level_bytes, width, height)?)
+            }
+            Conversion::Transcode(target) => {
+                data.extend(transcode_uastc(&level_bytes, width, height, target)?)
+            }
+        }
+    }
+
+    let extent = [header.pixel_width, header.pixel_height.max(1)];
+    upload(uploader, format, extent, data, regions, false)
+}
+
+/// Decodes an sRGB-encoded color channel in `[0, 1]`, e.g. from a color picker, to the linear
+/// value the renderer's colors are given in.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// A 1x1 texture of a single `color`, for bindings without a texture.
+pub fn solid_color(app: &App, color: [u8; 4]) -> Arc<ImageView> {
+    let region = BufferImageCopy {
+        image_subresource: ImageSubresourceLayers {
+            aspects: ImageAspects::COLOR,
+            mip_level: 0,
+            array_layers: 0..1,
+        },
+        image_extent: [1, 1, 1],
+        ..Default::default()
+    };
+    upload(
+        &Uploader::new(app),
+        Format::R8G8B8A8_UNORM,
+        [1, 1],
+        color.to_vec(),
+        vec![region],
+        false,
+    )
+    .unwrap()
+}
+
+/// Creates a sampled image with one mip level per region and fills it from `data`. A `cube`
+/// has six layers, and each region covers all of them.
+///
+/// The regions must be in level order and packed tightly. KTX2 headers decide the extent and
+/// the levels, so they are checked against `data` rather than left to fail validation.
+fn upload(
+    uploader: &Uploader,
+    format: Format,
+    extent: [u32; 2],
+    data: Vec<u8>,
+    regions: Vec<BufferImageCopy>,
+    cube: bool,
+) -> Result<Arc<ImageView>, TextureError> {
+    let properties = uploader.queue.device().physical_device().properties();
+    let limit = if cube {
+        properties.max_image_dimension_cube
+    } else {
+        properties.max_image_dimension2_d
+    };
+    if extent.iter().any(|&side| side == 0 || side > limit) {
+        return Err(TextureError::Extent(extent));
+    }
+    let max_levels = 32 - extent[0].max(extent[1]).leading_zeros();
+    if regions.is_empty() || regions.len() > max_levels as usize {
+        return Err(TextureError::LevelCount {
+            levels: regions.len(),
+            extent,
+        });
+    }
+    let layers = if cube { 6 } else { 1 };
+    for (level, region) in (0..).zip(&regions) {
+        let end = regions
+            .get(level as usize + 1)
+            .map_or(data.len() as u64, |next| next.buffer_offset);
+        let level_extent = extent.map(|side| (side >> level).max(1));
+        let expected = level_size(format, level_extent) * layers;
+        let actual = end.saturating_sub(region.buffer_offset);
+        if actual != expected {
+            return Err(TextureError::LevelSize {
+                level,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    let memory_allocator = uploader.memory_allocator.clone();
+    let staging_buffer = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap();
+    // Copied on the transfer queue but sampled on the graphics queue, so without ownership
+    // transfers both families need access.
+    let queue_families = &uploader.queue_families;
+    let sharing = if queue_families.len() > 1 {
+        Sharing::Concurrent(queue_families.iter().copied().collect())
+    } else {
+        Sharing::Exclusive
+    };
+    let image = Image::new(
+        memory_allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            mip_levels: regions.len() as u32,
+            array_layers: if cube { 6 } else { 1 },
+            flags: if cube {
+                ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                ImageCreateFlags::empty()
+            },
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            sharing,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+
+    let queue = uploader.queue.clone();
+    let mut builder = RecordingCommandBuffer::new(
+        uploader.command_buffer_allocator.clone(),
+        queue.queue_family_index(),
+        CommandBufferLevel::Primary,
+        CommandBufferBeginInfo {
+            usage: CommandBufferUsage::OneTimeSubmit,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo {
+            regions: regions.into(),
+            ..CopyBufferToImageInfo::buffer_image(staging_buffer, image.clone())
+        })
+        .unwrap();
+    let command_buffer = builder.end().unwrap();
+
+    sync::now(queue.device().clone())
+        .then_execute(queue, command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    let view_type = if cube {
+        ImageViewType::Cube
+    } else {
+        ImageViewType::Dim2d
+    };
+    Ok(ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type,
+            ..ImageViewCreateInfo::from_image(&image)
+        },
+    )
+    .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_bc1_fallback_to_bgra() {
+        // A single block with both endpoints set to pure red in RGB565.
+        let block = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+        let info = format_info(ktx2::Format::BC1_RGBA_UNORM_BLOCK).unwrap();
+        let bgra = decode_to_bgra(info.decoder.unwrap(), &block, 4, 4).unwrap();
+        assert_eq!(bgra.len(), 4 * 4 * 4);
+        assert!(bgra.chunks(4).all(|texel| texel == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn decodes_srgb_to_linear() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        // sRGB 188 is the usual neutral mid gray, about half the linear intensity.
+        assert!((srgb_to_linear(188.0 / 255.0) - 0.5029).abs() < 1e-4);
+        assert!((srgb_to_linear(0.04) - 0.04 / 12.92).abs() < 1e-7);
+    }
+
+    #[test]
+    fn sizes_levels_by_whole_blocks() {
+        assert_eq!(level_size(Format::R8G8B8A8_UNORM, [3, 2]), 24);
+        // A partial block still takes a whole one.
+        assert_eq!(level_size(Format::BC7_UNORM_BLOCK, [5, 4]), 32);
+        assert_eq!(level_size(Format::BC1_RGBA_UNORM_BLOCK, [1, 1]), 8);
+    }
+
+    #[test]
+    fn rejects_unmapped_formats() {
+        assert!(format_info(ktx2::Format::R8G8B8_UNORM).is_none());
+    }
+}
@@ -0,0 +1,325 @@
+//! The egui panels drawn over the window: the render settings the keys otherwise change, and an
+//! overlay with frame stats.
+
+use std::{fmt::Debug, sync::Arc};
+
+use egui::{Align2, Area, ClippedPrimitive, ComboBox, Context, Frame, Id, Slider, ViewportId};
+use vulkano::{command_buffer::RecordingCommandBuffer, image::view::ImageView};
+use winit::{
+    event::WindowEvent,
+    window::{Window, WindowId},
+};
+
+use crate::{
+    pipeline::{
+        egui::EguiPipeline,
+        sample::{AmbientSettings, DebugView, FogFalloff, FogSettings, LightingModel},
+        tonemap::TonemapSettings,
+        AaMode, FaceCulling,
+    },
+    App, CONTRAST_STEP, FOG_DENSITY, Z_FAR,
+};
+
+/// What the settings panel edits, read from and applied to an [`App`] around each frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Settings {
+    pub aa_mode: AaMode,
+    pub face_culling: FaceCulling,
+    pub wireframe: bool,
+    pub depth_heatmap: bool,
+    pub debug_view: DebugView,
+    pub lighting_model: LightingModel,
+    pub ambient: AmbientSettings,
+    pub fog: Option<FogSettings>,
+    /// Only adjustable when tone mapping is on, see [`App::set_tonemap`].
+    pub tonemap: Option<TonemapSettings>,
+    pub vsync: bool,
+}
+
+/// What the stats overlay shows, of the last frame.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FrameStats {
+    /// See [`crate::frame_clock::FrameClock::fps`].
+    pub fps: f32,
+    /// In milliseconds.
+    pub frame_time: f32,
+    /// In milliseconds, see [`crate::frame_clock::FrameClock::gpu_time`].
+    pub gpu_time: Option<f32>,
+    /// Of the main or G-buffer pass.
+    pub triangles: u64,
+}
+
+/// egui's state for one window, and the pipeline drawing it over the window's frames.
+pub(crate) struct Ui {
+    state: egui_winit::State,
+    pipeline: EguiPipeline,
+    /// Laid out by the last [`Self::run`], drawn by [`Self::render`].
+    primitives: Vec<ClippedPrimitive>,
+    pixels_per_point: f32,
+    /// Toggled with F1 or the panel's close button.
+    settings_open: bool,
+    /// Toggled with F2.
+    stats_visible: bool,
+    /// Whether egui wants another frame, see [`Self::take_repaint`].
+    repaint: bool,
+}
+
+impl Ui {
+    pub fn new(app: &App, window_id: WindowId) -> Ui {
+        let window = app.windows.get_window(window_id).unwrap();
+        let renderer = app.windows.get_renderer(window_id).unwrap();
+        let properties = app.context.device().physical_device().properties();
+        let state = egui_winit::State::new(
+            Context::default(),
+            ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(properties.max_image_dimension2_d as usize),
+        );
+        let pipeline = EguiPipeline::new(
+            app,
+            app.context.graphics_queue().clone(),
+            renderer.swapchain_format(),
+        );
+        Self {
+            state,
+            pipeline,
+            primitives: Vec::new(),
+            pixels_per_point: 1.0,
+            settings_open: false,
+            stats_visible: false,
+            repaint: false,
+        }
+    }
+
+    /// Passes `event` to egui. Returns whether egui took it, e.g. a click on the panel, which
+    /// then shouldn't reach the camera.
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        let response = self.state.on_window_event(window, event);
+        // Without the panel, there's nothing for the pointer to hover.
+        self.repaint |= response.repaint && self.settings_open;
+        response.consumed
+    }
+
+    pub fn toggle_settings(&mut self) {
+        self.settings_open = !self.settings_open;
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.stats_visible = !self.stats_visible;
+    }
+
+    /// Whether egui wanted another frame since the last call, e.g. to highlight a hovered
+    /// widget.
+    pub fn take_repaint(&mut self) -> bool {
+        std::mem::take(&mut self.repaint)
+    }
+
+    /// Lays out the panels for the next [`Self::render`], letting the settings panel edit
+    /// `settings`.
+    pub fn run(&mut self, window: &Window, settings: &mut Settings, stats: &FrameStats) {
+        let raw_input = self.state.take_egui_input(window);
+        let settings_open = &mut self.settings_open;
+        let stats_visible = self.stats_visible;
+        let output = self.state.egui_ctx().run(raw_input, |ctx| {
+            egui::Window::new("Render settings")
+                .open(&mut *settings_open)
+                .resizable(false)
+                .show(ctx, |ui| settings_ui(ui, settings));
+            if stats_visible {
+                stats_overlay(ctx, stats);
+            }
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+        self.pipeline.set_textures(&output.textures_delta);
+        self.primitives = self
+            .state
+            .egui_ctx()
+            .tessellate(output.shapes, output.pixels_per_point);
+        self.pixels_per_point = output.pixels_per_point;
+        // Animations, like a collapsing section, ask for the next frame right away.
+        self.repaint |= output
+            .viewport_output
+            .get(&ViewportId::ROOT)
+            .is_some_and(|viewport| viewport.repaint_delay.is_zero());
+    }
+
+    /// Draws what the last [`Self::run`] laid out over `dst_image`, a swapchain image of the
+    /// window. Must be recorded outside of any render pass.
+    pub fn render(&mut self, builder: &mut RecordingCommandBuffer, dst_image: Arc<ImageView>) {
+        self.pipeline
+            .render(builder, dst_image, &self.primitives, self.pixels_per_point);
+    }
+}
+
+/// In the top right corner, out of the settings panel's way. It doesn't take any input.
+fn stats_overlay(ctx: &Context, stats: &FrameStats) {
+    Area::new(Id::new("frame stats"))
+        .anchor(Align2::RIGHT_TOP, [-8.0, 8.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.monospace(format!("{:.0} fps", stats.fps));
+                ui.monospace(format!("{:.2} ms frame", stats.frame_time));
+                if let Some(gpu_time) = stats.gpu_time {
+                    ui.monospace(format!("{gpu_time:.2} ms GPU"));
+                }
+                ui.monospace(format!("{} triangles", stats.triangles));
+            });
+        });
+}
+
+fn settings_ui(ui: &mut egui::Ui, settings: &mut Settings) {
+    combo(
+        ui,
+        "Anti-aliasing",
+        &mut settings.aa_mode,
+        cycle(AaMode::Off, AaMode::next),
+    );
+    combo(
+        ui,
+        "Face culling",
+        &mut settings.face_culling,
+        cycle(FaceCulling::Back, FaceCulling::next),
+    );
+    ui.checkbox(&mut settings.wireframe, "Wireframe");
+    ui.checkbox(&mut settings.depth_heatmap, "Depth heatmap");
+    ui.checkbox(&mut settings.vsync, "Vsync");
+
+    ui.separator();
+    // Only the forward path has these.
+    combo(ui, "Debug view", &mut settings.debug_view, DebugView::ALL);
+    combo(
+        ui,
+        "Lighting model",
+        &mut settings.lighting_model,
+        cycle(LightingModel::Phong, LightingModel::toggled),
+    );
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut settings.ambient.color);
+        ui.label("Ambient color");
+    });
+    ui.add(
+        Slider::new(&mut settings.ambient.occlusion_strength, 0.0..=1.0)
+            .text("Ambient occlusion strength"),
+    );
+
+    ui.separator();
+    fog_ui(ui, &mut settings.fog);
+
+    ui.separator();
+    match &mut settings.tonemap {
+        Some(tonemap) => {
+            ui.add(
+                Slider::new(&mut tonemap.white_point, 1.0..=64.0)
+                    .logarithmic(true)
+                    .text("White point"),
+            );
+            ui.add(Slider::new(&mut tonemap.contrast, CONTRAST_STEP..=3.0).text("Contrast"));
+        }
+        None => {
+            ui.label("Tone mapping is off, see App::set_tonemap");
+        }
+    }
+}
+
+/// Switches between no fog and the two falloffs like the G key, and edits the one chosen.
+fn fog_ui(ui: &mut egui::Ui, fog: &mut Option<FogSettings>) {
+    let name = |fog: Option<&FogSettings>| match fog.map(|fog| fog.falloff) {
+        None => "Off",
+        Some(FogFalloff::Linear { .. }) => "Linear",
+        Some(FogFalloff::Exponential { .. }) => "Exponential",
+    };
+    let color = fog.map_or(FogSettings::default().color, |fog| fog.color);
+    let options = [
+        None,
+        Some(FogSettings {
+            color,
+            ..Default::default()
+        }),
+        Some(FogSettings {
+            color,
+            falloff: FogFalloff::Exponential {
+                density: FOG_DENSITY,
+            },
+        }),
+    ];
+    ComboBox::from_label("Fog")
+        .selected_text(name(fog.as_ref()))
+        .show_ui(ui, |ui| {
+            for option in options {
+                let selected = name(fog.as_ref()) == name(option.as_ref());
+                if ui
+                    .selectable_label(selected, name(option.as_ref()))
+                    .clicked()
+                    && !selected
+                {
+                    *fog = option;
+                }
+            }
+        });
+
+    let Some(fog) = fog else {
+        return;
+    };
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut fog.color);
+        ui.label("Fog color");
+    });
+    match &mut fog.falloff {
+        FogFalloff::Linear { start, end } => {
+            ui.add(Slider::new(start, 0.0..=*end).text("Fog start"));
+            ui.add(Slider::new(end, *start..=Z_FAR).text("Fog end"));
+        }
+        FogFalloff::Exponential { density } => {
+            ui.add(
+                Slider::new(density, 0.001..=1.0)
+                    .logarithmic(true)
+                    .text("Fog density"),
+            );
+        }
+    }
+}
+
+/// A combo box choosing `value` from `options`, which are named by their [`Debug`] output.
+fn combo<T: Copy + PartialEq + Debug>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut T,
+    options: impl IntoIterator<Item = T>,
+) {
+    ComboBox::from_label(label)
+        .selected_text(format!("{value:?}"))
+        .show_ui(ui, |ui| {
+            for option in options {
+                ui.selectable_value(value, option, format!("{option:?}"));
+            }
+        });
+}
+
+/// Every value `next` cycles through from `first`, e.g. the ones a key steps through.
+fn cycle<T: Copy + PartialEq>(first: T, next: impl Fn(T) -> T) -> impl Iterator<Item = T> {
+    std::iter::successors(Some(first), move |&value| {
+        Some(next(value)).filter(|&value| value != first)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_through_every_option_once() {
+        let aa_modes: Vec<_> = cycle(AaMode::Off, AaMode::next).collect();
+        assert_eq!(aa_modes.len(), 5);
+        assert_eq!(aa_modes[4], AaMode::Fxaa);
+        let face_culling: Vec<_> = cycle(FaceCulling::Back, FaceCulling::next).collect();
+        assert_eq!(
+            face_culling,
+            [FaceCulling::Back, FaceCulling::Front, FaceCulling::None]
+        );
+    }
+}